@@ -4,7 +4,7 @@ extern crate locustdb;
 extern crate test;
 
 use futures_executor::block_on;
-use locustdb::{LocustDB, Options};
+use locustdb::{LoadOptions, LocustDB, Options};
 use std::env;
 use std::u32;
 
@@ -26,6 +26,8 @@ fn db() -> &'static LocustDB {
                 gen_table(&locustdb, "trips_e8", 100, 1 << 20);
                 gen_table(&locustdb, "trips_e7", 80, 1 << 17);
                 gen_table(&locustdb, "trips_e6", 64, 1 << 14);
+                load_small_ints(&locustdb, "small_ints_u8", false);
+                load_small_ints(&locustdb, "small_ints_bit_packed", true);
                 eprintln!("Done");
 
                 DB = Some(locustdb);
@@ -84,6 +86,18 @@ fn gen_table(db: &LocustDB, name: &str, partitions: usize, partition_size: usize
     ));
 }
 
+/// Loads 2^22 rows with a 3 bit column, stored as one byte per value or bit-packed.
+fn load_small_ints(db: &LocustDB, name: &str, bit_packing: bool) {
+    let mut csv = "small\n".to_string();
+    for i in 0..(1u64 << 22) {
+        csv.push_str(&format!("{}\n", i.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 61));
+    }
+    let opts = LoadOptions::new("bench", name)
+        .with_partition_size(1 << 16)
+        .with_bit_packing(bit_packing);
+    db.load_csv_stream(csv.as_bytes(), opts).unwrap();
+}
+
 fn bench_query(b: &mut test::Bencher, query_str: &str) {
     let locustdb = db();
     b.iter(|| {
@@ -138,7 +152,32 @@ fn hashmap_grouping(b: &mut test::Bencher) {
     bench_query(b, "SELECT passenger_count, reducible1, reducible2, count(0) FROM trips_e7;");
 }
 
+#[bench]
+fn high_cardinality_grouping(b: &mut test::Bencher) {
+    bench_query(b, "SELECT uniform_u32, count(0) FROM trips_e6;");
+}
+
 #[bench]
 fn group_by_trip_id(b: &mut test::Bencher) {
     bench_query(b, "SELECT trip_id / 5, sum(total_amount) FROM trips_e6;");
 }
+
+#[bench]
+fn sum_small_ints_u8(b: &mut test::Bencher) {
+    bench_query(b, "SELECT sum(small) FROM small_ints_u8;");
+}
+
+#[bench]
+fn sum_small_ints_bit_packed(b: &mut test::Bencher) {
+    bench_query(b, "SELECT sum(small) FROM small_ints_bit_packed;");
+}
+
+#[bench]
+fn count_by_small_ints_u8(b: &mut test::Bencher) {
+    bench_query(b, "SELECT small, count(0) FROM small_ints_u8;");
+}
+
+#[bench]
+fn count_by_small_ints_bit_packed(b: &mut test::Bencher) {
+    bench_query(b, "SELECT small, count(0) FROM small_ints_bit_packed;");
+}
@@ -11,6 +11,8 @@ extern crate env_logger;
 extern crate log;
 extern crate sqlparser;
 
+use std::io;
+
 use failure::Fail;
 use futures_executor::block_on;
 use locustdb::unit_fmt::*;
@@ -18,9 +20,19 @@ use locustdb::LocustDB;
 use time::precise_time_ns;
 use clap::{Arg, App};
 
+/// Prints to stderr when query results are written as csv or json, so that stdout only contains result rows.
+macro_rules! status {
+    ($data_output:expr, $($arg:tt)*) => {
+        if $data_output { eprintln!($($arg)*) } else { println!($($arg)*) }
+    };
+}
+
 mod print_results;
 mod fmt_table;
 
+/// Number of rows used to determine column types with --infer
+const INFER_SAMPLE_ROWS: usize = 10_000;
+
 fn main() {
     env_logger::init();
 
@@ -43,8 +55,11 @@ fn main() {
             .value_name("CSV_FILE")
             .multiple(true)
             .takes_value(true))
+        .arg(Arg::with_name("stdin")
+            .help("Load .csv or .csv.gz data piped to stdin into the database")
+            .long("stdin"))
         .arg(Arg::with_name("table")
-            .help("Name for the table populated with --load")
+            .help("Name for the table populated with --load or --stdin")
             .long("table")
             .value_name("NAME")
             .default_value("default")
@@ -71,11 +86,34 @@ fn main() {
         .arg(Arg::with_name("seq-disk-read")
             .help("Improves performance on HDD, can hurt performance on SSD.")
             .long("seq-disk-read"))
+        .arg(Arg::with_name("detailed-stats")
+            .help("Report time spent in each phase of query execution")
+            .long("detailed-stats"))
+        .arg(Arg::with_name("output")
+            .help("Format of query results. Csv and json output omit query plans and statistics \
+                   and write everything except result rows to stderr.")
+            .long("output")
+            .value_name("FORMAT")
+            .possible_values(&["table", "csv", "json"])
+            .default_value("table"))
+        .arg(Arg::with_name("stream")
+            .help("Print result rows line by line as soon as each partition has been processed. \
+                   Everything except result rows is written to stderr.")
+            .long("stream"))
+        .arg(Arg::with_name("float-precision")
+            .help("Number of decimal places to round floats to in query results")
+            .long("float-precision")
+            .value_name("DIGITS")
+            .takes_value(true))
         .arg(Arg::with_name("threads")
             .help(&help_threads)
             .long("threads")
             .value_name("INTEGER")
             .takes_value(true))
+        .arg(Arg::with_name("infer")
+            .help("Print the column types inferred from the first rows of the files passed to --load and exit")
+            .long("infer")
+            .requires("load"))
         .arg(Arg::with_name("reduced-trips")
             .help("Set ingestion schema for select set of columns from nyc taxi ride dataset")
             .long("reduced-trips")
@@ -93,9 +131,21 @@ fn main() {
     let full_nyc = matches.is_present("trips");
     let db_path = matches.value_of("db-path");
     let file_count = files.len();
+    let stream = matches.is_present("stream");
+    let output_format = matches.value_of("output").unwrap().to_string();
+    // `output` has a default value, so the conflict can not be declared with `conflicts_with`
+    if stream && matches.occurrences_of("output") > 0 {
+        clap::Error::with_description("--stream can not be combined with --output", clap::ErrorKind::ArgumentConflict).exit();
+    }
+    let data_output = stream || output_format != "table";
+    let float_precision = if matches.is_present("float-precision") {
+        Some(value_t!(matches, "float-precision", usize).unwrap())
+    } else {
+        None
+    };
 
     if matches.is_present("db-path") && !cfg!(feature = "enable_rocksdb") {
-        println!("WARNING: --db-path option passed, but RocksDB storage backend is not enabled in this build of LocustDB.");
+        status!(data_output, "WARNING: --db-path option passed, but RocksDB storage backend is not enabled in this build of LocustDB.");
     }
 
     options.db_path = db_path.map(|x| x.to_string());
@@ -114,13 +164,27 @@ fn main() {
         .map(|x| x * 1024 * 1024)
         .expect("Argument --readahead must be a positive integer!");
     options.mem_lz4 = matches.is_present("mem-lz4");
+    options.detailed_stats = matches.is_present("detailed-stats");
     if matches.is_present("seq-disk-read") {
         options.seq_disk_read = true;
         options.read_threads = 1;
     }
 
     if options.readahead > options.mem_size_limit_tables {
-        println!("WARNING: `mem-limit-tables` should be at least as large as `readahead`");
+        status!(data_output, "WARNING: `mem-limit-tables` should be at least as large as `readahead`");
+    }
+
+    if matches.is_present("infer") {
+        for file in files {
+            println!("\n# {} #", file);
+            match locustdb::infer_schema(&locustdb::LoadOptions::new(&file, &tablename), INFER_SAMPLE_ROWS) {
+                Ok(columns) => for column in columns {
+                    println!("{}: {:?} ({:.1}% null)", column.name, column.basic_type, column.null_fraction * 100.0);
+                },
+                Err(err) => println!("Failed to infer schema: {}", err),
+            }
+        }
+        return;
     }
 
     let locustdb = locustdb::LocustDB::new(&options);
@@ -139,40 +203,47 @@ fn main() {
         let load = locustdb.load_csv(opts);
         loads.push(load);
         if file_count < 4 {
-            println!("Loading {} into table {}.", file, tablename);
+            status!(data_output, "Loading {} into table {}.", file, tablename);
         }
     }
     if file_count >= 4 {
-        println!("Loading {} files into table {}.", file_count, tablename);
+        status!(data_output, "Loading {} files into table {}.", file_count, tablename);
     }
     for l in loads {
         block_on(l)
             .expect("Ingestion crashed!")
             .expect("Failed to load file!");
     }
-    if file_count > 0 {
-        println!("Loaded data in {:.3}.", ns((precise_time_ns() - start_time) as usize));
+    if matches.is_present("stdin") {
+        let opts = locustdb::LoadOptions::new("stdin", &tablename).with_partition_size(partition_size);
+        status!(data_output, "Loading stdin into table {}.", tablename);
+        locustdb.load_csv_stream(io::stdin(), opts).expect("Failed to load stdin!");
+    }
+    if file_count > 0 || matches.is_present("stdin") {
+        status!(data_output, "Loaded data in {:.3}.", ns((precise_time_ns() - start_time) as usize));
     }
 
-    table_stats(&locustdb);
-    repl(&locustdb);
+    table_stats(&locustdb, data_output);
+    repl(&locustdb, &output_format, stream, float_precision);
 }
 
-fn table_stats(locustdb: &LocustDB) {
+fn table_stats(locustdb: &LocustDB, data_output: bool) {
     let stats = block_on(locustdb.table_stats()).expect("!?!");
     for table in stats {
         let size = table.batches_bytes + table.buffer_bytes;
-        println!("\n# Table `{}` ({} rows, {}) #", &table.name, table.rows, bite(size));
-        for &(ref columname, heapsize) in &table.size_per_column {
-            println!("{}: {:.2}", columname, bite(heapsize));
+        status!(data_output, "\n# Table `{}` ({} rows, {}) #", &table.name, table.rows, bite(size));
+        for column in &table.columns {
+            status!(data_output, "{}: {:.2} ({:.1}% of {:.2} raw)",
+                    column.name, bite(column.encoded_bytes), 100.0 * column.compression_ratio(), bite(column.raw_bytes));
         }
     }
 }
 
-fn repl(locustdb: &LocustDB) {
+fn repl(locustdb: &LocustDB, output_format: &str, stream: bool, float_precision: Option<usize>) {
+    let data_output = stream || output_format != "table";
     let mut rl = rustyline::Editor::<()>::new();
     rl.load_history(".locustdb_history").ok();
-    while let Ok(mut s) = rl.readline("locustdb> ") {
+    while let Some(mut s) = read_line(&mut rl, data_output) {
         if let Some('\n') = s.chars().next_back() {
             s.pop();
         }
@@ -201,9 +272,9 @@ fn repl(locustdb: &LocustDB) {
             } else { 2 };
             match block_on(locustdb.mem_tree(depth)) {
                 Ok(trees) => for tree in trees {
-                    println!("{}\n", &tree)
+                    status!(data_output, "{}\n", &tree)
                 },
-                _ => println!("Error: Query execution was canceled!"),
+                _ => status!(data_output, "Error: Query execution was canceled!"),
             }
             continue;
         }
@@ -211,13 +282,13 @@ fn repl(locustdb: &LocustDB) {
             let start = precise_time_ns();
             match block_on(locustdb.bulk_load()) {
                 Ok(trees) => {
-                    println!("Restored DB from disk in {}",
-                             ns((precise_time_ns() - start) as usize));
+                    status!(data_output, "Restored DB from disk in {}",
+                            ns((precise_time_ns() - start) as usize));
                     for tree in trees {
-                        println!("{}\n", &tree)
+                        status!(data_output, "{}\n", &tree)
                     }
                 }
-                _ => println!("Error: Query execution was canceled!"),
+                _ => status!(data_output, "Error: Query execution was canceled!"),
             }
             continue;
         }
@@ -245,7 +316,14 @@ fn repl(locustdb: &LocustDB) {
             continue;
         }
         if s.starts_with(":ast") {
-            println!("{}", locustdb.ast(&s[5..]));
+            status!(data_output, "{}", locustdb.ast(&s[5..]));
+            continue;
+        }
+
+        if stream && !explain && !print_trace && show.is_empty() {
+            if let Err(fail) = print_results::stream_query_result(locustdb.run_query_streaming(s), float_precision) {
+                print_error(&fail, data_output);
+            }
             continue;
         }
 
@@ -253,25 +331,41 @@ fn repl(locustdb: &LocustDB) {
         match block_on(query) {
             Ok((result, trace)) => {
                 if print_trace {
-                    trace.print();
+                    status!(data_output, "{}", trace.format().trim_right());
                 }
                 match result {
-                    Ok(output) => print_results::print_query_result(&output),
-                    Err(mut fail) => print_error(&fail),
+                    Ok(ref output) if output_format == "csv" => print!("{}", output.to_csv_with_precision(float_precision)),
+                    Ok(ref output) if output_format == "json" => println!("{}", output.to_json_with_precision(float_precision)),
+                    Ok(output) => print_results::print_query_result(&output, float_precision),
+                    Err(mut fail) => print_error(&fail, data_output),
                 }
             }
-            _ => println!("Error: Query execution was canceled!"),
+            _ => status!(data_output, "Error: Query execution was canceled!"),
         }
     }
     rl.save_history(".locustdb_history").ok();
 }
 
-fn print_error(fail: &locustdb::QueryError) {
-    println!("{}", fail);
+fn read_line(rl: &mut rustyline::Editor<()>, data_output: bool) -> Option<String> {
+    if data_output {
+        // rustyline always writes the prompt to stdout
+        eprint!("locustdb> ");
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    } else {
+        rl.readline("locustdb> ").ok()
+    }
+}
+
+fn print_error(fail: &locustdb::QueryError, data_output: bool) {
+    status!(data_output, "{}", fail);
     while let Some(cause) = fail.cause() {
-        println!("{}", cause);
+        status!(data_output, "{}", cause);
         if let Some(bt) = cause.backtrace() {
-            println!("{}", bt);
+            status!(data_output, "{}", bt);
         }
     }
 }
@@ -1,28 +1,88 @@
+use std::io::{self, Write};
+
 use fmt_table::fmt_table;
 use locustdb::*;
 use locustdb::unit_fmt::*;
 
-pub fn print_query_result(results: &QueryOutput) {
-    let rt = results.stats.runtime_ns;
-
+pub fn print_query_result(results: &QueryOutput, float_precision: Option<usize>) {
     println!();
     for (query_plan, count) in &results.query_plans {
         println!("Query plan in {} batches{}", count, query_plan)
     }
-    println!("Scanned {} rows in {} ({:.2} rows/s)!",
-             short_scale(results.stats.rows_scanned as f64),
-             ns(rt as usize),
-             billion(results.stats.rows_scanned as f64 / rt as f64));
-    println!("\n{}", format_results(&results.colnames, &results.rows));
+    println!("{}", format_stats(&results.stats));
+    println!("\n{}", format_results(&results.colnames, &results.rows, float_precision));
     println!();
 }
 
-fn format_results(colnames: &[String], rows: &[Vec<Value>]) -> String {
+/// Prints the column names and then one line per row of each chunk of `chunks` as soon as the chunk is available.
+/// Returns the number of rows printed, or the error of the query.
+pub fn stream_query_result<I>(chunks: I, float_precision: Option<usize>) -> Result<usize, QueryError>
+    where I: Iterator<Item=QueryResult> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    stream_rows(chunks, float_precision, &mut out)
+        .unwrap_or_else(|err| Err(QueryError::FatalError(format!("Failed to write results: {}", err))))
+}
+
+/// Writes the rows of each chunk to `out` and flushes it before waiting for the next chunk.
+fn stream_rows<I, W>(chunks: I, float_precision: Option<usize>, out: &mut W) -> io::Result<Result<usize, QueryError>>
+    where I: Iterator<Item=QueryResult>, W: Write {
+    let mut rows = 0;
+    for (i, chunk) in chunks.enumerate() {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => return Ok(Err(err)),
+        };
+        if i == 0 {
+            writeln!(out, "{}", chunk.colnames.join(", "))?;
+        }
+        for row in &chunk.rows {
+            let formatted = row.iter().map(|val| format_value(val, float_precision)).collect::<Vec<_>>();
+            writeln!(out, "{}", formatted.join(", "))?;
+        }
+        rows += chunk.rows.len();
+        out.flush()?;
+    }
+    Ok(Ok(rows))
+}
+
+fn format_stats(stats: &QueryStats) -> String {
+    let rt = stats.runtime_ns;
+    // Runtime can be reported as 0 for trivial queries, in which case ratios are undefined
+    let throughput = if rt == 0 {
+        "n/a".to_string()
+    } else {
+        format!("{:.2}", billion(stats.rows_scanned as f64 / rt as f64))
+    };
+    let mut result = format!("Scanned {} rows in {} ({} rows/s)!",
+                             short_scale(stats.rows_scanned as f64),
+                             ns(rt as usize),
+                             throughput);
+    if stats.rows_scanned > 0 {
+        result += &format!("\n  {} rows passed filter ({})",
+                           short_scale(stats.rows_returned as f64),
+                           percent(stats.rows_returned as f64 * 100.0 / stats.rows_scanned as f64));
+    }
+
+    let mut breakdown = stats.breakdown.iter().collect::<Vec<_>>();
+    breakdown.sort();
+    for (phase, &duration) in breakdown {
+        let fraction = if rt == 0 {
+            "n/a".to_string()
+        } else {
+            format!("{}", percent(duration as f64 * 100.0 / rt as f64))
+        };
+        result += &format!("\n  {}: {} ({})", phase, ns(duration as usize), fraction);
+    }
+    result
+}
+
+fn format_results(colnames: &[String], rows: &[Vec<Value>], float_precision: Option<usize>) -> String {
     let strcolnames: Vec<&str> = colnames.iter().map(|s| s as &str).collect();
     let formattedrows: Vec<Vec<String>> = rows.iter()
         .map(|row| {
             row.iter()
-                .map(|val| format!("{}", val))
+                .map(|val| format_value(val, float_precision))
                 .collect()
         })
         .collect();
@@ -32,3 +92,89 @@ fn format_results(colnames: &[String], rows: &[Vec<Value>]) -> String {
     fmt_table(&strcolnames, &strrows)
 }
 
+fn format_value(val: &Value, float_precision: Option<usize>) -> String {
+    match float_precision {
+        Some(precision) => format!("{:.*}", precision, val),
+        None => format!("{}", val),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_format_stats_zero_runtime() {
+        let mut breakdown = HashMap::new();
+        breakdown.insert("merge".to_string(), 0);
+        let stats = QueryStats {
+            runtime_ns: 0,
+            rows_scanned: 0,
+            rows_returned: 0,
+            vector_ops: 0,
+            breakdown,
+        };
+        let formatted = format_stats(&stats);
+        assert!(formatted.contains("(n/a rows/s)"), "{}", formatted);
+        assert!(formatted.contains("merge: "), "{}", formatted);
+    }
+
+    /// Keeps written bytes in `pending` until `flush` moves them to `flushed`
+    struct FlushRecorder {
+        pending: Vec<u8>,
+        flushed: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl Write for FlushRecorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.pending.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushed.borrow_mut().extend(self.pending.drain(..));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_stream_rows() {
+        let locustdb = LocustDB::memory_only();
+        let csv = (0..12).map(|i| format!("{},x{}\n", i, i)).collect::<String>();
+        locustdb.load_csv_stream(format!("n,s\n{}", csv).as_bytes(),
+                                 LoadOptions::new("stdin", "default").with_partition_size(4)).unwrap();
+        let flushed = Rc::new(RefCell::new(Vec::new()));
+        let mut out = FlushRecorder { pending: Vec::new(), flushed: flushed.clone() };
+        // Output that was written before each chunk was received
+        let mut written_before = Vec::new();
+        let chunks = locustdb.run_query_streaming("SELECT n, s FROM default;").map(|chunk| {
+            written_before.push(String::from_utf8(flushed.borrow().clone()).unwrap());
+            chunk
+        });
+        let rows = stream_rows(chunks, None, &mut out).unwrap().unwrap();
+
+        assert_eq!(rows, 12);
+        let lines = |n: i64| (0..n).map(|i| format!("{}, \"x{}\"\n", i, i)).collect::<String>();
+        assert_eq!(written_before, vec![
+            String::new(),
+            format!("n, s\n{}", lines(4)),
+            format!("n, s\n{}", lines(8)),
+        ]);
+        assert_eq!(String::from_utf8(flushed.borrow().clone()).unwrap(), format!("n, s\n{}", lines(12)));
+    }
+
+    #[test]
+    fn test_format_results_float_precision() {
+        let colnames = vec!["a".to_string(), "b".to_string()];
+        let rows = vec![vec![Value::Int(1), Value::Float(2.0 / 3.0)]];
+        let formatted = format_results(&colnames, &rows, Some(2));
+        assert!(formatted.contains("0.67"), "{}", formatted);
+        assert!(!formatted.contains("0.667"), "{}", formatted);
+        assert!(!formatted.contains("1.00"), "{}", formatted);
+        assert!(format_results(&colnames, &rows, None).contains("0.6666666666666666"));
+    }
+}
@@ -0,0 +1,382 @@
+use std::io;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use engine::types::EncodingType as Type;
+use ingest::csv_loader::BatchKey;
+use mem_store::*;
+
+
+/// Identifies files written by `write_tables` and the version of the format.
+const MAGIC: &[u8; 8] = b"LOCUST03";
+
+/// Writes the partitions of each table, keeping all columns in their compressed encodings,
+/// followed by the keys of deduplicated batches of each table.
+/// Data shared between columns (e.g. string dictionaries) is written separately for each column.
+pub fn write_tables<W: Write>(w: &mut W,
+                              tables: &[(String, Vec<Vec<Arc<Column>>>)],
+                              batch_keys: &[(String, BatchKey)]) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_u64::<LittleEndian>(tables.len() as u64)?;
+    for (name, partitions) in tables {
+        write_str(w, name)?;
+        w.write_u64::<LittleEndian>(partitions.len() as u64)?;
+        for columns in partitions {
+            write_columns(w, columns)?;
+        }
+    }
+    w.write_u64::<LittleEndian>(batch_keys.len() as u64)?;
+    for (name, key) in batch_keys {
+        write_str(w, name)?;
+        write_batch_key(w, *key)?;
+    }
+    Ok(())
+}
+
+/// Reads the tables and batch keys written by `write_tables`.
+pub fn read_tables<R: Read>(r: &mut R) -> io::Result<(Vec<(String, Vec<Vec<Arc<Column>>>)>, Vec<(String, BatchKey)>)> {
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a LocustDB data file"));
+    }
+    let mut tables = Vec::new();
+    for _ in 0..r.read_u64::<LittleEndian>()? {
+        let name = read_str(r)?;
+        let mut partitions = Vec::new();
+        for _ in 0..r.read_u64::<LittleEndian>()? {
+            partitions.push(read_columns(r)?);
+        }
+        tables.push((name, partitions));
+    }
+    let mut batch_keys = Vec::new();
+    for _ in 0..r.read_u64::<LittleEndian>()? {
+        let name = read_str(r)?;
+        batch_keys.push((name, read_batch_key(r)?));
+    }
+    Ok((tables, batch_keys))
+}
+
+/// Appends a single partition of `table` and the key of the batch it was created from (if deduplicated)
+/// to a write-ahead log as one length prefixed record.
+pub fn append_partition<W: Write>(w: &mut W,
+                                  table: &str,
+                                  columns: &[Arc<Column>],
+                                  batch_key: Option<BatchKey>) -> io::Result<()> {
+    let mut record = Vec::new();
+    write_str(&mut record, table)?;
+    write_columns(&mut record, columns)?;
+    match batch_key {
+        None => record.write_u8(0)?,
+        Some(key) => {
+            record.write_u8(1)?;
+            write_batch_key(&mut record, key)?;
+        }
+    }
+    w.write_u64::<LittleEndian>(record.len() as u64)?;
+    w.write_all(&record)?;
+    w.flush()
+}
+
+/// Reads all partitions appended by `append_partition` to a log of `log_len` bytes, and returns them together with
+/// the offset following the last complete record.
+/// A partially written record at the end of the log (e.g. after a crash) is ignored, records that claim to be longer
+/// than the remainder of the log are treated as partially written.
+pub fn read_log<R: Read>(r: &mut R, log_len: u64) -> io::Result<(Vec<(String, Vec<Arc<Column>>, Option<BatchKey>)>, u64)> {
+    let mut partitions = Vec::new();
+    let mut offset = 0;
+    loop {
+        let len = match r.read_u64::<LittleEndian>() {
+            Ok(len) => len,
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        };
+        if len > log_len.saturating_sub(offset + 8) { break; }
+        let mut record = vec![0; len as usize];
+        match r.read_exact(&mut record) {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        }
+        let mut record = &record[..];
+        let table = read_str(&mut record)?;
+        let columns = read_columns(&mut record)?;
+        let batch_key = match record.read_u8()? {
+            0 => None,
+            1 => Some(read_batch_key(&mut record)?),
+            tag => return Err(invalid_data(&format!("invalid batch key tag {}", tag))),
+        };
+        partitions.push((table, columns, batch_key));
+        offset += 8 + len;
+    }
+    Ok((partitions, offset))
+}
+
+fn write_batch_key<W: Write>(w: &mut W, (hash, count): BatchKey) -> io::Result<()> {
+    w.write_all(&hash)?;
+    w.write_u64::<LittleEndian>(count as u64)
+}
+
+fn read_batch_key<R: Read>(r: &mut R) -> io::Result<BatchKey> {
+    let mut hash = [0u8; 16];
+    r.read_exact(&mut hash)?;
+    Ok((hash, r.read_u64::<LittleEndian>()? as usize))
+}
+
+fn write_columns<W: Write>(w: &mut W, columns: &[Arc<Column>]) -> io::Result<()> {
+    w.write_u64::<LittleEndian>(columns.len() as u64)?;
+    for column in columns {
+        write_column(w, column)?;
+    }
+    Ok(())
+}
+
+fn read_columns<R: Read>(r: &mut R) -> io::Result<Vec<Arc<Column>>> {
+    let mut columns = Vec::new();
+    for _ in 0..r.read_u64::<LittleEndian>()? {
+        columns.push(Arc::new(read_column(r)?));
+    }
+    Ok(columns)
+}
+
+fn write_column<W: Write>(w: &mut W, col: &Column) -> io::Result<()> {
+    write_str(w, col.name())?;
+    w.write_u64::<LittleEndian>(col.len() as u64)?;
+    match col.range() {
+        None => w.write_u8(0)?,
+        Some((start, end)) => {
+            w.write_u8(1)?;
+            w.write_i64::<LittleEndian>(start)?;
+            w.write_i64::<LittleEndian>(end)?;
+        }
+    }
+    let ops = col.codec().ops().to_vec();
+    w.write_u64::<LittleEndian>(ops.len() as u64)?;
+    for op in ops {
+        write_codec_op(w, op)?;
+    }
+    w.write_u64::<LittleEndian>(col.data().len() as u64)?;
+    for section in col.data() {
+        write_data_section(w, section)?;
+    }
+    match col.nulls() {
+        None => w.write_u8(0),
+        Some(nulls) => {
+            w.write_u8(1)?;
+            write_u8s(w, nulls)
+        }
+    }
+}
+
+fn read_column<R: Read>(r: &mut R) -> io::Result<Column> {
+    let name = read_str(r)?;
+    let len = r.read_u64::<LittleEndian>()? as usize;
+    let range = match r.read_u8()? {
+        0 => None,
+        1 => Some((r.read_i64::<LittleEndian>()?, r.read_i64::<LittleEndian>()?)),
+        tag => return Err(invalid_data(&format!("invalid range tag {}", tag))),
+    };
+    let mut codec = Vec::new();
+    for _ in 0..r.read_u64::<LittleEndian>()? {
+        codec.push(read_codec_op(r)?);
+    }
+    let mut data = Vec::new();
+    for _ in 0..r.read_u64::<LittleEndian>()? {
+        data.push(read_data_section(r)?);
+    }
+    let mut column = Column::new(&name, len, range, codec, data);
+    match r.read_u8()? {
+        0 => {}
+        1 => match read_data_section(r)? {
+            DataSection::U8(ref nulls) if nulls.len() != len =>
+                return Err(invalid_data(&format!("null map of column {} has wrong length", name))),
+            DataSection::U8(nulls) => column.set_nulls(nulls),
+            _ => return Err(invalid_data(&format!("invalid null map for column {}", name))),
+        },
+        tag => return Err(invalid_data(&format!("invalid null map tag {}", tag))),
+    }
+    Ok(column)
+}
+
+fn write_codec_op<W: Write>(w: &mut W, op: CodecOp) -> io::Result<()> {
+    match op {
+        CodecOp::Add(t, amount) => {
+            w.write_u8(0)?;
+            write_type(w, t)?;
+            w.write_i64::<LittleEndian>(amount)
+        }
+        CodecOp::Delta(t) => {
+            w.write_u8(1)?;
+            write_type(w, t)
+        }
+        CodecOp::ToI64(t) => {
+            w.write_u8(2)?;
+            write_type(w, t)
+        }
+        CodecOp::PushDataSection(section) => {
+            w.write_u8(3)?;
+            w.write_u64::<LittleEndian>(section as u64)
+        }
+        CodecOp::DictLookup(t) => {
+            w.write_u8(4)?;
+            write_type(w, t)
+        }
+        CodecOp::LZ4(t, decoded_length) => {
+            w.write_u8(5)?;
+            write_type(w, t)?;
+            w.write_u64::<LittleEndian>(decoded_length as u64)
+        }
+        CodecOp::UnpackBits(width, decoded_length) => {
+            w.write_u8(6)?;
+            w.write_u8(width)?;
+            w.write_u64::<LittleEndian>(decoded_length as u64)
+        }
+        CodecOp::UnpackBools(decoded_length) => {
+            w.write_u8(7)?;
+            w.write_u64::<LittleEndian>(decoded_length as u64)
+        }
+        CodecOp::UnpackStrings => w.write_u8(8),
+        CodecOp::UnhexpackStrings(uppercase, total_bytes) => {
+            w.write_u8(9)?;
+            w.write_u8(uppercase as u8)?;
+            w.write_u64::<LittleEndian>(total_bytes as u64)
+        }
+        CodecOp::Unknown => panic!("Trying to serialize CodecOp::Unkown"),
+    }
+}
+
+fn read_codec_op<R: Read>(r: &mut R) -> io::Result<CodecOp> {
+    Ok(match r.read_u8()? {
+        0 => CodecOp::Add(read_type(r)?, r.read_i64::<LittleEndian>()?),
+        1 => CodecOp::Delta(read_type(r)?),
+        2 => CodecOp::ToI64(read_type(r)?),
+        3 => CodecOp::PushDataSection(r.read_u64::<LittleEndian>()? as usize),
+        4 => CodecOp::DictLookup(read_type(r)?),
+        5 => CodecOp::LZ4(read_type(r)?, r.read_u64::<LittleEndian>()? as usize),
+        6 => CodecOp::UnpackBits(r.read_u8()?, r.read_u64::<LittleEndian>()? as usize),
+        7 => CodecOp::UnpackBools(r.read_u64::<LittleEndian>()? as usize),
+        8 => CodecOp::UnpackStrings,
+        9 => CodecOp::UnhexpackStrings(r.read_u8()? != 0, r.read_u64::<LittleEndian>()? as usize),
+        tag => return Err(invalid_data(&format!("invalid codec op tag {}", tag))),
+    })
+}
+
+fn write_type<W: Write>(w: &mut W, t: Type) -> io::Result<()> {
+    w.write_u8(match t {
+        Type::U8 => 0,
+        Type::U16 => 1,
+        Type::U32 => 2,
+        Type::U64 => 3,
+        Type::I64 => 4,
+        Type::Null => 5,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported encoding type {:?}", t))),
+    })
+}
+
+fn read_type<R: Read>(r: &mut R) -> io::Result<Type> {
+    Ok(match r.read_u8()? {
+        0 => Type::U8,
+        1 => Type::U16,
+        2 => Type::U32,
+        3 => Type::U64,
+        4 => Type::I64,
+        5 => Type::Null,
+        tag => return Err(invalid_data(&format!("invalid encoding type tag {}", tag))),
+    })
+}
+
+fn write_data_section<W: Write>(w: &mut W, section: &DataSection) -> io::Result<()> {
+    match section {
+        DataSection::U8(x) => write_u8s(w, x),
+        DataSection::SharedU8(x) => write_u8s(w, x),
+        DataSection::U16(x) => {
+            w.write_u8(1)?;
+            w.write_u64::<LittleEndian>(x.len() as u64)?;
+            for &v in x { w.write_u16::<LittleEndian>(v)?; }
+            Ok(())
+        }
+        DataSection::U32(x) => {
+            w.write_u8(2)?;
+            w.write_u64::<LittleEndian>(x.len() as u64)?;
+            for &v in x { w.write_u32::<LittleEndian>(v)?; }
+            Ok(())
+        }
+        DataSection::U64(x) => write_u64s(w, x),
+        DataSection::SharedU64(x) => write_u64s(w, x),
+        DataSection::I64(x) => {
+            w.write_u8(4)?;
+            w.write_u64::<LittleEndian>(x.len() as u64)?;
+            for &v in x { w.write_i64::<LittleEndian>(v)?; }
+            Ok(())
+        }
+        DataSection::Null(count) => {
+            w.write_u8(5)?;
+            w.write_u64::<LittleEndian>(*count as u64)
+        }
+    }
+}
+
+fn write_u8s<W: Write>(w: &mut W, x: &[u8]) -> io::Result<()> {
+    w.write_u8(0)?;
+    w.write_u64::<LittleEndian>(x.len() as u64)?;
+    w.write_all(x)
+}
+
+fn write_u64s<W: Write>(w: &mut W, x: &[u64]) -> io::Result<()> {
+    w.write_u8(3)?;
+    w.write_u64::<LittleEndian>(x.len() as u64)?;
+    for &v in x { w.write_u64::<LittleEndian>(v)?; }
+    Ok(())
+}
+
+fn read_data_section<R: Read>(r: &mut R) -> io::Result<DataSection> {
+    let tag = r.read_u8()?;
+    let len = r.read_u64::<LittleEndian>()? as usize;
+    Ok(match tag {
+        0 => {
+            let mut buffer = vec![0; len];
+            r.read_exact(&mut buffer)?;
+            DataSection::U8(buffer)
+        }
+        1 => {
+            let mut buffer = vec![0; len];
+            r.read_u16_into::<LittleEndian>(&mut buffer)?;
+            DataSection::U16(buffer)
+        }
+        2 => {
+            let mut buffer = vec![0; len];
+            r.read_u32_into::<LittleEndian>(&mut buffer)?;
+            DataSection::U32(buffer)
+        }
+        3 => {
+            let mut buffer = vec![0; len];
+            r.read_u64_into::<LittleEndian>(&mut buffer)?;
+            DataSection::U64(buffer)
+        }
+        4 => {
+            let mut buffer = vec![0; len];
+            r.read_i64_into::<LittleEndian>(&mut buffer)?;
+            DataSection::I64(buffer)
+        }
+        5 => DataSection::Null(len),
+        _ => return Err(invalid_data(&format!("invalid data section tag {}", tag))),
+    })
+}
+
+fn write_str<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    w.write_u64::<LittleEndian>(s.len() as u64)?;
+    w.write_all(s.as_bytes())
+}
+
+fn read_str<R: Read>(r: &mut R) -> io::Result<String> {
+    let len = r.read_u64::<LittleEndian>()? as usize;
+    let mut buffer = vec![0; len];
+    r.read_exact(&mut buffer)?;
+    String::from_utf8(buffer).map_err(|_| invalid_data("invalid utf8 in name"))
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
@@ -1,3 +1,4 @@
+pub mod file_format;
 pub mod interface;
 pub mod noop_storage;
 
@@ -175,6 +175,11 @@ fn deserialize_column(data: &[u8]) -> Column {
                 let lz4 = lz4.unwrap();
                 CodecOp::LZ4(deserialize_type(lz4.get_type().unwrap()), lz4.get_len_decoded() as usize)
             }
+            UnpackBits(unpack) => {
+                let unpack = unpack.unwrap();
+                CodecOp::UnpackBits(unpack.get_width(), unpack.get_len_decoded() as usize)
+            }
+            UnpackBools(len) => CodecOp::UnpackBools(len as usize),
             UnpackStrings(_) => CodecOp::UnpackStrings,
             UnhexpackStrings(uhps) => {
                 let uhps = uhps.unwrap();
@@ -220,7 +225,14 @@ fn deserialize_column(data: &[u8]) -> Column {
         }
     }).collect::<Vec<_>>();
 
-    Column::new(name, len, range, codec, data_sections)
+    let mut result = Column::new(name, len, range, codec, data_sections);
+    if column.has_nulls() {
+        let nulls = column.get_nulls().unwrap();
+        let mut buffer = Vec::with_capacity(nulls.len() as usize);
+        buffer.extend(nulls);
+        result.set_nulls(buffer);
+    }
+    result
 }
 
 fn deserialize_type(t: EncodingType) -> Type {
@@ -309,6 +321,12 @@ fn serialize_column(col: &Column) -> Vec<u8> {
                         lz4.set_type(encoding_type_to_capnp(t));
                         lz4.set_len_decoded(decoded_length as u64);
                     }
+                    CodecOp::UnpackBits(width, decoded_length) => {
+                        let mut unpack = capnp_op.init_unpack_bits();
+                        unpack.set_width(width);
+                        unpack.set_len_decoded(decoded_length as u64);
+                    }
+                    CodecOp::UnpackBools(decoded_length) => capnp_op.set_unpack_bools(decoded_length as u64),
                     CodecOp::UnpackStrings => capnp_op.set_unpack_strings(()),
                     CodecOp::UnhexpackStrings(uppercase, total_bytes) => {
                         let mut uhps = capnp_op.init_unhexpack_strings();
@@ -345,9 +363,21 @@ fn serialize_column(col: &Column) -> Vec<u8> {
                         populate_primitive_list(&mut builder, x);
                     }
                     DataSection::Null(count) => ds.set_null(*count as u64),
+                    DataSection::SharedU8(x) => {
+                        let mut builder = ds.init_u8(x.len() as u32);
+                        populate_primitive_list(&mut builder, x);
+                    }
+                    DataSection::SharedU64(x) => {
+                        let mut builder = ds.init_u64(x.len() as u32);
+                        populate_primitive_list(&mut builder, x);
+                    }
                 }
             }
         }
+        if let Some(nulls) = col.nulls() {
+            let mut builder = column.reborrow().init_nulls(nulls.len() as u32);
+            populate_primitive_list(&mut builder, nulls);
+        }
     }
     let mut buffer = Vec::new();
     capnp::serialize::write_message(&mut buffer, &builder).unwrap();
@@ -1,11 +1,320 @@
+use std::cmp;
+use std::collections::BTreeMap;
+
+use engine::vector_op::hyperloglog;
+use ingest::raw_val::RawVal;
+
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Aggregator {
     Sum,
     Count,
+    Min,
+    Max,
+    /// HyperLogLog estimate of the number of distinct values with 2^precision registers
+    ApproxCountDistinct(u8),
+    /// Nearest-rank percentile, with the percentage given as an integer between 0 and 100
+    Percentile(u8),
+    /// Percentile that interpolates linearly between the two nearest values, with the fraction given between 0 and 1
+    PercentileCont(f64),
+    /// Smallest value whose cumulative distribution is at least the fraction, which is given between 0 and 1
+    PercentileDisc(f64),
+    /// Sample variance
+    Variance,
+    /// Sample standard deviation
+    StdDev,
+    /// Value of the row with the smallest ordering key
+    First,
+    /// Value of the row with the largest ordering key
+    Last,
+    /// Most frequent value, ties are broken by the smallest value
+    Mode,
 }
 
 impl Aggregator {
     pub fn combine_i64(self, accumulator: i64, elem: i64) -> i64 {
-        accumulator + elem
+        match self {
+            Aggregator::Sum | Aggregator::Count => accumulator + elem,
+            Aggregator::Min => cmp::min(accumulator, elem),
+            Aggregator::Max => cmp::max(accumulator, elem),
+            // Sketches are merged by taking the maximum of each register
+            Aggregator::ApproxCountDistinct(_) => hyperloglog::merge_packed_registers(accumulator, elem),
+            Aggregator::Variance | Aggregator::StdDev =>
+                panic!("Intermediate results of {:?} have to be combined with Moments::merge", self),
+            Aggregator::Percentile(_) | Aggregator::PercentileCont(_) | Aggregator::PercentileDisc(_) |
+            Aggregator::First | Aggregator::Last | Aggregator::Mode =>
+                panic!("Intermediate results of {:?} have to be combined with AggregateState::merge", self),
+        }
+    }
+
+    pub fn combine_i128(self, accumulator: i128, elem: i128) -> i128 {
+        match self {
+            Aggregator::Sum | Aggregator::Count => accumulator + elem,
+            Aggregator::Min => cmp::min(accumulator, elem),
+            Aggregator::Max => cmp::max(accumulator, elem),
+            Aggregator::ApproxCountDistinct(_) => i128::from(hyperloglog::merge_packed_registers(accumulator as i64, elem as i64)),
+            Aggregator::Variance | Aggregator::StdDev =>
+                panic!("Intermediate results of {:?} have to be combined with Moments::merge", self),
+            Aggregator::Percentile(_) | Aggregator::PercentileCont(_) | Aggregator::PercentileDisc(_) |
+            Aggregator::First | Aggregator::Last | Aggregator::Mode =>
+                panic!("Intermediate results of {:?} have to be combined with AggregateState::merge", self),
+        }
+    }
+
+    /// Number of columns used to represent the intermediate results of this aggregator.
+    pub fn intermediate_columns(self) -> usize {
+        match self {
+            Aggregator::ApproxCountDistinct(precision) => (1 << precision) / hyperloglog::REGISTERS_PER_COLUMN,
+            // Count, mean and M2
+            Aggregator::Variance | Aggregator::StdDev => 3,
+            _ => 1,
+        }
+    }
+
+    /// Whether the intermediate results of this aggregator are `AggregateState`s that are merged across partitions
+    /// and evaluated once all partial results have been combined.
+    pub fn has_state(self) -> bool {
+        match self {
+            Aggregator::Percentile(_) | Aggregator::PercentileCont(_) | Aggregator::PercentileDisc(_) |
+            Aggregator::First | Aggregator::Last | Aggregator::Mode => true,
+            _ => false,
+        }
+    }
+}
+
+/// Mergeable intermediate result of an aggregator that can only be evaluated once all values of a group are known.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggregateState {
+    /// Ordering key and value of the row with the smallest key that is not null, ties are broken by the smallest value
+    First(Option<(RawVal, RawVal)>),
+    /// Ordering key and value of the row with the largest key that is not null, ties are broken by the largest value
+    Last(Option<(RawVal, RawVal)>),
+    /// Number of occurrences of each value that is not null
+    Counts(BTreeMap<RawVal, i64>),
+}
+
+impl AggregateState {
+    pub fn new(aggregator: Aggregator) -> AggregateState {
+        match aggregator {
+            Aggregator::First => AggregateState::First(None),
+            Aggregator::Last => AggregateState::Last(None),
+            _ => AggregateState::Counts(BTreeMap::new()),
+        }
+    }
+
+    /// Adds `count` rows with the given value. The ordering key is only used by `First` and `Last`.
+    pub fn push(&mut self, key: RawVal, value: RawVal, count: i64) {
+        match *self {
+            AggregateState::First(_) | AggregateState::Last(_) if key == RawVal::Null => {}
+            AggregateState::First(ref mut first) => {
+                if first.as_ref().map_or(true, |&(ref k, ref v)| (&key, &value) < (k, v)) {
+                    *first = Some((key, value));
+                }
+            }
+            AggregateState::Last(ref mut last) => {
+                if last.as_ref().map_or(true, |&(ref k, ref v)| (&key, &value) > (k, v)) {
+                    *last = Some((key, value));
+                }
+            }
+            AggregateState::Counts(ref mut counts) => {
+                if value != RawVal::Null {
+                    *counts.entry(value).or_insert(0) += count;
+                }
+            }
+        }
+    }
+
+    /// Combines the state of a disjoint set of rows of the same group and aggregator into this state.
+    pub fn merge(&mut self, other: AggregateState) {
+        match other {
+            AggregateState::First(Some((key, value))) | AggregateState::Last(Some((key, value))) =>
+                self.push(key, value, 1),
+            AggregateState::First(None) | AggregateState::Last(None) => {}
+            AggregateState::Counts(counts) => for (value, count) in counts {
+                self.push(RawVal::Null, value, count);
+            },
+        }
+    }
+
+    /// Result of `aggregator` over all rows of this state.
+    pub fn evaluate(&self, aggregator: Aggregator) -> RawVal {
+        match (aggregator, self) {
+            (Aggregator::First, &AggregateState::First(ref first)) =>
+                first.as_ref().map_or(RawVal::Null, |&(_, ref value)| value.clone()),
+            (Aggregator::Last, &AggregateState::Last(ref last)) =>
+                last.as_ref().map_or(RawVal::Null, |&(_, ref value)| value.clone()),
+            (Aggregator::Percentile(p), &AggregateState::Counts(ref counts)) => {
+                let total = counts.values().sum::<i64>();
+                nth_value(counts, cmp::max(1, (i64::from(p) * total + 99) / 100) - 1)
+            }
+            (Aggregator::PercentileDisc(fraction), &AggregateState::Counts(ref counts)) => {
+                let total = counts.values().sum::<i64>();
+                nth_value(counts, cmp::max(1, (fraction * total as f64).ceil() as i64) - 1)
+            }
+            (Aggregator::PercentileCont(fraction), &AggregateState::Counts(ref counts)) => {
+                let total = counts.values().sum::<i64>();
+                if total == 0 { return RawVal::Null; }
+                let position = fraction * (total - 1) as f64;
+                let lower = position.floor();
+                match (nth_value(counts, lower as i64), nth_value(counts, position.ceil() as i64)) {
+                    (RawVal::Int(below), RawVal::Int(above)) =>
+                        RawVal::Float(below as f64 + (above - below) as f64 * (position - lower)),
+                    _ => RawVal::Null,
+                }
+            }
+            // Ties are broken by the smallest value to make the result deterministic
+            (Aggregator::Mode, &AggregateState::Counts(ref counts)) => counts.iter()
+                .min_by(|&(v1, c1), &(v2, c2)| c2.cmp(c1).then_with(|| v1.cmp(v2)))
+                .map_or(RawVal::Null, |(value, _)| value.clone()),
+            _ => panic!("{:?} can not be evaluated from {:?}", aggregator, self),
+        }
+    }
+}
+
+/// Value with the given (zero based) index in the sorted sequence of all values, or null if there are fewer values.
+fn nth_value(counts: &BTreeMap<RawVal, i64>, index: i64) -> RawVal {
+    let mut cumulative = 0;
+    for (value, &count) in counts {
+        cumulative += count;
+        if cumulative > index {
+            return value.clone();
+        }
+    }
+    RawVal::Null
+}
+
+/// Partial state of Welford's algorithm for computing the variance of a stream of values.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Moments {
+    pub count: i64,
+    pub mean: f64,
+    /// Sum of squared differences from the mean
+    pub m2: f64,
+}
+
+impl Moments {
+    /// Moments of `count` identical values.
+    pub fn constant(count: i64, value: f64) -> Moments {
+        Moments { count, mean: value, m2: 0.0 }
+    }
+
+    /// Combines the moments of two disjoint sets of values (Chan et al.).
+    pub fn merge(self, other: Moments) -> Moments {
+        if self.count == 0 { return other; }
+        if other.count == 0 { return self; }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        let mean = self.mean + delta * other.count as f64 / count as f64;
+        let m2 = self.m2 + other.m2
+            + delta * delta * self.count as f64 * other.count as f64 / count as f64;
+        Moments { count, mean, m2 }
+    }
+
+    /// Adds a single value (Welford's update).
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn sample_variance(&self) -> Option<f64> {
+        if self.count < 2 {
+            None
+        } else {
+            Some(self.m2 / (self.count - 1) as f64)
+        }
+    }
+
+    /// Result of a variance or standard deviation aggregator over these moments.
+    pub fn evaluate(&self, aggregator: Aggregator) -> RawVal {
+        match self.sample_variance() {
+            Some(variance) if aggregator == Aggregator::StdDev => RawVal::Float(variance.sqrt()),
+            Some(variance) => RawVal::Float(variance),
+            None => RawVal::Null,
+        }
+    }
+
+    /// Values of the count, mean and M2 intermediate columns, mean and M2 are represented by the bits of their f64 value.
+    pub fn to_intermediate(&self) -> [i64; 3] {
+        [self.count, self.mean.to_bits() as i64, self.m2.to_bits() as i64]
+    }
+
+    pub fn from_intermediate(count: i64, mean: i64, m2: i64) -> Moments {
+        Moments { count, mean: f64::from_bits(mean as u64), m2: f64::from_bits(m2 as u64) }
+    }
+
+    pub fn read_intermediate(count: &[i64], mean: &[i64], m2: &[i64]) -> Vec<Moments> {
+        count.iter().zip(mean).zip(m2)
+            .map(|((&count, &mean), &m2)| Moments::from_intermediate(count, mean, m2))
+            .collect()
+    }
+
+    /// Moments from the count, mean and M2 columns of a result row, missing values are treated as an empty set.
+    pub fn from_raw(intermediate: &[RawVal]) -> Moments {
+        match (&intermediate[0], &intermediate[1], &intermediate[2]) {
+            (&RawVal::Int(count), &RawVal::Int(mean), &RawVal::Int(m2)) => Moments::from_intermediate(count, mean, m2),
+            _ => Moments::default(),
+        }
+    }
+
+    pub fn to_raw(&self) -> Vec<RawVal> {
+        self.to_intermediate().iter().map(|&x| RawVal::Int(x)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moments_merge() {
+        let values = [1e9 + 4.0, 1e9 + 7.0, 1e9 + 13.0, 1e9 + 16.0];
+        let split = values[..2].iter().fold(Moments::default(), |m, &x| m.merge(Moments::constant(1, x)))
+            .merge(values[2..].iter().fold(Moments::default(), |m, &x| m.merge(Moments::constant(1, x))));
+        assert_eq!(split.count, 4);
+        assert_eq!(split.sample_variance(), Some(30.0));
+    }
+
+    #[test]
+    fn test_moments_intermediate_roundtrip() {
+        let mut moments = Moments::default();
+        for &x in &[-3.0, 0.5, 1e12, 7.25] {
+            moments.push(x);
+        }
+        let intermediate = moments.to_intermediate();
+        assert_eq!(Moments::from_intermediate(intermediate[0], intermediate[1], intermediate[2]), moments);
+        assert_eq!(Moments::from_raw(&moments.to_raw()), moments);
+    }
+
+    #[test]
+    fn test_aggregate_state_merge() {
+        let mut left = AggregateState::new(Aggregator::Percentile(50));
+        let mut right = AggregateState::new(Aggregator::Percentile(50));
+        for &x in &[5, 1, 3] {
+            left.push(RawVal::Null, RawVal::Int(x), 1);
+        }
+        right.push(RawVal::Null, RawVal::Int(3), 2);
+        right.push(RawVal::Null, RawVal::Null, 4);
+        left.merge(right);
+        assert_eq!(left.evaluate(Aggregator::Percentile(50)), RawVal::Int(3));
+        assert_eq!(left.evaluate(Aggregator::Percentile(100)), RawVal::Int(5));
+        assert_eq!(left.evaluate(Aggregator::PercentileDisc(0.5)), RawVal::Int(3));
+        assert_eq!(left.evaluate(Aggregator::PercentileCont(0.5)), RawVal::Float(3.0));
+        assert_eq!(AggregateState::new(Aggregator::Percentile(50)).evaluate(Aggregator::Percentile(50)), RawVal::Null);
+    }
+
+    #[test]
+    fn test_first_last_state() {
+        let rows = [(RawVal::Int(3), "c"), (RawVal::Null, "null"), (RawVal::Int(1), "b"), (RawVal::Int(1), "a"), (RawVal::Int(7), "d")];
+        for &(aggregator, expected) in &[(Aggregator::First, "a"), (Aggregator::Last, "d")] {
+            let (mut left, mut right) = (AggregateState::new(aggregator), AggregateState::new(aggregator));
+            for (i, &(ref key, value)) in rows.iter().enumerate() {
+                let state = if i % 2 == 0 { &mut left } else { &mut right };
+                state.push(key.clone(), RawVal::Str(value.to_string()), 1);
+            }
+            left.merge(right);
+            assert_eq!(left.evaluate(aggregator), RawVal::Str(expected.to_string()));
+        }
     }
 }
@@ -4,12 +4,16 @@ use std::usize;
 use engine::*;
 use engine::aggregator::Aggregator;
 use errors::QueryError;
+use syntax::collation::Collation;
 
 
 pub struct BatchResult<'a> {
     pub group_by: Option<Vec<BoxedVec<'a>>>,
     pub sort_by: Option<usize>,
     pub desc: bool,
+    /// Further columns that order rows with equal values in `sort_by`, and whether they are sorted in descending order
+    pub then_sort_by: Vec<(usize, bool)>,
+    pub collation: Collation,
     pub select: Vec<BoxedVec<'a>>,
     pub aggregators: Vec<Aggregator>,
     pub level: u32,
@@ -68,14 +72,15 @@ pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usiz
                 (vec![merged], ops)
             } else {
                 let mut partitioning = executor.buffer_premerge("partitioning");
-                executor.push(VecOperator::partition(left[0], right[0], partitioning, limit));
+                executor.push(VecOperator::partition(left[0], right[0], partitioning, limit, false));
 
                 for i in 1..(left.len() - 1) {
                     let subpartitioning = executor.buffer_premerge("subpartitioning");
                     executor.push(VecOperator::subpartition(partitioning,
                                                             left[i],
                                                             right[i],
-                                                            subpartitioning));
+                                                            subpartitioning,
+                                                            false));
                     partitioning = subpartitioning;
                 }
 
@@ -99,20 +104,44 @@ pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usiz
                 (group_by_cols, ops)
             };
 
+            let left = batch1.select.into_iter().map(|select| set(&mut executor, "left", select)).collect::<Vec<_>>();
+            let right = batch2.select.into_iter().map(|select| set(&mut executor, "right", select)).collect::<Vec<_>>();
             let mut aggregates = Vec::with_capacity(batch1.aggregators.len());
-            for ((aggregator, select1), select2) in batch1.aggregators.iter().zip(batch1.select).zip(batch2.select) {
-                let left = set(&mut executor, "left", select1).i64();
-                let right = set(&mut executor, "right", select2).i64();
-                let aggregated = executor.buffer_i64("aggregated");
+            let mut i = 0;
+            while i < batch1.aggregators.len() {
+                let aggregator = batch1.aggregators[i];
+                if aggregator.has_state() {
+                    let merged = executor.named_buffer("aggregated", EncodingType::AggregateState);
+                    executor.push(VecOperator::merge_aggregate_states(ops, left[i].any(), right[i].any(), merged.any()));
+                    aggregates.push(merged);
+                    i += 1;
+                    continue;
+                }
+                // The count, mean and M2 columns of moments have to be merged jointly
+                if aggregator == Aggregator::Variance || aggregator == Aggregator::StdDev {
+                    let merged = [executor.named_buffer("aggregated", EncodingType::I64),
+                        executor.named_buffer("aggregated", EncodingType::I64),
+                        executor.named_buffer("aggregated", EncodingType::I64)];
+                    executor.push(VecOperator::merge_moments(ops,
+                                                             [left[i].i64(), left[i + 1].i64(), left[i + 2].i64()],
+                                                             [right[i].i64(), right[i + 1].i64(), right[i + 2].i64()],
+                                                             [merged[0].i64(), merged[1].i64(), merged[2].i64()]));
+                    aggregates.extend(merged.iter().cloned());
+                    i += aggregator.intermediate_columns();
+                    continue;
+                }
+                let aggregated = executor.named_buffer("aggregated", left[i].tag);
                 executor.push(VecOperator::merge_aggregate(ops,
-                                                           left,
-                                                           right,
+                                                           left[i],
+                                                           right[i],
                                                            aggregated,
-                                                           *aggregator));
+                                                           aggregator));
                 aggregates.push(aggregated);
+                i += 1;
             }
 
-            let mut results = executor.prepare_no_columns();
+            let outputs = group_by_cols.iter().chain(aggregates.iter()).map(|buffer| buffer.any()).collect::<Vec<_>>();
+            let mut results = executor.prepare_no_columns(&outputs);
             executor.run(1, &mut results, batch1.show || batch2.show);
             let group_by_cols = group_by_cols.into_iter().map(|i| results.collect(i.any())).collect();
             let select = aggregates.into_iter().map(|i| results.collect(i.any())).collect();
@@ -121,6 +150,8 @@ pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usiz
                 group_by: Some(group_by_cols),
                 sort_by: None,
                 desc: batch1.desc,
+                then_sort_by: Vec::new(),
+                collation: batch1.collation,
                 select,
                 aggregators: batch1.aggregators,
                 level: batch1.level + 1,
@@ -139,25 +170,53 @@ pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usiz
                 // Sort query
                 Some(index) => {
                     let mut executor = QueryExecutor::default();
-                    let left = batch1.select.into_iter()
+                    let (select1, select2) = unify_types(batch1.select, batch2.select);
+                    let left = select1.into_iter()
                         .map(|vec| set(&mut executor, "left", vec))
                         .collect::<Vec<_>>();
-                    let right = batch2.select.into_iter()
+                    let right = select2.into_iter()
                         .map(|vec| set(&mut executor, "right", vec))
                         .collect::<Vec<_>>();
                     let ops = executor.buffer_u8("take_left");
-                    let merged_sort_cols = executor.named_buffer("merged_sort_cols", left[index].tag);
-                    executor.push(VecOperator::merge(
-                        left[index],
-                        right[index],
-                        merged_sort_cols,
-                        ops,
-                        limit,
-                        batch1.desc));
+                    // Index of the column that is merged while determining the merge order
+                    let (merged_index, merged_sort_cols) = match batch1.then_sort_by.last() {
+                        None => {
+                            let merged_sort_cols = executor.named_buffer("merged_sort_cols", left[index].tag);
+                            executor.push(VecOperator::merge(
+                                left[index],
+                                right[index],
+                                merged_sort_cols,
+                                ops,
+                                limit,
+                                batch1.desc,
+                                batch1.collation));
+                            (index, merged_sort_cols)
+                        }
+                        // Rows are split into runs that are equal in all sort columns but the last, which are merged by the last column
+                        Some(&(last, last_desc)) => {
+                            let mut partitioning = executor.buffer_premerge("partitioning");
+                            executor.push(VecOperator::partition(left[index], right[index], partitioning, limit, batch1.desc));
+                            for &(i, desc) in &batch1.then_sort_by[..batch1.then_sort_by.len() - 1] {
+                                let subpartitioning = executor.buffer_premerge("subpartitioning");
+                                executor.push(VecOperator::subpartition(partitioning, left[i], right[i], subpartitioning, desc));
+                                partitioning = subpartitioning;
+                            }
+                            let merged_sort_cols = executor.named_buffer("merged_sort_cols", left[last].tag);
+                            executor.push(VecOperator::merge_partitioned(
+                                partitioning,
+                                left[last],
+                                right[last],
+                                merged_sort_cols,
+                                ops,
+                                limit,
+                                last_desc));
+                            (last, merged_sort_cols)
+                        }
+                    };
 
                     let mut select = Vec::with_capacity(left.len());
                     for (i, (&left, right)) in left.iter().zip(right).enumerate() {
-                        if i == index {
+                        if i == merged_index {
                             select.push(error_buffer_ref("MERGE_ERROR"));
                         } else {
                             let merged = executor.named_buffer("merged_sort_cols", left.tag);
@@ -165,9 +224,9 @@ pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usiz
                             select.push(merged.any());
                         }
                     }
-                    select[index] = merged_sort_cols.any();
+                    select[merged_index] = merged_sort_cols.any();
 
-                    let mut results = executor.prepare_no_columns();
+                    let mut results = executor.prepare_no_columns(&select);
                     executor.run(1, &mut results, batch1.show || batch2.show);
                     let select = select.into_iter().map(|i| results.collect(i)).collect();
 
@@ -176,6 +235,8 @@ pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usiz
                         sort_by: Some(index),
                         select,
                         desc: batch1.desc,
+                        then_sort_by: batch1.then_sort_by,
+                        collation: batch1.collation,
                         aggregators: Vec::new(),
                         level: batch1.level + 1,
                         batch_count: batch1.batch_count + batch2.batch_count,
@@ -205,6 +266,8 @@ pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usiz
                         sort_by: None,
                         select: result,
                         desc: batch1.desc,
+                        then_sort_by: Vec::new(),
+                        collation: batch1.collation,
                         aggregators: Vec::new(),
                         level: batch1.level + 1,
                         batch_count: batch1.batch_count + batch2.batch_count,
@@ -222,6 +285,21 @@ pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usiz
     }
 }
 
+/// Converts columns that have a different type in each batch to `Val`,
+/// e.g. columns that only contain nulls in some partitions.
+fn unify_types<'a>(select1: Vec<BoxedVec<'a>>, select2: Vec<BoxedVec<'a>>) -> (Vec<BoxedVec<'a>>, Vec<BoxedVec<'a>>) {
+    fn mixed(col: BoxedVec) -> BoxedVec {
+        if col.get_type() == EncodingType::Val { col } else { Box::new(col.to_mixed()) }
+    }
+    select1.into_iter().zip(select2)
+        .map(|(col1, col2)| if col1.get_type() == col2.get_type() {
+            (col1, col2)
+        } else {
+            (mixed(col1), mixed(col2))
+        })
+        .unzip()
+}
+
 fn set<'a>(executor: &mut QueryExecutor<'a>,
            name: &'static str,
            vec: BoxedVec<'a>) -> TypedBufferRef {
@@ -5,6 +5,7 @@ pub mod aggregator;
 pub mod filter;
 pub mod query;
 pub mod query_task;
+pub mod space_saving;
 pub mod typed_vec;
 pub mod types;
 pub mod byte_slices;
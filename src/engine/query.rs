@@ -2,58 +2,131 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::iter::Iterator;
 use std::sync::Arc;
+use std::usize;
+
+use hex;
+use std::mem;
 
 use ::QueryError;
+use QueryResult;
 use engine::*;
 use engine::aggregator::*;
 use engine::batch_merging::*;
 use engine::query_plan::QueryPlan;
 use engine::query_plan;
+use engine::query_task::{self, QueryOutput, QueryStats};
+use engine::types::BasicType;
 use engine::types::EncodingType;
 use engine::types::Type;
+use ingest::buffer::Buffer;
 use ingest::raw_val::RawVal;
+use mem_store::codec::Codec;
 use mem_store::column::Column;
+use syntax::collation::Collation;
 use syntax::expression::*;
 use syntax::limit::*;
+use time::precise_time_ns;
 
 
 #[derive(Debug, Clone)]
 pub struct Query {
     pub select: Vec<Expr>,
+    /// Names given to the select expressions with `AS`, if any
+    pub select_aliases: Vec<Option<String>>,
+    /// Removes duplicate result rows by grouping on all select columns
+    pub distinct: bool,
+    /// Appends a subtotal row for each prefix of the group by columns and a grand total row to aggregation results
+    pub rollup: bool,
     pub table: String,
+    /// Only a random sample of partitions is scanned, count and sum aggregates are scaled accordingly
+    pub sample: Option<TableSample>,
     pub filter: Expr,
-    pub aggregate: Vec<(Aggregator, Expr)>,
+    /// Aggregators with their argument and an optional expression that orders the values within each group
+    pub aggregate: Vec<(Aggregator, Expr, Option<Expr>)>,
     pub order_by: Option<String>,
     pub order_desc: bool,
+    /// Further ORDER BY columns and whether they are descending, which order rows with equal `order_by` values
+    pub then_order_by: Vec<(String, bool)>,
+    /// Collation of the first ORDER BY expression, further ORDER BY expressions use binary collation
+    pub collation: Collation,
     pub limit: LimitClause,
     pub order_by_index: Option<usize>,
+    pub then_order_by_indices: Vec<(usize, bool)>,
+    /// Limits the number of rows per group, applied before `limit`
+    pub group_limit: Option<GroupLimit>,
+    /// Returns the approximately `k` most frequent values of the single select column with their estimated counts
+    pub top_k: Option<usize>,
 }
 
 impl Query {
     #[inline(never)] // produces more useful profiles
-    pub fn run<'a>(&self, columns: &'a HashMap<String, Arc<Column>>, explain: bool, show: bool, partition: usize)
-                   -> Result<(BatchResult<'a>, Option<String>), QueryError> {
-        let limit = (self.limit.limit + self.limit.offset) as usize;
-        let len = columns.iter().next().unwrap().1.len();
+    pub fn run<'a>(&self,
+                   columns: &'a HashMap<String, Arc<Column>>,
+                   len: usize,
+                   explain: bool,
+                   show: bool,
+                   partition: usize,
+                   mut breakdown: Option<&mut HashMap<&'static str, u64>>)
+                   -> Result<(BatchResult<'a>, usize, usize, Option<String>), QueryError> {
+        let plan_start = precise_time_ns();
+        let limit = self.row_limit();
         let mut executor = QueryExecutor::default();
 
         let (filter_plan, filter_type) = QueryPlan::create_query_plan(&self.filter, Filter::None, columns)?;
+        // Selected columns are gathered at the positions of rows that pass the filter,
+        // so any decoding that follows only has to process the selected rows
+        let mut filter_count = None;
         let mut filter = match filter_type.encoding_type() {
             EncodingType::BitVec => {
-                let mut compiled_filter = query_plan::prepare(filter_plan, &mut executor);
-                Filter::BitVec(compiled_filter.u8())
+                let compiled_filter = query_plan::prepare(filter_plan, &mut executor);
+                filter_count = Some(query_plan::prepare(
+                    QueryPlan::FilterCount(Box::new(QueryPlan::ReadBuffer(compiled_filter))),
+                    &mut executor).usize());
+                let filter_indices = query_plan::prepare(
+                    QueryPlan::FilterIndices(Box::new(QueryPlan::ReadBuffer(compiled_filter))),
+                    &mut executor);
+                Filter::Indices(filter_indices.usize())
             }
             _ => Filter::None,
         };
 
         let mut select = Vec::new();
         if let Some(index) = self.order_by_index {
-            let (plan, plan_t) = query_plan::order_preserving(
-                QueryPlan::create_query_plan(&self.select[index], filter, columns)?);
+            let (plan, plan_t) = QueryPlan::create_nullable_query_plan(&self.select[index], filter, columns)?;
+            let collation = if plan_t.decoded == BasicType::String { self.collation } else { Collation::Binary };
+            let (plan, plan_t) = match collation {
+                Collation::Binary => query_plan::order_preserving((plan, plan_t)),
+                // Dictionaries are sorted by binary collation, so strings have to be decoded
+                _ => match plan_t.codec.clone() {
+                    Some(codec) => (*codec.decode(Box::new(plan)), plan_t.decoded()),
+                    None => (plan, plan_t),
+                },
+            };
             // TODO(clemens): Reuse sort_column for result
             let sort_column = query_plan::prepare(plan.clone(), &mut executor);
             // TODO(clemens): better criterion
-            let sort_indices = if limit < len / 2 {
+            let sort_indices = if self.sorts_by_all_columns() {
+                // Sorting stably by each ORDER BY column, starting from the last one, sorts by all columns.
+                // Only the first `limit` rows can be part of the result.
+                let mut sort_indices = None;
+                for &(index, desc) in self.then_order_by_indices.iter().rev() {
+                    let ranking = query_plan::order_preserving(
+                        QueryPlan::create_nullable_query_plan(&self.select[index], filter, columns)?).0;
+                    sort_indices = Some(query_plan::prepare(match sort_indices {
+                        None => QueryPlan::SortIndices(Box::new(ranking), desc, Collation::Binary),
+                        Some(indices) => QueryPlan::SortBy(
+                            Box::new(ranking), Box::new(QueryPlan::ReadBuffer(indices)), desc, usize::MAX),
+                    }, &mut executor));
+                }
+                query_plan::prepare(
+                    QueryPlan::SortBy(
+                        Box::new(QueryPlan::ReadBuffer(sort_column)),
+                        Box::new(QueryPlan::ReadBuffer(sort_indices.unwrap())),
+                        self.order_desc,
+                        limit),
+                    &mut executor)
+            } else if limit > 0 && limit < len / 2 && collation == Collation::Binary
+                && self.then_order_by.is_empty() && plan_t.decoded != BasicType::Val {
                 query_plan::prepare(
                     QueryPlan::TopN(
                         Box::new(QueryPlan::ReadBuffer(sort_column)),
@@ -64,58 +137,88 @@ impl Query {
                 query_plan::prepare(
                     QueryPlan::SortIndices(
                         Box::new(QueryPlan::ReadBuffer(sort_column)),
-                        self.order_desc),
+                        self.order_desc,
+                        collation),
                     &mut executor)
             };
-            filter = Filter::Indices(sort_indices.usize());
+            filter = match filter {
+                // Sort indices refer to positions within the filtered rows
+                Filter::Indices(filter_indices) => Filter::Indices(query_plan::prepare(
+                    QueryPlan::Select(
+                        Box::new(QueryPlan::ReadBuffer(filter_indices.tagged())),
+                        Box::new(QueryPlan::ReadBuffer(sort_indices))),
+                    &mut executor).usize()),
+                _ => Filter::Indices(sort_indices.usize()),
+            };
         }
         for expr in &self.select {
-            let (mut plan, plan_type) = QueryPlan::create_query_plan(expr, filter, columns)?;
+            let (mut plan, plan_type) = QueryPlan::create_nullable_query_plan(expr, filter, columns)?;
             if let Some(codec) = plan_type.codec {
                 plan = *codec.decode(Box::new(plan));
             }
             select.push(query_plan::prepare_no_alias(plan, &mut executor));
         }
 
+        let outputs = select.iter().map(|buffer| buffer.any())
+            .chain(filter_count.map(|count| count.any()))
+            .collect::<Vec<_>>();
         for c in columns {
             debug!("{}: {:?}", partition, c);
         }
-        let mut results = executor.prepare(Query::column_data(columns));
+        let mut results = executor.prepare(Query::column_data(columns), &outputs);
         debug!("{:#}", &executor);
-        executor.run(columns.iter().next().unwrap().1.len(), &mut results, show);
+        let execute_start = record_phase(&mut breakdown, "plan_partition", plan_start);
+        executor.run(len, &mut results, show);
         let select = select.into_iter().map(|i| results.collect(i.any())).collect();
+        let rows_matched = filter_count.map_or(len, |count| results.get(count)[0]);
+        record_phase(&mut breakdown, "execute_partition", execute_start);
 
+        // Unless partitions are sorted by all ORDER BY columns, rows that tie on the first column are only ordered
+        // by the remaining columns once all partitions are merged
+        let then_sort_by = if self.sorts_by_all_columns() { self.then_order_by_indices.clone() } else { Vec::new() };
         Ok(
             (BatchResult {
                 group_by: None,
                 sort_by: self.order_by_index,
                 select,
                 desc: self.order_desc,
+                then_sort_by,
+                collation: self.collation,
                 aggregators: Vec::with_capacity(0),
                 level: 0,
                 batch_count: 1,
                 show,
                 unsafe_referenced_buffers: results.collect_pinned(),
             },
+             rows_matched,
+             executor.op_count(),
              if explain { Some(format!("{}", executor)) } else { None }))
     }
 
     #[inline(never)] // produces more useful profiles
     pub fn run_aggregate<'a>(&self,
                              columns: &'a HashMap<String, Arc<Column>>,
+                             len: usize,
                              explain: bool,
                              show: bool,
-                             partition: usize)
-                             -> Result<(BatchResult<'a>, Option<String>), QueryError> {
+                             partition: usize,
+                             combine_aggregates: bool,
+                             mut breakdown: Option<&mut HashMap<&'static str, u64>>)
+                             -> Result<(BatchResult<'a>, usize, usize, Option<String>), QueryError> {
         trace_start!("run_aggregate");
+        let plan_start = precise_time_ns();
 
         let mut executor = QueryExecutor::default();
 
         // Filter
         let (filter_plan, filter_type) = QueryPlan::create_query_plan(&self.filter, Filter::None, columns)?;
+        let mut filter_count = None;
         let filter = match filter_type.encoding_type() {
             EncodingType::BitVec => {
                 let compiled_filter = query_plan::prepare(filter_plan, &mut executor);
+                filter_count = Some(query_plan::prepare(
+                    QueryPlan::FilterCount(Box::new(QueryPlan::ReadBuffer(compiled_filter))),
+                    &mut executor).usize());
                 Filter::BitVec(compiled_filter.u8())
             }
             _ => Filter::None,
@@ -130,10 +233,12 @@ impl Query {
 
         // Reduce cardinality of grouping key if necessary and perform grouping
         // TODO(clemens): also determine and use is_dense. always true for hashmap, depends on group by columns for raw.
+        let count = self.aggregate.iter().any(|&(aggregator, _, _)| aggregator == Aggregator::Count);
         let (encoded_group_by_column,
             grouping_key,
             grouping_key_type,
-            aggregation_cardinality) =
+            aggregation_cardinality,
+            group_counts) =
         // TODO(clemens): refine criterion
             if max_grouping_key < 1 << 16 && raw_grouping_key_type.is_positive_integer() {
                 let max_grouping_key_buf = query_plan::prepare(
@@ -141,11 +246,13 @@ impl Query {
                 (None,
                  raw_grouping_key,
                  raw_grouping_key_type.clone(),
-                 max_grouping_key_buf.const_i64())
+                 max_grouping_key_buf.const_i64(),
+                 None)
             } else {
                 query_plan::prepare_hashmap_grouping(
                     raw_grouping_key,
                     max_grouping_key as usize,
+                    count,
                     &mut executor)
             };
 
@@ -153,8 +260,108 @@ impl Query {
         let mut aggregation_results = Vec::new();
         let mut selector = None;
         let mut selector_index = None;
-        for (i, &(aggregator, ref expr)) in self.aggregate.iter().enumerate() {
-            let (plan, plan_type) = QueryPlan::create_query_plan(expr, filter, columns)?;
+        // Counts of expressions that may be null, which can be zero for groups that occur
+        let mut null_counts = Vec::new();
+        let mut plans = Vec::with_capacity(self.aggregate.len());
+        for &(aggregator, ref expr, _) in &self.aggregate {
+            // Counts and aggregate states have to see the null map of columns to skip missing values
+            let (plan, plan_type) = if aggregator == Aggregator::Count || aggregator.has_state() {
+                QueryPlan::create_nullable_query_plan(expr, filter, columns)?
+            } else {
+                QueryPlan::create_query_plan(expr, filter, columns)?
+            };
+            let null_count = aggregator == Aggregator::Count &&
+                (plan_type.decoded == BasicType::Null || plan_type.decoded == BasicType::Val);
+            plans.push(Some((plan, plan_type, null_count)));
+        }
+        // Sums, minimums, maximums and counts that are not tallied by the hashmap grouping are folded in one pass
+        let combinable = self.aggregate.iter().zip(&plans)
+            .map(|(&(aggregator, _, _), plan)| match aggregator {
+                Aggregator::Sum | Aggregator::Min | Aggregator::Max => true,
+                Aggregator::Count => group_counts.is_none() && plan.as_ref().map_or(false, |&(_, _, null_count)| !null_count),
+                _ => false,
+            })
+            .collect::<Vec<_>>();
+        let mut combined = Vec::new();
+        if combine_aggregates && combinable.iter().filter(|&&c| c).count() > 1 {
+            let mut aggregates = Vec::new();
+            for (i, &(aggregator, _, _)) in self.aggregate.iter().enumerate() {
+                if combinable[i] {
+                    let (plan, plan_type, _) = plans[i].take().unwrap();
+                    aggregates.push((aggregator, plan, plan_type));
+                }
+            }
+            let mut outputs = query_plan::prepare_combined_aggregation(
+                aggregates,
+                grouping_key,
+                aggregation_cardinality,
+                &mut executor)?.into_iter();
+            combined = combinable.iter().map(|&c| if c { outputs.next() } else { None }).collect();
+        }
+        for (i, &(aggregator, _, ref ordering)) in self.aggregate.iter().enumerate() {
+            if let Some((aggregate, t)) = combined.get_mut(i).and_then(Option::take) {
+                if aggregator == Aggregator::Count {
+                    selector = Some((aggregate, t.encoding_type()));
+                    selector_index = Some(aggregation_results.len());
+                }
+                aggregation_results.push((aggregator, aggregate, t));
+                null_counts.push(false);
+                continue;
+            }
+            let (plan, plan_type, null_count) = plans[i].take().unwrap();
+            // Counts were already tallied by the hashmap grouping, which only works if no row is null
+            if let (Aggregator::Count, Some(counts), false) = (aggregator, group_counts, null_count) {
+                selector = Some((counts.tagged(), EncodingType::U32));
+                selector_index = Some(aggregation_results.len());
+                aggregation_results.push((aggregator, counts.tagged(), Type::encoded(Codec::integer_cast(EncodingType::U32))));
+                null_counts.push(false);
+                continue;
+            }
+            if aggregator.has_state() {
+                let ordering = match *ordering {
+                    Some(ref ordering) => Some(QueryPlan::create_nullable_query_plan(ordering, filter, columns)?),
+                    None => None,
+                };
+                let (states, t) = query_plan::prepare_aggregate_states(
+                    plan,
+                    plan_type,
+                    ordering,
+                    grouping_key,
+                    aggregation_cardinality,
+                    aggregator,
+                    &mut executor)?;
+                aggregation_results.push((aggregator, states, t));
+                null_counts.push(false);
+                continue;
+            }
+            if aggregator == Aggregator::Variance || aggregator == Aggregator::StdDev {
+                let moments = query_plan::prepare_moments(
+                    plan,
+                    plan_type,
+                    grouping_key,
+                    aggregation_cardinality,
+                    aggregator,
+                    &mut executor)?;
+                for (column, t) in moments {
+                    aggregation_results.push((aggregator, column, t));
+                    null_counts.push(false);
+                }
+                continue;
+            }
+            if let Aggregator::ApproxCountDistinct(precision) = aggregator {
+                let registers = query_plan::prepare_hyperloglog(
+                    plan,
+                    plan_type,
+                    grouping_key,
+                    aggregation_cardinality,
+                    precision,
+                    &mut executor)?;
+                for (register, t) in registers {
+                    aggregation_results.push((aggregator, register, t));
+                    null_counts.push(false);
+                }
+                continue;
+            }
             let (aggregate, t) = query_plan::prepare_aggregation(
                 plan,
                 plan_type,
@@ -163,11 +370,12 @@ impl Query {
                 aggregator,
                 &mut executor)?;
             // TODO(clemens): if summation column is strictly positive, can use sum as well
-            if aggregator == Aggregator::Count {
+            if aggregator == Aggregator::Count && !null_count {
                 selector = Some((aggregate, t.encoding_type()));
-                selector_index = Some(i)
+                selector_index = Some(aggregation_results.len())
             }
-            aggregation_results.push((aggregator, aggregate, t))
+            aggregation_results.push((aggregator, aggregate, t));
+            null_counts.push(null_count);
         }
 
         // Determine selector
@@ -198,14 +406,25 @@ impl Query {
             let mut decode_compact = |aggregator: Aggregator,
                                       aggregate: TypedBufferRef,
                                       t: Type,
+                                      null_count: bool,
                                       select: &mut Vec<TypedBufferRef>| {
                 let compacted = match aggregator {
+                    Aggregator::Count if null_count => query_plan::prepare(
+                        QueryPlan::Compact(
+                            Box::new(QueryPlan::ReadBuffer(aggregate)), t.encoding_type(),
+                            Box::new(QueryPlan::ReadBuffer(selector)), selector_type),
+                        &mut executor),
                     // TODO(clemens): if summation column is strictly positive, can use NonzeroCompact
-                    Aggregator::Sum => query_plan::prepare(
+                    Aggregator::Sum | Aggregator::Min | Aggregator::Max | Aggregator::ApproxCountDistinct(_) |
+                    Aggregator::Variance | Aggregator::StdDev | Aggregator::Percentile(_) |
+                    Aggregator::PercentileCont(_) | Aggregator::PercentileDisc(_) | Aggregator::Mode |
+                    Aggregator::First | Aggregator::Last => query_plan::prepare(
                         QueryPlan::Compact(
                             Box::new(QueryPlan::ReadBuffer(aggregate)), t.encoding_type(),
                             Box::new(QueryPlan::ReadBuffer(selector)), selector_type),
                         &mut executor),
+                    // Tallies of the hashmap grouping only contain groups that occur
+                    Aggregator::Count if group_counts.is_some() => aggregate,
                     Aggregator::Count => query_plan::prepare(
                         QueryPlan::NonzeroCompact(Box::new(QueryPlan::ReadBuffer(aggregate)), t.encoding_type()),
                         &mut executor),
@@ -222,14 +441,14 @@ impl Query {
 
             for (i, &(aggregator, aggregate, ref t)) in aggregation_results.iter().enumerate() {
                 if selector_index != Some(i) {
-                    decode_compact(aggregator, aggregate, t.clone(), &mut select);
+                    decode_compact(aggregator, aggregate, t.clone(), null_counts[i], &mut select);
                 }
             }
 
             // TODO(clemens): is there a simpler way to do this?
             if let Some(i) = selector_index {
                 let (aggregator, aggregate, ref t) = aggregation_results[i];
-                decode_compact(aggregator, aggregate, t.clone(), &mut select);
+                decode_compact(aggregator, aggregate, t.clone(), null_counts[i], &mut select);
                 let last = select.pop().unwrap();
                 select.insert(i, last);
             }
@@ -249,7 +468,8 @@ impl Query {
                 query_plan::prepare(
                     QueryPlan::SortIndices(
                         Box::new(QueryPlan::ReadBuffer(encoded_group_by_column)),
-                        false),
+                        false,
+                        Collation::Binary),
                     &mut executor)
             } else {
                 if grouping_columns.len() != 1 {
@@ -261,7 +481,8 @@ impl Query {
                 query_plan::prepare(
                     QueryPlan::SortIndices(
                         Box::new(QueryPlan::ReadBuffer(grouping_columns[0])),
-                        false),
+                        false,
+                        Collation::Binary),
                     &mut executor)
             };
 
@@ -283,21 +504,29 @@ impl Query {
             }).collect();
         }
 
+        let outputs = select.iter().chain(grouping_columns.iter()).map(|buffer| buffer.any())
+            .chain(filter_count.map(|count| count.any()))
+            .collect::<Vec<_>>();
         for c in columns {
             debug!("{}: {:?}", partition, c);
         }
-        let mut results = executor.prepare(Query::column_data(columns));
+        let mut results = executor.prepare(Query::column_data(columns), &outputs);
         debug!("{:#}", &executor);
-        executor.run(columns.iter().next().unwrap().1.len(), &mut results, show);
+        let execute_start = record_phase(&mut breakdown, "plan_partition", plan_start);
+        executor.run(len, &mut results, show);
         let select_cols = select.iter().map(|i| results.collect(i.any())).collect();
         let group_by_cols = grouping_columns.iter().map(|i| results.collect(i.any())).collect();
+        let rows_matched = filter_count.map_or(len, |count| results.get(count)[0]);
+        record_phase(&mut breakdown, "execute_partition", execute_start);
 
         let batch = BatchResult {
             group_by: Some(group_by_cols),
             sort_by: None,
             select: select_cols,
             desc: self.order_desc,
-            aggregators: self.aggregate.iter().map(|x| x.0).collect(),
+            then_sort_by: Vec::new(),
+            collation: self.collation,
+            aggregators: aggregation_results.iter().map(|x| x.0).collect(),
             level: 0,
             batch_count: 1,
             show,
@@ -310,11 +539,237 @@ impl Query {
         } else {
             Ok((
                 batch,
+                rows_matched,
+                executor.op_count(),
                 if explain { Some(format!("{}", executor)) } else { None }
             ))
         }
     }
 
+    /// Runs the query on the calling thread against the rows of `batch`, independently of any table.
+    /// The table name of the query is ignored.
+    pub fn run_on_batch(&self, batch: Buffer) -> QueryResult {
+        let start_time_ns = precise_time_ns();
+        if self.sample.is_some() || self.top_k.is_some() || self.rollup || self.group_limit.is_some() {
+            bail!(QueryError::NotImplemented, "TABLESAMPLE, TOP, ROLLUP and LIMIT BY are not supported on batches");
+        }
+        let len = batch.len();
+        let columns = batch.buffer.into_iter()
+            .map(|(name, raw_col)| {
+                let column = raw_col.finalize(&name);
+                (name, column)
+            })
+            .collect::<HashMap<String, Arc<Column>>>();
+        let col_types = columns.iter()
+            .map(|(name, column)| (name.clone(), column.basic_type()))
+            .collect::<HashMap<_, _>>();
+
+        let mut query = self.clone();
+        if query.is_select_star() {
+            let mut colnames = columns.keys().cloned().collect::<Vec<_>>();
+            colnames.sort();
+            query.select = colnames.into_iter().map(Expr::ColName).collect();
+        }
+        if len > 0 {
+            let mut unknown_cols = query.find_referenced_cols().into_iter()
+                .filter(|col| !columns.contains_key(col))
+                .collect::<Vec<_>>();
+            unknown_cols.sort();
+            if let Some(col) = unknown_cols.into_iter().next() {
+                return Err(QueryError::UnknownColumn(col));
+            }
+        }
+        query.type_check(&col_types)?;
+        let colnames = query.result_column_names();
+        let coltypes = query.result_column_types(&col_types);
+        query.resolve_order_by_indices(&colnames)?;
+        let outputs = query_task::aggregate_outputs(&query.aggregate);
+        if query.distinct && query.aggregate.is_empty() {
+            // Grouping by all select columns collapses duplicate rows, the count itself is not part of the output
+            query.aggregate.push((Aggregator::Count, Expr::Const(RawVal::Int(1)), None));
+        }
+
+        let (mut rows, rows_returned, vector_ops) = if len == 0 {
+            (Vec::new(), 0, 0)
+        } else if query.aggregate.is_empty() {
+            let (result, rows_returned, ops, _) = query.run(&columns, len, false, false, 0, None)?;
+            let rows = (0..result.len())
+                .map(|i| result.group_by.iter().flat_map(|gs| gs.iter())
+                    .chain(result.select.iter())
+                    .map(|col| col.get_raw(i))
+                    .collect::<Vec<_>>())
+                .collect();
+            (rows, rows_returned, ops)
+        } else {
+            let (result, rows_returned, ops, _) = query.run_aggregate(&columns, len, false, false, 0, true, None)?;
+            let rows = (0..result.len())
+                .map(|i| {
+                    let group = result.group_by.iter().flat_map(|gs| gs.iter().map(|g| g.get_raw(i))).collect();
+                    let intermediate = result.select.iter().map(|col| col.get_raw(i)).collect::<Vec<_>>();
+                    let states = query_task::row_states(&outputs, &result, i);
+                    query_task::output_row(&query, &outputs, group, &intermediate, &states)
+                })
+                .collect::<Vec<_>>();
+            (rows, rows_returned, ops)
+        };
+        if query.sorts_result_rows() {
+            query_task::sort_rows(&mut rows, &query, colnames.len() - outputs.len());
+        }
+        rows = rows.into_iter()
+            .skip(query.limit.offset as usize)
+            .take(query.limit.limit as usize)
+            .collect();
+        if rows.is_empty() {
+            rows = query_task::empty_result_rows(&query, &outputs, colnames.len());
+        }
+
+        let stats = QueryStats {
+            runtime_ns: precise_time_ns() - start_time_ns,
+            rows_scanned: len,
+            rows_returned,
+            vector_ops,
+            breakdown: HashMap::new(),
+        };
+        Ok(QueryOutput::new(colnames, coltypes, rows, stats))
+    }
+
+    /// Resolves the ORDER BY columns to their index in `output_colnames`, the names of the result columns.
+    pub fn resolve_order_by_indices(&mut self, output_colnames: &[String]) -> Result<(), QueryError> {
+        let column_index = |col: &str| match output_colnames.iter().rposition(|name| name == col) {
+            Some(index) => Ok(index),
+            None => Err(QueryError::UnknownColumn(col.to_string())),
+        };
+        self.order_by_index = match self.order_by {
+            Some(ref col) => Some(column_index(col.as_str())?),
+            None => None,
+        };
+        self.then_order_by_indices = self.then_order_by.iter()
+            .map(|&(ref col, desc)| column_index(col.as_str()).map(|i| (i, desc)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(())
+    }
+
+    /// Whether the query orders the rows of an aggregation, which is only possible once all groups are known.
+    fn orders_aggregation(&self) -> bool {
+        !self.aggregate.is_empty() && self.order_by_index.is_some()
+    }
+
+    /// Whether result rows are ordered after merging all partial results, which is required for aggregations and
+    /// for ordering by multiple columns if partitions are sorted and merged by the first ORDER BY column only.
+    pub fn sorts_result_rows(&self) -> bool {
+        self.orders_aggregation() || (self.order_by_index.is_some() && !self.then_order_by_indices.is_empty()
+            && !self.sorts_by_all_columns())
+    }
+
+    /// Whether the rows of each partition are sorted, and partitions are merged, by all ORDER BY columns.
+    /// Columns compared with a collation other than binary are only sorted by after merging all partitions.
+    pub fn sorts_by_all_columns(&self) -> bool {
+        self.aggregate.is_empty() && self.order_by_index.is_some() && !self.then_order_by_indices.is_empty()
+            && self.collation == Collation::Binary
+    }
+
+    /// Whether the query returns a cursor, which requires a single ORDER BY column and no aggregates.
+    fn supports_cursor(&self) -> bool {
+        self.order_by.is_some() && self.aggregate.is_empty() && self.then_order_by.is_empty()
+    }
+
+    /// Number of rows each partition (and the merged result) has to retain. Queries that support cursors retain one
+    /// additional row, which tells whether the last row of a page ties with the first row of the next page.
+    pub fn row_limit(&self) -> usize {
+        let limit = self.limit.limit.saturating_add(self.limit.offset) as usize;
+        if self.supports_cursor() { limit.saturating_add(1) } else { limit }
+    }
+
+    /// Returns an opaque cursor for the page of results that ends with a row with value `last` in the ORDER BY column
+    /// and is followed by a row with value `next`, or `None` if there are no further rows.
+    /// Cursors are only supported for queries without aggregates that are ordered by a single integer column.
+    pub fn cursor_after(&self, last: &RawVal, next: Option<&RawVal>) -> Option<String> {
+        match (&self.order_by, last, next) {
+            (&Some(ref column), &RawVal::Int(value), Some(next)) if self.supports_cursor() => {
+                // Rows are resumed strictly after `value`, so rows that tie with the last row would be skipped
+                let ties = *next == RawVal::Int(value);
+                Some(hex::encode(format!("{}:{}:{}", value, ties as u8, column)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Restricts the query to the rows following the page that returned `cursor`, which replaces any offset.
+    /// Fails if the last row of that page has the same value in the ORDER BY column as the row that follows it,
+    /// since the remaining rows with that value can't be told apart from the rows that were already returned.
+    pub fn resume_after(&mut self, cursor: &str) -> Result<(), QueryError> {
+        let decoded = hex::decode(cursor).ok().and_then(|bytes| String::from_utf8(bytes).ok()).unwrap_or_default();
+        let mut parts = decoded.splitn(3, ':');
+        let value = match parts.next().and_then(|value| value.parse::<i64>().ok()) {
+            Some(value) => value,
+            None => bail!(QueryError::ParseError, "Invalid cursor: {}", cursor),
+        };
+        let ties = match parts.next() {
+            Some("0") => false,
+            Some("1") => true,
+            _ => bail!(QueryError::ParseError, "Invalid cursor: {}", cursor),
+        };
+        let column = parts.next().unwrap_or("").to_string();
+        if self.order_by.as_ref() != Some(&column) {
+            bail!(QueryError::ParseError, "Cursor was created for a query ordered by `{}`", column);
+        }
+        if !self.aggregate.is_empty() {
+            bail!(QueryError::NotImplemented, "Cursors are not supported for queries with aggregates");
+        }
+        if ties {
+            bail!(QueryError::NotImplemented,
+                  "Cursors require unique values in the ORDER BY column, but several rows have value {} in column `{}`",
+                  value, column);
+        }
+        let comparison = if self.order_desc { Func2Type::LT } else { Func2Type::GT };
+        let after = Expr::func(comparison, Expr::ColName(column), Expr::Const(RawVal::Int(value)));
+        self.filter = match mem::replace(&mut self.filter, Expr::Const(RawVal::Int(1))) {
+            Expr::Const(RawVal::Int(1)) => after,
+            filter => Expr::func(Func2Type::And, filter, after),
+        };
+        self.limit.offset = 0;
+        Ok(())
+    }
+
+    /// Checks that the operands of all expressions and the arguments of all aggregators have compatible types.
+    /// `column_types` holds the decoded type of each column, columns with unknown type are assumed to be valid.
+    pub fn type_check(&self, column_types: &HashMap<String, BasicType>) -> Result<(), QueryError> {
+        for expr in &self.select {
+            infer_type(expr, column_types)?;
+        }
+        match (&self.filter, infer_type(&self.filter, column_types)?) {
+            (&Expr::Const(_), _) => {}
+            (_, Some(t)) if t != BasicType::Boolean =>
+                bail!(QueryError::TypeMismatch, "Found filter of type {:?}, expected boolean: {:?}", t, self.filter),
+            _ => {}
+        }
+        for &(aggregator, ref expr, ref ordering) in &self.aggregate {
+            let t = infer_type(expr, column_types)?;
+            match aggregator {
+                Aggregator::Sum | Aggregator::Min | Aggregator::Max | Aggregator::Percentile(_) |
+                Aggregator::PercentileCont(_) | Aggregator::PercentileDisc(_) |
+                Aggregator::Variance | Aggregator::StdDev => match t {
+                    Some(t) if t != BasicType::Integer =>
+                        bail!(QueryError::TypeMismatch, "{:?} is only supported for integers, got {:?}: {:?}", aggregator, t, expr),
+                    _ => {}
+                },
+                Aggregator::Count | Aggregator::ApproxCountDistinct(_) |
+                Aggregator::First | Aggregator::Last | Aggregator::Mode => {}
+            }
+            if let Some(ref ordering) = *ordering {
+                infer_type(ordering, column_types)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the rows of each partition are part of the result independently of all other partitions,
+    /// which allows returning them as soon as the partition has been processed.
+    pub fn is_streamable(&self) -> bool {
+        self.aggregate.is_empty() && self.order_by.is_none() && self.then_order_by.is_empty() && !self.distinct
+            && !self.rollup && self.sample.is_none() && self.group_limit.is_none() && self.top_k.is_none()
+    }
+
     pub fn is_select_star(&self) -> bool {
         if self.select.len() == 1 {
             match self.select[0] {
@@ -330,8 +785,10 @@ impl Query {
         let mut anon_columns = -1;
         let select_cols = self.select
             .iter()
-            .map(|expr| match *expr {
-                Expr::ColName(ref name) => name.clone(),
+            .enumerate()
+            .map(|(i, expr)| match (self.select_aliases.get(i), expr) {
+                (Some(&Some(ref alias)), _) => alias.clone(),
+                (_, &Expr::ColName(ref name)) => name.clone(),
                 _ => {
                     anon_columns += 1;
                     format!("col_{}", anon_columns)
@@ -340,15 +797,51 @@ impl Query {
         let mut anon_aggregates = -1;
         let aggregate_cols = self.aggregate
             .iter()
-            .map(|&(agg, _)| {
+            .map(|&(agg, _, _)| {
                 anon_aggregates += 1;
                 match agg {
                     Aggregator::Count => format!("count_{}", anon_aggregates),
                     Aggregator::Sum => format!("sum_{}", anon_aggregates),
+                    Aggregator::Min => format!("min_{}", anon_aggregates),
+                    Aggregator::Max => format!("max_{}", anon_aggregates),
+                    Aggregator::ApproxCountDistinct(_) => format!("approx_count_distinct_{}", anon_aggregates),
+                    Aggregator::Percentile(_) => format!("percentile_{}", anon_aggregates),
+                    Aggregator::PercentileCont(_) => format!("percentile_cont_{}", anon_aggregates),
+                    Aggregator::PercentileDisc(_) => format!("percentile_disc_{}", anon_aggregates),
+                    Aggregator::Variance => format!("var_{}", anon_aggregates),
+                    Aggregator::StdDev => format!("stddev_{}", anon_aggregates),
+                    Aggregator::First => format!("first_{}", anon_aggregates),
+                    Aggregator::Last => format!("last_{}", anon_aggregates),
+                    Aggregator::Mode => format!("mode_{}", anon_aggregates),
                 }
             });
 
-        select_cols.chain(aggregate_cols).collect()
+        let top_k_cols = self.top_k.map(|_| "approx_count".to_string());
+
+        select_cols.chain(aggregate_cols).chain(top_k_cols).collect()
+    }
+
+    /// Determines the type of each result column, in the same order as `result_column_names`.
+    /// `column_types` holds the decoded type of each column, see `type_check`.
+    /// Columns that contain values of more than one type, or whose type can not be determined statically, are `Val`.
+    pub fn result_column_types(&self, column_types: &HashMap<String, BasicType>) -> Vec<BasicType> {
+        let expr_type = |expr: &Expr| match *expr {
+            Expr::ColName(ref name) => column_types.get(name).cloned().unwrap_or(BasicType::Null),
+            _ => infer_type(expr, column_types).ok().and_then(|t| t).unwrap_or(BasicType::Val),
+        };
+        let select_types = self.select.iter().map(|expr| expr_type(expr));
+        let aggregate_types = self.aggregate
+            .iter()
+            .map(|&(aggregator, ref expr, _)| match aggregator {
+                Aggregator::Count | Aggregator::ApproxCountDistinct(_) |
+                Aggregator::Sum | Aggregator::Min | Aggregator::Max |
+                Aggregator::Percentile(_) | Aggregator::PercentileDisc(_) => BasicType::Integer,
+                Aggregator::PercentileCont(_) | Aggregator::Variance | Aggregator::StdDev => BasicType::Float,
+                Aggregator::First | Aggregator::Last | Aggregator::Mode => expr_type(expr),
+            });
+        let top_k_types = self.top_k.map(|_| BasicType::Integer);
+
+        select_types.chain(aggregate_types).chain(top_k_types).collect()
     }
 
     pub fn find_referenced_cols(&self) -> HashSet<String> {
@@ -357,12 +850,53 @@ impl Query {
             expr.add_colnames(&mut colnames);
         }
         self.filter.add_colnames(&mut colnames);
-        for &(_, ref expr) in &self.aggregate {
+        for &(_, ref expr, ref ordering) in &self.aggregate {
             expr.add_colnames(&mut colnames);
+            if let Some(ref ordering) = *ordering {
+                ordering.add_colnames(&mut colnames);
+            }
         }
         colnames
     }
 
+    /// Renames referenced columns that don't exist in `columns` to the column in `columns` that matches case-insensitively.
+    /// Names that match several columns case-insensitively are left unchanged.
+    pub fn resolve_colnames_case_insensitive(&mut self, columns: &[String]) {
+        let mut canonical = HashMap::new();
+        for col in columns {
+            canonical.entry(col.to_lowercase()).or_insert_with(Vec::new).push(col);
+        }
+        let replacements = self.find_referenced_cols().into_iter()
+            .filter(|col| col != "*" && !columns.contains(col))
+            .filter_map(|col| match canonical.get(&col.to_lowercase()) {
+                Some(matches) if matches.len() == 1 => Some((col, Expr::ColName(matches[0].clone()))),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        if replacements.is_empty() { return; }
+
+        let renamed = |col: &mut String, aliases: &[Option<String>]| {
+            if aliases.iter().any(|alias| alias.as_ref() == Some(&*col)) { return; }
+            if let Some(&Expr::ColName(ref name)) = replacements.get(col) {
+                *col = name.clone();
+            }
+        };
+        if let Some(ref mut col) = self.order_by {
+            renamed(col, &self.select_aliases);
+        }
+        for &mut (ref mut col, _) in &mut self.then_order_by {
+            renamed(col, &self.select_aliases);
+        }
+        self.select = self.select.drain(..).map(|expr| expr.replace_colnames(&replacements)).collect();
+        self.filter = mem::replace(&mut self.filter, Expr::Const(RawVal::Null)).replace_colnames(&replacements);
+        self.aggregate = self.aggregate.drain(..)
+            .map(|(aggregator, expr, ordering)| (
+                aggregator,
+                expr.replace_colnames(&replacements),
+                ordering.map(|ordering| ordering.replace_colnames(&replacements))))
+            .collect();
+    }
+
     fn column_data<'a>(columns: &'a HashMap<String, Arc<Column>>)
                        -> HashMap<String, Vec<&'a AnyVec<'a>>> {
         columns.iter()
@@ -371,4 +905,77 @@ impl Query {
     }
 }
 
+/// Determines the decoded type of `expr`, returning `None` if the type can not be determined statically.
+fn infer_type(expr: &Expr, column_types: &HashMap<String, BasicType>) -> Result<Option<BasicType>, QueryError> {
+    use self::Func2Type::*;
+    fn known(t: Option<BasicType>) -> Option<BasicType> {
+        match t {
+            Some(BasicType::Val) | Some(BasicType::Null) => None,
+            t => t,
+        }
+    }
+    let t = match *expr {
+        Expr::ColName(ref name) => known(column_types.get(name).cloned()),
+        Expr::Const(ref value) => known(Some(value.get_type())),
+        Expr::Func1(Func1Type::Not, ref inner) => {
+            match infer_type(inner, column_types)? {
+                Some(t) if t != BasicType::Boolean =>
+                    bail!(QueryError::TypeMismatch, "Found NOT {:?}, expected NOT boolean: {:?}", t, expr),
+                _ => Some(BasicType::Boolean),
+            }
+        }
+        Expr::Func1(Func1Type::Cast(target), ref inner) => {
+            infer_type(inner, column_types)?;
+            Some(target)
+        }
+        Expr::Func1(ftype, ref inner) => {
+            match infer_type(inner, column_types)? {
+                Some(t) if t != BasicType::Integer =>
+                    bail!(QueryError::TypeMismatch, "Found {:?}({:?}), expected {:?}(integer): {:?}", ftype, t, ftype, expr),
+                _ => Some(BasicType::Integer),
+            }
+        }
+        Expr::Func2(ftype, ref lhs, ref rhs) => {
+            let type_lhs = infer_type(lhs, column_types)?;
+            let type_rhs = infer_type(rhs, column_types)?;
+            let (expected, result) = match ftype {
+                Equals | NotEquals | NullSafeEquals => match (type_lhs, type_rhs) {
+                    (Some(t1), Some(t2)) if t1 != t2 => (None, None),
+                    _ => (type_lhs.or(type_rhs), Some(BasicType::Boolean)),
+                },
+                // Strings can only be compared with other string columns, which is checked during planning
+                LT | GT => match (type_lhs, type_rhs) {
+                    (Some(BasicType::String), Some(BasicType::String)) => (Some(BasicType::String), Some(BasicType::Boolean)),
+                    _ => (Some(BasicType::Integer), Some(BasicType::Boolean)),
+                },
+                And | Or => (Some(BasicType::Boolean), Some(BasicType::Boolean)),
+                Add | Subtract | Multiply | Divide | Modulo => (Some(BasicType::Integer), Some(BasicType::Integer)),
+                RegexMatch | Like | Glob | EqualsNoCase | NotEqualsNoCase => (Some(BasicType::String), Some(BasicType::Boolean)),
+            };
+            let valid = |t: Option<BasicType>| t.is_none() || expected.is_none() || t == expected;
+            if result.is_none() || !valid(type_lhs) || !valid(type_rhs) {
+                bail!(QueryError::TypeMismatch, "Found {} {:?} {}: {:?}",
+                      type_lhs.map_or("unknown".to_string(), |t| format!("{:?}", t)), ftype,
+                      type_rhs.map_or("unknown".to_string(), |t| format!("{:?}", t)), expr)
+            }
+            match ftype {
+                // Only division by a nonzero constant is planned without a `Val` output that holds nulls
+                Divide | Modulo => match (**rhs).clone().fold_constants() {
+                    Expr::Const(RawVal::Int(n)) if n != 0 => result,
+                    _ => None,
+                },
+                _ => result,
+            }
+        }
+    };
+    Ok(t)
+}
 
+/// Adds the time elapsed since `start_ns` to `phase` and returns the current time.
+fn record_phase(breakdown: &mut Option<&mut HashMap<&'static str, u64>>, phase: &'static str, start_ns: u64) -> u64 {
+    let now = precise_time_ns();
+    if let Some(ref mut breakdown) = *breakdown {
+        *breakdown.entry(phase).or_insert(0) += now - start_ns;
+    }
+    now
+}
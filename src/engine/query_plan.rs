@@ -16,6 +16,7 @@ use engine::vector_op::vector_operator::BufferRef;
 use ingest::raw_val::RawVal;
 use mem_store::*;
 use mem_store::column::Column;
+use syntax::collation::Collation;
 use syntax::expression::*;
 
 
@@ -28,8 +29,10 @@ pub enum QueryPlan {
 
     DictLookup(Box<QueryPlan>, EncodingType, Box<QueryPlan>, Box<QueryPlan>),
     InverseDictLookup(Box<QueryPlan>, Box<QueryPlan>, Box<QueryPlan>),
+    DictPrefixMatch(Box<QueryPlan>, EncodingType, Box<QueryPlan>, Box<QueryPlan>, String),
     Cast(Box<QueryPlan>, EncodingType, EncodingType),
     LZ4Decode(Box<QueryPlan>, usize, EncodingType),
+    UnpackBits(Box<QueryPlan>, u8, usize),
     UnpackStrings(Box<QueryPlan>),
     UnhexpackStrings(Box<QueryPlan>, bool, usize),
     DeltaDecode(Box<QueryPlan>, EncodingType),
@@ -37,6 +40,8 @@ pub enum QueryPlan {
     Exists(Box<QueryPlan>, EncodingType, Box<QueryPlan>),
     NonzeroCompact(Box<QueryPlan>, EncodingType),
     NonzeroIndices(Box<QueryPlan>, EncodingType, EncodingType),
+    FilterIndices(Box<QueryPlan>),
+    FilterCount(Box<QueryPlan>),
     Compact(Box<QueryPlan>, EncodingType, Box<QueryPlan>, EncodingType),
 
     EncodeIntConstant(Box<QueryPlan>, Codec),
@@ -48,15 +53,44 @@ pub enum QueryPlan {
     SliceUnpack(Box<QueryPlan>, EncodingType, usize, usize),
 
     LessThanVS(EncodingType, Box<QueryPlan>, Box<QueryPlan>),
+    GreaterThanVS(EncodingType, Box<QueryPlan>, Box<QueryPlan>),
     EqualsVS(EncodingType, Box<QueryPlan>, Box<QueryPlan>),
     NotEqualsVS(EncodingType, Box<QueryPlan>, Box<QueryPlan>),
+    EqualsNoCaseVS(Box<QueryPlan>, Box<QueryPlan>),
+    NotEqualsNoCaseVS(Box<QueryPlan>, Box<QueryPlan>),
+    Like(Box<QueryPlan>, String),
+    Glob(Box<QueryPlan>, String),
     DivideVS(Box<QueryPlan>, Box<QueryPlan>),
+    ModuloVS(Box<QueryPlan>, Box<QueryPlan>),
+    DivideVV(Box<QueryPlan>, Box<QueryPlan>),
+    LessThanVV(Box<QueryPlan>, Box<QueryPlan>),
+    EqualsVV(Box<QueryPlan>, Box<QueryPlan>),
+    NotEqualsVV(Box<QueryPlan>, Box<QueryPlan>),
+    ModuloVV(Box<QueryPlan>, Box<QueryPlan>),
+    DivideSV(Box<QueryPlan>, Box<QueryPlan>),
+    ModuloSV(Box<QueryPlan>, Box<QueryPlan>),
+    NullVec(Box<QueryPlan>),
+    /// Decoded values and a null map (see `Column::nulls`), combined into `Val`
+    FuseNulls(Box<QueryPlan>, Box<QueryPlan>),
+    IsNull(Box<QueryPlan>),
+    IsNotNull(Box<QueryPlan>),
+    ConstantBoolVec(Box<QueryPlan>, bool),
     AddVS(EncodingType, Box<QueryPlan>, Box<QueryPlan>),
     And(Box<QueryPlan>, Box<QueryPlan>),
     Or(Box<QueryPlan>, Box<QueryPlan>),
+    Not(Box<QueryPlan>),
     ToYear(Box<QueryPlan>),
+    IntToString(Box<QueryPlan>),
+    StringToInt(Box<QueryPlan>),
+    StringIsNotInt(Box<QueryPlan>),
+    StringToFloat(Box<QueryPlan>),
+    IntToFloat(Box<QueryPlan>),
+    CastVals(Box<QueryPlan>, BasicType),
+    ValToString(Box<QueryPlan>),
 
-    SortIndices(Box<QueryPlan>, bool),
+    SortIndices(Box<QueryPlan>, bool, Collation),
+    /// Stably sorts the indices of the second plan by the values of the first plan and keeps the first `usize` indices
+    SortBy(Box<QueryPlan>, Box<QueryPlan>, bool, usize),
     TopN(Box<QueryPlan>, EncodingType, usize, bool),
 
     Select(Box<QueryPlan>, Box<QueryPlan>),
@@ -125,6 +159,13 @@ fn _prepare(plan: QueryPlan, no_alias: bool, result: &mut QueryExecutor) -> Type
                 prepare(*constant, result).string(),
                 result.buffer_raw_val("encoded"))
         }
+        QueryPlan::DictPrefixMatch(plan, _t, dict_indices, dict_data, prefix) =>
+            VecOperator::dict_prefix_match(
+                prepare(*plan, result),
+                prepare(*dict_indices, result).u64(),
+                prepare(*dict_data, result).u8(),
+                prefix,
+                result.buffer_u8("like")),
         QueryPlan::Cast(plan, _initial_type, target_type) =>
             VecOperator::type_conversion(
                 prepare(*plan, result),
@@ -139,6 +180,12 @@ fn _prepare(plan: QueryPlan, no_alias: bool, result: &mut QueryExecutor) -> Type
                 result.named_buffer("decoded", t),
                 decoded_len)
         }
+        QueryPlan::UnpackBits(plan, width, decoded_len) =>
+            VecOperator::unpack_bits(
+                prepare(*plan, result).u8(),
+                result.buffer_u8("unpacked"),
+                width,
+                decoded_len),
         QueryPlan::UnpackStrings(plan) =>
             VecOperator::unpack_strings(
                 prepare(*plan, result).u8(),
@@ -165,6 +212,14 @@ fn _prepare(plan: QueryPlan, no_alias: bool, result: &mut QueryExecutor) -> Type
             VecOperator::nonzero_indices(
                 prepare(*indices, result),
                 result.named_buffer("nonzero_indices", output_t)),
+        QueryPlan::FilterIndices(filter) =>
+            VecOperator::filter_indices(
+                prepare(*filter, result).u8(),
+                result.buffer_usize("filter_indices")),
+        QueryPlan::FilterCount(filter) =>
+            VecOperator::filter_count(
+                prepare(*filter, result).u8(),
+                result.buffer_usize("filter_count")),
         QueryPlan::NonzeroCompact(data, _data_t) => {
             let inplace = prepare(*data, result);
             result.push(VecOperator::nonzero_compact(inplace));
@@ -210,6 +265,11 @@ fn _prepare(plan: QueryPlan, no_alias: bool, result: &mut QueryExecutor) -> Type
                 prepare(*lhs, result),
                 prepare(*rhs, result).const_i64(),
                 result.buffer_u8("less_than")),
+        QueryPlan::GreaterThanVS(_left_type, lhs, rhs) =>
+            VecOperator::greater_than_vs(
+                prepare(*lhs, result),
+                prepare(*rhs, result).const_i64(),
+                result.buffer_u8("greater_than")),
         QueryPlan::EqualsVS(_left_type, lhs, rhs) =>
             VecOperator::equals_vs(
                 prepare(*lhs, result),
@@ -220,11 +280,79 @@ fn _prepare(plan: QueryPlan, no_alias: bool, result: &mut QueryExecutor) -> Type
                 prepare(*lhs, result),
                 prepare(*rhs, result),
                 result.buffer_u8("equals")),
+        QueryPlan::LessThanVV(lhs, rhs) =>
+            VecOperator::less_than_vv(
+                prepare(*lhs, result),
+                prepare(*rhs, result),
+                result.buffer_u8("less_than")),
+        QueryPlan::EqualsVV(lhs, rhs) =>
+            VecOperator::equals_vv(
+                prepare(*lhs, result),
+                prepare(*rhs, result),
+                result.buffer_u8("equals")),
+        QueryPlan::NotEqualsVV(lhs, rhs) =>
+            VecOperator::not_equals_vv(
+                prepare(*lhs, result),
+                prepare(*rhs, result),
+                result.buffer_u8("equals")),
+        QueryPlan::EqualsNoCaseVS(lhs, rhs) =>
+            VecOperator::equals_no_case_vs(
+                prepare(*lhs, result).str(),
+                prepare(*rhs, result).string(),
+                result.buffer_u8("equals")),
+        QueryPlan::NotEqualsNoCaseVS(lhs, rhs) =>
+            VecOperator::not_equals_no_case_vs(
+                prepare(*lhs, result).str(),
+                prepare(*rhs, result).string(),
+                result.buffer_u8("equals")),
+        QueryPlan::Like(plan, pattern) =>
+            VecOperator::like(prepare(*plan, result).str(), &pattern, result.buffer_u8("like")),
+        QueryPlan::Glob(plan, pattern) =>
+            VecOperator::glob(prepare(*plan, result).str(), &pattern, result.buffer_u8("glob")),
         QueryPlan::DivideVS(lhs, rhs) =>
             VecOperator::divide_vs(
                 prepare(*lhs, result).i64(),
                 prepare(*rhs, result).const_i64(),
                 result.buffer_i64("division")),
+        QueryPlan::ModuloVS(lhs, rhs) =>
+            VecOperator::modulo_vs(
+                prepare(*lhs, result).i64(),
+                prepare(*rhs, result).const_i64(),
+                result.buffer_i64("modulo")),
+        QueryPlan::DivideVV(lhs, rhs) =>
+            VecOperator::divide_vv(
+                prepare(*lhs, result).i64(),
+                prepare(*rhs, result).i64(),
+                result.buffer_val("division")),
+        QueryPlan::ModuloVV(lhs, rhs) =>
+            VecOperator::modulo_vv(
+                prepare(*lhs, result).i64(),
+                prepare(*rhs, result).i64(),
+                result.buffer_val("modulo")),
+        QueryPlan::DivideSV(lhs, rhs) =>
+            VecOperator::divide_sv(
+                prepare(*lhs, result).const_i64(),
+                prepare(*rhs, result).i64(),
+                result.buffer_val("division")),
+        QueryPlan::ModuloSV(lhs, rhs) =>
+            VecOperator::modulo_sv(
+                prepare(*lhs, result).const_i64(),
+                prepare(*rhs, result).i64(),
+                result.buffer_val("modulo")),
+        QueryPlan::FuseNulls(plan, nulls) =>
+            VecOperator::fuse_nulls(
+                prepare(*plan, result),
+                prepare(*nulls, result).u8(),
+                result.buffer_val("nullable")),
+        QueryPlan::NullVec(plan) =>
+            VecOperator::null_vec(
+                prepare(*plan, result).any(),
+                result.named_buffer("nulls", EncodingType::Null).any()),
+        QueryPlan::ConstantBoolVec(plan, value) =>
+            VecOperator::constant_bool_vec(
+                prepare(*plan, result).any(),
+                value,
+                result.buffer_u8("constant_bool")),
         QueryPlan::AddVS(_left_type, lhs, rhs) =>
             VecOperator::addition_vs(
                 prepare(*lhs, result),
@@ -242,14 +370,46 @@ fn _prepare(plan: QueryPlan, no_alias: bool, result: &mut QueryExecutor) -> Type
             result.push(op);
             return inplace;
         }
+        QueryPlan::Not(plan) =>
+            VecOperator::not(prepare(*plan, result).u8(), result.buffer_u8("not")),
+        QueryPlan::IsNull(plan) =>
+            VecOperator::is_null(prepare(*plan, result).val(), result.buffer_u8("is_null")),
+        QueryPlan::IsNotNull(plan) =>
+            VecOperator::is_not_null(prepare(*plan, result).val(), result.buffer_u8("not_null")),
         QueryPlan::ToYear(plan) =>
             VecOperator::to_year(prepare(*plan, result).i64(), result.buffer_i64("year")),
+        QueryPlan::IntToString(plan) => {
+            let stringstore = result.buffer_u8("stringstore");
+            VecOperator::int_to_string(prepare(*plan, result).i64(), result.buffer_str("strings"), stringstore)
+        }
+        QueryPlan::StringToInt(plan) =>
+            VecOperator::string_to_int(prepare(*plan, result).str(), result.buffer_i64("ints")),
+        QueryPlan::StringIsNotInt(plan) =>
+            VecOperator::string_is_not_int(prepare(*plan, result).str(), result.buffer_u8("not_int")),
+        QueryPlan::StringToFloat(plan) =>
+            VecOperator::string_to_float(prepare(*plan, result).str(), result.buffer_val("floats")),
+        QueryPlan::IntToFloat(plan) =>
+            VecOperator::int_to_float(prepare(*plan, result).i64(), result.buffer_val("floats")),
+        QueryPlan::CastVals(plan, target) =>
+            VecOperator::cast_vals(prepare(*plan, result).val(), result.buffer_val("casted"), target),
+        QueryPlan::ValToString(plan) => {
+            let stringstore = result.buffer_u8("stringstore");
+            VecOperator::val_to_string(prepare(*plan, result).val(), result.buffer_val("strings"), stringstore)
+        }
         QueryPlan::EncodedGroupByPlaceholder => return result.encoded_group_by().unwrap(),
-        QueryPlan::SortIndices(plan, descending) =>
+        QueryPlan::SortIndices(plan, descending, collation) =>
             VecOperator::sort_indices(
                 prepare(*plan, result).any(),
                 result.buffer_usize("permutation"),
-                descending),
+                descending,
+                collation),
+        QueryPlan::SortBy(ranking, indices, desc, limit) =>
+            VecOperator::sort_by(
+                prepare(*ranking, result),
+                prepare(*indices, result).usize(),
+                result.buffer_usize("permutation"),
+                limit,
+                desc),
         QueryPlan::TopN(plan, t, n, desc) =>
             VecOperator::top_n(
                 prepare(*plan, result),
@@ -265,23 +425,28 @@ fn _prepare(plan: QueryPlan, no_alias: bool, result: &mut QueryExecutor) -> Type
     result.last_buffer()
 }
 
+/// If `count` is set, the grouping operator also tallies the number of rows in each group which makes a separate `count` operator unnecessary.
 pub fn prepare_hashmap_grouping(raw_grouping_key: TypedBufferRef,
                                 max_cardinality: usize,
+                                count: bool,
                                 result: &mut QueryExecutor)
-                                -> (Option<TypedBufferRef>, TypedBufferRef, Type, BufferRef<i64>) {
+                                -> (Option<TypedBufferRef>, TypedBufferRef, Type, BufferRef<i64>, Option<BufferRef<u32>>) {
     let unique_out = result.named_buffer("unique", raw_grouping_key.tag.clone());
     let grouping_key_out = result.buffer_u32("grouping_key");
     let cardinality_out = result.buffer_i64("cardinality");
+    let count_out = if count { Some(result.buffer_u32("count")) } else { None };
     result.push(
         VecOperator::hash_map_grouping(raw_grouping_key,
                                        unique_out,
                                        grouping_key_out,
                                        cardinality_out,
+                                       count_out,
                                        max_cardinality));
     (Some(unique_out),
      grouping_key_out.tagged(),
      Type::encoded(Codec::opaque(EncodingType::U32, BasicType::Integer, false, false, true, true)),
-     cardinality_out)
+     cardinality_out,
+     count_out)
 }
 
 // TODO(clemens): add QueryPlan::Aggregation and merge with prepare function
@@ -294,29 +459,209 @@ pub fn prepare_aggregation<'a>(plan: QueryPlan,
                                -> Result<(TypedBufferRef, Type), QueryError> {
     let output_location;
     let (operation, t): (BoxedOperator<'a>, _) = match (aggregator, plan) {
-        (Aggregator::Count, _) => {
+        // Null values are not counted
+        (Aggregator::Count, _) if plan_type.decoded == BasicType::Null => {
+            output_location = result.named_buffer("count", EncodingType::U32);
+            (VecOperator::null_count(output_location.u32(), max_index),
+             Type::encoded(Codec::integer_cast(EncodingType::U32)))
+        }
+        (Aggregator::Count, mut plan) => {
+            // Only rows where the value is not null are counted
+            let grouping_key = if plan_type.decoded == BasicType::Val {
+                if let Some(codec) = plan_type.codec.clone() {
+                    plan = *codec.decode(Box::new(plan));
+                }
+                prepare(QueryPlan::Filter(
+                    Box::new(QueryPlan::ReadBuffer(grouping_key)),
+                    Box::new(QueryPlan::IsNotNull(Box::new(plan)))), result)
+            } else {
+                grouping_key
+            };
             output_location = result.named_buffer("count", EncodingType::U32);
             (VecOperator::count(grouping_key,
                                 output_location.u32(),
                                 max_index),
              Type::encoded(Codec::integer_cast(EncodingType::U32)))
         }
-        (Aggregator::Sum, mut plan) => {
-            output_location = result.named_buffer("sum", EncodingType::I64);
-            if !plan_type.is_summation_preserving() {
-                plan = *plan_type.codec.clone().unwrap().decode(Box::new(plan));
+        (Aggregator::Sum, plan) => {
+            if plan_type.decoded != BasicType::Integer {
+                bail!(QueryError::TypeMismatch, "{:?} is only supported for integers, got {:?}", aggregator, plan_type.decoded);
             }
+            output_location = result.named_buffer("sum", EncodingType::I128);
+            let (plan, offset) = summation_input(plan, &plan_type);
             (VecOperator::summation(prepare(plan, result),
                                     grouping_key,
-                                    output_location.i64(),
-                                    max_index), // TODO(clemens): determine dense groupings
-             Type::unencoded(BasicType::Integer))
+                                    output_location.i128(),
+                                    max_index,
+                                    offset), // TODO(clemens): determine dense groupings
+             Type::encoded(Codec::wide_integer()))
+        }
+        (Aggregator::ApproxCountDistinct(_), _) | (Aggregator::Percentile(_), _) |
+        (Aggregator::PercentileCont(_), _) | (Aggregator::PercentileDisc(_), _) |
+        (Aggregator::Variance, _) | (Aggregator::StdDev, _) |
+        (Aggregator::First, _) | (Aggregator::Last, _) | (Aggregator::Mode, _) =>
+            bail!(QueryError::FatalError, "{:?} can not be computed by prepare_aggregation", aggregator),
+        (Aggregator::Min, mut plan) | (Aggregator::Max, mut plan) => {
+            if plan_type.decoded != BasicType::Integer {
+                bail!(QueryError::TypeMismatch, "{:?} is only supported for integers, got {:?}", aggregator, plan_type.decoded);
+            }
+            if let Some(codec) = plan_type.codec.clone() {
+                plan = *codec.decode(Box::new(plan));
+            }
+            let input = prepare(plan, result);
+            if aggregator == Aggregator::Min {
+                output_location = result.named_buffer("min", EncodingType::I64);
+                (VecOperator::minimum(input, grouping_key, output_location.i64(), max_index),
+                 Type::unencoded(BasicType::Integer))
+            } else {
+                output_location = result.named_buffer("max", EncodingType::I64);
+                (VecOperator::maximum(input, grouping_key, output_location.i64(), max_index),
+                 Type::unencoded(BasicType::Integer))
+            }
         }
     };
     result.push(operation);
     Ok((output_location, t))
 }
 
+/// Computes all `aggregates`, which are sums, minimums, maximums and counts, with a single operator
+/// that folds every aggregate in one pass over the grouping key.
+/// Returns the output of each aggregate in the same order as `aggregates`.
+pub fn prepare_combined_aggregation<'a>(aggregates: Vec<(Aggregator, QueryPlan, Type)>,
+                                        grouping_key: TypedBufferRef,
+                                        max_index: BufferRef<i64>,
+                                        result: &mut QueryExecutor<'a>)
+                                        -> Result<Vec<(TypedBufferRef, Type)>, QueryError> {
+    let mut combined = Vec::with_capacity(aggregates.len());
+    let mut outputs = Vec::with_capacity(aggregates.len());
+    for (aggregator, mut plan, plan_type) in aggregates {
+        if aggregator != Aggregator::Count && plan_type.decoded != BasicType::Integer {
+            bail!(QueryError::TypeMismatch, "{:?} is only supported for integers, got {:?}", aggregator, plan_type.decoded);
+        }
+        let (input, output, offset, t) = match aggregator {
+            Aggregator::Count => (None, result.named_buffer("count", EncodingType::U32), 0,
+                                  Type::encoded(Codec::integer_cast(EncodingType::U32))),
+            Aggregator::Sum => {
+                let (plan, offset) = summation_input(plan, &plan_type);
+                (Some(prepare(plan, result)), result.named_buffer("sum", EncodingType::I128), offset,
+                 Type::encoded(Codec::wide_integer()))
+            }
+            Aggregator::Min | Aggregator::Max => {
+                if let Some(codec) = plan_type.codec.clone() {
+                    plan = *codec.decode(Box::new(plan));
+                }
+                let name = if aggregator == Aggregator::Min { "min" } else { "max" };
+                (Some(prepare(plan, result)), result.named_buffer(name, EncodingType::I64), 0,
+                 Type::unencoded(BasicType::Integer))
+            }
+            _ => bail!(QueryError::FatalError, "{:?} can not be computed by prepare_combined_aggregation", aggregator),
+        };
+        combined.push((aggregator, input, output, offset));
+        outputs.push((output, t));
+    }
+    result.push(VecOperator::combined_aggregation(grouping_key, max_index, combined));
+    Ok(outputs)
+}
+
+/// Returns the values that are summed and the offset added to each of them.
+/// Integer offset encoded values are summed without decoding them, `offset * count` is added to the sum of each group.
+fn summation_input(plan: QueryPlan, plan_type: &Type) -> (QueryPlan, i64) {
+    if plan_type.is_summation_preserving() {
+        return (plan, 0);
+    }
+    let codec = plan_type.codec.clone().unwrap();
+    match codec.ops()[0] {
+        CodecOp::Add(t, x) if codec.ops().len() == 1 && t != EncodingType::U64 => (plan, x),
+        _ => (*codec.decode(Box::new(plan)), 0),
+    }
+}
+
+pub fn prepare_hyperloglog<'a>(mut plan: QueryPlan,
+                               plan_type: Type,
+                               grouping_key: TypedBufferRef,
+                               max_index: BufferRef<i64>,
+                               precision: u8,
+                               result: &mut QueryExecutor<'a>)
+                               -> Result<Vec<(TypedBufferRef, Type)>, QueryError> {
+    // Encodings differ between partitions, so sketches have to be computed from decoded values to be mergeable
+    if let Some(codec) = plan_type.codec.clone() {
+        plan = *codec.decode(Box::new(plan));
+    }
+    let input = prepare(plan, result);
+    let registers = result.buffer_u8("hll_registers");
+    let packed = (0..Aggregator::ApproxCountDistinct(precision).intermediate_columns())
+        .map(|_| result.named_buffer("hll_packed_registers", EncodingType::I64))
+        .collect::<Vec<_>>();
+    result.push(VecOperator::hyperloglog(input,
+                                         grouping_key,
+                                         registers,
+                                         packed.iter().map(|r| r.i64()).collect(),
+                                         max_index,
+                                         precision));
+    Ok(packed.into_iter().map(|r| (r, Type::unencoded(BasicType::Integer))).collect())
+}
+
+/// Computes the count, mean and M2 intermediate columns of a variance or standard deviation aggregator.
+pub fn prepare_moments<'a>(mut plan: QueryPlan,
+                           plan_type: Type,
+                           grouping_key: TypedBufferRef,
+                           max_index: BufferRef<i64>,
+                           aggregator: Aggregator,
+                           result: &mut QueryExecutor<'a>)
+                           -> Result<Vec<(TypedBufferRef, Type)>, QueryError> {
+    // Null values are not counted, so all columns are zero
+    if plan_type.decoded == BasicType::Null {
+        return Ok((0..aggregator.intermediate_columns())
+            .map(|_| {
+                let zero = result.named_buffer("null_moments", EncodingType::U32);
+                result.push(VecOperator::null_count(zero.u32(), max_index));
+                (zero, Type::encoded(Codec::integer_cast(EncodingType::U32)))
+            })
+            .collect());
+    }
+    if plan_type.decoded != BasicType::Integer {
+        bail!(QueryError::TypeMismatch, "{:?} is only supported for integers, got {:?}", aggregator, plan_type.decoded);
+    }
+    if let Some(codec) = plan_type.codec.clone() {
+        plan = *codec.decode(Box::new(plan));
+    }
+    let input = prepare(plan, result);
+    let count = result.named_buffer("moments_count", EncodingType::I64);
+    let mean = result.named_buffer("moments_mean", EncodingType::I64);
+    let m2 = result.named_buffer("moments_m2", EncodingType::I64);
+    result.push(VecOperator::moments(input, grouping_key, count.i64(), mean.i64(), m2.i64(), max_index));
+    Ok(vec![count, mean, m2].into_iter().map(|r| (r, Type::unencoded(BasicType::Integer))).collect())
+}
+
+/// Folds the values of each group into the `AggregateState` of an aggregator that is evaluated after merging.
+/// `ordering` holds the plan for the ordering key of `FIRST` and `LAST`.
+pub fn prepare_aggregate_states<'a>(mut plan: QueryPlan,
+                                    plan_type: Type,
+                                    ordering: Option<(QueryPlan, Type)>,
+                                    grouping_key: TypedBufferRef,
+                                    max_index: BufferRef<i64>,
+                                    aggregator: Aggregator,
+                                    result: &mut QueryExecutor<'a>)
+                                    -> Result<(TypedBufferRef, Type), QueryError> {
+    // Encodings differ between partitions, so states have to be computed from decoded values to be mergeable
+    if let Some(codec) = plan_type.codec.clone() {
+        plan = *codec.decode(Box::new(plan));
+    }
+    let input = prepare(plan, result);
+    let ordering = ordering.map(|(plan, t)| prepare(*decode(plan, &t), result));
+    let states = result.named_buffer("aggregate_states", EncodingType::AggregateState);
+    result.push(VecOperator::aggregate_states(input, ordering, grouping_key, states.any(), max_index, aggregator));
+    Ok((states, Type::unencoded(BasicType::Val)))
+}
+
+/// Decodes `plan` so that values can be compared with another column, integers are decoded to i64.
+fn decode(plan: QueryPlan, t: &Type) -> Box<QueryPlan> {
+    match t.codec {
+        Some(ref codec) => codec.decode(Box::new(plan)),
+        None => Box::new(plan),
+    }
+}
+
 pub fn order_preserving((plan, t): (QueryPlan, Type)) -> (QueryPlan, Type) {
     if t.is_order_preserving() {
         (plan, t)
@@ -345,22 +690,9 @@ impl QueryPlan {
                         t = Type::encoded(codec);
                         plan = *fixed_width;
                     }
-                    plan = match filter {
-                        Filter::BitVec(filter) => {
-                            QueryPlan::Filter(
-                                Box::new(plan),
-                                Box::new(QueryPlan::ReadBuffer(filter.tagged())))
-                        }
-                        Filter::Indices(indices) => {
-                            QueryPlan::Select(
-                                Box::new(plan),
-                                Box::new(QueryPlan::ReadBuffer(indices.tagged())))
-                        }
-                        Filter::None => plan,
-                    };
-                    (plan, t)
+                    (QueryPlan::apply_filter(plan, filter), t)
                 }
-                None => bail!(QueryError::NotImplemented, "Referencing missing column {}", name)
+                None => bail!(QueryError::UnknownColumn, "{}", name)
             }
             Func2(LT, ref lhs, ref rhs) => {
                 let (plan_lhs, type_lhs) = QueryPlan::create_query_plan(lhs, filter, columns)?;
@@ -374,12 +706,35 @@ impl QueryPlan {
                             } else {
                                 QueryPlan::LessThanVS(type_lhs.encoding_type(), Box::new(plan_lhs), Box::new(plan_rhs))
                             }
+                        } else if !type_lhs.is_scalar {
+                            QueryPlan::LessThanVV(decode(plan_lhs, &type_lhs), decode(plan_rhs, &type_rhs))
                         } else {
                             bail!(QueryError::NotImplemented, "< operator only implemented for column < constant")
                         };
                         (plan, Type::new(BasicType::Boolean, None).mutable())
                     }
-                    _ => bail!(QueryError::TypeError, "{:?} < {:?}", type_lhs, type_rhs)
+                    (BasicType::String, BasicType::String) if !type_lhs.is_scalar && !type_rhs.is_scalar => {
+                        let plan = QueryPlan::LessThanVV(decode(plan_lhs, &type_lhs), decode(plan_rhs, &type_rhs));
+                        (plan, Type::new(BasicType::Boolean, None).mutable())
+                    }
+                    _ => bail!(QueryError::TypeMismatch, "{:?} < {:?}", type_lhs, type_rhs)
+                }
+            }
+            Func2(GT, ref lhs, ref rhs) => {
+                let (plan_lhs, type_lhs) = QueryPlan::create_query_plan(lhs, filter, columns)?;
+                let (plan_rhs, type_rhs) = QueryPlan::create_query_plan(rhs, filter, columns)?;
+                match (type_lhs.decoded, type_rhs.decoded) {
+                    (BasicType::Integer, BasicType::Integer) if type_rhs.is_scalar => {
+                        let plan = if type_lhs.is_encoded() {
+                            let encoded = QueryPlan::EncodeIntConstant(Box::new(plan_rhs), type_lhs.codec.clone().unwrap());
+                            QueryPlan::GreaterThanVS(type_lhs.encoding_type(), Box::new(plan_lhs), Box::new(encoded))
+                        } else {
+                            QueryPlan::GreaterThanVS(type_lhs.encoding_type(), Box::new(plan_lhs), Box::new(plan_rhs))
+                        };
+                        (plan, Type::new(BasicType::Boolean, None).mutable())
+                    }
+                    // Evaluated as `rhs < lhs`
+                    _ => QueryPlan::create_query_plan(&Func2(LT, rhs.clone(), lhs.clone()), filter, columns)?,
                 }
             }
             Func2(Equals, ref lhs, ref rhs) => {
@@ -394,6 +749,8 @@ impl QueryPlan {
                             } else {
                                 QueryPlan::EqualsVS(type_lhs.encoding_type(), Box::new(plan_lhs), Box::new(plan_rhs))
                             }
+                        } else if !type_lhs.is_scalar {
+                            QueryPlan::EqualsVV(decode(plan_lhs, &type_lhs), decode(plan_rhs, &type_rhs))
                         } else {
                             bail!(QueryError::NotImplemented, "= operator only implemented for column = constant")
                         };
@@ -407,12 +764,14 @@ impl QueryPlan {
                             } else {
                                 QueryPlan::EqualsVS(type_lhs.encoding_type(), Box::new(plan_lhs), Box::new(plan_rhs))
                             }
+                        } else if !type_lhs.is_scalar {
+                            QueryPlan::EqualsVV(decode(plan_lhs, &type_lhs), decode(plan_rhs, &type_rhs))
                         } else {
                             bail!(QueryError::NotImplemented, "= operator only implemented for column = constant")
                         };
                         (plan, Type::new(BasicType::Boolean, None).mutable())
                     }
-                    _ => bail!(QueryError::TypeError, "{:?} = {:?}", type_lhs, type_rhs)
+                    _ => bail!(QueryError::TypeMismatch, "{:?} = {:?}", type_lhs, type_rhs)
                 }
             }
             Func2(NotEquals, ref lhs, ref rhs) => {
@@ -427,6 +786,8 @@ impl QueryPlan {
                             } else {
                                 QueryPlan::NotEqualsVS(type_lhs.encoding_type(), Box::new(plan_lhs), Box::new(plan_rhs))
                             }
+                        } else if !type_lhs.is_scalar {
+                            QueryPlan::NotEqualsVV(decode(plan_lhs, &type_lhs), decode(plan_rhs, &type_rhs))
                         } else {
                             bail!(QueryError::NotImplemented, "<> operator only implemented for column <> constant")
                         };
@@ -440,19 +801,140 @@ impl QueryPlan {
                             } else {
                                 QueryPlan::NotEqualsVS(type_lhs.encoding_type(), Box::new(plan_lhs), Box::new(plan_rhs))
                             }
+                        } else if !type_lhs.is_scalar {
+                            QueryPlan::NotEqualsVV(decode(plan_lhs, &type_lhs), decode(plan_rhs, &type_rhs))
                         } else {
                             bail!(QueryError::NotImplemented, "<> operator only implemented for column <> constant")
                         };
                         (plan, Type::new(BasicType::Boolean, None).mutable())
                     }
-                    _ => bail!(QueryError::TypeError, "{:?} <> {:?}", type_lhs, type_rhs)
+                    _ => bail!(QueryError::TypeMismatch, "{:?} <> {:?}", type_lhs, type_rhs)
                 }
             }
+            Func2(NullSafeEquals, ref lhs, ref rhs) => {
+                let (plan_lhs, type_lhs) = QueryPlan::create_query_plan(lhs, filter, columns)?;
+                let (plan_rhs, type_rhs) = QueryPlan::create_query_plan(rhs, filter, columns)?;
+                let lhs_null = type_lhs.decoded == BasicType::Null;
+                let rhs_null = type_rhs.decoded == BasicType::Null;
+                if type_lhs.is_scalar && type_rhs.is_scalar {
+                    bail!(QueryError::NotImplemented, "<=> operator only implemented for column <=> constant and column <=> column")
+                }
+                if lhs_null && rhs_null {
+                    let rows = if type_lhs.is_scalar { plan_rhs } else { plan_lhs };
+                    (QueryPlan::ConstantBoolVec(Box::new(rows), true), Type::bit_vec())
+                } else if lhs_null || rhs_null {
+                    // Compares the other side with null, which is a per row test if its values may be null
+                    let (other, plan_other, type_other, plan_null) = if lhs_null {
+                        (rhs, plan_rhs, type_rhs, plan_lhs)
+                    } else {
+                        (lhs, plan_lhs, type_lhs, plan_rhs)
+                    };
+                    match QueryPlan::create_null_test(other, filter, columns)? {
+                        Some(is_null) => (is_null, Type::bit_vec()),
+                        None => {
+                            let rows = if type_other.is_scalar { plan_null } else { plan_other };
+                            (QueryPlan::ConstantBoolVec(Box::new(rows), false), Type::bit_vec())
+                        }
+                    }
+                } else {
+                    let (equals, t) = if type_lhs.is_scalar {
+                        QueryPlan::create_query_plan(&Func2(Equals, rhs.clone(), lhs.clone()), filter, columns)?
+                    } else {
+                        QueryPlan::create_query_plan(&Func2(Equals, lhs.clone(), rhs.clone()), filter, columns)?
+                    };
+                    match (QueryPlan::create_null_test(lhs, filter, columns)?,
+                           QueryPlan::create_null_test(rhs, filter, columns)?) {
+                        (None, None) => (equals, t),
+                        (Some(is_null), None) | (None, Some(is_null)) =>
+                            (QueryPlan::And(Box::new(equals), Box::new(QueryPlan::Not(Box::new(is_null)))), Type::bit_vec()),
+                        // Missing values are stored as the same zero or empty string, so rows where both sides are null are equal
+                        // and it is sufficient to exclude rows where exactly one side is null
+                        (Some(lhs_is_null), Some(rhs_is_null)) =>
+                            (QueryPlan::And(
+                                Box::new(equals),
+                                Box::new(QueryPlan::EqualsVV(Box::new(lhs_is_null), Box::new(rhs_is_null)))),
+                             Type::bit_vec()),
+                    }
+                }
+            }
+            Func2(ftype @ EqualsNoCase, ref lhs, ref rhs) | Func2(ftype @ NotEqualsNoCase, ref lhs, ref rhs) => {
+                let (plan_lhs, type_lhs) = QueryPlan::create_query_plan(lhs, filter, columns)?;
+                let (plan_rhs, type_rhs) = QueryPlan::create_query_plan(rhs, filter, columns)?;
+                if type_lhs.decoded != BasicType::String || type_rhs.decoded != BasicType::String {
+                    bail!(QueryError::TypeMismatch, "{:?} {:?} {:?}", type_lhs, ftype, type_rhs)
+                }
+                if !type_rhs.is_scalar {
+                    bail!(QueryError::NotImplemented, "{:?} operator only implemented for column = constant", ftype)
+                }
+                // Dictionary indices only identify strings under binary collation, so the strings are compared after decoding
+                let decoded = match type_lhs.codec {
+                    Some(codec) => *codec.decode(Box::new(plan_lhs)),
+                    None => plan_lhs,
+                };
+                let plan = match ftype {
+                    EqualsNoCase => QueryPlan::EqualsNoCaseVS(Box::new(decoded), Box::new(plan_rhs)),
+                    _ => QueryPlan::NotEqualsNoCaseVS(Box::new(decoded), Box::new(plan_rhs)),
+                };
+                (plan, Type::new(BasicType::Boolean, None).mutable())
+            }
+            Func2(Like, ref lhs, ref rhs) => {
+                let (plan_lhs, type_lhs) = QueryPlan::create_query_plan(lhs, filter, columns)?;
+                let pattern = match **rhs {
+                    Const(RawVal::Str(ref pattern)) => pattern,
+                    _ => bail!(QueryError::NotImplemented, "LIKE operator only implemented for column LIKE constant"),
+                };
+                if type_lhs.decoded != BasicType::String {
+                    bail!(QueryError::TypeMismatch, "Found {:?} LIKE {:?}, expected string LIKE string", &type_lhs, pattern)
+                }
+                let prefix_match = match (like_prefix(pattern), &type_lhs.codec) {
+                    (Some(prefix), &Some(ref codec)) => codec.dict_prefix_match(Box::new(plan_lhs.clone()), prefix),
+                    _ => None,
+                };
+                let plan = match prefix_match {
+                    Some(plan) => *plan,
+                    None => {
+                        let decoded = match type_lhs.codec {
+                            Some(codec) => *codec.decode(Box::new(plan_lhs)),
+                            None => plan_lhs,
+                        };
+                        QueryPlan::Like(Box::new(decoded), pattern.to_string())
+                    }
+                };
+                (plan, Type::new(BasicType::Boolean, None).mutable())
+            }
+            Func2(Glob, ref lhs, ref rhs) => {
+                let (plan_lhs, type_lhs) = QueryPlan::create_query_plan(lhs, filter, columns)?;
+                let pattern = match **rhs {
+                    Const(RawVal::Str(ref pattern)) => pattern,
+                    _ => bail!(QueryError::NotImplemented, "GLOB function only implemented for glob(column, constant)"),
+                };
+                if type_lhs.decoded != BasicType::String {
+                    bail!(QueryError::TypeMismatch, "Found glob({:?}, {:?}), expected glob(string, string)", &type_lhs, pattern)
+                }
+                if let Err(err) = glob_to_regex(pattern) {
+                    bail!(QueryError::ParseError, "Invalid glob pattern {:?}: {}", pattern, err)
+                }
+                let prefix_match = match (glob_prefix(pattern), &type_lhs.codec) {
+                    (Some(prefix), &Some(ref codec)) => codec.dict_prefix_match(Box::new(plan_lhs.clone()), prefix),
+                    _ => None,
+                };
+                let plan = match prefix_match {
+                    Some(plan) => *plan,
+                    None => {
+                        let decoded = match type_lhs.codec {
+                            Some(codec) => *codec.decode(Box::new(plan_lhs)),
+                            None => plan_lhs,
+                        };
+                        QueryPlan::Glob(Box::new(decoded), pattern.to_string())
+                    }
+                };
+                (plan, Type::new(BasicType::Boolean, None).mutable())
+            }
             Func2(Or, ref lhs, ref rhs) => {
                 let (plan_lhs, type_lhs) = QueryPlan::create_query_plan(lhs, filter, columns)?;
                 let (plan_rhs, type_rhs) = QueryPlan::create_query_plan(rhs, filter, columns)?;
                 if type_lhs.decoded != BasicType::Boolean || type_rhs.decoded != BasicType::Boolean {
-                    bail!(QueryError::TypeError, "Found {} OR {}, expected bool OR bool")
+                    bail!(QueryError::TypeMismatch, "Found {} OR {}, expected bool OR bool")
                 }
                 (QueryPlan::Or(Box::new(plan_lhs), Box::new(plan_rhs)), Type::bit_vec())
             }
@@ -460,12 +942,12 @@ impl QueryPlan {
                 let (plan_lhs, type_lhs) = QueryPlan::create_query_plan(lhs, filter, columns)?;
                 let (plan_rhs, type_rhs) = QueryPlan::create_query_plan(rhs, filter, columns)?;
                 if type_lhs.decoded != BasicType::Boolean || type_rhs.decoded != BasicType::Boolean {
-                    bail!(QueryError::TypeError, "Found {} AND {}, expected bool AND bool")
+                    bail!(QueryError::TypeMismatch, "Found {} AND {}, expected bool AND bool")
                 }
                 (QueryPlan::And(Box::new(plan_lhs), Box::new(plan_rhs)), Type::bit_vec())
             }
-            Func2(Divide, ref lhs, ref rhs) => {
-                let (mut plan_lhs, mut type_lhs) = QueryPlan::create_query_plan(lhs, filter, columns)?;
+            Func2(Add, ref lhs, ref rhs) => {
+                let (mut plan_lhs, type_lhs) = QueryPlan::create_query_plan(lhs, filter, columns)?;
                 let (plan_rhs, type_rhs) = QueryPlan::create_query_plan(rhs, filter, columns)?;
                 match (type_lhs.decoded, type_rhs.decoded) {
                     (BasicType::Integer, BasicType::Integer) => {
@@ -473,19 +955,67 @@ impl QueryPlan {
                             if let Some(codec) = type_lhs.codec {
                                 plan_lhs = *codec.decode(Box::new(plan_lhs));
                             }
-                            QueryPlan::DivideVS(Box::new(plan_lhs), Box::new(plan_rhs))
+                            QueryPlan::AddVS(EncodingType::I64, Box::new(plan_lhs), Box::new(plan_rhs))
                         } else {
-                            bail!(QueryError::NotImplemented, "/ operator only implemented for column / constant")
+                            bail!(QueryError::NotImplemented, "+ operator only implemented for column + constant")
                         };
                         (plan, Type::unencoded(BasicType::Integer).mutable())
                     }
-                    _ => bail!(QueryError::TypeError, "{:?} / {:?}", type_lhs, type_rhs)
+                    _ => bail!(QueryError::TypeMismatch, "{:?} + {:?}", type_lhs, type_rhs)
+                }
+            }
+            Func2(ftype @ Divide, ref lhs, ref rhs) | Func2(ftype @ Modulo, ref lhs, ref rhs) => {
+                // Constant operands are evaluated first so that e.g. `x / (1 - 1)` is recognized as division by zero
+                let lhs = (**lhs).clone().fold_constants();
+                let rhs = (**rhs).clone().fold_constants();
+                let folded = Expr::func(ftype, lhs.clone(), rhs.clone()).fold_constants();
+                if let Const(_) = folded {
+                    return QueryPlan::create_query_plan(&folded, filter, columns);
+                }
+                let (mut plan_lhs, type_lhs) = QueryPlan::create_query_plan(&lhs, filter, columns)?;
+                let (mut plan_rhs, type_rhs) = QueryPlan::create_query_plan(&rhs, filter, columns)?;
+                if type_lhs.decoded != BasicType::Integer || type_rhs.decoded != BasicType::Integer {
+                    bail!(QueryError::TypeMismatch, "{:?} {:?} {:?}", type_lhs, ftype, type_rhs)
+                }
+                if let Some(codec) = type_lhs.codec {
+                    plan_lhs = *codec.decode(Box::new(plan_lhs));
+                }
+                let modulo = match ftype { Modulo => true, _ => false };
+                if let Const(RawVal::Int(0)) = rhs {
+                    // Division and modulo by zero yield null
+                    (QueryPlan::NullVec(Box::new(plan_lhs)), Type::unencoded(BasicType::Null))
+                } else if type_rhs.is_scalar {
+                    let plan = if modulo {
+                        QueryPlan::ModuloVS(Box::new(plan_lhs), Box::new(plan_rhs))
+                    } else {
+                        QueryPlan::DivideVS(Box::new(plan_lhs), Box::new(plan_rhs))
+                    };
+                    (plan, Type::unencoded(BasicType::Integer).mutable())
+                } else {
+                    if let Some(codec) = type_rhs.codec {
+                        plan_rhs = *codec.decode(Box::new(plan_rhs));
+                    }
+                    // Rows with a zero divisor are null, so the result has to be stored as `Val`
+                    let plan = match (modulo, type_lhs.is_scalar) {
+                        (true, true) => QueryPlan::ModuloSV(Box::new(plan_lhs), Box::new(plan_rhs)),
+                        (false, true) => QueryPlan::DivideSV(Box::new(plan_lhs), Box::new(plan_rhs)),
+                        (true, false) => QueryPlan::ModuloVV(Box::new(plan_lhs), Box::new(plan_rhs)),
+                        (false, false) => QueryPlan::DivideVV(Box::new(plan_lhs), Box::new(plan_rhs)),
+                    };
+                    (plan, Type::unencoded(BasicType::Val).mutable())
+                }
+            }
+            Func1(Not, ref inner) => {
+                let (plan, t) = QueryPlan::create_query_plan(inner, filter, columns)?;
+                if t.decoded != BasicType::Boolean {
+                    bail!(QueryError::TypeMismatch, "Found NOT {:?}, expected NOT bool", &t)
                 }
+                (QueryPlan::Not(Box::new(plan)), Type::bit_vec())
             }
             Func1(ToYear, ref inner) => {
                 let (plan, t) = QueryPlan::create_query_plan(inner, filter, columns)?;
                 if t.decoded != BasicType::Integer {
-                    bail!(QueryError::TypeError, "Found to_year({:?}), expected to_year(integer)", &t)
+                    bail!(QueryError::TypeMismatch, "Found to_year({:?}), expected to_year(integer)", &t)
                 }
                 let decoded = match t.codec.clone() {
                     Some(codec) => *codec.decode(Box::new(plan)),
@@ -493,12 +1023,131 @@ impl QueryPlan {
                 };
                 (QueryPlan::ToYear(Box::new(decoded)), t.decoded())
             }
+            Func1(Cast(target), ref inner) => {
+                // Floats are stored as `Val` which can hold the nulls of `inner`, casts to integers and strings
+                // report values that are null or can not be converted in the null map returned by `read_nulls`
+                let (plan, t) = if target == BasicType::Float {
+                    QueryPlan::create_nullable_query_plan(inner, filter, columns)?
+                } else {
+                    QueryPlan::create_query_plan(inner, filter, columns)?
+                };
+                // Null columns remain null
+                if t.decoded == target || t.decoded == BasicType::Null {
+                    (plan, t)
+                } else {
+                    if t.is_scalar {
+                        bail!(QueryError::NotImplemented, "CAST of {:?} constant to {:?} that is not folded during parsing", t.decoded, target)
+                    }
+                    let decoded = match t.codec.clone() {
+                        Some(codec) => *codec.decode(Box::new(plan)),
+                        None => plan,
+                    };
+                    match (t.decoded, target) {
+                        (BasicType::Integer, BasicType::String) =>
+                            (QueryPlan::IntToString(Box::new(decoded)), Type::unencoded(BasicType::String).mutable()),
+                        (BasicType::String, BasicType::Integer) =>
+                            (QueryPlan::StringToInt(Box::new(decoded)), Type::unencoded(BasicType::Integer).mutable()),
+                        (BasicType::Integer, BasicType::Float) =>
+                            (QueryPlan::IntToFloat(Box::new(decoded)), Type::unencoded(BasicType::Val).mutable()),
+                        (BasicType::String, BasicType::Float) =>
+                            (QueryPlan::StringToFloat(Box::new(decoded)), Type::unencoded(BasicType::Val).mutable()),
+                        (BasicType::Val, BasicType::String) =>
+                            (QueryPlan::ValToString(Box::new(decoded)), Type::unencoded(BasicType::Val).mutable()),
+                        (BasicType::Val, _) =>
+                            (QueryPlan::CastVals(Box::new(decoded), target), Type::unencoded(BasicType::Val).mutable()),
+                        (source, target) => bail!(QueryError::NotImplemented, "CAST from {:?} to {:?}", source, target),
+                    }
+                }
+            }
             Const(ref v) => (QueryPlan::Constant(v.clone(), false), Type::scalar(v.get_type())),
             ref x => bail!(QueryError::NotImplemented, "{:?}.compile_vec()", x),
         })
     }
 
-    fn encoding_range(&self) -> Option<(i64, i64)> {
+    /// Like `create_query_plan`, but columns with a null map (see `Column::nulls`) are returned as `Val`
+    /// that is null for missing values instead of the zero or empty string they are stored as.
+    pub fn create_nullable_query_plan(
+        expr: &Expr,
+        filter: Filter,
+        columns: &HashMap<String, Arc<Column>>) -> Result<(QueryPlan, Type), QueryError> {
+        let (plan, t) = QueryPlan::create_query_plan(expr, filter, columns)?;
+        match QueryPlan::read_nulls(expr, filter, columns)? {
+            Some(nulls) => {
+                let decoded = match t.codec {
+                    Some(codec) => *codec.decode(Box::new(plan)),
+                    None => plan,
+                };
+                Ok((QueryPlan::FuseNulls(Box::new(decoded), Box::new(nulls)), Type::unencoded(BasicType::Val).mutable()))
+            }
+            None => Ok((plan, t)),
+        }
+    }
+
+    /// Plan for a boolean vector that is 1 for rows in which `expr` is null, or `None` if `expr` is never null.
+    fn create_null_test(
+        expr: &Expr,
+        filter: Filter,
+        columns: &HashMap<String, Arc<Column>>) -> Result<Option<QueryPlan>, QueryError> {
+        let (plan, t) = QueryPlan::create_nullable_query_plan(expr, filter, columns)?;
+        Ok(match t.decoded {
+            BasicType::Null => Some(QueryPlan::ConstantBoolVec(Box::new(plan), true)),
+            BasicType::Val => {
+                let decoded = match t.codec {
+                    Some(codec) => *codec.decode(Box::new(plan)),
+                    None => plan,
+                };
+                Some(QueryPlan::IsNull(Box::new(decoded)))
+            }
+            _ => None,
+        })
+    }
+
+    /// Reads the null map of `expr` if it is a column that has one.
+    fn read_nulls(expr: &Expr, filter: Filter, columns: &HashMap<String, Arc<Column>>) -> Result<Option<QueryPlan>, QueryError> {
+        Ok(match *expr {
+            Expr::ColName(ref name) => columns.get::<str>(name.as_ref())
+                .and_then(|c| c.null_section())
+                .map(|section| QueryPlan::apply_filter(
+                    QueryPlan::ReadColumnSection(name.to_string(), section, None, EncodingType::U8), filter)),
+            Expr::Func1(Func1Type::Cast(target), ref inner) if target != BasicType::Float => {
+                let nulls = QueryPlan::read_nulls(inner, filter, columns)?;
+                let (plan, t) = QueryPlan::create_query_plan(inner, filter, columns)?;
+                if t.decoded == BasicType::String && target == BasicType::Integer && !t.is_scalar {
+                    let decoded = match t.codec {
+                        Some(codec) => *codec.decode(Box::new(plan)),
+                        None => plan,
+                    };
+                    let not_int = QueryPlan::StringIsNotInt(Box::new(decoded));
+                    Some(match nulls {
+                        Some(nulls) => QueryPlan::Or(Box::new(not_int), Box::new(nulls)),
+                        None => not_int,
+                    })
+                } else {
+                    nulls
+                }
+            }
+            _ => None,
+        })
+    }
+
+    /// Restricts the rows of a column section to those selected by `filter`.
+    fn apply_filter(plan: QueryPlan, filter: Filter) -> QueryPlan {
+        match filter {
+            Filter::BitVec(filter) => {
+                QueryPlan::Filter(
+                    Box::new(plan),
+                    Box::new(QueryPlan::ReadBuffer(filter.tagged())))
+            }
+            Filter::Indices(indices) => {
+                QueryPlan::Select(
+                    Box::new(plan),
+                    Box::new(QueryPlan::ReadBuffer(indices.tagged())))
+            }
+            Filter::None => plan,
+        }
+    }
+
+    pub fn encoding_range(&self) -> Option<(i64, i64)> {
         // TODO(clemens): need more principled approach - this currently doesn't work for all partially decodings
         // Example: [LZ4, Add, Delta] will have as bottom decoding range the range after Delta, but without the Add :/
         // This works in this case because we always have to decode the Delta, but is hard to reason about and has caused bugs
@@ -514,10 +1163,18 @@ impl QueryPlan {
             DivideVS(ref left, box Constant(RawVal::Int(c), _)) =>
                 left.encoding_range().map(|(min, max)|
                     if c > 0 { (min / c, max / c) } else { (max / c, min / c) }),
+            ModuloVS(ref left, box Constant(RawVal::Int(c), _)) => {
+                let max = c.checked_abs().map_or(i64::MAX, |c| c - 1);
+                match left.encoding_range() {
+                    Some((min, _)) if min >= 0 => Some((0, max)),
+                    _ => Some((-max, max)),
+                }
+            }
             AddVS(_, ref left, box Constant(RawVal::Int(c), _)) =>
-                left.encoding_range().map(|(min, max)| (min + c, max + c)),
+                left.encoding_range().map(|(min, max)| (min.saturating_add(c), max.saturating_add(c))),
             Cast(ref left, _, _) => left.encoding_range(),
             LZ4Decode(ref plan, _, _) => plan.encoding_range(),
+            UnpackBits(ref plan, _, _) => plan.encoding_range(),
             DeltaDecode(ref plan, _) => plan.encoding_range(),
             _ => None, // TODO(clemens): many more cases where we can determine range
         }
@@ -561,6 +1218,17 @@ fn replace_common_subexpression(plan: QueryPlan, executor: &mut QueryExecutor) -
                 hasher.input(&s3);
                 InverseDictLookup(dict_indices, dict_data, constant)
             }
+            DictPrefixMatch(indices, t, dict_indices, dict_data, prefix) => {
+                let (indices, s1) = replace_common_subexpression(*indices, executor);
+                let (dict_indices, s2) = replace_common_subexpression(*dict_indices, executor);
+                let (dict_data, s3) = replace_common_subexpression(*dict_data, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                hasher.input(&s3);
+                hasher.input(&discriminant_value(&t).to_ne_bytes());
+                hasher.input_str(&prefix);
+                DictPrefixMatch(indices, t, dict_indices, dict_data, prefix)
+            }
             Cast(plan, initial_type, target_type) => {
                 let (plan, s1) = replace_common_subexpression(*plan, executor);
                 hasher.input(&s1);
@@ -574,6 +1242,12 @@ fn replace_common_subexpression(plan: QueryPlan, executor: &mut QueryExecutor) -
                 hasher.input(&discriminant_value(&t).to_ne_bytes());
                 LZ4Decode(plan, decoded_len, t)
             }
+            UnpackBits(plan, width, decoded_len) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                hasher.input(&[width]);
+                UnpackBits(plan, width, decoded_len)
+            }
             UnpackStrings(plan) => {
                 let (plan, s1) = replace_common_subexpression(*plan, executor);
                 hasher.input(&s1);
@@ -613,6 +1287,16 @@ fn replace_common_subexpression(plan: QueryPlan, executor: &mut QueryExecutor) -
                 hasher.input(&discriminant_value(&t2).to_ne_bytes());
                 NonzeroIndices(plan, t1, t2)
             }
+            FilterIndices(filter) => {
+                let (filter, s1) = replace_common_subexpression(*filter, executor);
+                hasher.input(&s1);
+                FilterIndices(filter)
+            }
+            FilterCount(filter) => {
+                let (filter, s1) = replace_common_subexpression(*filter, executor);
+                hasher.input(&s1);
+                FilterCount(filter)
+            }
             Compact(data, data_t, select, select_t) => {
                 let (data, s1) = replace_common_subexpression(*data, executor);
                 let (select, s2) = replace_common_subexpression(*select, executor);
@@ -674,6 +1358,14 @@ fn replace_common_subexpression(plan: QueryPlan, executor: &mut QueryExecutor) -
                 hasher.input(&discriminant_value(&left_type).to_ne_bytes());
                 LessThanVS(left_type, lhs, rhs)
             }
+            GreaterThanVS(left_type, lhs, rhs) => {
+                let (lhs, s1) = replace_common_subexpression(*lhs, executor);
+                let (rhs, s2) = replace_common_subexpression(*rhs, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                hasher.input(&discriminant_value(&left_type).to_ne_bytes());
+                GreaterThanVS(left_type, lhs, rhs)
+            }
             EqualsVS(left_type, lhs, rhs) => {
                 let (lhs, s1) = replace_common_subexpression(*lhs, executor);
                 let (rhs, s2) = replace_common_subexpression(*rhs, executor);
@@ -690,6 +1382,20 @@ fn replace_common_subexpression(plan: QueryPlan, executor: &mut QueryExecutor) -
                 hasher.input(&discriminant_value(&left_type).to_ne_bytes());
                 NotEqualsVS(left_type, lhs, rhs)
             }
+            EqualsNoCaseVS(lhs, rhs) => {
+                let (lhs, s1) = replace_common_subexpression(*lhs, executor);
+                let (rhs, s2) = replace_common_subexpression(*rhs, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                EqualsNoCaseVS(lhs, rhs)
+            }
+            NotEqualsNoCaseVS(lhs, rhs) => {
+                let (lhs, s1) = replace_common_subexpression(*lhs, executor);
+                let (rhs, s2) = replace_common_subexpression(*rhs, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                NotEqualsNoCaseVS(lhs, rhs)
+            }
             DivideVS(lhs, rhs) => {
                 let (lhs, s1) = replace_common_subexpression(*lhs, executor);
                 let (rhs, s2) = replace_common_subexpression(*rhs, executor);
@@ -697,6 +1403,92 @@ fn replace_common_subexpression(plan: QueryPlan, executor: &mut QueryExecutor) -
                 hasher.input(&s2);
                 DivideVS(lhs, rhs)
             }
+            ModuloVS(lhs, rhs) => {
+                let (lhs, s1) = replace_common_subexpression(*lhs, executor);
+                let (rhs, s2) = replace_common_subexpression(*rhs, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                ModuloVS(lhs, rhs)
+            }
+            DivideVV(lhs, rhs) => {
+                let (lhs, s1) = replace_common_subexpression(*lhs, executor);
+                let (rhs, s2) = replace_common_subexpression(*rhs, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                DivideVV(lhs, rhs)
+            }
+            LessThanVV(lhs, rhs) => {
+                let (lhs, s1) = replace_common_subexpression(*lhs, executor);
+                let (rhs, s2) = replace_common_subexpression(*rhs, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                LessThanVV(lhs, rhs)
+            }
+            EqualsVV(lhs, rhs) => {
+                let (lhs, s1) = replace_common_subexpression(*lhs, executor);
+                let (rhs, s2) = replace_common_subexpression(*rhs, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                EqualsVV(lhs, rhs)
+            }
+            NotEqualsVV(lhs, rhs) => {
+                let (lhs, s1) = replace_common_subexpression(*lhs, executor);
+                let (rhs, s2) = replace_common_subexpression(*rhs, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                NotEqualsVV(lhs, rhs)
+            }
+            ModuloVV(lhs, rhs) => {
+                let (lhs, s1) = replace_common_subexpression(*lhs, executor);
+                let (rhs, s2) = replace_common_subexpression(*rhs, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                ModuloVV(lhs, rhs)
+            }
+            DivideSV(lhs, rhs) => {
+                let (lhs, s1) = replace_common_subexpression(*lhs, executor);
+                let (rhs, s2) = replace_common_subexpression(*rhs, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                DivideSV(lhs, rhs)
+            }
+            ModuloSV(lhs, rhs) => {
+                let (lhs, s1) = replace_common_subexpression(*lhs, executor);
+                let (rhs, s2) = replace_common_subexpression(*rhs, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                ModuloSV(lhs, rhs)
+            }
+            NullVec(plan) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                NullVec(plan)
+            }
+            FuseNulls(plan, nulls) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                let (nulls, s2) = replace_common_subexpression(*nulls, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                FuseNulls(plan, nulls)
+            }
+            ConstantBoolVec(plan, value) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                hasher.input(&[value as u8]);
+                ConstantBoolVec(plan, value)
+            }
+            Like(plan, pattern) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                hasher.input_str(&pattern);
+                Like(plan, pattern)
+            }
+            Glob(plan, pattern) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                hasher.input_str(&pattern);
+                Glob(plan, pattern)
+            }
             AddVS(left_type, lhs, rhs) => {
                 let (lhs, s1) = replace_common_subexpression(*lhs, executor);
                 let (rhs, s2) = replace_common_subexpression(*rhs, executor);
@@ -719,16 +1511,77 @@ fn replace_common_subexpression(plan: QueryPlan, executor: &mut QueryExecutor) -
                 hasher.input(&s2);
                 Or(lhs, rhs)
             }
+            Not(plan) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                Not(plan)
+            }
+            IsNull(plan) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                IsNull(plan)
+            }
+            IsNotNull(plan) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                IsNotNull(plan)
+            }
             ToYear(plan) => {
                 let (plan, s1) = replace_common_subexpression(*plan, executor);
                 hasher.input(&s1);
                 ToYear(plan)
             }
-            SortIndices(plan, descending) => {
+            IntToString(plan) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                IntToString(plan)
+            }
+            StringToInt(plan) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                StringToInt(plan)
+            }
+            StringIsNotInt(plan) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                StringIsNotInt(plan)
+            }
+            StringToFloat(plan) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                StringToFloat(plan)
+            }
+            IntToFloat(plan) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                IntToFloat(plan)
+            }
+            CastVals(plan, target) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                hasher.input(&discriminant_value(&target).to_ne_bytes());
+                CastVals(plan, target)
+            }
+            ValToString(plan) => {
+                let (plan, s1) = replace_common_subexpression(*plan, executor);
+                hasher.input(&s1);
+                ValToString(plan)
+            }
+            SortIndices(plan, descending, collation) => {
                 let (plan, s1) = replace_common_subexpression(*plan, executor);
                 hasher.input(&s1);
                 hasher.input(&[descending as u8]);
-                SortIndices(plan, descending)
+                hasher.input(&discriminant_value(&collation).to_ne_bytes());
+                SortIndices(plan, descending, collation)
+            }
+            SortBy(ranking, indices, desc, limit) => {
+                let (ranking, s1) = replace_common_subexpression(*ranking, executor);
+                let (indices, s2) = replace_common_subexpression(*indices, executor);
+                hasher.input(&s1);
+                hasher.input(&s2);
+                hasher.input(&[desc as u8]);
+                hasher.input(&limit.to_ne_bytes());
+                SortBy(ranking, indices, desc, limit)
             }
             TopN(plan, t, n, desc) => {
                 let (plan, s1) = replace_common_subexpression(*plan, executor);
@@ -756,6 +1609,8 @@ fn replace_common_subexpression(plan: QueryPlan, executor: &mut QueryExecutor) -
             Constant(val, show) => {
                 match val {
                     RawVal::Int(i) => hasher.input(&(i as u64).to_ne_bytes()),
+                    RawVal::BigInt(i) => hasher.input(&(i as u128).to_ne_bytes()),
+                    RawVal::Float(x) => hasher.input(&x.to_bits().to_ne_bytes()),
                     RawVal::Str(ref s) => hasher.input_str(s),
                     RawVal::Null => {}
                 }
@@ -904,6 +1759,10 @@ fn try_bitpacking(
             }
             decode_plans.push((decode_plan, plan_type.decoded()));
 
+            // The packed key has to remain a positive i64
+            if total_width + bits(adjusted_max) > 63 {
+                return Ok(None);
+            }
             largest_key += adjusted_max << total_width;
             total_width += bits(adjusted_max);
         } else {
@@ -911,18 +1770,12 @@ fn try_bitpacking(
         }
     }
 
-    Ok(
-        if total_width <= 64 {
-            plan.map(|plan| {
-                decode_plans.reverse();
-                let t = Type::encoded(Codec::opaque(
-                    EncodingType::I64, BasicType::Integer, false, order_preserving, true, true));
-                ((plan, t), largest_key, decode_plans)
-            })
-        } else {
-            None
-        }
-    )
+    Ok(plan.map(|plan| {
+        decode_plans.reverse();
+        let t = Type::encoded(Codec::opaque(
+            EncodingType::I64, BasicType::Integer, false, order_preserving, true, true));
+        ((plan, t), largest_key, decode_plans)
+    }))
 }
 
 fn to_hex_string(bytes: &[u8]) -> String {
@@ -943,3 +1796,25 @@ mod syntax {
         }
     }
 }
+
+/// Returns `prefix` if the `LIKE` pattern is of the form `prefix%` where `prefix` contains no wildcards.
+fn like_prefix(pattern: &str) -> Option<&str> {
+    if pattern.ends_with('%') {
+        let prefix = &pattern[..pattern.len() - 1];
+        if !prefix.contains(|c| c == '%' || c == '_') {
+            return Some(prefix);
+        }
+    }
+    None
+}
+
+/// Returns `prefix` if the `GLOB` pattern is of the form `prefix*` where `prefix` contains no metacharacters.
+fn glob_prefix(pattern: &str) -> Option<&str> {
+    if pattern.ends_with('*') {
+        let prefix = &pattern[..pattern.len() - 1];
+        if !prefix.contains(|c| c == '*' || c == '?' || c == '[') {
+            return Some(prefix);
+        }
+    }
+    None
+}
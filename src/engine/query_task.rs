@@ -1,26 +1,42 @@
 use std::cmp;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::hash::Hasher;
+use std::iter;
 use std::iter::Iterator;
 use std::mem;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
+use std::u64;
+
+use rand;
+use seahash::SeaHasher;
 
 use ::QueryError;
 use QueryResult;
 use engine::aggregator::*;
 use engine::batch_merging::*;
 use engine::query::Query;
+use engine::space_saving::SpaceSaving;
+use engine::types::BasicType;
+use engine::vector_op::hyperloglog;
 use ingest::raw_val::RawVal;
 use mem_store::partition::Partition;
 use mem_store::column::Column;
 use scheduler::*;
 use scheduler::disk_read_scheduler::DiskReadScheduler;
+use syntax::collation::Collation;
 use syntax::expression::*;
+use syntax::limit::{GroupLimit, LimitClause, TableSample};
 use time::precise_time_ns;
 
 
+/// Counters tracked by the summaries of `TOP_K` queries per requested value, which bounds the error of the estimated counts.
+const TOP_K_COUNTERS_PER_VALUE: usize = 10;
+const MIN_TOP_K_COUNTERS: usize = 100;
+
 pub struct QueryTask {
     query: Query,
     explain: bool,
@@ -28,8 +44,15 @@ pub struct QueryTask {
     partitions: Vec<Arc<Partition>>,
     referenced_cols: HashSet<String>,
     output_colnames: Vec<String>,
+    output_coltypes: Vec<BasicType>,
     aggregate: Vec<Aggregator>,
+    aggregate_outputs: Vec<AggregateOutput>,
     start_time_ns: u64,
+    timeout_ns: Option<u64>,
+    cancel: Option<Arc<AtomicBool>>,
+    detailed_stats: bool,
+    /// Fold sums, minimums, maximums and counts into a single pass over the grouping key, see `Options::combine_aggregates`
+    combine_aggregates: bool,
     db: Arc<DiskReadScheduler>,
 
     // Lifetime is not actually static, but tied to the lifetime of this struct.
@@ -42,20 +65,247 @@ pub struct QueryTask {
     sender: SharedSender<QueryResult>,
 }
 
+/// Describes how the value of an aggregate output column is computed from the query results.
+#[derive(Debug, Clone, Copy)]
+pub enum AggregateOutput {
+    /// Computed by the query engine, with intermediate results stored in the select columns starting at the given index.
+    Direct(Aggregator, usize),
+    /// Evaluated from the `AggregateState` in the select column at the given index once all partial results are merged.
+    State(Aggregator, usize),
+}
+
+/// Statistics about the partitions processed by a single thread
+#[derive(Debug, Default)]
+struct PartitionStats {
+    batches: usize,
+    rows_scanned: usize,
+    rows_returned: usize,
+    rows_collected: usize,
+    vector_ops: usize,
+    explains: Vec<String>,
+}
+
 pub struct QueryState<'a> {
     completed_batches: usize,
+    /// Holds at most one result, the results of other threads are merged into it as they complete
     partial_results: Vec<BatchResult<'a>>,
+    /// Number of threads that are merging a result taken from `partial_results`
+    merging: usize,
     explains: Vec<String>,
     rows_scanned: usize,
+    rows_returned: usize,
     rows_collected: usize,
+    vector_ops: usize,
+    breakdown: HashMap<&'static str, u64>,
     colstacks: Vec<Vec<HashMap<String, Arc<Column>>>>,
+    /// Merged summaries of all threads for `TOP_K` queries
+    top_k: Option<SpaceSaving>,
 }
 
+#[derive(Clone)]
 pub struct QueryOutput {
     pub colnames: Vec<String>,
+    /// Type of each column, `Val` for columns that contain values of different types
+    pub coltypes: Vec<BasicType>,
     pub rows: Vec<Vec<RawVal>>,
     pub query_plans: HashMap<String, u32>,
     pub stats: QueryStats,
+    cursor: Option<String>,
+}
+
+impl QueryOutput {
+    pub(crate) fn new(colnames: Vec<String>, coltypes: Vec<BasicType>, rows: Vec<Vec<RawVal>>, stats: QueryStats) -> QueryOutput {
+        QueryOutput { colnames, coltypes, rows, query_plans: HashMap::new(), stats, cursor: None }
+    }
+
+    /// Returns a cursor that retrieves the next page of results when passed to `LocustDB::run_query_after`.
+    /// Only set if the query is ordered by an integer column and returned a full page of rows.
+    pub fn next_cursor(&self) -> Option<&str> {
+        self.cursor.as_ref().map(|cursor| cursor.as_str())
+    }
+
+    /// Appends the rows of `other`, which must have the same number of columns with matching types, as for `UNION ALL`.
+    /// Column names are taken from `self` and statistics are summed, except for the runtime which is the maximum
+    /// of both queries since they run concurrently.
+    pub fn union_all(mut self, other: QueryOutput) -> Result<QueryOutput, QueryError> {
+        if self.colnames.len() != other.colnames.len() {
+            bail!(QueryError::TypeMismatch, "Queries combined by UNION ALL return {} and {} columns",
+                  self.colnames.len(), other.colnames.len());
+        }
+        for (i, &t) in other.coltypes.iter().enumerate() {
+            self.coltypes[i] = match (self.coltypes[i], t) {
+                (t1, t2) if t1 == t2 => t1,
+                (BasicType::Null, t) | (t, BasicType::Null) => t,
+                (BasicType::Val, _) | (_, BasicType::Val) => BasicType::Val,
+                (t1, t2) => bail!(QueryError::TypeMismatch, "Column `{}` has type {:?} and {:?} in queries combined by UNION ALL",
+                                  self.colnames[i], t1, t2),
+            };
+        }
+        self.rows.extend(other.rows);
+        for (plan, count) in other.query_plans {
+            *self.query_plans.entry(plan).or_insert(0) += count;
+        }
+        self.stats.runtime_ns = cmp::max(self.stats.runtime_ns, other.stats.runtime_ns);
+        self.stats.rows_scanned += other.stats.rows_scanned;
+        self.stats.rows_returned += other.stats.rows_returned;
+        self.stats.vector_ops += other.stats.vector_ops;
+        for (phase, ns) in other.stats.breakdown {
+            *self.stats.breakdown.entry(phase).or_insert(0) += ns;
+        }
+        self.cursor = None;
+        Ok(self)
+    }
+
+    /// Sorts the rows by the columns `order_by` (with a flag that is set for descending order), which are resolved
+    /// against the column names of the result, and then applies `limit`. Used for the ORDER BY, LIMIT and OFFSET
+    /// clauses that follow the last query combined by `UNION ALL`. `collation` applies to the first column only.
+    pub fn order_and_limit(mut self, order_by: &[(String, bool)], collation: Collation, limit: &LimitClause)
+                           -> Result<QueryOutput, QueryError> {
+        let mut keys = Vec::with_capacity(order_by.len());
+        for &(ref column, desc) in order_by {
+            match self.column_index(column) {
+                Some(index) => keys.push((index, desc)),
+                None => bail!(QueryError::UnknownColumn, "{}", column),
+            }
+        }
+        if !keys.is_empty() {
+            // Stable sort, rows with equal keys remain in the order of the queries that returned them
+            self.rows.sort_by(|a, b| keys.iter().enumerate().fold(cmp::Ordering::Equal, |order, (i, &(index, desc))| {
+                order.then_with(|| {
+                    let collation = if i == 0 { collation } else { Collation::Binary };
+                    let key_order = compare_values(&a[index], &b[index], collation);
+                    if desc { key_order.reverse() } else { key_order }
+                })
+            }));
+        }
+        let offset = cmp::min(limit.offset, self.rows.len() as u64) as usize;
+        self.rows = self.rows.drain(..).skip(offset).take(limit.limit as usize).collect();
+        Ok(self)
+    }
+
+    /// Returns the index of the column with name `colname`.
+    pub fn column_index(&self, colname: &str) -> Option<usize> {
+        self.colnames.iter().position(|c| c == colname)
+    }
+
+    /// Returns the type of column `colname`, which is known even if the result contains no values of the column.
+    pub fn column_type(&self, colname: &str) -> Option<BasicType> {
+        self.column_index(colname).map(|i| self.coltypes[i])
+    }
+
+    /// Returns the integer in column `colname` of row `row`, or `None` if the value is not an integer.
+    pub fn get_i64(&self, row: usize, colname: &str) -> Option<i64> {
+        match self.get(row, colname) {
+            Some(&RawVal::Int(i)) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// Returns the string in column `colname` of row `row`, or `None` if the value is not a string.
+    pub fn get_str(&self, row: usize, colname: &str) -> Option<&str> {
+        match self.get(row, colname) {
+            Some(&RawVal::Str(ref s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn get(&self, row: usize, colname: &str) -> Option<&RawVal> {
+        let col = self.column_index(colname)?;
+        self.rows.get(row).and_then(|row| row.get(col))
+    }
+
+    /// Serializes column names and rows as CSV with a header line. Null values are written as empty fields.
+    pub fn to_csv(&self) -> String {
+        self.to_csv_with_precision(None)
+    }
+
+    /// Like `to_csv`, but rounds floats to `float_precision` decimal places if given.
+    pub fn to_csv_with_precision(&self, float_precision: Option<usize>) -> String {
+        let mut csv = self.colnames.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(",");
+        csv.push('\n');
+        for row in &self.rows {
+            let fields = row.iter().map(|val| match *val {
+                RawVal::Str(ref s) => csv_field(s),
+                RawVal::Null => String::new(),
+                ref val => format_value(val, float_precision),
+            });
+            csv.push_str(&fields.collect::<Vec<_>>().join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Serializes column names, rows and query statistics as a JSON object. Floats are written with full precision.
+    pub fn to_json(&self) -> String {
+        self.to_json_with_precision(None)
+    }
+
+    /// Like `to_json`, but rounds floats to `float_precision` decimal places if given.
+    pub fn to_json_with_precision(&self, float_precision: Option<usize>) -> String {
+        let colnames = self.colnames.iter().map(|c| json_string(c)).collect::<Vec<_>>();
+        let rows = self.rows.iter()
+            .map(|row| format!("[{}]", row.iter()
+                .map(|val| json_value(val, float_precision))
+                .collect::<Vec<_>>()
+                .join(",")))
+            .collect::<Vec<_>>();
+        format!("{{\"colnames\":[{}],\"rows\":[{}],\"stats\":{{\"runtime_ns\":{},\"rows_scanned\":{},\"rows_returned\":{}}}}}",
+                colnames.join(","),
+                rows.join(","),
+                self.stats.runtime_ns,
+                self.stats.rows_scanned,
+                self.stats.rows_returned)
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn format_value(value: &RawVal, float_precision: Option<usize>) -> String {
+    match float_precision {
+        Some(precision) => format!("{:.*}", precision, value),
+        None => value.to_string(),
+    }
+}
+
+fn compare_values(left: &RawVal, right: &RawVal, collation: Collation) -> cmp::Ordering {
+    match (left, right) {
+        (&RawVal::Str(ref left), &RawVal::Str(ref right)) => collation.cmp(left, right),
+        _ => left.cmp(right),
+    }
+}
+
+fn json_value(value: &RawVal, float_precision: Option<usize>) -> String {
+    match *value {
+        RawVal::Int(i) => i.to_string(),
+        RawVal::BigInt(i) => i.to_string(),
+        RawVal::Float(x) if x.is_finite() => format_value(value, float_precision),
+        RawVal::Float(_) | RawVal::Null => "null".to_string(),
+        RawVal::Str(ref s) => json_string(s),
+    }
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
 }
 
 
@@ -63,6 +313,20 @@ pub struct QueryOutput {
 pub struct QueryStats {
     pub runtime_ns: u64,
     pub rows_scanned: usize,
+    /// Number of scanned rows that passed the filter
+    pub rows_returned: usize,
+    /// Number of vector operators in the query plans of all partitions, excluding the merging of partial results
+    pub vector_ops: usize,
+    /// Total time in ns spent on each phase of query execution, summed over all threads.
+    /// Only populated when `Options::detailed_stats` is set.
+    pub breakdown: HashMap<String, u64>,
+}
+
+impl QueryStats {
+    /// Total time in ns spent on `phase`, or 0 if the phase was not recorded.
+    pub fn phase_ns(&self, phase: &str) -> u64 {
+        self.breakdown.get(phase).cloned().unwrap_or(0)
+    }
 }
 
 impl Default for QueryStats {
@@ -70,6 +334,9 @@ impl Default for QueryStats {
         QueryStats {
             runtime_ns: 0,
             rows_scanned: 0,
+            rows_returned: 0,
+            vector_ops: 0,
+            breakdown: HashMap::new(),
         }
     }
 }
@@ -78,111 +345,238 @@ impl Default for QueryStats {
 impl QueryTask {
     pub fn new(mut query: Query, explain: bool, show: Vec<usize>,
                source: Vec<Arc<Partition>>,
+               timeout_ns: Option<u64>,
+               cancel: Option<Arc<AtomicBool>>,
+               detailed_stats: bool,
+               combine_aggregates: bool,
                db: Arc<DiskReadScheduler>,
-               sender: SharedSender<QueryResult>) -> QueryTask {
+               sender: SharedSender<QueryResult>) -> Result<QueryTask, QueryError> {
         let start_time_ns = precise_time_ns();
+        let source = match query.sample {
+            Some(sample) => sample_partitions(source, sample),
+            None => source,
+        };
         if query.is_select_star() {
             query.select = find_all_cols(&source).into_iter().map(Expr::ColName).collect();
         }
 
         let output_colnames = query.result_column_names();
-        let mut order_by_index = None;
-        if let Some(ref col) = query.order_by {
-            for (i, name) in output_colnames.iter().enumerate() {
-                if name == col {
-                    order_by_index = Some(i);
-                }
-            }
+        let output_coltypes = query.result_column_types(&find_col_types(&source));
+        query.resolve_order_by_indices(&output_colnames)?;
+        let aggregate_outputs = aggregate_outputs(&query.aggregate);
+        if query.distinct && query.aggregate.is_empty() {
+            // Grouping by all select columns collapses duplicate rows, the count itself is not part of the output
+            query.aggregate.push((Aggregator::Count, Expr::Const(RawVal::Int(1)), None));
         }
-        query.order_by_index = order_by_index;
         let referenced_cols = query.find_referenced_cols();
-        let aggregate = query.aggregate.iter().map(|&(aggregate, _)| aggregate).collect();
+        let aggregate = query.aggregate.iter().map(|&(aggregate, _, _)| aggregate).collect();
 
-        QueryTask {
+        Ok(QueryTask {
             query,
             explain,
             show,
             partitions: source,
             referenced_cols,
             output_colnames,
+            output_coltypes,
             aggregate,
+            aggregate_outputs,
             start_time_ns,
+            timeout_ns,
+            cancel,
+            detailed_stats,
+            combine_aggregates,
             db,
 
             unsafe_state: Mutex::new(QueryState {
                 partial_results: Vec::new(),
+                merging: 0,
                 completed_batches: 0,
                 explains: Vec::new(),
                 rows_scanned: 0,
+                rows_returned: 0,
                 rows_collected: 0,
+                vector_ops: 0,
+                breakdown: HashMap::new(),
                 colstacks: Vec::new(),
+                top_k: None,
             }),
             batch_index: AtomicUsize::new(0),
             completed: AtomicBool::new(false),
             sender,
-        }
+        })
     }
 
     pub fn run(&self) {
-        let mut rows_scanned = 0;
-        let mut rows_collected = 0;
-        let mut colstack = Vec::new();
+        if self.partitions.is_empty() {
+            self.push_empty_result();
+            return;
+        }
+        if let Some(k) = self.query.top_k {
+            self.run_top_k(k);
+            return;
+        }
+        let mut stats = PartitionStats::default();
         let mut batch_results = Vec::<BatchResult>::new();
-        let mut explains = Vec::new();
+        let mut breakdown = HashMap::new();
+        let colstack = {
+            let processed = self.run_partitions(&mut stats, &mut breakdown, |mut batch_result, stats, breakdown| {
+                // Merge only with previous batch results of same level to get O(n log n) complexity
+                let phase_start = self.phase_start();
+                while let Some(br) = batch_results.pop() {
+                    if br.level == batch_result.level {
+                        batch_result = combine(br, batch_result, self.combined_limit())?;
+                    } else {
+                        batch_results.push(br);
+                        break;
+                    }
+                }
+                batch_results.push(batch_result);
+                self.record_phase(breakdown, "merge", phase_start);
+                Ok(!self.completed.load(Ordering::SeqCst) && !self.sufficient_rows(stats.rows_collected))
+            });
+            match processed {
+                Ok(colstack) => colstack,
+                Err(error) => {
+                    self.fail_with(error);
+                    return;
+                }
+            }
+        };
+        if self.completed.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let phase_start = self.phase_start();
+        let combined = QueryTask::combine_results(batch_results, self.combined_limit());
+        self.record_phase(&mut breakdown, "merge", phase_start);
+        match combined {
+            Ok(Some(result)) => self.push_result(result, stats.rows_scanned, stats.rows_returned, stats.rows_collected,
+                                                 stats.vector_ops, stats.explains, breakdown),
+            Err(error) => self.fail_with(error),
+            _ => {}
+        }
+        // need to keep colstack alive, otherwise results may reference freed data
+        self.push_colstack(colstack);
+    }
+
+    /// Summarizes the values of each partition with a space saving summary and merges the summaries of all partitions,
+    /// which requires memory proportional to `k` rather than the number of distinct values.
+    fn run_top_k(&self, k: usize) {
+        let capacity = cmp::max(MIN_TOP_K_COUNTERS, TOP_K_COUNTERS_PER_VALUE * k);
+        let mut summary = SpaceSaving::new(capacity);
+        let mut stats = PartitionStats::default();
+        let mut breakdown = HashMap::new();
+        let processed = self.run_partitions(&mut stats, &mut breakdown, |batch_result, _, breakdown| {
+            let phase_start = self.phase_start();
+            let mut batch_summary = SpaceSaving::new(capacity);
+            for i in 0..batch_result.len() {
+                batch_summary.offer(batch_result.select[0].get_raw(i), 1);
+            }
+            summary = mem::replace(&mut summary, SpaceSaving::new(0)).merge(batch_summary);
+            self.record_phase(breakdown, "merge", phase_start);
+            Ok(true)
+        });
+        if let Err(error) = processed {
+            self.fail_with(error);
+            return;
+        }
+        self.push_top_k(summary, k, stats.batches, stats.rows_scanned, stats.rows_returned, stats.vector_ops,
+                        stats.explains, breakdown);
+    }
+
+    /// Runs the query on each partition that has not been claimed by another thread yet and passes the results to
+    /// `process` together with the statistics of all partitions processed so far. `process` returns whether to continue
+    /// with the next partition.
+    /// Returns the columns of all processed partitions, which have to outlive the results.
+    fn run_partitions<'a, F>(&self,
+                             stats: &mut PartitionStats,
+                             breakdown: &mut HashMap<&'static str, u64>,
+                             mut process: F) -> Result<Vec<HashMap<String, Arc<Column>>>, QueryError>
+        where F: FnMut(BatchResult<'a>, &PartitionStats, &mut HashMap<&'static str, u64>) -> Result<bool, QueryError> {
+        let mut colstack = Vec::new();
         while let Some((partition, id)) = self.next_partition() {
+            self.check_aborted(stats.vector_ops)?;
             trace_start!("Batch {}", id);
             let show = self.show.iter().any(|&x| x == id);
+            let phase_start = self.phase_start();
             let cols = partition.get_cols(&self.referenced_cols, &self.db);
-            rows_scanned += cols.iter().next().map_or(0, |c| c.1.len());
-            let (mut batch_result, explain) = match if self.aggregate.is_empty() {
-                self.query.run(unsafe { mem::transmute(&cols) }, self.explain, show, id)
+            self.record_phase(breakdown, "load_columns", phase_start);
+            stats.rows_scanned += partition.len();
+            let phase_start = self.phase_start();
+            let (batch_result, rows_matched, ops, explain) = if self.aggregate.is_empty() {
+                self.query.run(unsafe { mem::transmute(&cols) }, partition.len(), self.explain, show, id,
+                               self.partition_breakdown(breakdown))?
             } else {
-                self.query.run_aggregate(unsafe { mem::transmute(&cols) }, self.explain, show, id)
-            } {
-                Ok(result) => result,
-                Err(error) => {
-                    self.fail_with(error);
-                    return;
-                }
+                self.query.run_aggregate(unsafe { mem::transmute(&cols) }, partition.len(), self.explain, show, id,
+                                         self.combine_aggregates, self.partition_breakdown(breakdown))?
             };
+            self.record_phase(breakdown, "run_partition", phase_start);
             colstack.push(cols);
-            rows_collected += batch_result.len();
+            stats.batches += 1;
+            stats.rows_returned += rows_matched;
+            stats.rows_collected += batch_result.len();
+            stats.vector_ops += ops;
             if let Some(explain) = explain {
-                explains.push(explain);
+                stats.explains.push(explain);
             }
 
-            // Merge only with previous batch results of same level to get O(n log n) complexity
-            while let Some(br) = batch_results.pop() {
-                if br.level == batch_result.level {
-                    match combine(br, batch_result, self.combined_limit()) {
-                        Ok(result) => batch_result = result,
-                        Err(error) => {
-                            self.fail_with(error);
-                            return;
-                        }
-                    };
-                } else {
-                    batch_results.push(br);
-                    break;
-                }
-            }
-            batch_results.push(batch_result);
-
-            if self.completed.load(Ordering::SeqCst) {
-                return;
-            }
-            if self.sufficient_rows(rows_collected) {
+            if !process(batch_result, stats, breakdown)? {
                 break;
             }
         }
+        Ok(colstack)
+    }
 
-        match QueryTask::combine_results(batch_results, self.combined_limit()) {
-            Ok(Some(result)) => self.push_result(result, rows_scanned, rows_collected, explains),
-            Err(error) => self.fail_with(error),
-            _ => {}
+    fn push_top_k(&self,
+                  summary: SpaceSaving,
+                  k: usize,
+                  batches: usize,
+                  rows_scanned: usize,
+                  rows_returned: usize,
+                  vector_ops: usize,
+                  explains: Vec<String>,
+                  breakdown: HashMap<&'static str, u64>) {
+        let mut state = self.unsafe_state.lock().unwrap();
+        if self.completed.load(Ordering::SeqCst) { return; }
+        state.completed_batches += batches;
+        state.explains.extend(explains);
+        state.rows_scanned += rows_scanned;
+        state.rows_returned += rows_returned;
+        state.vector_ops += vector_ops;
+        for (phase, ns) in breakdown {
+            *state.breakdown.entry(phase).or_insert(0) += ns;
         }
-        // need to keep colstack alive, otherwise results may reference freed data
-        self.push_colstack(colstack);
+        let summary = match state.top_k.take() {
+            Some(partial) => partial.merge(summary),
+            None => summary,
+        };
+        if state.completed_batches < self.partitions.len() {
+            state.top_k = Some(summary);
+            return;
+        }
+        let rows = summary.top(k).into_iter()
+            .map(|(value, count)| vec![value, RawVal::Int(count)])
+            .collect();
+        let mut query_plans = HashMap::new();
+        for plan in &state.explains {
+            *query_plans.entry(plan.to_owned()).or_insert(0) += 1
+        }
+        self.sender.send(Ok(QueryOutput {
+            colnames: self.output_colnames.clone(),
+            coltypes: self.output_coltypes.clone(),
+            rows,
+            query_plans,
+            stats: QueryStats {
+                runtime_ns: precise_time_ns() - self.start_time_ns,
+                rows_scanned: state.rows_scanned,
+                rows_returned: state.rows_returned,
+                vector_ops: state.vector_ops,
+                breakdown: state.breakdown.drain().map(|(phase, ns)| (phase.to_string(), ns)).collect(),
+            },
+            cursor: None,
+        }));
+        self.completed.store(true, Ordering::SeqCst);
     }
 
     fn combine_results(batch_results: Vec<BatchResult>, limit: usize) -> Result<Option<BatchResult>, QueryError> {
@@ -197,32 +591,77 @@ impl QueryTask {
         Ok(full_result)
     }
 
-    fn push_result(&self, result: BatchResult, rows_scanned: usize, rows_collected: usize, explains: Vec<String>) {
+    fn push_result(&self,
+                   result: BatchResult,
+                   rows_scanned: usize,
+                   rows_returned: usize,
+                   rows_collected: usize,
+                   vector_ops: usize,
+                   explains: Vec<String>,
+                   breakdown: HashMap<&'static str, u64>) {
+        let mut result = unsafe { mem::transmute::<_, BatchResult<'static>>(result) };
         let mut state = self.unsafe_state.lock().unwrap();
         if self.completed.load(Ordering::SeqCst) { return; }
         state.completed_batches += result.batch_count;
         state.explains.extend(explains);
         state.rows_scanned += rows_scanned;
+        state.rows_returned += rows_returned;
         state.rows_collected += rows_collected;
-        unsafe {
-            let result = mem::transmute::<_, BatchResult<'static>>(result);
-            state.partial_results.push(result);
+        state.vector_ops += vector_ops;
+        for (phase, ns) in breakdown {
+            *state.breakdown.entry(phase).or_insert(0) += ns;
         }
-        if state.completed_batches == self.partitions.len() || self.sufficient_rows(state.rows_collected) {
-            let mut owned_results = Vec::with_capacity(0);
-            mem::swap(&mut owned_results, &mut state.partial_results);
-            // TODO(clemens): Handle empty table
-            let full_result = match QueryTask::combine_results(owned_results, self.combined_limit()) {
-                Ok(result) => result.unwrap(),
+        // Every worker thread merges its result with the result of another thread until a single result is left.
+        // The lock is released while merging, so the results of different threads are merged in parallel.
+        loop {
+            let partial = match state.partial_results.pop() {
+                Some(partial) => partial,
+                None => break,
+            };
+            state.merging += 1;
+            drop(state);
+            let phase_start = self.phase_start();
+            let merged = combine(partial, result, self.combined_limit());
+            state = self.unsafe_state.lock().unwrap();
+            state.merging -= 1;
+            self.record_phase(&mut state.breakdown, "final_merge", phase_start);
+            if self.completed.load(Ordering::SeqCst) { return; }
+            result = match merged {
+                Ok(merged) => merged,
                 Err(error) => {
                     self.fail_with_no_lock(error);
                     return;
                 }
             };
-            let final_result = self.convert_to_output_format(&full_result, state.rows_scanned, &state.explains);
-            self.sender.send(Ok(final_result));
-            self.completed.store(true, Ordering::SeqCst);
         }
+        let sufficient = state.completed_batches == self.partitions.len() || self.sufficient_rows(state.rows_collected);
+        // Threads that are still merging will pick up this result once they are done
+        if state.merging > 0 || !sufficient {
+            state.partial_results.push(result);
+            return;
+        }
+        let breakdown = mem::replace(&mut state.breakdown, HashMap::new());
+        let final_result = self.convert_to_output_format(&result, state.rows_scanned, state.rows_returned,
+                                                         state.vector_ops, &state.explains, breakdown);
+        self.sender.send(Ok(final_result));
+        self.completed.store(true, Ordering::SeqCst);
+    }
+
+    fn push_empty_result(&self) {
+        let _state = self.unsafe_state.lock().unwrap();
+        if self.completed.load(Ordering::SeqCst) { return; }
+        self.sender.send(Ok(QueryOutput {
+            colnames: self.output_colnames.clone(),
+            coltypes: self.output_coltypes.clone(),
+            rows: empty_result_rows(&self.query, &self.aggregate_outputs, self.output_colnames.len()),
+            query_plans: HashMap::new(),
+            stats: QueryStats {
+                runtime_ns: precise_time_ns() - self.start_time_ns,
+                ..QueryStats::default()
+            },
+            cursor: None,
+        }));
+        self.completed.store(true, Ordering::SeqCst);
     }
 
     fn push_colstack(&self, colstack: Vec<HashMap<String, Arc<Column>>>) {
@@ -242,6 +681,35 @@ impl QueryTask {
         self.sender.send(Err(error));
     }
 
+    fn phase_start(&self) -> u64 {
+        if self.detailed_stats { precise_time_ns() } else { 0 }
+    }
+
+    fn partition_breakdown<'b>(&self, breakdown: &'b mut HashMap<&'static str, u64>) -> Option<&'b mut HashMap<&'static str, u64>> {
+        if self.detailed_stats { Some(breakdown) } else { None }
+    }
+
+    fn record_phase(&self, breakdown: &mut HashMap<&'static str, u64>, phase: &'static str, start_ns: u64) {
+        if self.detailed_stats {
+            *breakdown.entry(phase).or_insert(0) += precise_time_ns() - start_ns;
+        }
+    }
+
+    fn check_aborted(&self, vector_ops: usize) -> Result<(), QueryError> {
+        if let Some(ref cancel) = self.cancel {
+            if cancel.load(Ordering::SeqCst) {
+                return Err(QueryError::Cancelled(vector_ops));
+            }
+        }
+        if let Some(timeout_ns) = self.timeout_ns {
+            let elapsed_ns = precise_time_ns() - self.start_time_ns;
+            if elapsed_ns > timeout_ns {
+                bail!(QueryError::Timeout, "exceeded limit of {}ns after {}ns", timeout_ns, elapsed_ns);
+            }
+        }
+        Ok(())
+    }
+
     fn sufficient_rows(&self, rows_collected: usize) -> bool {
         let unordered_select = self.query.aggregate.is_empty() && self.query.order_by.is_none();
         unordered_select && self.combined_limit() < rows_collected
@@ -255,22 +723,70 @@ impl QueryTask {
     fn convert_to_output_format(&self,
                                 full_result: &BatchResult,
                                 rows_scanned: usize,
-                                explains: &[String]) -> QueryOutput {
-        let limit = self.query.limit.limit as usize;
-        let offset = self.query.limit.offset as usize;
-        let mut result_rows = Vec::new();
-        let count = cmp::min(limit, full_result.len() - offset);
-        for i in offset..(count + offset) {
-            let mut record = Vec::with_capacity(self.output_colnames.len());
-            if let Some(ref gs) = full_result.group_by {
-                for g in gs {
-                    record.push(g.get_raw(i));
+                                rows_returned: usize,
+                                vector_ops: usize,
+                                explains: &[String],
+                                mut breakdown: HashMap<&'static str, u64>) -> QueryOutput {
+        let phase_start = self.phase_start();
+        // With a limit per group, the offset and limit apply to the rows that remain after limiting each group
+        let (limit, offset) = match self.query.group_limit {
+            Some(_) => (usize::MAX, 0),
+            None => (self.query.limit.limit as usize, self.query.limit.offset),
+        };
+        // Offsets beyond the end of the result yield no rows
+        let offset = cmp::min(offset, full_result.len() as u64) as usize;
+        // Aggregation results and rows ordered by multiple columns are ordered only after all rows have been computed
+        let (skip, take) = if self.query.sorts_result_rows() || self.query.rollup { (0, usize::MAX) } else { (offset, limit) };
+        // First row that follows the returned rows, if the query retains it to create a cursor
+        let mut next_row = None;
+        let mut result_rows = if self.aggregate.is_empty() {
+            let record = |i: usize| {
+                let mut record = Vec::with_capacity(self.output_colnames.len());
+                if let Some(ref gs) = full_result.group_by {
+                    for g in gs {
+                        record.push(g.get_raw(i));
+                    }
                 }
+                for col in &full_result.select {
+                    record.push(col.get_raw(i));
+                }
+                record
+            };
+            let count = cmp::min(take, full_result.len() - skip);
+            if count + skip < full_result.len() {
+                next_row = Some(record(count + skip));
             }
-            for col in &full_result.select {
-                record.push(col.get_raw(i));
-            }
-            result_rows.push(record);
+            (skip..(count + skip)).map(record).collect::<Vec<_>>()
+        } else if self.query.rollup {
+            self.rollup_rows(full_result)
+        } else {
+            (0..full_result.len())
+                .skip(skip)
+                .take(take)
+                .map(|i| {
+                    let group = full_result.group_by.iter().flat_map(|gs| gs.iter().map(|g| g.get_raw(i))).collect();
+                    let intermediate = full_result.select.iter().map(|col| col.get_raw(i)).collect::<Vec<_>>();
+                    let states = row_states(&self.aggregate_outputs, full_result, i);
+                    output_row(&self.query, &self.aggregate_outputs, group, &intermediate, &states)
+                })
+                .collect()
+        };
+        if self.query.sorts_result_rows() {
+            sort_rows(&mut result_rows, &self.query, self.output_colnames.len() - self.aggregate_outputs.len());
+            result_rows = result_rows.into_iter().skip(offset).take(limit).collect();
+        } else if self.query.rollup {
+            result_rows = result_rows.into_iter().skip(offset).take(limit).collect();
+        }
+        if let Some(ref group_limit) = self.query.group_limit {
+            result_rows = self.limit_per_group(result_rows, group_limit);
+        }
+        let cursor = match (self.query.order_by_index, result_rows.last()) {
+            (Some(index), Some(last)) if result_rows.len() == limit =>
+                self.query.cursor_after(&last[index], next_row.as_ref().map(|next| &next[index])),
+            _ => None,
+        };
+        if result_rows.is_empty() {
+            result_rows = empty_result_rows(&self.query, &self.aggregate_outputs, self.output_colnames.len());
         }
 
         let mut query_plans = HashMap::new();
@@ -278,19 +794,96 @@ impl QueryTask {
             *query_plans.entry(plan.to_owned()).or_insert(0) += 1
         }
 
+        self.record_phase(&mut breakdown, "convert_output", phase_start);
+
         QueryOutput {
             colnames: self.output_colnames.clone(),
+            coltypes: self.output_coltypes.clone(),
             rows: result_rows,
             query_plans,
             stats: QueryStats {
                 runtime_ns: precise_time_ns() - self.start_time_ns,
                 rows_scanned,
+                rows_returned,
+                vector_ops,
+                breakdown: breakdown.into_iter().map(|(phase, ns)| (phase.to_string(), ns)).collect(),
             },
+            cursor,
         }
     }
 
+    /// Aggregation result rows followed by a subtotal row after all groups that share the same first `k` group by columns,
+    /// for each `k` from the number of group by columns minus one down to zero. Subtotal rows set the remaining
+    /// group by columns to null, the final row with all group by columns set to null is the grand total.
+    fn rollup_rows(&self, full_result: &BatchResult) -> Vec<Vec<RawVal>> {
+        let groups = (0..full_result.len())
+            .map(|i| {
+                let group = full_result.group_by.iter().flat_map(|gs| gs.iter().map(|g| g.get_raw(i))).collect();
+                let intermediate = full_result.select.iter().map(|col| col.get_raw(i)).collect::<Vec<_>>();
+                (group, (intermediate, row_states(&self.aggregate_outputs, full_result, i)))
+            })
+            .collect::<BTreeMap<Vec<RawVal>, (Vec<RawVal>, Vec<AggregateState>)>>();
+        let aggregators = self.aggregate.iter()
+            .flat_map(|&aggregator| iter::repeat(aggregator).take(aggregator.intermediate_columns()))
+            .collect::<Vec<_>>();
+        let group_columns = self.output_colnames.len() - self.aggregate_outputs.len();
+        // Combined results of the groups that share the first `k` group by columns with the current group, at index `k`
+        let mut subtotals: Vec<Option<(Vec<RawVal>, Vec<RawVal>, Vec<AggregateState>)>> = vec![None; group_columns];
+        let mut rows = Vec::with_capacity(groups.len() + group_columns);
+        for (group, (intermediate, states)) in groups {
+            for k in (0..group_columns).rev() {
+                if subtotals[k].as_ref().map_or(false, |&(ref prefix, _, _)| prefix[..] != group[..k]) {
+                    rows.push(self.subtotal_row(subtotals[k].take().unwrap(), group_columns));
+                }
+            }
+            for k in 0..group_columns {
+                if let Some((_, ref mut accumulators, ref mut combined)) = subtotals[k] {
+                    combine_intermediate(accumulators, &intermediate, &aggregators);
+                    merge_states(combined, states.clone());
+                    continue;
+                }
+                subtotals[k] = Some((group[..k].to_vec(), intermediate.clone(), states.clone()));
+            }
+            rows.push(output_row(&self.query, &self.aggregate_outputs, group, &intermediate, &states));
+        }
+        for k in (0..group_columns).rev() {
+            if let Some(subtotal) = subtotals[k].take() {
+                rows.push(self.subtotal_row(subtotal, group_columns));
+            }
+        }
+        rows
+    }
+
+    fn subtotal_row(&self,
+                    (prefix, intermediate, states): (Vec<RawVal>, Vec<RawVal>, Vec<AggregateState>),
+                    group_columns: usize) -> Vec<RawVal> {
+        let mut group = prefix;
+        group.resize(group_columns, RawVal::Null);
+        output_row(&self.query, &self.aggregate_outputs, group, &intermediate, &states)
+    }
+
+    /// Keeps the first `group_limit.limit` rows of each group and then applies the offset and limit of the query.
+    fn limit_per_group(&self, rows: Vec<Vec<RawVal>>, group_limit: &GroupLimit) -> Vec<Vec<RawVal>> {
+        let key_columns = group_limit.columns.iter()
+            .filter_map(|column| self.output_colnames.iter().position(|name| name == column))
+            .collect::<Vec<_>>();
+        let mut counts = HashMap::new();
+        rows.into_iter()
+            .filter(|row| {
+                let key = key_columns.iter().map(|&i| row[i].clone()).collect::<Vec<_>>();
+                let count = counts.entry(key).or_insert(0u64);
+                *count += 1;
+                *count <= group_limit.limit
+            })
+            .skip(self.query.limit.offset as usize)
+            .take(self.query.limit.limit as usize)
+            .collect()
+    }
+
     fn combined_limit(&self) -> usize {
-        (self.query.limit.limit + self.query.limit.offset) as usize
+        // Subtotals include all groups
+        if self.query.sorts_result_rows() || self.query.rollup { return usize::MAX; }
+        self.query.row_limit()
     }
 }
 
@@ -303,7 +896,158 @@ impl Task for QueryTask {
     fn multithreaded(&self) -> bool { true }
 }
 
-fn find_all_cols(source: &[Arc<Partition>]) -> Vec<String> {
+/// Aggregate states of row `i` of `result`, in the order of the aggregate `outputs` that are evaluated from them.
+pub fn row_states(outputs: &[AggregateOutput], result: &BatchResult, i: usize) -> Vec<AggregateState> {
+    outputs.iter()
+        .filter_map(|output| match *output {
+            AggregateOutput::Direct(_, _) => None,
+            AggregateOutput::State(_, index) => Some(result.select[index].cast_ref_aggregate_states()[i].clone()),
+        })
+        .collect()
+}
+
+/// Appends the values of the aggregate `outputs` of a result row to `record`, which holds its group by columns.
+pub fn output_row(query: &Query,
+                  outputs: &[AggregateOutput],
+                  mut record: Vec<RawVal>,
+                  intermediate: &[RawVal],
+                  states: &[AggregateState]) -> Vec<RawVal> {
+    let mut states = states.iter();
+    for output in outputs {
+        match *output {
+            AggregateOutput::Direct(aggregator @ Aggregator::ApproxCountDistinct(_), start) => {
+                let packed = intermediate[start..(start + aggregator.intermediate_columns())]
+                    .iter()
+                    .map(|packed| match *packed {
+                        RawVal::Int(packed) => packed,
+                        _ => 0,
+                    })
+                    .collect::<Vec<_>>();
+                record.push(RawVal::Int(hyperloglog::estimate_cardinality(&hyperloglog::unpack_registers(&packed))));
+            }
+            AggregateOutput::Direct(aggregator @ Aggregator::Variance, start) |
+            AggregateOutput::Direct(aggregator @ Aggregator::StdDev, start) => {
+                let moments = Moments::from_raw(&intermediate[start..(start + aggregator.intermediate_columns())]);
+                record.push(moments.evaluate(aggregator));
+            }
+            AggregateOutput::Direct(Aggregator::Count, index) |
+            AggregateOutput::Direct(Aggregator::Sum, index) => {
+                let value = intermediate[index].clone();
+                record.push(match query.sample {
+                    Some(sample) => scale_to_population(value, sample),
+                    None => value,
+                });
+            }
+            AggregateOutput::Direct(_, index) => record.push(intermediate[index].clone()),
+            AggregateOutput::State(aggregator, _) => record.push(states.next().unwrap().evaluate(aggregator)),
+        }
+    }
+    record
+}
+
+/// Sorts `rows` by the ORDER BY columns of `query`, see `Query::sorts_result_rows`.
+/// Remaining ties are broken by the first `group_columns` columns (or all columns, if there are no aggregates)
+/// in ascending order, independently of the order in which partial results were merged.
+pub fn sort_rows(rows: &mut [Vec<RawVal>], query: &Query, group_columns: usize) {
+    let keys = query.order_by_index.map(|index| (index, query.order_desc)).into_iter()
+        .chain(query.then_order_by_indices.iter().cloned())
+        .collect::<Vec<_>>();
+    let collation = query.collation;
+    rows.sort_by(|a, b| {
+        let order = keys.iter().enumerate().fold(cmp::Ordering::Equal, |order, (i, &(index, desc))| {
+            order.then_with(|| {
+                let collation = if i == 0 { collation } else { Collation::Binary };
+                let key_order = compare_values(&a[index], &b[index], collation);
+                if desc { key_order.reverse() } else { key_order }
+            })
+        });
+        order.then_with(|| a[..group_columns].cmp(&b[..group_columns]))
+    });
+}
+
+/// Result rows of a query with `output_columns` columns if no rows passed the filter.
+/// Aggregates without group by columns always return exactly one row.
+pub fn empty_result_rows(query: &Query, outputs: &[AggregateOutput], output_columns: usize) -> Vec<Vec<RawVal>> {
+    let global_aggregate = !outputs.is_empty() && output_columns == outputs.len();
+    if global_aggregate && query.limit.offset == 0 && query.limit.limit > 0 {
+        vec![outputs.iter().map(|output| match *output {
+            AggregateOutput::Direct(Aggregator::Count, _) |
+            AggregateOutput::Direct(Aggregator::ApproxCountDistinct(_), _) => RawVal::Int(0),
+            _ => RawVal::Null,
+        }).collect()]
+    } else {
+        vec![]
+    }
+}
+
+/// Combines the intermediate results of `aggregators` for two disjoint sets of rows of the same group into `accumulators`.
+fn combine_intermediate(accumulators: &mut [RawVal], intermediate: &[RawVal], aggregators: &[Aggregator]) {
+    let mut i = 0;
+    while i < aggregators.len() {
+        let aggregator = aggregators[i];
+        if aggregator == Aggregator::Variance || aggregator == Aggregator::StdDev {
+            let end = i + aggregator.intermediate_columns();
+            let merged = Moments::from_raw(&accumulators[i..end]).merge(Moments::from_raw(&intermediate[i..end]));
+            accumulators[i..end].clone_from_slice(&merged.to_raw());
+            i = end;
+            continue;
+        }
+        if let (Some(acc), Some(elem)) = (accumulators[i].as_i128(), intermediate[i].as_i128()) {
+            accumulators[i] = RawVal::from_i128(aggregator.combine_i128(acc, elem));
+        }
+        i += 1;
+    }
+}
+
+/// Merges the aggregate states of two disjoint sets of rows of the same group into `accumulators`.
+fn merge_states(accumulators: &mut [AggregateState], states: Vec<AggregateState>) {
+    for (accumulator, state) in accumulators.iter_mut().zip(states) {
+        accumulator.merge(state);
+    }
+}
+
+/// Describes how each aggregate output column is computed from the intermediate results in the select columns.
+pub fn aggregate_outputs(aggregate: &[(Aggregator, Expr, Option<Expr>)]) -> Vec<AggregateOutput> {
+    let mut start = 0;
+    aggregate.iter()
+        .map(|&(aggregator, _, _)| {
+            let output = if aggregator.has_state() {
+                AggregateOutput::State(aggregator, start)
+            } else {
+                AggregateOutput::Direct(aggregator, start)
+            };
+            start += aggregator.intermediate_columns();
+            output
+        })
+        .collect()
+}
+
+/// Selects each partition with probability `sample.fraction`.
+/// The selection only depends on the seed and partition id, so repeated queries with the same seed scan the same partitions.
+fn sample_partitions(source: Vec<Arc<Partition>>, sample: TableSample) -> Vec<Arc<Partition>> {
+    let seed = sample.seed.unwrap_or_else(rand::random);
+    source.into_iter()
+        .filter(|partition| {
+            let mut hasher = SeaHasher::new();
+            hasher.write_u64(seed);
+            hasher.write_u64(partition.id());
+            hasher.finish() as f64 <= sample.fraction * u64::MAX as f64
+        })
+        .collect()
+}
+
+/// Extrapolates a count or sum over sampled partitions to the full table.
+fn scale_to_population(value: RawVal, sample: TableSample) -> RawVal {
+    match value {
+        RawVal::Int(i) => RawVal::Int((i as f64 / sample.fraction).round() as i64),
+        RawVal::BigInt(i) => RawVal::from_i128((i as f64 / sample.fraction).round() as i128),
+        RawVal::Float(f) => RawVal::Float(f / sample.fraction),
+        value => value,
+    }
+}
+
+/// Returns the names of all columns in `source`.
+pub fn find_all_cols(source: &[Arc<Partition>]) -> Vec<String> {
     let mut cols = HashSet::new();
     for partition in source {
         for name in partition.col_names() {
@@ -313,3 +1057,34 @@ fn find_all_cols(source: &[Arc<Partition>]) -> Vec<String> {
 
     cols.into_iter().collect()
 }
+
+/// Returns the decoded type of all resident columns in `source`.
+/// Columns with different types in different partitions have type `BasicType::Val`.
+pub fn find_col_types(source: &[Arc<Partition>]) -> HashMap<String, BasicType> {
+    let mut types = HashMap::new();
+    for partition in source {
+        for (colname, t) in partition.col_types() {
+            if let Some(t) = t {
+                let unified = match types.get(colname).cloned() {
+                    None => t,
+                    Some(BasicType::Null) => t,
+                    Some(t1) if t1 == t || t == BasicType::Null => t1,
+                    Some(_) => BasicType::Val,
+                };
+                types.insert(colname.to_string(), unified);
+            }
+        }
+    }
+    types
+}
+
+/// Returns the name of a column referenced by `query` that does not exist in any of the partitions in `source`.
+pub fn find_unknown_col(query: &Query, source: &[Arc<Partition>]) -> Option<String> {
+    if source.is_empty() { return None; }
+    let known_cols = find_all_cols(source).into_iter().collect::<HashSet<_>>();
+    let mut unknown_cols = query.find_referenced_cols().into_iter()
+        .filter(|col| col != "*" && !known_cols.contains(col))
+        .collect::<Vec<_>>();
+    unknown_cols.sort();
+    unknown_cols.into_iter().next()
+}
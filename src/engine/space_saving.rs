@@ -0,0 +1,127 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+
+use ingest::raw_val::RawVal;
+
+
+/// Space-Saving summary (Metwally et al.) that estimates the counts of the most frequent values using at most
+/// `capacity` counters. Every value that occurs more than `total / capacity` times is guaranteed to be tracked,
+/// and estimated counts exceed the true counts by at most the count of the smallest counter.
+#[derive(Debug, Clone)]
+pub struct SpaceSaving {
+    capacity: usize,
+    counters: HashMap<RawVal, i64>,
+    /// Counters ordered by increasing count and decreasing value, which finds the counter to evict in O(log capacity)
+    by_count: BTreeSet<(i64, Reverse<RawVal>)>,
+}
+
+impl SpaceSaving {
+    pub fn new(capacity: usize) -> SpaceSaving {
+        SpaceSaving {
+            capacity,
+            counters: HashMap::with_capacity(capacity),
+            by_count: BTreeSet::new(),
+        }
+    }
+
+    /// Records `count` occurrences of `value`, replacing the smallest counter if `value` is not tracked yet
+    /// and all counters are in use.
+    pub fn offer(&mut self, value: RawVal, count: i64) {
+        let current = self.counters.get(&value).cloned();
+        if let Some(c) = current {
+            self.by_count.remove(&(c, Reverse(value.clone())));
+            self.insert(value, c + count);
+            return;
+        }
+        if self.counters.len() < self.capacity {
+            self.insert(value, count);
+            return;
+        }
+        if let Some((min, evicted)) = self.min_counter() {
+            self.by_count.remove(&(min, Reverse(evicted.clone())));
+            self.counters.remove(&evicted);
+            self.insert(value, min + count);
+        }
+    }
+
+    /// Combines the summaries of two disjoint sets of values (Agarwal et al.).
+    /// Values missing from a full summary may have occurred up to as often as its smallest counter.
+    pub fn merge(self, other: SpaceSaving) -> SpaceSaving {
+        let capacity = self.capacity.max(other.capacity);
+        let missing_self = self.missing_count();
+        let missing_other = other.missing_count();
+        let mut counters = HashMap::with_capacity(self.counters.len() + other.counters.len());
+        for (value, &count) in &self.counters {
+            counters.insert(value.clone(), count + other.counters.get(value).cloned().unwrap_or(missing_other));
+        }
+        for (value, &count) in &other.counters {
+            if !self.counters.contains_key(value) {
+                counters.insert(value.clone(), count + missing_self);
+            }
+        }
+        let mut merged = SpaceSaving::new(capacity);
+        for (value, count) in SpaceSaving::sorted(counters).into_iter().take(capacity) {
+            merged.insert(value, count);
+        }
+        merged
+    }
+
+    /// The `k` values with the largest estimated counts, ordered by decreasing count and then by value.
+    pub fn top(&self, k: usize) -> Vec<(RawVal, i64)> {
+        self.by_count.iter().rev()
+            .take(k)
+            .map(|&(count, Reverse(ref value))| (value.clone(), count))
+            .collect()
+    }
+
+    fn insert(&mut self, value: RawVal, count: i64) {
+        self.by_count.insert((count, Reverse(value.clone())));
+        self.counters.insert(value, count);
+    }
+
+    fn sorted(counters: HashMap<RawVal, i64>) -> Vec<(RawVal, i64)> {
+        let mut counters = counters.into_iter().collect::<Vec<_>>();
+        counters.sort_by(|&(ref v1, c1), &(ref v2, c2)| c2.cmp(&c1).then_with(|| v1.cmp(v2)));
+        counters
+    }
+
+    fn missing_count(&self) -> i64 {
+        if self.counters.len() < self.capacity { 0 } else { self.min_counter().map_or(0, |(count, _)| count) }
+    }
+
+    fn min_counter(&self) -> Option<(i64, RawVal)> {
+        self.by_count.iter().next().map(|&(count, Reverse(ref value))| (count, value.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offer_replaces_smallest_counter() {
+        let mut summary = SpaceSaving::new(2);
+        summary.offer(RawVal::Int(1), 3);
+        summary.offer(RawVal::Int(2), 1);
+        summary.offer(RawVal::Int(1), 1);
+        summary.offer(RawVal::Int(3), 1);
+        summary.offer(RawVal::Int(4), 2);
+        assert_eq!(summary.top(2), vec![(RawVal::Int(1), 4), (RawVal::Int(4), 4)]);
+    }
+
+    #[test]
+    fn test_merge_keeps_heavy_hitters() {
+        let mut left = SpaceSaving::new(3);
+        let mut right = SpaceSaving::new(3);
+        for i in 0..100 {
+            left.offer(RawVal::Int(i % 10), 1);
+            left.offer(RawVal::Int(100), 1);
+            right.offer(RawVal::Int(10 + i % 10), 1);
+            right.offer(RawVal::Int(200), 2);
+        }
+        let top = left.merge(right).top(2);
+        assert_eq!(top.iter().map(|&(ref value, _)| value.clone()).collect::<Vec<_>>(), vec![RawVal::Int(200), RawVal::Int(100)]);
+        assert!(top[0].1 >= 200);
+        assert!(top[1].1 >= 100);
+    }
+}
@@ -10,8 +10,8 @@ use std::io::Cursor;
 use num::PrimInt;
 use byteorder::{NativeEndian, ReadBytesExt};
 
+use engine::aggregator::AggregateState;
 use engine::types::*;
-use heapsize::HeapSizeOf;
 use ingest::raw_val::RawVal;
 use itertools::Itertools;
 use mem_store::value::Val;
@@ -33,6 +33,7 @@ pub trait AnyVec<'a>: Send + Sync {
     fn cast_ref_str<'b>(&'b self) -> &'b [&'a str] { panic!(self.type_error("cast_ref_str")) }
     fn cast_ref_usize(&self) -> &[usize] { panic!(self.type_error("cast_ref_usize")) }
     fn cast_ref_i64(&self) -> &[i64] { panic!(self.type_error("cast_ref_i64")) }
+    fn cast_ref_i128(&self) -> &[i128] { panic!(self.type_error("cast_ref_i128")) }
     fn cast_ref_u64(&self) -> &[u64] { panic!(self.type_error("cast_ref_u64")) }
     fn cast_ref_u32(&self) -> &[u32] { panic!(self.type_error("cast_ref_u32")) }
     fn cast_ref_u16(&self) -> &[u16] { panic!(self.type_error("cast_ref_u16")) }
@@ -43,10 +44,12 @@ pub trait AnyVec<'a>: Send + Sync {
     fn cast_str_const(&self) -> string::String { panic!(self.type_error("cast_str_const")) }
     fn cast_i64_const(&self) -> i64 { panic!(self.type_error("cast_i64_const")) }
     fn cast_ref_byte_slices(&self) -> &ByteSlices<'a> { panic!(self.type_error("cast_ref_byte_slices")) }
+    fn cast_ref_aggregate_states(&self) -> &[AggregateState] { panic!(self.type_error("cast_ref_aggregate_states")) }
 
     fn cast_ref_mut_str(&mut self) -> &mut Vec<&'a str> { panic!(self.type_error("cast_ref_mut_str")) }
     fn cast_ref_mut_usize(&mut self) -> &mut Vec<usize> { panic!(self.type_error("cast_ref_mut_usize")) }
     fn cast_ref_mut_i64(&mut self) -> &mut Vec<i64> { panic!(self.type_error("cast_ref_mut_i64")) }
+    fn cast_ref_mut_i128(&mut self) -> &mut Vec<i128> { panic!(self.type_error("cast_ref_mut_i128")) }
     fn cast_ref_mut_u64(&mut self) -> &mut Vec<u64> { panic!(self.type_error("cast_ref_mut_u64")) }
     fn cast_ref_mut_u32(&mut self) -> &mut Vec<u32> { panic!(self.type_error("cast_ref_mut_u32")) }
     fn cast_ref_mut_u16(&mut self) -> &mut Vec<u16> { panic!(self.type_error("cast_ref_mut_u16")) }
@@ -55,9 +58,13 @@ pub trait AnyVec<'a>: Send + Sync {
     fn cast_ref_mut_merge_op(&mut self) -> &mut Vec<MergeOp> { panic!(self.type_error("cast_ref_mut_merge_op")) }
     fn cast_ref_mut_premerge(&mut self) -> &mut Vec<Premerge> { panic!(self.type_error("cast_ref_mut_premerge_op")) }
     fn cast_ref_mut_byte_slices(&mut self) -> &mut ByteSlices<'a> { panic!(self.type_error("cast_ref_mut_byte_slices")) }
+    fn cast_ref_mut_aggregate_states(&mut self) -> &mut Vec<AggregateState> { panic!(self.type_error("cast_ref_mut_aggregate_states")) }
 
     fn to_mixed(&self) -> Vec<Val<'a>> { panic!(self.type_error("to_mixed")) }
 
+    /// Number of bytes allocated by this vector, not counting data it references.
+    fn heap_size_bytes(&self) -> usize { 0 }
+
     fn display(&self) -> String;
 }
 
@@ -84,6 +91,7 @@ impl<'a, T: GenericVec<T> + 'a> AnyVec<'a> for Vec<T> {
     }
 
     fn type_error(&self, func_name: &str) -> String { format!("Vec<{:?}>.{}", T::t(), func_name) }
+    fn heap_size_bytes(&self) -> usize { self.capacity() * mem::size_of::<T>() }
 
     default fn append_all(&mut self, other: &AnyVec<'a>, count: usize) -> Option<BoxedVec<'a>> {
         if other.get_type() != self.get_type() {
@@ -115,6 +123,7 @@ impl<'a> AnyVec<'a> for Vec<&'a str> {
 impl<'a> AnyVec<'a> for Vec<Val<'a>> {
     fn cast_ref_mixed<'b>(&'b self) -> &'b [Val<'a>] { self }
     fn cast_ref_mut_mixed<'b>(&'b mut self) -> &'b mut Vec<Val<'a>> { self }
+    fn to_mixed(&self) -> Vec<Val<'a>> { self.clone() }
 
     fn append_all(&mut self, other: &AnyVec<'a>, count: usize) -> Option<BoxedVec<'a>> {
         if other.get_type() == EncodingType::Val {
@@ -139,6 +148,11 @@ impl<'a> AnyVec<'a> for Vec<i64> {
     }
 }
 
+impl<'a> AnyVec<'a> for Vec<i128> {
+    fn cast_ref_i128(&self) -> &[i128] { self }
+    fn cast_ref_mut_i128(&mut self) -> &mut Vec<i128> { self }
+}
+
 impl<'a> AnyVec<'a> for Vec<u64> {
     fn cast_ref_u64(&self) -> &[u64] { self }
     fn cast_ref_mut_u64(&mut self) -> &mut Vec<u64> { self }
@@ -170,6 +184,32 @@ impl<'a> AnyVec<'a> for Vec<Premerge> {
 }
 
 
+impl<'a> AnyVec<'a> for Vec<AggregateState> {
+    fn len(&self) -> usize { Vec::len(self) }
+    // States are read with `cast_ref_aggregate_states` and evaluated after merging all partial results
+    fn get_raw(&self, i: usize) -> RawVal {
+        assert!(i < self.len());
+        RawVal::Null
+    }
+    fn get_type(&self) -> EncodingType { EncodingType::AggregateState }
+    fn sort_indices_desc(&self, _indices: &mut Vec<usize>) { panic!(self.type_error("sort_indices_desc")) }
+    fn sort_indices_asc(&self, _indices: &mut Vec<usize>) { panic!(self.type_error("sort_indices_asc")) }
+    fn type_error(&self, func_name: &str) -> String { format!("Vec<AggregateState>.{}", func_name) }
+    fn append_all(&mut self, _other: &AnyVec<'a>, _count: usize) -> Option<BoxedVec<'a>> {
+        panic!(self.type_error("append_all"))
+    }
+    fn slice_box<'b>(&'b self, from: usize, to: usize) -> BoxedVec<'b> where 'a: 'b {
+        let to = min(to, self.len());
+        Box::new(self[from..to].to_vec())
+    }
+
+    fn cast_ref_aggregate_states(&self) -> &[AggregateState] { self }
+    fn cast_ref_mut_aggregate_states(&mut self) -> &mut Vec<AggregateState> { self }
+    fn heap_size_bytes(&self) -> usize { self.capacity() * mem::size_of::<AggregateState>() }
+
+    fn display(&self) -> String { format!("Vec<AggregateState>[{}]", self.len()) }
+}
+
 impl<'a, T: GenericVec<T> + 'a> AnyVec<'a> for &'a [T] {
     fn len(&self) -> usize { <[T]>::len(self) }
     fn get_raw(&self, i: usize) -> RawVal { T::wrap_one(self[i]) }
@@ -196,10 +236,14 @@ impl<'a, T: GenericVec<T> + 'a> AnyVec<'a> for &'a [T] {
 
 impl<'a> AnyVec<'a> for &'a [&'a str] {
     fn cast_ref_str(&self) -> &[&'a str] { self }
+    fn to_mixed(&self) -> Vec<Val<'a>> {
+        self.iter().map(|s| Val::Str(*s)).collect()
+    }
 }
 
 impl<'a> AnyVec<'a> for &'a [Val<'a>] {
     fn cast_ref_mixed(&self) -> &[Val<'a>] { self }
+    fn to_mixed(&self) -> Vec<Val<'a>> { self.to_vec() }
 }
 
 impl<'a> AnyVec<'a> for &'a [usize] {
@@ -208,6 +252,13 @@ impl<'a> AnyVec<'a> for &'a [usize] {
 
 impl<'a> AnyVec<'a> for &'a [i64] {
     fn cast_ref_i64(&self) -> &[i64] { self }
+    fn to_mixed(&self) -> Vec<Val<'a>> {
+        self.iter().map(|i| Val::Integer(*i)).collect()
+    }
+}
+
+impl<'a> AnyVec<'a> for &'a [i128] {
+    fn cast_ref_i128(&self) -> &[i128] { self }
 }
 
 impl<'a> AnyVec<'a> for &'a [u64] {
@@ -290,7 +341,7 @@ impl<'a> AnyVec<'a> for RawVal {
     fn display(&self) -> String { format!("Scalar({})", self) }
 }
 
-pub trait GenericVec<T>: PartialEq + Ord + Copy + Debug + Display + Sync + Send + HeapSizeOf {
+pub trait GenericVec<T>: PartialEq + Ord + Copy + Debug + Display + Sync + Send {
     fn unwrap<'a, 'b>(vec: &'b AnyVec<'a>) -> &'b [T] where T: 'a;
     fn unwrap_mut<'a, 'b>(vec: &'b mut AnyVec<'a>) -> &'b mut Vec<T> where T: 'a;
     fn wrap_one(_value: T) -> RawVal { panic!("Can't wrap scalar of type {:?}", Self::t()) }
@@ -323,6 +374,14 @@ impl GenericVec<i64> for i64 {
     fn t() -> EncodingType { EncodingType::I64 }
 }
 
+/// Only used for the results of summations, which may exceed the range of i64.
+impl GenericVec<i128> for i128 {
+    fn unwrap<'a, 'b>(vec: &'b AnyVec<'a>) -> &'b [i128] where i128: 'a { vec.cast_ref_i128() }
+    fn unwrap_mut<'a, 'b>(vec: &'b mut AnyVec<'a>) -> &'b mut Vec<i128> where i128: 'a { vec.cast_ref_mut_i128() }
+    fn wrap_one(value: i128) -> RawVal { RawVal::from_i128(value) }
+    fn t() -> EncodingType { EncodingType::I128 }
+}
+
 impl GenericVec<u64> for u64 {
     fn unwrap<'a, 'b>(vec: &'b AnyVec<'a>) -> &'b [u64] where u64: 'a { vec.cast_ref_u64() }
     fn unwrap_mut<'a, 'b>(vec: &'b mut AnyVec<'a>) -> &'b mut Vec<u64> where u64: 'a { vec.cast_ref_mut_u64() }
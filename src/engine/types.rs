@@ -4,6 +4,8 @@ use mem_store::*;
 pub enum EncodingType {
     Str,
     I64,
+    /// Only used for the results of summations
+    I128,
     USize,
     Val,
     Null,
@@ -18,13 +20,15 @@ pub enum EncodingType {
     ByteSlices(usize),
     Premerge,
     MergeOp,
+    /// Intermediate results of aggregators that are evaluated after merging, see `AggregateState`
+    AggregateState,
 }
 
 impl EncodingType {
     pub fn cast_to_basic(self) -> BasicType {
         match self {
             EncodingType::Str => BasicType::String,
-            EncodingType::I64 => BasicType::Integer,
+            EncodingType::I64 | EncodingType::I128 => BasicType::Integer,
             EncodingType::Val => BasicType::Val,
             EncodingType::Null => BasicType::Null,
             EncodingType::BitVec => BasicType::Boolean,
@@ -37,6 +41,7 @@ impl EncodingType {
 pub enum BasicType {
     String,
     Integer,
+    Float,
     Val,
     Null,
     Boolean,
@@ -47,6 +52,8 @@ impl BasicType {
         match self {
             BasicType::String => EncodingType::Str,
             BasicType::Integer => EncodingType::I64,
+            // There are no float columns, floats only occur as constants which are stored in buffers of `RawVal`
+            BasicType::Float => EncodingType::Val,
             BasicType::Val => EncodingType::Val,
             BasicType::Null => EncodingType::Null,
             BasicType::Boolean => EncodingType::BitVec,
@@ -2,6 +2,7 @@ use engine::*;
 use engine::vector_op::vector_operator::*;
 
 
+/// Adds a constant to each element, saturating at the bounds of i64 on overflow.
 #[derive(Debug)]
 pub struct AdditionVS<T> {
     pub lhs: BufferRef<T>,
@@ -16,7 +17,7 @@ impl<'a, T: GenericIntVec<T>> VecOperator<'a> for AdditionVS<T> {
         let data = scratchpad.get(self.lhs);
         let c = scratchpad.get_const::<i64>(&self.rhs);
         for d in data.iter() {
-            output.push(d.to_i64().unwrap() + c);
+            output.push(d.to_i64().unwrap().saturating_add(c));
         }
     }
 
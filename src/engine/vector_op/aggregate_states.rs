@@ -0,0 +1,183 @@
+use std::mem;
+
+use engine::aggregator::{AggregateState, Aggregator};
+use engine::typed_vec::MergeOp;
+use engine::vector_op::*;
+use engine::*;
+use ingest::raw_val::RawVal;
+
+
+/// Folds the values in each group into the `AggregateState` of an aggregator that is evaluated after merging.
+/// `FIRST` and `LAST` read the ordering key of each row from `ordering`.
+#[derive(Debug)]
+pub struct VecAggregateStates<U> {
+    pub input: BufferRef<Any>,
+    pub ordering: Option<BufferRef<Any>>,
+    pub grouping: BufferRef<U>,
+    pub output: BufferRef<Any>,
+    pub max_index: BufferRef<i64>,
+    pub aggregator: Aggregator,
+    pub states: Vec<AggregateState>,
+}
+
+impl<'a, U: GenericIntVec<U>> VecOperator<'a> for VecAggregateStates<U> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let input = scratchpad.get_any(self.input);
+        let ordering = self.ordering.map(|ordering| scratchpad.get_any(ordering));
+        let grouping = scratchpad.get(self.grouping);
+
+        let len = scratchpad.get_const::<i64>(&self.max_index) as usize + 1;
+        if len > self.states.len() {
+            self.states.resize(len, AggregateState::new(self.aggregator));
+        }
+
+        for (i, g) in grouping.iter().enumerate() {
+            let key = match ordering {
+                Some(ref ordering) => ordering.get_raw(i),
+                None => RawVal::Null,
+            };
+            self.states[g.cast_usize()].push(key, input.get_raw(i), 1);
+        }
+    }
+
+    fn finalize(&mut self, scratchpad: &mut Scratchpad<'a>) {
+        let states = mem::replace(&mut self.states, Vec::new());
+        scratchpad.set_any(self.output, Box::new(states));
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set_any(self.output, Box::new(Vec::<AggregateState>::new()));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> {
+        let mut inputs = vec![self.grouping.any(), self.input, self.max_index.any()];
+        inputs.extend(self.ordering);
+        inputs
+    }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        match self.ordering {
+            Some(ordering) => format!("{:?}_states[{}]({} order_by {})", self.aggregator, self.grouping, self.input, ordering),
+            None => format!("{:?}_states[{}]({})", self.aggregator, self.grouping, self.input),
+        }
+    }
+}
+
+/// Merges the aggregate states of two aggregation results.
+#[derive(Debug)]
+pub struct MergeAggregateStates {
+    pub merge_ops: BufferRef<MergeOp>,
+    pub left: BufferRef<Any>,
+    pub right: BufferRef<Any>,
+    pub merged: BufferRef<Any>,
+}
+
+impl<'a> VecOperator<'a> for MergeAggregateStates {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let merged = {
+            let ops = scratchpad.get(self.merge_ops);
+            let left = scratchpad.get_any(self.left);
+            let right = scratchpad.get_any(self.right);
+            merge_states(&ops, left.cast_ref_aggregate_states(), right.cast_ref_aggregate_states())
+        };
+        scratchpad.set_any(self.merged, Box::new(merged));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.merge_ops.any(), self.left, self.right] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.merged] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("merge_states({}; {}, {})", self.merge_ops, self.left, self.right)
+    }
+}
+
+fn merge_states(ops: &[MergeOp], left: &[AggregateState], right: &[AggregateState]) -> Vec<AggregateState> {
+    let mut result: Vec<AggregateState> = Vec::with_capacity(ops.len());
+    let mut i = 0;
+    let mut j = 0;
+    for op in ops {
+        match *op {
+            MergeOp::TakeLeft => {
+                result.push(left[i].clone());
+                i += 1;
+            }
+            MergeOp::TakeRight => {
+                result.push(right[j].clone());
+                j += 1;
+            }
+            MergeOp::MergeRight => {
+                let last = result.len() - 1;
+                result[last].merge(right[j].clone());
+                j += 1;
+            }
+        }
+    }
+    result
+}
+
+/// Removes the states of all groups that do not occur, like `Compact`.
+#[derive(Debug)]
+pub struct CompactAggregateStates<U> {
+    pub data: BufferRef<Any>,
+    pub select: BufferRef<U>,
+}
+
+impl<'a, U: GenericIntVec<U>> VecOperator<'a> for CompactAggregateStates<U> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let select = scratchpad.get(self.select);
+        let mut data = scratchpad.get_any_mut(self.data);
+        let mut i = 0;
+        data.cast_ref_mut_aggregate_states().retain(|_| {
+            let keep = select.get(i).map_or(false, |&s| s > U::zero());
+            i += 1;
+            keep
+        });
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.data, self.select.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.data] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { false }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{} > 0]", self.data, self.select)
+    }
+}
+
+/// Reorders aggregate states by `indices`, like `Select`.
+#[derive(Debug)]
+pub struct SelectAggregateStates {
+    pub input: BufferRef<Any>,
+    pub indices: BufferRef<usize>,
+    pub output: BufferRef<Any>,
+}
+
+impl<'a> VecOperator<'a> for SelectAggregateStates {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let selection = {
+            let data = scratchpad.get_any(self.input);
+            let states = data.cast_ref_aggregate_states();
+            let indices = scratchpad.get(self.indices);
+            indices.iter().map(|&i| states[i].clone()).collect::<Vec<_>>()
+        };
+        scratchpad.set_any(self.output, Box::new(selection));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input, self.indices.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{}]", self.input, self.indices)
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt;
+use std::mem;
+use std::str;
+
+use engine::vector_op::vector_operator::*;
+use ingest::extractor;
+use mem_store::value::Val;
+
+
+/// Maximum number of bytes in the decimal representation of an i64.
+const MAX_I64_DIGITS: usize = 20;
+
+/// Converts integers into their decimal representation.
+pub struct IntToString<'a> {
+    pub input: BufferRef<i64>,
+    pub output: BufferRef<&'a str>,
+    pub stringstore: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for IntToString<'a> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        unsafe { scratchpad.unpin(self.stringstore.any()) };
+        {
+            let ints = scratchpad.get(self.input);
+            let mut strings = scratchpad.get_mut(self.output);
+            let mut stringstore = scratchpad.get_mut(self.stringstore);
+            if stream { strings.clear() }
+            for i in ints.iter() {
+                let string = i.to_string();
+                let bytes = string.as_bytes();
+                // unsafe if this were false
+                assert!(stringstore.len() + bytes.len() <= stringstore.capacity());
+                stringstore.extend_from_slice(bytes);
+                strings.push(unsafe {
+                    mem::transmute::<_, &'a str>(
+                        str::from_utf8_unchecked(&stringstore[stringstore.len() - bytes.len()..])
+                    )
+                });
+            }
+        }
+        scratchpad.pin(self.stringstore.any());
+    }
+
+    fn init(&mut self, total_count: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+        // Initializing with sufficient capacity is required for safety - this vector must never get reallocated
+        scratchpad.set(self.stringstore, Vec::with_capacity(total_count * MAX_I64_DIGITS));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("cast({} as string)", self.input)
+    }
+}
+
+impl<'a> fmt::Debug for IntToString<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IntToString {{ input: {}, output: {} }}", self.input, self.output)
+    }
+}
+
+/// Parses strings as integers like integer columns are ingested. Strings that are not numbers are converted to 0
+/// and marked as null by `StringIsNotInt`.
+#[derive(Debug)]
+pub struct StringToInt<'a> {
+    pub input: BufferRef<&'a str>,
+    pub output: BufferRef<i64>,
+}
+
+impl<'a> VecOperator<'a> for StringToInt<'a> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let strings = scratchpad.get(self.input);
+        let mut ints = scratchpad.get_mut(self.output);
+        if stream { ints.clear() }
+        for s in strings.iter() {
+            ints.push(extractor::parse_int(s).unwrap_or(0));
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("cast({} as integer)", self.input)
+    }
+}
+
+/// Yields 1 for every string that `StringToInt` can not convert.
+#[derive(Debug)]
+pub struct StringIsNotInt<'a> {
+    pub input: BufferRef<&'a str>,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for StringIsNotInt<'a> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let strings = scratchpad.get(self.input);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear() }
+        for s in strings.iter() {
+            output.push(extractor::parse_int(s).is_none() as u8);
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("cast({} as integer) is null", self.input)
+    }
+}
+
+/// Parses strings as floats, strings that are not numbers are converted to null.
+#[derive(Debug)]
+pub struct StringToFloat<'a> {
+    pub input: BufferRef<&'a str>,
+    pub output: BufferRef<Val<'a>>,
+}
+
+impl<'a> VecOperator<'a> for StringToFloat<'a> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let strings = scratchpad.get(self.input);
+        let mut floats = scratchpad.get_mut(self.output);
+        if stream { floats.clear() }
+        for s in strings.iter() {
+            floats.push(s.parse::<f64>().map(Val::Float).unwrap_or(Val::Null));
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("cast({} as float)", self.input)
+    }
+}
@@ -0,0 +1,148 @@
+use std::mem;
+use std::str;
+
+use engine::types::BasicType;
+use engine::vector_op::vector_operator::*;
+use ingest::extractor;
+use mem_store::value::Val;
+
+
+/// Converts integers into floats.
+#[derive(Debug)]
+pub struct IntToFloat<'a> {
+    pub input: BufferRef<i64>,
+    pub output: BufferRef<Val<'a>>,
+}
+
+impl<'a> VecOperator<'a> for IntToFloat<'a> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let ints = scratchpad.get(self.input);
+        let mut floats = scratchpad.get_mut(self.output);
+        if stream { floats.clear() }
+        for &i in ints.iter() {
+            floats.push(Val::Float(i as f64));
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("cast({} as float)", self.input)
+    }
+}
+
+/// Converts values that may be null or floats to integers or floats like `RawVal::cast`.
+/// Values that can not be converted become null.
+#[derive(Debug)]
+pub struct CastVals<'a> {
+    pub input: BufferRef<Val<'a>>,
+    pub output: BufferRef<Val<'a>>,
+    pub target: BasicType,
+}
+
+impl<'a> VecOperator<'a> for CastVals<'a> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let vals = scratchpad.get(self.input);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear() }
+        for &val in vals.iter() {
+            output.push(cast_val(val, self.target));
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("cast({} as {:?})", self.input, self.target)
+    }
+}
+
+fn cast_val<'a>(val: Val<'a>, target: BasicType) -> Val<'a> {
+    match (val, target) {
+        (Val::Integer(i), BasicType::Integer) => Val::Integer(i),
+        (Val::Integer(i), BasicType::Float) => Val::Float(i as f64),
+        (Val::Float(x), BasicType::Integer) => extractor::float_to_int(x).map_or(Val::Null, Val::Integer),
+        (Val::Float(x), BasicType::Float) => Val::Float(x),
+        (Val::Str(s), BasicType::Integer) => extractor::parse_int(s).map_or(Val::Null, Val::Integer),
+        (Val::Str(s), BasicType::Float) => s.parse::<f64>().map(Val::Float).unwrap_or(Val::Null),
+        _ => Val::Null,
+    }
+}
+
+/// Converts values that may be null or floats into strings, nulls remain null.
+#[derive(Debug)]
+pub struct ValToString<'a> {
+    pub input: BufferRef<Val<'a>>,
+    pub output: BufferRef<Val<'a>>,
+    pub stringstore: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for ValToString<'a> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        unsafe { scratchpad.unpin(self.stringstore.any()) };
+        {
+            let vals = scratchpad.get(self.input);
+            let mut strings = scratchpad.get_mut(self.output);
+            let mut stringstore = scratchpad.get_mut(self.stringstore);
+            if stream { strings.clear() }
+            let formatted = vals.iter()
+                .map(|val| match *val {
+                    Val::Integer(i) => Some(i.to_string()),
+                    Val::Float(x) => Some(x.to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            // The decimal representation of floats has no useful upper bound, so the stringstore is only allocated
+            // once the length of all strings is known. It must never get reallocated after that.
+            assert!(stringstore.is_empty());
+            stringstore.reserve_exact(formatted.iter().map(|s| s.as_ref().map_or(0, |s| s.len())).sum());
+            for (val, string) in vals.iter().zip(formatted) {
+                strings.push(match (*val, string) {
+                    (Val::Str(s), _) => Val::Str(s),
+                    (_, Some(string)) => {
+                        let bytes = string.as_bytes();
+                        stringstore.extend_from_slice(bytes);
+                        Val::Str(unsafe {
+                            mem::transmute::<_, &'a str>(
+                                str::from_utf8_unchecked(&stringstore[stringstore.len() - bytes.len()..])
+                            )
+                        })
+                    }
+                    _ => Val::Null,
+                });
+            }
+        }
+        scratchpad.pin(self.stringstore.any());
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+        scratchpad.set(self.stringstore, Vec::new());
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("cast({} as string)", self.input)
+    }
+}
@@ -0,0 +1,42 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use engine::vector_op::checked_division_vv::CheckedDivisionOp;
+use engine::vector_op::vector_operator::*;
+use mem_store::value::Val;
+
+
+/// Divides a constant by each element, yielding null wherever the divisor is zero.
+#[derive(Debug)]
+pub struct CheckedDivisionSV<'a, Op> {
+    pub lhs: BufferRef<i64>,
+    pub rhs: BufferRef<i64>,
+    pub output: BufferRef<Val<'a>>,
+    pub op: PhantomData<Op>,
+}
+
+impl<'a, Op: CheckedDivisionOp + fmt::Debug> VecOperator<'a> for CheckedDivisionSV<'a, Op> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear(); }
+        let c = scratchpad.get_const::<i64>(&self.lhs);
+        let rhs = scratchpad.get(self.rhs);
+        for &r in rhs.iter() {
+            output.push(if r == 0 { Val::Null } else { Val::Integer(Op::perform(c, r)) });
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.lhs.any(), self.rhs.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} {} {}", self.lhs, Op::symbol(), self.rhs)
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt;
+use std::i64;
+use std::marker::PhantomData;
+
+use engine::vector_op::vector_operator::*;
+use mem_store::value::Val;
+
+
+/// Divides two columns elementwise, yielding null wherever the divisor is zero.
+#[derive(Debug)]
+pub struct CheckedDivisionVV<'a, Op> {
+    pub lhs: BufferRef<i64>,
+    pub rhs: BufferRef<i64>,
+    pub output: BufferRef<Val<'a>>,
+    pub op: PhantomData<Op>,
+}
+
+impl<'a, Op: CheckedDivisionOp + fmt::Debug> VecOperator<'a> for CheckedDivisionVV<'a, Op> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear(); }
+        let lhs = scratchpad.get(self.lhs);
+        let rhs = scratchpad.get(self.rhs);
+        for (&l, &r) in lhs.iter().zip(rhs.iter()) {
+            output.push(if r == 0 { Val::Null } else { Val::Integer(Op::perform(l, r)) });
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.lhs.any(), self.rhs.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} {} {}", self.lhs, Op::symbol(), self.rhs)
+    }
+}
+
+pub trait CheckedDivisionOp {
+    /// Called with a nonzero divisor only.
+    fn perform(lhs: i64, rhs: i64) -> i64;
+    fn symbol() -> &'static str;
+}
+
+#[derive(Debug)]
+pub struct Division;
+
+impl CheckedDivisionOp for Division {
+    // Only overflows for i64::MIN / -1
+    fn perform(lhs: i64, rhs: i64) -> i64 { lhs.checked_div(rhs).unwrap_or(i64::MAX) }
+    fn symbol() -> &'static str { "/" }
+}
+
+#[derive(Debug)]
+pub struct Modulo;
+
+impl CheckedDivisionOp for Modulo {
+    fn perform(lhs: i64, rhs: i64) -> i64 { lhs.checked_rem(rhs).unwrap_or(0) }
+    fn symbol() -> &'static str { "%" }
+}
@@ -0,0 +1,165 @@
+use std::cmp;
+use std::i64;
+use std::mem;
+
+use engine::aggregator::Aggregator;
+use engine::vector_op::*;
+use engine::*;
+
+
+/// A sum, minimum, maximum or row count computed by `VecCombinedAggregation`.
+#[derive(Debug)]
+pub struct CombinedAggregate {
+    pub aggregator: Aggregator,
+    /// Values that are aggregated, row counts do not read any input
+    pub input: Option<TypedBufferRef>,
+    pub output: TypedBufferRef,
+    /// Added to every input value of sums, allows summing integer offset encoded columns without decoding them
+    pub offset: i64,
+    pub accumulators: Vec<i128>,
+}
+
+/// Computes all sums, minimums, maximums and row counts of a grouping in a single pass over the grouping key.
+#[derive(Debug)]
+pub struct VecCombinedAggregation<U> {
+    pub grouping: BufferRef<U>,
+    pub max_index: BufferRef<i64>,
+    pub aggregates: Vec<CombinedAggregate>,
+    /// Number of rows in each group
+    pub counts: Vec<u32>,
+}
+
+impl<'a, U: GenericIntVec<U>> VecOperator<'a> for VecCombinedAggregation<U> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let grouping = scratchpad.get(self.grouping);
+        let mut inputs = Vec::with_capacity(self.aggregates.len());
+        for aggregate in &self.aggregates {
+            inputs.push(match aggregate.input {
+                Some(input) => Some((input.tag, scratchpad.get_any(input.any()))),
+                None => None,
+            });
+        }
+        let values = inputs.iter()
+            .map(|input| match *input {
+                Some((tag, ref data)) => IntSlice::new(tag, &**data),
+                None => IntSlice::None,
+            })
+            .collect::<Vec<_>>();
+
+        let len = scratchpad.get_const::<i64>(&self.max_index) as usize + 1;
+        if len > self.counts.len() {
+            self.counts.resize(len, 0);
+            for aggregate in &mut self.aggregates {
+                let initial = match aggregate.aggregator {
+                    Aggregator::Count => continue,
+                    Aggregator::Min => i128::from(i64::MAX),
+                    Aggregator::Max => i128::from(i64::MIN),
+                    _ => 0,
+                };
+                aggregate.accumulators.resize(len, initial);
+            }
+        }
+
+        for (row, g) in grouping.iter().enumerate() {
+            let g = g.cast_usize();
+            self.counts[g] += 1;
+            for (aggregate, values) in self.aggregates.iter_mut().zip(&values) {
+                match aggregate.aggregator {
+                    Aggregator::Sum => aggregate.accumulators[g] += i128::from(values.get(row)),
+                    Aggregator::Min => aggregate.accumulators[g] = cmp::min(aggregate.accumulators[g], i128::from(values.get(row))),
+                    Aggregator::Max => aggregate.accumulators[g] = cmp::max(aggregate.accumulators[g], i128::from(values.get(row))),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn finalize(&mut self, scratchpad: &mut Scratchpad<'a>) {
+        let counts = &self.counts;
+        for aggregate in &mut self.aggregates {
+            let accumulators = mem::replace(&mut aggregate.accumulators, Vec::new());
+            match aggregate.aggregator {
+                Aggregator::Sum => {
+                    let offset = i128::from(aggregate.offset);
+                    let sums = accumulators.into_iter()
+                        .zip(counts)
+                        .map(|(sum, &count)| sum + offset * i128::from(count))
+                        .collect();
+                    scratchpad.set(aggregate.output.i128(), sums);
+                }
+                Aggregator::Min | Aggregator::Max =>
+                    scratchpad.set(aggregate.output.i64(), accumulators.into_iter().map(|x| x as i64).collect()),
+                _ => scratchpad.set(aggregate.output.u32(), counts.clone()),
+            }
+        }
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        for aggregate in &self.aggregates {
+            match aggregate.aggregator {
+                Aggregator::Sum => scratchpad.set(aggregate.output.i128(), Vec::with_capacity(0)),
+                Aggregator::Min | Aggregator::Max => scratchpad.set(aggregate.output.i64(), Vec::with_capacity(0)),
+                _ => scratchpad.set(aggregate.output.u32(), Vec::with_capacity(0)),
+            }
+        }
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> {
+        let mut inputs = vec![self.grouping.any(), self.max_index.any()];
+        inputs.extend(self.aggregates.iter().filter_map(|aggregate| aggregate.input.map(|input| input.any())));
+        inputs
+    }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { self.aggregates.iter().map(|aggregate| aggregate.output.any()).collect() }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        self.aggregates.iter()
+            .map(|aggregate| {
+                let output = aggregate.output.buffer;
+                match (aggregate.aggregator, aggregate.input) {
+                    (Aggregator::Min, Some(input)) => format!("{}[{}] min= {}", output, self.grouping, input.buffer),
+                    (Aggregator::Max, Some(input)) => format!("{}[{}] max= {}", output, self.grouping, input.buffer),
+                    (_, Some(input)) if aggregate.offset != 0 =>
+                        format!("{}[{}] += {} (offset {})", output, self.grouping, input.buffer, aggregate.offset),
+                    (_, Some(input)) => format!("{}[{}] += {}", output, self.grouping, input.buffer),
+                    (_, None) => format!("{}[{}] += 1", output, self.grouping),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+    fn display_output(&self) -> bool { false }
+}
+
+/// Values of one aggregate, each aggregate may read a column with a different integer encoding.
+enum IntSlice<'a> {
+    None,
+    U8(&'a [u8]),
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+    I64(&'a [i64]),
+}
+
+impl<'a> IntSlice<'a> {
+    fn new<'b>(tag: EncodingType, data: &'a AnyVec<'b>) -> IntSlice<'a> {
+        match tag {
+            EncodingType::U8 => IntSlice::U8(data.cast_ref_u8()),
+            EncodingType::U16 => IntSlice::U16(data.cast_ref_u16()),
+            EncodingType::U32 => IntSlice::U32(data.cast_ref_u32()),
+            EncodingType::I64 => IntSlice::I64(data.cast_ref_i64()),
+            _ => panic!("combined_aggregation does not support inputs of type {:?}", tag),
+        }
+    }
+
+    fn get(&self, i: usize) -> i64 {
+        match *self {
+            IntSlice::None => 0,
+            IntSlice::U8(values) => i64::from(values[i]),
+            IntSlice::U16(values) => i64::from(values[i]),
+            IntSlice::U32(values) => i64::from(values[i]),
+            IntSlice::I64(values) => values[i],
+        }
+    }
+}
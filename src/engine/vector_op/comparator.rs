@@ -1,3 +1,9 @@
+use std::cmp::Ordering;
+
+use mem_store::value::Val;
+use syntax::collation::Collation;
+
+
 pub trait Comparator<T> {
     fn cmp(left: T, right: T) -> bool;
     fn cmp_eq(left: T, right: T) -> bool;
@@ -45,6 +51,12 @@ impl<'a> Comparator<&'a str> for CmpLessThan {
     fn is_less_than() -> bool { true }
 }
 
+impl<'a> Comparator<Val<'a>> for CmpLessThan {
+    fn cmp(left: Val<'a>, right: Val<'a>) -> bool { left < right }
+    fn cmp_eq(left: Val<'a>, right: Val<'a>) -> bool { left <= right }
+    fn is_less_than() -> bool { true }
+}
+
 
 #[derive(Debug)]
 pub struct CmpGreaterThan;
@@ -85,3 +97,28 @@ impl<'a> Comparator<&'a str> for CmpGreaterThan {
     fn is_less_than() -> bool { false }
 }
 
+impl<'a> Comparator<Val<'a>> for CmpGreaterThan {
+    fn cmp(left: Val<'a>, right: Val<'a>) -> bool { left > right }
+    fn cmp_eq(left: Val<'a>, right: Val<'a>) -> bool { left >= right }
+    fn is_less_than() -> bool { false }
+}
+
+
+#[derive(Debug)]
+pub struct CmpLessThanNoCase;
+
+impl<'a> Comparator<&'a str> for CmpLessThanNoCase {
+    fn cmp(left: &str, right: &str) -> bool { Collation::NoCase.cmp(left, right) == Ordering::Less }
+    fn cmp_eq(left: &str, right: &str) -> bool { Collation::NoCase.cmp(left, right) != Ordering::Greater }
+    fn is_less_than() -> bool { true }
+}
+
+
+#[derive(Debug)]
+pub struct CmpGreaterThanNoCase;
+
+impl<'a> Comparator<&'a str> for CmpGreaterThanNoCase {
+    fn cmp(left: &str, right: &str) -> bool { Collation::NoCase.cmp(left, right) == Ordering::Greater }
+    fn cmp_eq(left: &str, right: &str) -> bool { Collation::NoCase.cmp(left, right) != Ordering::Less }
+    fn is_less_than() -> bool { false }
+}
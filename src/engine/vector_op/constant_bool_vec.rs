@@ -0,0 +1,33 @@
+use engine::vector_op::vector_operator::*;
+
+
+/// Produces a boolean vector with the same length as `input` in which every element is `value`.
+#[derive(Debug)]
+pub struct ConstantBoolVec {
+    pub input: BufferRef<Any>,
+    pub value: bool,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for ConstantBoolVec {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let len = scratchpad.get_any(self.input).len();
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear() }
+        output.resize(len, self.value as u8);
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("[{}; len({})]", self.value, self.input)
+    }
+}
@@ -39,3 +39,35 @@ impl<'a, T: GenericIntVec<T> + CastUsize> VecOperator<'a> for VecCount<T> {
     }
     fn display_output(&self) -> bool { false }
 }
+
+/// Counts the values of an expression that is null for all rows, which results in a count of zero for every group.
+#[derive(Debug)]
+pub struct NullCount {
+    pub output: BufferRef<u32>,
+    pub max_index: BufferRef<i64>,
+}
+
+impl<'a> VecOperator<'a> for NullCount {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let mut result = scratchpad.get_mut(self.output);
+        let len = scratchpad.get_const::<i64>(&self.max_index) as usize + 1;
+        if len > result.len() {
+            result.resize(len, 0);
+        }
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(0));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.max_index.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[0..{}] = 0", self.output, self.max_index)
+    }
+    fn display_output(&self) -> bool { false }
+}
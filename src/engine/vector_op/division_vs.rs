@@ -1,6 +1,9 @@
+use std::i64;
+
 use engine::vector_op::vector_operator::*;
 
 
+/// Divides each element by a nonzero constant, saturating at the bounds of i64 on overflow.
 #[derive(Debug)]
 pub struct DivideVS {
     pub lhs: BufferRef<i64>,
@@ -15,7 +18,8 @@ impl<'a> VecOperator<'a> for DivideVS {
         let data = scratchpad.get(self.lhs);
         let c = scratchpad.get_const::<i64>(&self.rhs);
         for d in data.iter() {
-            output.push(d / c);
+            // Only overflows for i64::MIN / -1, division by zero is rejected during query planning
+            output.push(d.checked_div(c).unwrap_or(i64::MAX));
         }
     }
 
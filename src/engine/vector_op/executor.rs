@@ -8,12 +8,15 @@ use engine::typed_vec::*;
 use engine::query_plan::QueryPlan;
 use engine::vector_op::*;
 use ingest::raw_val::RawVal;
+use mem_store::value::Val;
 
 
 pub struct QueryExecutor<'a> {
     ops: Vec<Box<VecOperator<'a> + 'a>>,
     ops_cache: HashMap<[u8; 16], TypedBufferRef>,
     stages: Vec<ExecutorStage>,
+    // Buffers that can be freed once the corresponding stage has completed
+    releases: Vec<Vec<BufferRef<Any>>>,
     encoded_group_by: Option<TypedBufferRef>,
     count: usize,
     last_buffer: TypedBufferRef,
@@ -59,6 +62,10 @@ impl<'a> QueryExecutor<'a> {
         self.named_buffer(name, EncodingType::U32).u32()
     }
 
+    pub fn buffer_val(&mut self, name: &'static str) -> BufferRef<Val<'a>> {
+        self.named_buffer(name, EncodingType::Val).buffer.val()
+    }
+
     pub fn buffer_u8(&mut self, name: &'static str) -> BufferRef<u8> {
         let buffer = self.named_buffer(name, EncodingType::U8);
         buffer.u8()
@@ -79,6 +86,9 @@ impl<'a> QueryExecutor<'a> {
 
     pub fn last_buffer(&self) -> TypedBufferRef { self.last_buffer }
 
+    /// Number of vector operators in the query plan.
+    pub fn op_count(&self) -> usize { self.ops.len() }
+
     pub fn push(&mut self, op: Box<VecOperator<'a> + 'a>) {
         self.ops.push(op);
     }
@@ -89,14 +99,16 @@ impl<'a> QueryExecutor<'a> {
 
     pub fn encoded_group_by(&self) -> Option<TypedBufferRef> { self.encoded_group_by }
 
-    pub fn prepare(&mut self, columns: HashMap<String, Vec<&'a AnyVec<'a>>>) -> Scratchpad<'a> {
+    /// `outputs` are the buffers that are read from the scratchpad after execution, all other buffers are released
+    /// once no remaining operator reads them.
+    pub fn prepare(&mut self, columns: HashMap<String, Vec<&'a AnyVec<'a>>>, outputs: &[BufferRef<Any>]) -> Scratchpad<'a> {
         self.stages = self.partition();
+        self.releases = self.release_schedule(outputs);
         Scratchpad::new(self.count, columns)
     }
 
-    pub fn prepare_no_columns(&mut self) -> Scratchpad<'a> {
-        self.stages = self.partition();
-        Scratchpad::new(self.count, HashMap::default())
+    pub fn prepare_no_columns(&mut self, outputs: &[BufferRef<Any>]) -> Scratchpad<'a> {
+        self.prepare(HashMap::default(), outputs)
     }
 
     pub fn get(&self, signature: &[u8; 16]) -> Option<Box<QueryPlan>> {
@@ -110,7 +122,49 @@ impl<'a> QueryExecutor<'a> {
     pub fn run(&mut self, len: usize, scratchpad: &mut Scratchpad<'a>, show: bool) {
         for stage in 0..self.stages.len() {
             self.run_stage(len, stage, scratchpad, show);
+            if cfg!(test) {
+                scratchpad.record_allocation();
+            }
+            for &buffer in &self.releases[stage] {
+                scratchpad.release(buffer);
+            }
+        }
+    }
+
+    /// Determines the stage after which each buffer other than `outputs` is no longer used by any operator.
+    fn release_schedule(&self, outputs: &[BufferRef<Any>]) -> Vec<Vec<BufferRef<Any>>> {
+        let retained = outputs.iter().map(|buffer| buffer.i).collect::<HashSet<_>>();
+        let mut stage_for_op = vec![0; self.ops.len()];
+        for (i, stage) in self.stages.iter().enumerate() {
+            for &(op, _) in &stage.ops {
+                stage_for_op[op] = i;
+            }
+        }
+
+        let mut last_use = vec![None; self.count];
+        let mut consumed = vec![false; self.count];
+        for (i, op) in self.ops.iter().enumerate() {
+            for input in op.inputs() {
+                consumed[input.i] = true;
+            }
+            for buffer in op.inputs().into_iter().chain(op.outputs()) {
+                let stage = match last_use[buffer.i] {
+                    Some((stage, _)) => cmp::max(stage, stage_for_op[i]),
+                    None => stage_for_op[i],
+                };
+                last_use[buffer.i] = Some((stage, buffer));
+            }
+        }
+
+        let mut releases = vec![vec![]; self.stages.len()];
+        for (i, last_use) in last_use.into_iter().enumerate() {
+            if let Some((stage, buffer)) = last_use {
+                if consumed[i] && !retained.contains(&i) {
+                    releases[stage].push(buffer);
+                }
+            }
         }
+        releases
     }
 
     // TODO(clemens): Make this nicer?
@@ -326,6 +380,7 @@ impl<'a> Default for QueryExecutor<'a> {
             ops: vec![],
             ops_cache: HashMap::default(),
             stages: vec![],
+            releases: vec![],
             encoded_group_by: None,
             count: 0,
             last_buffer: TypedBufferRef::new(error_buffer_ref("ERROR"), EncodingType::Null),
@@ -360,3 +415,46 @@ impl<'a> fmt::Display for QueryExecutor<'a> {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::collation::Collation;
+
+    fn sort_twice<'a>(executor: &mut QueryExecutor<'a>, len: usize) -> Vec<BufferRef<Any>> {
+        let data = executor.named_buffer("data", EncodingType::I64);
+        executor.push(VecOperator::constant_vec(AnyVec::owned((0..len as i64).collect::<Vec<_>>()), data.any()));
+        let desc_indices = executor.buffer_usize("desc_indices");
+        executor.push(VecOperator::sort_indices(data.any(), desc_indices, true, Collation::Binary));
+        let desc = executor.named_buffer("desc", EncodingType::I64);
+        executor.push(VecOperator::select(data, desc_indices, desc));
+        let asc_indices = executor.buffer_usize("asc_indices");
+        executor.push(VecOperator::sort_indices(desc.any(), asc_indices, false, Collation::Binary));
+        let asc = executor.named_buffer("asc", EncodingType::I64);
+        executor.push(VecOperator::select(desc, asc_indices, asc));
+        vec![data.any(), desc_indices.any(), desc.any(), asc_indices.any(), asc.any()]
+    }
+
+    #[test]
+    fn test_release_reduces_peak_allocation() {
+        let len = 10_000;
+
+        let mut executor = QueryExecutor::default();
+        let buffers = sort_twice(&mut executor, len);
+        let mut scratchpad = executor.prepare_no_columns(&buffers[4..]);
+        executor.run(len, &mut scratchpad, false);
+        let releasing_peak = scratchpad.peak_bytes();
+        let result = scratchpad.collect(buffers[4]);
+        assert_eq!(result.cast_ref_i64(), &(0..len as i64).collect::<Vec<_>>()[..]);
+
+        let mut executor = QueryExecutor::default();
+        let buffers = sort_twice(&mut executor, len);
+        let mut scratchpad = executor.prepare_no_columns(&buffers);
+        executor.run(len, &mut scratchpad, false);
+        let retaining_peak = scratchpad.peak_bytes();
+
+        assert!(retaining_peak >= 5 * 8 * len);
+        assert!(releasing_peak < retaining_peak,
+                "peak allocation with release: {}, without release: {}", releasing_peak, retaining_peak);
+    }
+}
@@ -0,0 +1,31 @@
+use engine::vector_op::*;
+
+
+/// Counts the number of rows that pass a filter.
+#[derive(Debug)]
+pub struct FilterCount {
+    pub filter: BufferRef<u8>,
+    pub count: BufferRef<usize>,
+}
+
+impl<'a> VecOperator<'a> for FilterCount {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let filter = scratchpad.get(self.filter);
+        let mut count = scratchpad.get_mut(self.count);
+        count[0] += filter.iter().filter(|&&x| x != 0).count();
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.count, vec![0]);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.filter.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.count.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("count({})", self.filter)
+    }
+}
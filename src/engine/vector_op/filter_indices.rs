@@ -0,0 +1,37 @@
+use engine::vector_op::vector_operator::*;
+
+
+/// Determines the positions of all rows selected by a filter.
+#[derive(Debug)]
+pub struct FilterIndices {
+    pub filter: BufferRef<u8>,
+    pub indices: BufferRef<usize>,
+    pub offset: usize,
+}
+
+impl<'a> VecOperator<'a> for FilterIndices {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let filter = scratchpad.get(self.filter);
+        let mut indices = scratchpad.get_mut(self.indices);
+        for (i, &select) in filter.iter().enumerate() {
+            if select > 0 {
+                indices.push(self.offset + i);
+            }
+        }
+        self.offset += filter.len();
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.indices, Vec::new());
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.filter.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.indices.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("filter_indices({})", self.filter)
+    }
+}
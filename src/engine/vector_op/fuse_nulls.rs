@@ -0,0 +1,38 @@
+use engine::vector_op::*;
+use engine::*;
+use mem_store::value::Val;
+
+
+/// Converts values to `Val`, replacing the values at positions marked in `nulls` by null.
+#[derive(Debug)]
+pub struct FuseNulls<'a, T> {
+    pub input: BufferRef<T>,
+    pub nulls: BufferRef<u8>,
+    pub output: BufferRef<Val<'a>>,
+}
+
+impl<'a, T: GenericVec<T> + 'a> VecOperator<'a> for FuseNulls<'a, T> where Val<'a>: From<T> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear(); }
+        let input = scratchpad.get(self.input);
+        let nulls = scratchpad.get(self.nulls);
+        for (&x, &null) in input.iter().zip(nulls.iter()) {
+            output.push(if null == 1 { Val::Null } else { Val::from(x) });
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any(), self.nulls.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("fuse_nulls({}, {})", self.input, self.nulls)
+    }
+}
@@ -1,3 +1,4 @@
+use std::cmp;
 use std::hash::Hash;
 
 use fnv::FnvHashMap;
@@ -14,6 +15,9 @@ pub struct HashMapGrouping<T: GenericVec<T> + Hash> {
     unique_out: BufferRef<T>,
     grouping_key_out: BufferRef<u32>,
     cardinality_out: BufferRef<i64>,
+    /// Number of rows in each group, tallied while grouping
+    count_out: Option<BufferRef<u32>>,
+    max_index: usize,
     map: FnvHashMap<T, u32>,
 }
 
@@ -22,12 +26,15 @@ impl<'a, T: GenericVec<T> + Hash + 'a> HashMapGrouping<T> {
                  unique_out: BufferRef<T>,
                  grouping_key_out: BufferRef<u32>,
                  cardinality_out: BufferRef<i64>,
-                 _max_index: usize) -> BoxedOperator<'a> {
+                 count_out: Option<BufferRef<u32>>,
+                 max_index: usize) -> BoxedOperator<'a> {
         Box::new(HashMapGrouping::<T> {
             input,
             unique_out,
             grouping_key_out,
             cardinality_out,
+            count_out,
+            max_index,
             map: FnvHashMap::default(),
         })
     }
@@ -40,11 +47,28 @@ impl<'a, T: GenericVec<T> + Hash + 'a> VecOperator<'a> for HashMapGrouping<T> {
             let mut grouping = scratchpad.get_mut(self.grouping_key_out);
             let mut unique = scratchpad.get_mut(self.unique_out);
             if stream { grouping.clear() }
-            for i in raw_grouping_key.iter() {
-                grouping.push(*self.map.entry(*i).or_insert_with(|| {
-                    unique.push(*i);
-                    unique.len() as u32 - 1
-                }));
+            // Runs of identical keys are common (e.g. sorted or low cardinality columns) and skip the hash lookup
+            let mut previous = None;
+            for &key in raw_grouping_key.iter() {
+                let group = match previous {
+                    Some((previous_key, group)) if previous_key == key => group,
+                    _ => {
+                        let group = *self.map.entry(key).or_insert_with(|| {
+                            unique.push(key);
+                            unique.len() as u32 - 1
+                        });
+                        previous = Some((key, group));
+                        group
+                    }
+                };
+                grouping.push(group);
+            }
+            if let Some(count_out) = self.count_out {
+                let mut counts = scratchpad.get_mut(count_out);
+                counts.resize(unique.len(), 0);
+                for &group in &grouping[grouping.len() - raw_grouping_key.len()..] {
+                    counts[group as usize] += 1;
+                }
             }
             RawVal::Int(unique.len() as i64)
         };
@@ -52,15 +76,26 @@ impl<'a, T: GenericVec<T> + Hash + 'a> VecOperator<'a> for HashMapGrouping<T> {
     }
 
     fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
-        // TODO(clemens): Estimate capacities for unique + map?
-        scratchpad.set(self.unique_out, Vec::new());
+        // Size map and unique keys to hold all groups of the first batch without rehashing
+        let capacity = cmp::min(self.max_index, batch_size);
+        self.map.reserve(capacity);
+        scratchpad.set(self.unique_out, Vec::with_capacity(capacity));
         scratchpad.set(self.grouping_key_out, Vec::with_capacity(batch_size));
+        if let Some(count_out) = self.count_out {
+            scratchpad.set(count_out, Vec::with_capacity(capacity));
+        }
     }
 
     fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
-    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.unique_out.any(), self.grouping_key_out.any(), self.cardinality_out.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> {
+        let mut outputs = vec![self.unique_out.any(), self.grouping_key_out.any(), self.cardinality_out.any()];
+        if let Some(count_out) = self.count_out { outputs.push(count_out.any()); }
+        outputs
+    }
     fn can_stream_input(&self, _: usize) -> bool { true }
-    fn can_stream_output(&self, output: usize) -> bool { output != self.unique_out.i }
+    fn can_stream_output(&self, output: usize) -> bool {
+        output != self.unique_out.i && self.count_out.map_or(true, |c| output != c.i)
+    }
     fn allocates(&self) -> bool { true }
 
     fn display_op(&self, _: bool) -> String {
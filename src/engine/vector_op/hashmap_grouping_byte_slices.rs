@@ -12,6 +12,7 @@ pub struct HashMapGroupingByteSlices {
     unique_out: BufferRef<Any>,
     grouping_key_out: BufferRef<u32>,
     cardinality_out: BufferRef<i64>,
+    count_out: Option<BufferRef<u32>>,
     columns: usize,
 }
 
@@ -20,12 +21,14 @@ impl<'a> HashMapGroupingByteSlices {
                  unique_out: BufferRef<Any>,
                  grouping_key_out: BufferRef<u32>,
                  cardinality_out: BufferRef<i64>,
+                 count_out: Option<BufferRef<u32>>,
                  columns: usize) -> BoxedOperator<'a> {
         Box::new(HashMapGroupingByteSlices {
             input,
             unique_out,
             grouping_key_out,
             cardinality_out,
+            count_out,
             columns,
         })
     }
@@ -50,6 +53,13 @@ impl<'a> VecOperator<'a> for HashMapGroupingByteSlices {
                     unique.len() as u32 - 1
                 }));
             }
+            if let Some(count_out) = self.count_out {
+                let mut counts = scratchpad.get_mut(count_out);
+                counts.resize(unique.len(), 0);
+                for &group in grouping.iter() {
+                    counts[group as usize] += 1;
+                }
+            }
             RawVal::Int(unique.len() as i64)
         };
         scratchpad.set_any(self.cardinality_out.any(), AnyVec::constant(count));
@@ -59,12 +69,21 @@ impl<'a> VecOperator<'a> for HashMapGroupingByteSlices {
         // TODO(clemens): Estimate capacities for unique + map?
         scratchpad.set_any(self.unique_out, Box::new(ByteSlices::new(self.columns)));
         scratchpad.set(self.grouping_key_out, Vec::with_capacity(batch_size));
+        if let Some(count_out) = self.count_out {
+            scratchpad.set(count_out, Vec::new());
+        }
     }
 
     fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input] }
-    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.unique_out, self.grouping_key_out.any(), self.cardinality_out.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> {
+        let mut outputs = vec![self.unique_out, self.grouping_key_out.any(), self.cardinality_out.any()];
+        if let Some(count_out) = self.count_out { outputs.push(count_out.any()); }
+        outputs
+    }
     fn can_stream_input(&self, _: usize) -> bool { false }
-    fn can_stream_output(&self, output: usize) -> bool { output != self.unique_out.i }
+    fn can_stream_output(&self, output: usize) -> bool {
+        output != self.unique_out.i && self.count_out.map_or(true, |c| output != c.i)
+    }
     fn allocates(&self) -> bool { true }
 
     fn display_op(&self, _: bool) -> String {
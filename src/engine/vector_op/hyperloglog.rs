@@ -0,0 +1,153 @@
+use std::cmp;
+use std::hash::{Hash, Hasher};
+
+use seahash::SeaHasher;
+
+use engine::vector_op::*;
+use engine::*;
+
+
+/// Number of 8 bit registers packed into each i64 intermediate column of a sketch.
+pub const REGISTERS_PER_COLUMN: usize = 8;
+/// Range of supported precisions. Every group of a sketch with precision `p` takes up `2^p / 8` intermediate
+/// columns, so the precision is capped to keep the number of columns per aggregate in the hundreds.
+pub const MIN_PRECISION: u8 = 4;
+pub const MAX_PRECISION: u8 = 12;
+
+/// Builds a HyperLogLog sketch for each group.
+/// The registers of all groups are stored in a single byte array, register `j` of the sketch of group `g` at index
+/// `g * 2^precision + j`. Once all values are processed, the registers are packed into `packed`, which holds
+/// registers `8 * i..8 * (i + 1)` of the sketch of group `g` in the bytes of `packed[i][g]`.
+/// Sketches are merged by taking the elementwise maximum of registers.
+#[derive(Debug)]
+pub struct HyperLogLog<T, U> {
+    pub input: BufferRef<T>,
+    pub grouping: BufferRef<U>,
+    pub registers: BufferRef<u8>,
+    pub packed: Vec<BufferRef<i64>>,
+    pub max_index: BufferRef<i64>,
+    pub precision: u8,
+}
+
+impl<'a, T, U> VecOperator<'a> for HyperLogLog<T, U> where
+    T: GenericVec<T> + Hash + 'a, U: GenericIntVec<U> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let input = scratchpad.get(self.input);
+        let grouping = scratchpad.get(self.grouping);
+        let mut registers = scratchpad.get_mut(self.registers);
+
+        let m = 1 << self.precision;
+        let len = (scratchpad.get_const::<i64>(&self.max_index) as usize + 1) * m;
+        if len > registers.len() {
+            registers.resize(len, 0);
+        }
+
+        let p = u32::from(self.precision);
+        for (g, value) in grouping.iter().zip(input.iter()) {
+            let mut hasher = SeaHasher::new();
+            value.hash(&mut hasher);
+            let hash = hasher.finish();
+            let bucket = (hash >> (64 - p)) as usize;
+            // Position of leftmost 1 bit in the remaining 64 - p bits (sentinel bit bounds this to 64 - p + 1)
+            let rank = (((hash << p) | (1 << (p - 1))).leading_zeros() + 1) as u8;
+            let register = &mut registers[g.cast_usize() * m + bucket];
+            if rank > *register {
+                *register = rank;
+            }
+        }
+    }
+
+    fn finalize(&mut self, scratchpad: &mut Scratchpad<'a>) {
+        let m = 1 << self.precision;
+        let registers = scratchpad.get(self.registers);
+        for (i, &packed) in self.packed.iter().enumerate() {
+            let mut packed = scratchpad.get_mut(packed);
+            packed.extend(registers.chunks(m).map(|sketch| pack_registers(&sketch[i * REGISTERS_PER_COLUMN..(i + 1) * REGISTERS_PER_COLUMN])));
+        }
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.registers, Vec::with_capacity(0));
+        for &packed in &self.packed {
+            scratchpad.set(packed, Vec::with_capacity(0));
+        }
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.grouping.any(), self.input.any(), self.max_index.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> {
+        let mut outputs = vec![self.registers.any()];
+        outputs.extend(self.packed.iter().map(|r| r.any()));
+        outputs
+    }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("hyperloglog<{}>[{}]({})", 1 << self.precision, self.grouping, self.input)
+    }
+    fn display_output(&self) -> bool { false }
+}
+
+fn pack_registers(registers: &[u8]) -> i64 {
+    registers.iter().rev().fold(0, |packed, &register| (packed << 8) | i64::from(register))
+}
+
+/// Merges two columns of packed registers by taking the maximum of each register.
+pub fn merge_packed_registers(packed1: i64, packed2: i64) -> i64 {
+    (0..REGISTERS_PER_COLUMN).fold(0, |merged, i| {
+        let shift = 8 * i;
+        let register = cmp::max((packed1 >> shift) & 0xff, (packed2 >> shift) & 0xff);
+        merged | (register << shift)
+    })
+}
+
+/// Unpacks the registers of a sketch from its intermediate columns.
+pub fn unpack_registers(packed: &[i64]) -> Vec<u8> {
+    packed.iter()
+        .flat_map(|&packed| (0..REGISTERS_PER_COLUMN).map(move |i| (packed >> (8 * i)) as u8))
+        .collect()
+}
+
+/// Estimates the number of distinct elements from the registers of a HyperLogLog sketch.
+pub fn estimate_cardinality(registers: &[u8]) -> i64 {
+    let m = registers.len() as f64;
+    let alpha = match registers.len() {
+        16 => 0.673,
+        32 => 0.697,
+        64 => 0.709,
+        _ => 0.7213 / (1.0 + 1.079 / m),
+    };
+    let mut sum = 0.0;
+    let mut zero_registers = 0;
+    for &register in registers {
+        sum += 2f64.powi(-i32::from(register));
+        if register == 0 {
+            zero_registers += 1;
+        }
+    }
+    let estimate = alpha * m * m / sum;
+    // Small range correction
+    if estimate <= 2.5 * m && zero_registers > 0 {
+        (m * (m / f64::from(zero_registers)).ln()).round() as i64
+    } else {
+        estimate.round() as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_empty() {
+        assert_eq!(estimate_cardinality(&[0; 64]), 0);
+    }
+
+    #[test]
+    fn test_merge_packed_registers() {
+        let packed1 = pack_registers(&[1, 7, 0, 3, 50, 0, 0, 2]);
+        let packed2 = pack_registers(&[2, 5, 0, 3, 49, 1, 0, 0]);
+        assert_eq!(unpack_registers(&[merge_packed_registers(packed1, packed2)]), vec![2, 7, 0, 3, 50, 1, 0, 2]);
+    }
+}
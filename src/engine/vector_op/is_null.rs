@@ -0,0 +1,66 @@
+use engine::vector_op::vector_operator::*;
+use mem_store::value::Val;
+
+/// Determines for each value whether it is null.
+#[derive(Debug)]
+pub struct IsNull<'a> {
+    pub input: BufferRef<Val<'a>>,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for IsNull<'a> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let input = scratchpad.get(self.input);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear() }
+        for x in input.iter() {
+            output.push(if let Val::Null = *x { 1 } else { 0 });
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} IS NULL", self.input)
+    }
+}
+
+/// Determines for each value whether it is not null.
+#[derive(Debug)]
+pub struct IsNotNull<'a> {
+    pub input: BufferRef<Val<'a>>,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for IsNotNull<'a> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let input = scratchpad.get(self.input);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear() }
+        for x in input.iter() {
+            output.push(if let Val::Null = *x { 0 } else { 1 });
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} IS NOT NULL", self.input)
+    }
+}
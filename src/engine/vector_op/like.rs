@@ -0,0 +1,158 @@
+use regex::{self, Regex};
+
+use engine::*;
+use engine::vector_op::vector_operator::*;
+
+
+/// Evaluates `LIKE` or `GLOB` with an arbitrary pattern by matching every string against its regex translation.
+#[derive(Debug)]
+pub struct Like<'a> {
+    pub input: BufferRef<&'a str>,
+    pub pattern: Regex,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for Like<'a> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let strings = scratchpad.get(self.input);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear(); }
+        for s in strings.iter() {
+            output.push(self.pattern.is_match(s) as u8);
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} like /{}/", self.input, self.pattern)
+    }
+}
+
+/// Evaluates `LIKE 'prefix%'` on dictionary encoded strings.
+/// Since dictionaries are sorted, all entries starting with `prefix` occupy a contiguous range of indices
+/// which is determined once by binary search, after which each row only requires a range check.
+#[derive(Debug)]
+pub struct DictPrefixMatch<T> {
+    pub indices: BufferRef<T>,
+    pub dict_indices: BufferRef<u64>,
+    pub dict_data: BufferRef<u8>,
+    pub prefix: String,
+    pub range: Option<(usize, usize)>,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for DictPrefixMatch<T> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let (start, end) = match self.range {
+            Some(range) => range,
+            None => {
+                let dict_indices = scratchpad.get(self.dict_indices);
+                let dict_data = scratchpad.get(self.dict_data);
+                let prefix = self.prefix.as_bytes();
+                let entry = |i: usize| {
+                    let offset_len = dict_indices[i];
+                    let offset = (offset_len >> 24) as usize;
+                    let len = (offset_len & 0x00ff_ffff) as usize;
+                    &dict_data[offset..(offset + len)]
+                };
+                let start = partition_point(dict_indices.len(), |i| entry(i) < prefix);
+                let end = partition_point(dict_indices.len(), |i| entry(i) < prefix || entry(i).starts_with(prefix));
+                self.range = Some((start, end));
+                (start, end)
+            }
+        };
+        let indices = scratchpad.get(self.indices);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear(); }
+        for i in indices.iter() {
+            let i = i.cast_usize();
+            output.push((start <= i && i < end) as u8);
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.indices.any(), self.dict_indices.any(), self.dict_data.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, buffer: usize) -> bool { buffer == self.indices.i }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("dict_prefix_match({}, {}, {}, {:?})", self.indices, self.dict_indices, self.dict_data, self.prefix)
+    }
+}
+
+/// Returns the number of leading indices in `0..len` that satisfy `pred`, which must hold for a prefix of the range.
+fn partition_point<F: Fn(usize) -> bool>(len: usize, pred: F) -> usize {
+    let (mut lo, mut hi) = (0, len);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(mid) { lo = mid + 1 } else { hi = mid }
+    }
+    lo
+}
+
+/// Translates a SQL `LIKE` pattern into an anchored regular expression.
+/// `%` matches any sequence of characters and `_` matches any single character.
+pub fn like_to_regex(pattern: &str) -> Regex {
+    let mut re = String::from("(?s)^");
+    for c in pattern.chars() {
+        match c {
+            '%' => re.push_str(".*"),
+            '_' => re.push('.'),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).unwrap()
+}
+
+/// Translates a shell-style `GLOB` pattern into an anchored regular expression.
+/// `*` matches any sequence of characters, `?` matches any single character and `[...]` matches one character
+/// from a class such as `[abc]` or `[a-c]`, which is negated by a leading `!` or `^`.
+/// Unlike `LIKE`, `%` and `_` have no special meaning.
+/// Fails for invalid character classes such as `[z-a]`.
+pub fn glob_to_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    let mut re = String::from("(?s)^");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            '[' => {
+                let rest = chars.clone().collect::<String>();
+                let negated = rest.starts_with('!') || rest.starts_with('^');
+                // A `]` directly after the opening bracket (or negation) is a member of the class
+                let first = if negated { 1 } else { 0 };
+                match rest.char_indices().skip(first + 1).find(|&(_, c)| c == ']') {
+                    Some((end, _)) => {
+                        re.push('[');
+                        if negated { re.push('^'); }
+                        for c in rest[first..end].chars() {
+                            if c == '\\' || c == '[' || c == ']' || c == '&' || c == '~' { re.push('\\'); }
+                            re.push(c);
+                        }
+                        re.push(']');
+                        for _ in 0..rest[..=end].chars().count() { chars.next(); }
+                    }
+                    None => re.push_str(&regex::escape("[")),
+                }
+            }
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re)
+}
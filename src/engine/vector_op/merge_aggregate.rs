@@ -1,18 +1,19 @@
 use engine::aggregator::Aggregator;
 use engine::typed_vec::MergeOp;
 use engine::vector_op::*;
+use engine::*;
 
 
 #[derive(Debug)]
-pub struct MergeAggregate {
+pub struct MergeAggregate<T> {
     pub merge_ops: BufferRef<MergeOp>,
-    pub left: BufferRef<i64>,
-    pub right: BufferRef<i64>,
-    pub aggregated: BufferRef<i64>,
+    pub left: BufferRef<T>,
+    pub right: BufferRef<T>,
+    pub aggregated: BufferRef<T>,
     pub aggregator: Aggregator,
 }
 
-impl<'a> VecOperator<'a> for MergeAggregate {
+impl<'a, T: Combine<T> + 'a> VecOperator<'a> for MergeAggregate<T> {
     fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
         let aggregated = {
             let ops = scratchpad.get(self.merge_ops);
@@ -34,7 +35,19 @@ impl<'a> VecOperator<'a> for MergeAggregate {
     }
 }
 
-fn merge_aggregate(ops: &[MergeOp], left: &[i64], right: &[i64], aggregator: Aggregator) -> Vec<i64> {
+pub trait Combine<T>: GenericVec<T> {
+    fn combine(aggregator: Aggregator, accumulator: T, elem: T) -> T;
+}
+
+impl Combine<i64> for i64 {
+    fn combine(aggregator: Aggregator, accumulator: i64, elem: i64) -> i64 { aggregator.combine_i64(accumulator, elem) }
+}
+
+impl Combine<i128> for i128 {
+    fn combine(aggregator: Aggregator, accumulator: i128, elem: i128) -> i128 { aggregator.combine_i128(accumulator, elem) }
+}
+
+fn merge_aggregate<T: Combine<T>>(ops: &[MergeOp], left: &[T], right: &[T], aggregator: Aggregator) -> Vec<T> {
     let mut result = Vec::with_capacity(ops.len());
     let mut i = 0;
     let mut j = 0;
@@ -57,7 +70,7 @@ fn merge_aggregate(ops: &[MergeOp], left: &[i64], right: &[i64], aggregator: Agg
             MergeOp::MergeRight => {
                 // TODO(clemens): make inlining of aggregator operation possible
                 let last = result.len() - 1;
-                result[last] = aggregator.combine_i64(result[last], right[j]);
+                result[last] = T::combine(aggregator, result[last], right[j]);
                 j += 1;
             }
         }
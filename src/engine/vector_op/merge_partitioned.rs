@@ -0,0 +1,91 @@
+use std::cmp;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use engine::typed_vec::Premerge;
+use engine::vector_op::*;
+use engine::vector_op::comparator::*;
+use engine::*;
+
+
+/// Merges two sorted columns within each group of `partitioning`, which consists of runs of rows that are equal in all
+/// preceding sort columns. Outputs at most `limit` values and whether each of them was taken from `left`.
+#[derive(Debug)]
+pub struct MergePartitioned<T, C: Debug> {
+    pub partitioning: BufferRef<Premerge>,
+    pub left: BufferRef<T>,
+    pub right: BufferRef<T>,
+    pub merged: BufferRef<T>,
+    pub take_left: BufferRef<u8>,
+    pub limit: usize,
+    pub c: PhantomData<C>,
+}
+
+impl<'a, T: GenericVec<T> + 'a, C: Comparator<T> + Debug> VecOperator<'a> for MergePartitioned<T, C> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let (merged, take_left) = {
+            let partitioning = scratchpad.get(self.partitioning);
+            let left = scratchpad.get(self.left);
+            let right = scratchpad.get(self.right);
+            merge_partitioned::<_, C>(&partitioning, &left, &right, self.limit)
+        };
+        scratchpad.set(self.merged, merged);
+        scratchpad.set(self.take_left, take_left);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.partitioning.any(), self.left.any(), self.right.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.merged.any(), self.take_left.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("merge_partitioned({}, {}, {})", self.partitioning, self.left, self.right)
+    }
+}
+
+pub fn merge_partitioned<'a, T: GenericVec<T> + 'a, C: Comparator<T>>(partitioning: &[Premerge],
+                                                                      left: &[T],
+                                                                      right: &[T],
+                                                                      limit: usize) -> (Vec<T>, Vec<u8>) {
+    let len = cmp::min(left.len() + right.len(), limit);
+    let mut result = Vec::with_capacity(len);
+    let mut take_left = Vec::with_capacity(len);
+
+    let mut i = 0;
+    let mut j = 0;
+    for group in partitioning {
+        let i_max = i + group.left as usize;
+        let j_max = j + group.right as usize;
+        while (i < i_max || j < j_max) && result.len() < limit {
+            if i < i_max && (j == j_max || C::cmp_eq(left[i], right[j])) {
+                result.push(left[i]);
+                take_left.push(1);
+                i += 1;
+            } else {
+                result.push(right[j]);
+                take_left.push(0);
+                j += 1;
+            }
+        }
+    }
+    (result, take_left)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine::vector_op::partition::partition;
+
+    #[test]
+    fn test_merge_partitioned_desc() {
+        let left1 = vec![3u32, 3, 1];
+        let right1 = vec![3u32, 2, 1];
+        let partitioning = partition::<_, CmpGreaterThan>(&left1, &right1, 6);
+        let left2 = vec!["a", "b", "x"];
+        let right2 = vec!["c", "y", "w"];
+        let (merged, take_left) = merge_partitioned::<_, CmpLessThan>(&partitioning, &left2, &right2, 5);
+        assert_eq!(merged, vec!["a", "b", "c", "y", "w"]);
+        assert_eq!(take_left, vec![1, 1, 0, 0, 0]);
+    }
+}
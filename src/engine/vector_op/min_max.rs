@@ -0,0 +1,56 @@
+use std::fmt;
+use std::i64;
+use std::marker::PhantomData;
+
+use engine::vector_op::*;
+use engine::vector_op::comparator::*;
+use engine::*;
+
+
+#[derive(Debug)]
+pub struct VecMinMax<T, U, C> {
+    pub input: BufferRef<T>,
+    pub grouping: BufferRef<U>,
+    pub output: BufferRef<i64>,
+    pub max_index: BufferRef<i64>,
+    pub c: PhantomData<C>,
+}
+
+impl<'a, T, U, C> VecOperator<'a> for VecMinMax<T, U, C> where
+    T: GenericIntVec<T> + Into<i64>, U: GenericIntVec<U>, C: Comparator<i64> + fmt::Debug {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let nums = scratchpad.get(self.input);
+        let grouping = scratchpad.get(self.grouping);
+        let mut accumulators = scratchpad.get_mut(self.output);
+
+        let len = scratchpad.get_const::<i64>(&self.max_index) as usize + 1;
+        if len > accumulators.len() {
+            let initial = if C::is_less_than() { i64::MAX } else { i64::MIN };
+            accumulators.resize(len, initial);
+        }
+
+        for (i, n) in grouping.iter().zip(nums.iter()) {
+            let n = Into::<i64>::into(*n);
+            let accumulator = &mut accumulators[i.cast_usize()];
+            if C::cmp(n, *accumulator) {
+                *accumulator = n;
+            }
+        }
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(0));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.grouping.any(), self.input.any(), self.max_index.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        let op = if C::is_less_than() { "min" } else { "max" };
+        format!("{}[{}] {}= {}", self.output, self.grouping, op, self.input)
+    }
+    fn display_output(&self) -> bool { false }
+}
@@ -3,11 +3,18 @@ pub mod executor;
 pub mod comparator;
 
 mod addition_vs;
+mod aggregate_states;
 mod bit_unpack;
 mod bool_op;
+mod cast_strings;
+mod cast_vals;
+mod checked_division_sv;
+mod checked_division_vv;
 mod column_ops;
+mod combined_aggregation;
 mod compact;
 mod constant;
+mod constant_bool_vec;
 mod constant_vec;
 mod count;
 mod delta_decode;
@@ -16,6 +23,11 @@ mod division_vs;
 mod encode_const;
 mod exists;
 mod filter;
+mod filter_count;
+mod filter_indices;
+mod fuse_nulls;
+mod is_null;
+mod like;
 mod hashmap_grouping;
 mod hashmap_grouping_byte_slices;
 mod merge;
@@ -23,10 +35,17 @@ mod merge_aggregate;
 mod merge_deduplicate;
 mod merge_drop;
 mod merge_keep;
+mod min_max;
+mod modulo_vs;
+mod moments;
 mod nonzero_compact;
+mod not;
+mod null_vec;
 mod nonzero_indices;
 mod parameterized_vec_vec_int_op;
 mod select;
+mod simd_compare;
+mod sort_by;
 mod sort_indices;
 mod sum;
 mod to_year;
@@ -34,10 +53,14 @@ mod top_n;
 mod unhexpack_strings;
 mod unpack_strings;
 mod type_conversion;
+mod unpack_bits;
 mod vec_const_bool_op;
+mod vec_vec_bool_op;
 #[cfg(feature = "enable_lz4")]
 mod lz4_decode;
+pub mod hyperloglog;
 pub mod merge_deduplicate_partitioned;
+pub mod merge_partitioned;
 pub mod partition;
 pub mod subpartition;
 pub mod slice_pack;
@@ -45,5 +68,6 @@ pub mod slice_unpack;
 
 pub use self::vector_operator::*;
 pub use self::executor::QueryExecutor;
+pub use self::like::glob_to_regex;
 
 
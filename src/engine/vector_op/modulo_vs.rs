@@ -0,0 +1,37 @@
+use engine::vector_op::vector_operator::*;
+
+
+/// Computes the remainder of dividing each element by a nonzero constant.
+#[derive(Debug)]
+pub struct ModuloVS {
+    pub lhs: BufferRef<i64>,
+    pub rhs: BufferRef<i64>,
+    pub output: BufferRef<i64>,
+}
+
+impl<'a> VecOperator<'a> for ModuloVS {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear(); }
+        let data = scratchpad.get(self.lhs);
+        let c = scratchpad.get_const::<i64>(&self.rhs);
+        for d in data.iter() {
+            // Only overflows for i64::MIN % -1, modulo zero is turned into null during query planning
+            output.push(d.checked_rem(c).unwrap_or(0));
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.lhs.any(), self.rhs.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} % {}", self.lhs, self.rhs)
+    }
+}
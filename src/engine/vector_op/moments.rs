@@ -0,0 +1,126 @@
+use engine::aggregator::Moments;
+use engine::typed_vec::MergeOp;
+use engine::vector_op::*;
+use engine::*;
+
+
+/// Computes the count, mean and sum of squared differences from the mean (M2) of the values in each group.
+/// Mean and M2 are stored as the bits of their f64 value to allow them to be merged like other intermediate results.
+#[derive(Debug)]
+pub struct VecMoments<T, U> {
+    pub input: BufferRef<T>,
+    pub grouping: BufferRef<U>,
+    pub count: BufferRef<i64>,
+    pub mean: BufferRef<i64>,
+    pub m2: BufferRef<i64>,
+    pub max_index: BufferRef<i64>,
+    pub moments: Vec<Moments>,
+}
+
+impl<'a, T, U> VecOperator<'a> for VecMoments<T, U> where
+    T: GenericIntVec<T> + Into<i64>, U: GenericIntVec<U> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let nums = scratchpad.get(self.input);
+        let grouping = scratchpad.get(self.grouping);
+
+        let len = scratchpad.get_const::<i64>(&self.max_index) as usize + 1;
+        if len > self.moments.len() {
+            self.moments.resize(len, Moments::default());
+        }
+
+        for (i, n) in grouping.iter().zip(nums.iter()) {
+            self.moments[i.cast_usize()].push(Into::<i64>::into(*n) as f64);
+        }
+    }
+
+    fn finalize(&mut self, scratchpad: &mut Scratchpad<'a>) {
+        let mut count = scratchpad.get_mut(self.count);
+        let mut mean = scratchpad.get_mut(self.mean);
+        let mut m2 = scratchpad.get_mut(self.m2);
+        for moments in &self.moments {
+            let intermediate = moments.to_intermediate();
+            count.push(intermediate[0]);
+            mean.push(intermediate[1]);
+            m2.push(intermediate[2]);
+        }
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.count, Vec::with_capacity(0));
+        scratchpad.set(self.mean, Vec::with_capacity(0));
+        scratchpad.set(self.m2, Vec::with_capacity(0));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.grouping.any(), self.input.any(), self.max_index.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.count.any(), self.mean.any(), self.m2.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("moments[{}]({})", self.grouping, self.input)
+    }
+}
+
+/// Merges the count, mean and M2 columns of two aggregation results, which have to be combined jointly.
+#[derive(Debug)]
+pub struct MergeMoments {
+    pub merge_ops: BufferRef<MergeOp>,
+    pub left: [BufferRef<i64>; 3],
+    pub right: [BufferRef<i64>; 3],
+    pub merged: [BufferRef<i64>; 3],
+}
+
+impl<'a> VecOperator<'a> for MergeMoments {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let merged = {
+            let ops = scratchpad.get(self.merge_ops);
+            let left = Moments::read_intermediate(
+                &scratchpad.get(self.left[0]), &scratchpad.get(self.left[1]), &scratchpad.get(self.left[2]));
+            let right = Moments::read_intermediate(
+                &scratchpad.get(self.right[0]), &scratchpad.get(self.right[1]), &scratchpad.get(self.right[2]));
+            merge_moments(&ops, &left, &right)
+        };
+        for (column, &output) in self.merged.iter().enumerate() {
+            scratchpad.set(output, merged.iter().map(|moments| moments.to_intermediate()[column]).collect());
+        }
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> {
+        let mut inputs = vec![self.merge_ops.any()];
+        inputs.extend(self.left.iter().chain(self.right.iter()).map(|b| b.any()));
+        inputs
+    }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { self.merged.iter().map(|b| b.any()).collect() }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("merge_moments({}; {}, {})", self.merge_ops, self.left[0], self.right[0])
+    }
+}
+
+fn merge_moments(ops: &[MergeOp], left: &[Moments], right: &[Moments]) -> Vec<Moments> {
+    let mut result = Vec::with_capacity(ops.len());
+    let mut i = 0;
+    let mut j = 0;
+    for op in ops {
+        match *op {
+            MergeOp::TakeLeft => {
+                result.push(left[i]);
+                i += 1;
+            }
+            MergeOp::TakeRight => {
+                result.push(right[j]);
+                j += 1;
+            }
+            MergeOp::MergeRight => {
+                let last = result.len() - 1;
+                result[last] = result[last].merge(right[j]);
+                j += 1;
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,33 @@
+use engine::vector_op::vector_operator::*;
+
+
+#[derive(Debug)]
+pub struct Not {
+    pub input: BufferRef<u8>,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for Not {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let input = scratchpad.get(self.input);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear() }
+        for &x in input.iter() {
+            output.push(x ^ 1);
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("!{}", self.input)
+    }
+}
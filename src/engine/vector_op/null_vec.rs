@@ -0,0 +1,27 @@
+use engine::typed_vec::AnyVec;
+use engine::vector_op::vector_operator::*;
+
+
+/// Produces a vector of nulls with the same length as `input`.
+#[derive(Debug)]
+pub struct NullVec {
+    pub input: BufferRef<Any>,
+    pub output: BufferRef<Any>,
+}
+
+impl<'a> VecOperator<'a> for NullVec {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let len = scratchpad.get_any(self.input).len();
+        scratchpad.set_any(self.output, AnyVec::empty(len));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("null({})", self.input)
+    }
+}
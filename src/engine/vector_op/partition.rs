@@ -1,25 +1,29 @@
 use std::cmp;
+use std::fmt::Debug;
+use std::marker::PhantomData;
 use std::u32;
 
 use engine::typed_vec::Premerge;
 use engine::vector_op::*;
+use engine::vector_op::comparator::*;
 use engine::*;
 
 
 #[derive(Debug)]
-pub struct Partition<T> {
+pub struct Partition<T, C: Debug> {
     pub left: BufferRef<T>,
     pub right: BufferRef<T>,
     pub partitioning: BufferRef<Premerge>,
     pub limit: usize,
+    pub c: PhantomData<C>,
 }
 
-impl<'a, T: GenericVec<T> + 'a> VecOperator<'a> for Partition<T> {
+impl<'a, T: GenericVec<T> + 'a, C: Comparator<T> + Debug> VecOperator<'a> for Partition<T, C> {
     fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
         let premerge = {
             let left = scratchpad.get(self.left);
             let right = scratchpad.get(self.right);
-            partition(&left, &right, self.limit)
+            partition::<_, C>(&left, &right, self.limit)
         };
         scratchpad.set(self.partitioning, premerge);
     }
@@ -35,7 +39,9 @@ impl<'a, T: GenericVec<T> + 'a> VecOperator<'a> for Partition<T> {
     }
 }
 
-pub fn partition<'a, T: GenericVec<T> + 'a>(left: &[T], right: &[T], limit: usize) -> Vec<Premerge> {
+/// Splits two columns sorted according to `C` into runs of equal values, stopping once the runs contain at least
+/// `limit` values of either column.
+pub fn partition<'a, T: GenericVec<T> + 'a, C: Comparator<T>>(left: &[T], right: &[T], limit: usize) -> Vec<Premerge> {
     let mut result = Vec::new();
     let mut i = 0;
     let mut j = 0;
@@ -43,7 +49,7 @@ pub fn partition<'a, T: GenericVec<T> + 'a>(left: &[T], right: &[T], limit: usiz
     let mut min_elems = 0u32;
     while i < left.len() && j < right.len() && min_elems < limit {
         let mut partition = Premerge { left: 0, right: 0 };
-        let elem = if left[i] <= right[j] { left[i] } else { right[j] };
+        let elem = if C::cmp_eq(left[i], right[j]) { left[i] } else { right[j] };
         while i < left.len() && elem == left[i] {
             partition.left += 1;
             i += 1;
@@ -0,0 +1,242 @@
+#[cfg(all(feature = "enable_simd", target_arch = "x86_64"))]
+use std::arch::x86_64::*;
+
+
+/// Comparisons of integer slices against a constant that write one byte (0 or 1) per element to `output`.
+/// Specialized to use SIMD instructions for `u8`, `u16`, `u32` and `i64` when compiled with `enable_simd`.
+pub trait SimdCompare: Sized {
+    fn less_than(data: &[Self], c: i64, output: &mut Vec<u8>);
+    fn equals(data: &[Self], c: i64, output: &mut Vec<u8>);
+}
+
+impl<T: Into<i64> + Copy> SimdCompare for T {
+    default fn less_than(data: &[T], c: i64, output: &mut Vec<u8>) {
+        scalar_less_than(data, c, output);
+    }
+
+    default fn equals(data: &[T], c: i64, output: &mut Vec<u8>) {
+        scalar_equals(data, c, output);
+    }
+}
+
+#[inline]
+pub fn scalar_less_than<T: Into<i64> + Copy>(data: &[T], c: i64, output: &mut Vec<u8>) {
+    for &d in data {
+        output.push((d.into() < c) as u8);
+    }
+}
+
+#[inline]
+pub fn scalar_equals<T: Into<i64> + Copy>(data: &[T], c: i64, output: &mut Vec<u8>) {
+    for &d in data {
+        output.push((d.into() == c) as u8);
+    }
+}
+
+#[cfg(all(feature = "enable_simd", target_arch = "x86_64"))]
+impl SimdCompare for u8 {
+    fn less_than(data: &[u8], c: i64, output: &mut Vec<u8>) {
+        if c <= 0 || c > 255 { return fill(output, data.len(), c > 255); }
+        let simd_len = data.len() - data.len() % 16;
+        unsafe {
+            // x < c <=> min(x, c - 1) == x
+            let max = _mm_set1_epi8((c - 1) as u8 as i8);
+            for i in (0..simd_len).step_by(16) {
+                let x = _mm_loadu_si128(data.as_ptr().add(i) as *const __m128i);
+                store_mask(output, _mm_cmpeq_epi8(_mm_min_epu8(x, max), x));
+            }
+        }
+        scalar_less_than(&data[simd_len..], c, output);
+    }
+
+    fn equals(data: &[u8], c: i64, output: &mut Vec<u8>) {
+        if c < 0 || c > 255 { return fill(output, data.len(), false); }
+        let simd_len = data.len() - data.len() % 16;
+        unsafe {
+            let c_vec = _mm_set1_epi8(c as u8 as i8);
+            for i in (0..simd_len).step_by(16) {
+                let x = _mm_loadu_si128(data.as_ptr().add(i) as *const __m128i);
+                store_mask(output, _mm_cmpeq_epi8(x, c_vec));
+            }
+        }
+        scalar_equals(&data[simd_len..], c, output);
+    }
+}
+
+#[cfg(all(feature = "enable_simd", target_arch = "x86_64"))]
+impl SimdCompare for u16 {
+    fn less_than(data: &[u16], c: i64, output: &mut Vec<u8>) {
+        if c <= 0 || c > 0xffff { return fill(output, data.len(), c > 0xffff); }
+        let simd_len = data.len() - data.len() % 16;
+        unsafe {
+            // Flipping the sign bit turns unsigned into signed comparisons
+            let sign = _mm_set1_epi16(-0x8000);
+            let c_vec = _mm_set1_epi16((c as u16 ^ 0x8000) as i16);
+            for i in (0..simd_len).step_by(16) {
+                let ptr = data.as_ptr().add(i) as *const __m128i;
+                let lt0 = _mm_cmplt_epi16(_mm_xor_si128(_mm_loadu_si128(ptr), sign), c_vec);
+                let lt1 = _mm_cmplt_epi16(_mm_xor_si128(_mm_loadu_si128(ptr.add(1)), sign), c_vec);
+                store_mask(output, _mm_packs_epi16(lt0, lt1));
+            }
+        }
+        scalar_less_than(&data[simd_len..], c, output);
+    }
+
+    fn equals(data: &[u16], c: i64, output: &mut Vec<u8>) {
+        if c < 0 || c > 0xffff { return fill(output, data.len(), false); }
+        let simd_len = data.len() - data.len() % 16;
+        unsafe {
+            let c_vec = _mm_set1_epi16(c as u16 as i16);
+            for i in (0..simd_len).step_by(16) {
+                let ptr = data.as_ptr().add(i) as *const __m128i;
+                let eq0 = _mm_cmpeq_epi16(_mm_loadu_si128(ptr), c_vec);
+                let eq1 = _mm_cmpeq_epi16(_mm_loadu_si128(ptr.add(1)), c_vec);
+                store_mask(output, _mm_packs_epi16(eq0, eq1));
+            }
+        }
+        scalar_equals(&data[simd_len..], c, output);
+    }
+}
+
+#[cfg(all(feature = "enable_simd", target_arch = "x86_64"))]
+impl SimdCompare for u32 {
+    fn less_than(data: &[u32], c: i64, output: &mut Vec<u8>) {
+        if c <= 0 || c > 0xffff_ffff { return fill(output, data.len(), c > 0xffff_ffff); }
+        let simd_len = data.len() - data.len() % 16;
+        unsafe {
+            let sign = _mm_set1_epi32(i32::min_value());
+            let c_vec = _mm_set1_epi32((c as u32 ^ 0x8000_0000) as i32);
+            for i in (0..simd_len).step_by(16) {
+                let ptr = data.as_ptr().add(i) as *const __m128i;
+                let mut lt = [_mm_setzero_si128(); 4];
+                for (j, lt) in lt.iter_mut().enumerate() {
+                    *lt = _mm_cmplt_epi32(_mm_xor_si128(_mm_loadu_si128(ptr.add(j)), sign), c_vec);
+                }
+                store_mask(output, _mm_packs_epi16(_mm_packs_epi32(lt[0], lt[1]), _mm_packs_epi32(lt[2], lt[3])));
+            }
+        }
+        scalar_less_than(&data[simd_len..], c, output);
+    }
+
+    fn equals(data: &[u32], c: i64, output: &mut Vec<u8>) {
+        if c < 0 || c > 0xffff_ffff { return fill(output, data.len(), false); }
+        let simd_len = data.len() - data.len() % 16;
+        unsafe {
+            let c_vec = _mm_set1_epi32(c as u32 as i32);
+            for i in (0..simd_len).step_by(16) {
+                let ptr = data.as_ptr().add(i) as *const __m128i;
+                let mut eq = [_mm_setzero_si128(); 4];
+                for (j, eq) in eq.iter_mut().enumerate() {
+                    *eq = _mm_cmpeq_epi32(_mm_loadu_si128(ptr.add(j)), c_vec);
+                }
+                store_mask(output, _mm_packs_epi16(_mm_packs_epi32(eq[0], eq[1]), _mm_packs_epi32(eq[2], eq[3])));
+            }
+        }
+        scalar_equals(&data[simd_len..], c, output);
+    }
+}
+
+#[cfg(all(feature = "enable_simd", target_arch = "x86_64"))]
+impl SimdCompare for i64 {
+    fn less_than(data: &[i64], c: i64, output: &mut Vec<u8>) {
+        if is_x86_feature_detected!("avx2") {
+            unsafe { avx2_less_than_i64(data, c, output) }
+        } else {
+            scalar_less_than(data, c, output)
+        }
+    }
+
+    fn equals(data: &[i64], c: i64, output: &mut Vec<u8>) {
+        if is_x86_feature_detected!("avx2") {
+            unsafe { avx2_equals_i64(data, c, output) }
+        } else {
+            scalar_equals(data, c, output)
+        }
+    }
+}
+
+#[cfg(all(feature = "enable_simd", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn avx2_less_than_i64(data: &[i64], c: i64, output: &mut Vec<u8>) {
+    let simd_len = data.len() - data.len() % 4;
+    let c_vec = _mm256_set1_epi64x(c);
+    for i in (0..simd_len).step_by(4) {
+        let x = _mm256_loadu_si256(data.as_ptr().add(i) as *const __m256i);
+        push_mask4(output, _mm256_movemask_pd(_mm256_castsi256_pd(_mm256_cmpgt_epi64(c_vec, x))));
+    }
+    scalar_less_than(&data[simd_len..], c, output);
+}
+
+#[cfg(all(feature = "enable_simd", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn avx2_equals_i64(data: &[i64], c: i64, output: &mut Vec<u8>) {
+    let simd_len = data.len() - data.len() % 4;
+    let c_vec = _mm256_set1_epi64x(c);
+    for i in (0..simd_len).step_by(4) {
+        let x = _mm256_loadu_si256(data.as_ptr().add(i) as *const __m256i);
+        push_mask4(output, _mm256_movemask_pd(_mm256_castsi256_pd(_mm256_cmpeq_epi64(x, c_vec))));
+    }
+    scalar_equals(&data[simd_len..], c, output);
+}
+
+/// Appends 16 bytes with the lowest bit of each byte in `mask`.
+#[cfg(all(feature = "enable_simd", target_arch = "x86_64"))]
+#[inline]
+unsafe fn store_mask(output: &mut Vec<u8>, mask: __m128i) {
+    let len = output.len();
+    output.reserve(16);
+    _mm_storeu_si128(output.as_mut_ptr().add(len) as *mut __m128i, _mm_and_si128(mask, _mm_set1_epi8(1)));
+    output.set_len(len + 16);
+}
+
+#[cfg(all(feature = "enable_simd", target_arch = "x86_64"))]
+#[inline]
+fn push_mask4(output: &mut Vec<u8>, mask: i32) {
+    for bit in 0..4 {
+        output.push(((mask >> bit) & 1) as u8);
+    }
+}
+
+#[cfg(all(feature = "enable_simd", target_arch = "x86_64"))]
+fn fill(output: &mut Vec<u8>, len: usize, value: bool) {
+    let new_len = output.len() + len;
+    output.resize(new_len, value as u8);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use bit_vec::BitVec;
+
+    fn check<T: Into<i64> + Copy>(data: &[T], constants: &[i64]) {
+        for &c in constants {
+            let (mut simd, mut scalar) = (Vec::new(), Vec::new());
+            T::less_than(data, c, &mut simd);
+            scalar_less_than(data, c, &mut scalar);
+            assert_eq!(BitVec::from_fn(simd.len(), |i| simd[i] == 1), BitVec::from_fn(scalar.len(), |i| scalar[i] == 1));
+            assert_eq!(simd, scalar);
+
+            let (mut simd, mut scalar) = (Vec::new(), Vec::new());
+            T::equals(data, c, &mut simd);
+            scalar_equals(data, c, &mut scalar);
+            assert_eq!(simd, scalar);
+        }
+    }
+
+    #[test]
+    fn test_simd_matches_scalar() {
+        let mut rng = XorShiftRng::from_seed([7; 16]);
+        // Odd length to exercise the scalar remainder
+        let len = 1003;
+        let u8s = (0..len).map(|_| rng.gen::<u8>()).collect::<Vec<_>>();
+        let u16s = (0..len).map(|_| rng.gen::<u16>()).collect::<Vec<_>>();
+        let u32s = (0..len).map(|_| rng.gen::<u32>()).collect::<Vec<_>>();
+        let i64s = (0..len).map(|_| rng.gen_range(-1000, 1000)).collect::<Vec<i64>>();
+        check(&u8s, &[-1, 0, 1, i64::from(u8s[17]), 128, 255, 256]);
+        check(&u16s, &[-1, 0, 1, i64::from(u16s[17]), 0x8000, 0xffff, 0x10000]);
+        check(&u32s, &[-1, 0, 1, i64::from(u32s[17]), 0x8000_0000, 0xffff_ffff, 0x1_0000_0000]);
+        check(&i64s, &[i64::min_value(), -1000, 0, i64s[17], 999, i64::max_value()]);
+    }
+}
@@ -0,0 +1,48 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use engine::*;
+use engine::vector_op::*;
+use engine::vector_op::comparator::*;
+
+
+/// Stably sorts `indices` by the values of `ranking` at each index and keeps the first `limit` indices.
+/// Applied to indices that are already sorted by all subsequent ORDER BY columns, this sorts by multiple columns.
+#[derive(Debug)]
+pub struct SortBy<T, C: Debug> {
+    pub ranking: BufferRef<T>,
+    pub indices: BufferRef<usize>,
+    pub output: BufferRef<usize>,
+    pub limit: usize,
+    pub c: PhantomData<C>,
+}
+
+impl<'a, T: GenericVec<T> + 'a, C: Comparator<T> + Debug> VecOperator<'a> for SortBy<T, C> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let result = {
+            let ranking = scratchpad.get(self.ranking);
+            let mut result = scratchpad.get(self.indices).to_vec();
+            result.sort_by(|&i, &j| if C::cmp(ranking[i], ranking[j]) {
+                Ordering::Less
+            } else if C::cmp(ranking[j], ranking[i]) {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            });
+            result.truncate(self.limit);
+            result
+        };
+        scratchpad.set(self.output, result);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.ranking.any(), self.indices.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("sort_by({}, {}; limit={})", self.ranking, self.indices, self.limit)
+    }
+}
@@ -1,4 +1,5 @@
 use engine::vector_op::vector_operator::*;
+use syntax::collation::Collation;
 
 
 #[derive(Debug)]
@@ -6,17 +7,25 @@ pub struct SortIndices {
     pub input: BufferRef<Any>,
     pub output: BufferRef<usize>,
     pub descending: bool,
+    pub collation: Collation,
 }
 
 impl<'a> VecOperator<'a> for SortIndices {
     fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
         let result = {
             let input = scratchpad.get_any(self.input);
-            let mut result = (0..input.len()).collect();
-            if self.descending {
-                input.sort_indices_desc(&mut result);
-            } else {
-                input.sort_indices_asc(&mut result);
+            let mut result = (0..input.len()).collect::<Vec<_>>();
+            match (self.collation, self.descending) {
+                (Collation::Binary, true) => input.sort_indices_desc(&mut result),
+                (Collation::Binary, false) => input.sort_indices_asc(&mut result),
+                (collation, descending) => {
+                    let strings = input.cast_ref_str();
+                    if descending {
+                        result.sort_by(|&i, &j| collation.cmp(strings[j], strings[i]));
+                    } else {
+                        result.sort_by(|&i, &j| collation.cmp(strings[i], strings[j]));
+                    }
+                }
             }
             result
         };
@@ -30,6 +39,9 @@ impl<'a> VecOperator<'a> for SortIndices {
     fn allocates(&self) -> bool { true }
 
     fn display_op(&self, _: bool) -> String {
-        format!("sort_indices({}; desc={})", self.input, self.descending)
+        match self.collation {
+            Collation::Binary => format!("sort_indices({}; desc={})", self.input, self.descending),
+            collation => format!("sort_indices({}; desc={}, collation={:?})", self.input, self.descending, collation),
+        }
     }
 }
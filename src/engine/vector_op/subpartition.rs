@@ -1,23 +1,28 @@
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
 use engine::typed_vec::Premerge;
 use engine::vector_op::*;
+use engine::vector_op::comparator::*;
 use engine::*;
 
 
 #[derive(Debug)]
-pub struct SubPartition<T> {
+pub struct SubPartition<T, C: Debug> {
     pub partitioning: BufferRef<Premerge>,
     pub left: BufferRef<T>,
     pub right: BufferRef<T>,
     pub sub_partitioning: BufferRef<Premerge>,
+    pub c: PhantomData<C>,
 }
 
-impl<'a, T: GenericVec<T> + 'a> VecOperator<'a> for SubPartition<T> {
+impl<'a, T: GenericVec<T> + 'a, C: Comparator<T> + Debug> VecOperator<'a> for SubPartition<T, C> {
     fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
         let sub_partitioning = {
             let partitioning = scratchpad.get(self.partitioning);
             let left = scratchpad.get(self.left);
             let right = scratchpad.get(self.right);
-            subpartition(&partitioning, &left, &right)
+            subpartition::<_, C>(&partitioning, &left, &right)
         };
         scratchpad.set(self.sub_partitioning, sub_partitioning);
     }
@@ -33,7 +38,7 @@ impl<'a, T: GenericVec<T> + 'a> VecOperator<'a> for SubPartition<T> {
     }
 }
 
-fn subpartition<'a, T: GenericVec<T> + 'a>(
+fn subpartition<'a, T: GenericVec<T> + 'a, C: Comparator<T>>(
     partitioning: &[Premerge],
     left: &[T],
     right: &[T]) -> Vec<Premerge> {
@@ -47,7 +52,7 @@ fn subpartition<'a, T: GenericVec<T> + 'a>(
         let j_max = j + group.right as usize;
         while i < i_max || j < j_max {
             let mut subpartition = Premerge { left: 0, right: 0 };
-            let elem = if i < i_max && (j == j_max || left[i] <= right[j]) { left[i] } else { right[j] };
+            let elem = if i < i_max && (j == j_max || C::cmp_eq(left[i], right[j])) { left[i] } else { right[j] };
             while i < i_max && elem == left[i] {
                 subpartition.left += 1;
                 i += 1;
@@ -67,6 +72,7 @@ fn subpartition<'a, T: GenericVec<T> + 'a>(
 mod tests {
     use engine::typed_vec::Premerge;
     use engine::typed_vec::MergeOp::*;
+    use engine::vector_op::comparator::CmpLessThan;
     use engine::vector_op::partition::partition;
     use engine::vector_op::subpartition::subpartition;
     use engine::vector_op::merge_deduplicate_partitioned::merge_deduplicate_partitioned;
@@ -75,7 +81,7 @@ mod tests {
     fn test_multipass_grouping() {
         let left1 = vec!["A", "A", "A", "C", "P"];
         let right1 = vec!["A", "A", "B", "C", "X", "X", "Z"];
-        let result = partition::<&str>(&left1, &right1, 7);
+        let result = partition::<&str, CmpLessThan>(&left1, &right1, 7);
         assert_eq!(result, vec![
             Premerge { left: 3, right: 2 },
             Premerge { left: 0, right: 1 },
@@ -102,7 +108,7 @@ mod tests {
             TakeRight,
         ]);
 
-        let subpartition = subpartition::<u32>(&result, &left2, &right2);
+        let subpartition = subpartition::<u32, CmpLessThan>(&result, &left2, &right2);
         assert_eq!(subpartition, vec![
             Premerge { left: 1, right: 0 },
             Premerge { left: 1, right: 1 },
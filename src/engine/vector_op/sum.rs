@@ -2,12 +2,17 @@ use engine::vector_op::*;
 use engine::*;
 
 
+/// Sums values by group, accumulating into 128 bit integers so that sums of 64 bit integers can not overflow.
 #[derive(Debug)]
 pub struct VecSum<T, U> {
     pub input: BufferRef<T>,
     pub grouping: BufferRef<U>,
-    pub output: BufferRef<i64>,
+    pub output: BufferRef<i128>,
     pub max_index: BufferRef<i64>,
+    /// Added to every input value, allows summing integer offset encoded columns without decoding them
+    pub offset: i64,
+    /// Number of values in each group, only tracked if `offset` is nonzero
+    pub counts: Vec<i64>,
 }
 
 impl<'a, T, U> VecOperator<'a> for VecSum<T, U> where
@@ -22,8 +27,27 @@ impl<'a, T, U> VecOperator<'a> for VecSum<T, U> where
             sums.resize(len, 0);
         }
 
-        for (i, n) in grouping.iter().zip(nums.iter()) {
-            sums[i.cast_usize()] += Into::<i64>::into(*n);
+        if self.offset == 0 {
+            for (i, n) in grouping.iter().zip(nums.iter()) {
+                sums[i.cast_usize()] += i128::from(Into::<i64>::into(*n));
+            }
+        } else {
+            if len > self.counts.len() {
+                self.counts.resize(len, 0);
+            }
+            for (i, n) in grouping.iter().zip(nums.iter()) {
+                sums[i.cast_usize()] += i128::from(Into::<i64>::into(*n));
+                self.counts[i.cast_usize()] += 1;
+            }
+        }
+    }
+
+    fn finalize(&mut self, scratchpad: &mut Scratchpad<'a>) {
+        if self.offset != 0 {
+            let mut sums = scratchpad.get_mut(self.output);
+            for (sum, &count) in sums.iter_mut().zip(&self.counts) {
+                *sum += i128::from(self.offset) * i128::from(count);
+            }
         }
     }
 
@@ -38,7 +62,11 @@ impl<'a, T, U> VecOperator<'a> for VecSum<T, U> where
     fn allocates(&self) -> bool { true }
 
     fn display_op(&self, _: bool) -> String {
-        format!("{}[{}] += {}", self.output, self.grouping, self.input)
+        if self.offset == 0 {
+            format!("{}[{}] += {}", self.output, self.grouping, self.input)
+        } else {
+            format!("{}[{}] += {} (offset {})", self.output, self.grouping, self.input, self.offset)
+        }
     }
     fn display_output(&self) -> bool { false }
 }
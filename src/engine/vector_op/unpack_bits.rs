@@ -0,0 +1,52 @@
+use std::cmp;
+
+use engine::vector_op::vector_operator::*;
+use mem_store::bit_packing;
+
+
+#[derive(Debug)]
+pub struct UnpackBits {
+    pub packed: BufferRef<u8>,
+    pub unpacked: BufferRef<u8>,
+    pub width: u8,
+    pub decoded_len: usize,
+    pub batch_size: usize,
+    pub position: usize,
+    pub has_more: bool,
+}
+
+impl<'a> VecOperator<'a> for UnpackBits {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let packed = scratchpad.get(self.packed);
+        let mut unpacked = scratchpad.get_mut(self.unpacked);
+        if stream { unpacked.clear(); }
+        let end = cmp::min(self.position + self.batch_size, self.decoded_len);
+        for i in self.position..end {
+            unpacked.push(bit_packing::unpack(&packed, self.width, i));
+        }
+        self.position = end;
+        self.has_more = end < self.decoded_len;
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.unpacked, Vec::with_capacity(batch_size));
+        self.batch_size = batch_size;
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.packed.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.unpacked.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+    fn is_streaming_producer(&self) -> bool { true }
+    fn has_more(&self) -> bool { self.has_more }
+    fn custom_output_len(&self) -> Option<usize> { Some(self.decoded_len) }
+
+    fn display_op(&self, alternate: bool) -> String {
+        if alternate {
+            format!("unpack_bits({}, {})", self.packed, self.width)
+        } else {
+            format!("unpack_bits({})", self.packed)
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use engine::*;
+use engine::vector_op::simd_compare::SimdCompare;
 use engine::vector_op::vector_operator::*;
+use std::cmp::Ordering;
 use std::fmt;
 use std::marker::PhantomData;
+use syntax::collation::Collation;
 
 
 #[derive(Debug)]
@@ -19,9 +22,7 @@ impl<'a, T: 'a, U, Op> VecOperator<'a> for VecConstBoolOperator<T, U, Op> where
         let c = &scratchpad.get_const::<U>(&self.rhs);
         let mut output = scratchpad.get_mut(self.output);
         if stream { output.clear(); }
-        for d in data.iter() {
-            output.push(Op::perform(d, &c));
-        }
+        Op::perform_all(&data, c, &mut output);
     }
 
     fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
@@ -42,6 +43,13 @@ impl<'a, T: 'a, U, Op> VecOperator<'a> for VecConstBoolOperator<T, U, Op> where
 pub trait BoolOperation<T, U> {
     fn perform(lhs: &T, rhs: &U) -> u8;
     fn symbol() -> &'static str;
+
+    #[inline]
+    fn perform_all(lhs: &[T], rhs: &U, output: &mut Vec<u8>) {
+        for l in lhs {
+            output.push(Self::perform(l, rhs));
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -51,6 +59,16 @@ impl<T: Into<i64> + Copy> BoolOperation<T, i64> for LessThanInt<T> {
     #[inline]
     fn perform(l: &T, r: &i64) -> u8 { (Into::<i64>::into(*l) < *r) as u8 }
     fn symbol() -> &'static str { "<" }
+    fn perform_all(l: &[T], r: &i64, output: &mut Vec<u8>) { T::less_than(l, *r, output) }
+}
+
+#[derive(Debug)]
+pub struct GreaterThanInt<T> { t: PhantomData<T> }
+
+impl<T: Into<i64> + Copy> BoolOperation<T, i64> for GreaterThanInt<T> {
+    #[inline]
+    fn perform(l: &T, r: &i64) -> u8 { (Into::<i64>::into(*l) > *r) as u8 }
+    fn symbol() -> &'static str { ">" }
 }
 
 #[derive(Debug)]
@@ -60,6 +78,7 @@ impl<T: Into<i64> + Copy> BoolOperation<T, i64> for EqualsInt<T> {
     #[inline]
     fn perform(l: &T, r: &i64) -> u8 { (Into::<i64>::into(*l) == *r) as u8 }
     fn symbol() -> &'static str { "==" }
+    fn perform_all(l: &[T], r: &i64, output: &mut Vec<u8>) { T::equals(l, *r, output) }
 }
 
 #[derive(Debug)]
@@ -89,3 +108,21 @@ impl<'a> BoolOperation<&'a str, String> for NotEqualsString {
     fn perform(l: &&'a str, r: &String) -> u8 { (l != r) as u8 }
     fn symbol() -> &'static str { "<>" }
 }
+
+#[derive(Debug)]
+pub struct EqualsStringNoCase;
+
+impl<'a> BoolOperation<&'a str, String> for EqualsStringNoCase {
+    #[inline]
+    fn perform(l: &&'a str, r: &String) -> u8 { (Collation::NoCase.cmp(l, r) == Ordering::Equal) as u8 }
+    fn symbol() -> &'static str { "== (nocase)" }
+}
+
+#[derive(Debug)]
+pub struct NotEqualsStringNoCase;
+
+impl<'a> BoolOperation<&'a str, String> for NotEqualsStringNoCase {
+    #[inline]
+    fn perform(l: &&'a str, r: &String) -> u8 { (Collation::NoCase.cmp(l, r) != Ordering::Equal) as u8 }
+    fn symbol() -> &'static str { "<> (nocase)" }
+}
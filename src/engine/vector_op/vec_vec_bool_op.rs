@@ -0,0 +1,70 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use engine::*;
+use engine::vector_op::vec_const_bool_op::BoolOperation;
+use engine::vector_op::vector_operator::*;
+
+
+/// Compares two vectors of the same type elementwise.
+#[derive(Debug)]
+pub struct VecVecBoolOperator<T, Op> {
+    pub lhs: BufferRef<T>,
+    pub rhs: BufferRef<T>,
+    pub output: BufferRef<u8>,
+    pub op: PhantomData<Op>,
+}
+
+impl<'a, T: 'a, Op> VecOperator<'a> for VecVecBoolOperator<T, Op> where
+    T: GenericVec<T>, Op: BoolOperation<T, T> + fmt::Debug {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let lhs = scratchpad.get(self.lhs);
+        let rhs = scratchpad.get(self.rhs);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear(); }
+        for (l, r) in lhs.iter().zip(rhs.iter()) {
+            output.push(Op::perform(l, r));
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.lhs.any(), self.rhs.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} {} {}", self.lhs, Op::symbol(), self.rhs)
+    }
+}
+
+#[derive(Debug)]
+pub struct LessThanVV;
+
+impl<T: PartialOrd> BoolOperation<T, T> for LessThanVV {
+    #[inline]
+    fn perform(l: &T, r: &T) -> u8 { (l < r) as u8 }
+    fn symbol() -> &'static str { "<" }
+}
+
+#[derive(Debug)]
+pub struct EqualsVV;
+
+impl<T: PartialEq> BoolOperation<T, T> for EqualsVV {
+    #[inline]
+    fn perform(l: &T, r: &T) -> u8 { (l == r) as u8 }
+    fn symbol() -> &'static str { "==" }
+}
+
+#[derive(Debug)]
+pub struct NotEqualsVV;
+
+impl<T: PartialEq> BoolOperation<T, T> for NotEqualsVV {
+    #[inline]
+    fn perform(l: &T, r: &T) -> u8 { (l != r) as u8 }
+    fn symbol() -> &'static str { "<>" }
+}
@@ -1,5 +1,6 @@
 use std::borrow::BorrowMut;
 use std::cell::{RefCell, Ref, RefMut};
+use std::cmp;
 use std::collections::HashMap;
 use std::fmt::Write;
 use std::fmt;
@@ -13,34 +14,49 @@ use regex::Regex;
 use engine::*;
 use engine::aggregator::Aggregator;
 use engine::typed_vec::*;
-use engine::types::EncodingType;
+use engine::types::{BasicType, EncodingType};
 use engine::vector_op::comparator::*;
 use ingest::raw_val::RawVal;
 use mem_store::*;
+use mem_store::value::Val;
+use syntax::collation::Collation;
 use locustdb_derive::reify_types;
 
 use engine::vector_op::addition_vs::AdditionVS;
+use engine::vector_op::aggregate_states::*;
 use engine::vector_op::bit_unpack::BitUnpackOperator;
 use engine::vector_op::bool_op::*;
+use engine::vector_op::checked_division_sv::CheckedDivisionSV;
+use engine::vector_op::checked_division_vv::*;
 use engine::vector_op::column_ops::*;
+use engine::vector_op::combined_aggregation::*;
 use engine::vector_op::compact::Compact;
 use engine::vector_op::constant::Constant;
 use engine::vector_op::constant_vec::ConstantVec;
-use engine::vector_op::count::VecCount;
+use engine::vector_op::count::{NullCount, VecCount};
 use engine::vector_op::delta_decode::*;
 use engine::vector_op::dict_lookup::*;
 use engine::vector_op::division_vs::DivideVS;
 use engine::vector_op::encode_const::*;
 use engine::vector_op::exists::Exists;
 use engine::vector_op::filter::Filter;
+use engine::vector_op::filter_count::FilterCount;
+use engine::vector_op::fuse_nulls::FuseNulls;
 use engine::vector_op::hashmap_grouping::HashMapGrouping;
 use engine::vector_op::hashmap_grouping_byte_slices::HashMapGroupingByteSlices;
+use engine::vector_op::hyperloglog::HyperLogLog;
+use engine::vector_op::is_null::*;
+use engine::vector_op::like::*;
 use engine::vector_op::merge::Merge;
 use engine::vector_op::merge_aggregate::MergeAggregate;
 use engine::vector_op::merge_deduplicate::MergeDeduplicate;
 use engine::vector_op::merge_deduplicate_partitioned::MergeDeduplicatePartitioned;
 use engine::vector_op::merge_drop::MergeDrop;
 use engine::vector_op::merge_keep::MergeKeep;
+use engine::vector_op::merge_partitioned::MergePartitioned;
+use engine::vector_op::min_max::VecMinMax;
+use engine::vector_op::modulo_vs::ModuloVS;
+use engine::vector_op::moments::{MergeMoments, VecMoments};
 use engine::vector_op::nonzero_compact::NonzeroCompact;
 use engine::vector_op::nonzero_indices::NonzeroIndices;
 use engine::vector_op::parameterized_vec_vec_int_op::*;
@@ -48,15 +64,24 @@ use engine::vector_op::partition::Partition;
 use engine::vector_op::select::Select;
 use engine::vector_op::slice_pack::*;
 use engine::vector_op::slice_unpack::*;
+use engine::vector_op::sort_by::SortBy;
 use engine::vector_op::sort_indices::SortIndices;
 use engine::vector_op::subpartition::SubPartition;
 use engine::vector_op::sum::VecSum;
+use engine::vector_op::not::Not;
+use engine::vector_op::null_vec::NullVec;
+use engine::vector_op::constant_bool_vec::ConstantBoolVec;
 use engine::vector_op::to_year::ToYear;
 use engine::vector_op::top_n::TopN;
 use engine::vector_op::type_conversion::TypeConversionOperator;
+use engine::vector_op::cast_strings::*;
+use engine::vector_op::cast_vals::*;
 use engine::vector_op::unhexpack_strings::UnhexpackStrings;
+use engine::vector_op::filter_indices::FilterIndices;
+use engine::vector_op::unpack_bits::UnpackBits;
 use engine::vector_op::unpack_strings::UnpackStrings;
 use engine::vector_op::vec_const_bool_op::*;
+use engine::vector_op::vec_vec_bool_op::*;
 
 
 pub type BoxedOperator<'a> = Box<VecOperator<'a> + 'a>;
@@ -84,6 +109,11 @@ impl TypedBufferRef {
         self.buffer.i64()
     }
 
+    pub fn i128(&self) -> BufferRef<i128> {
+        assert_eq!(self.tag, EncodingType::I128);
+        self.buffer.i128()
+    }
+
     pub fn u64(&self) -> BufferRef<u64> {
         assert_eq!(self.tag, EncodingType::U64);
         self.buffer.u64()
@@ -126,6 +156,11 @@ impl TypedBufferRef {
         self.buffer.string()
     }
 
+    pub fn val<'a>(&self) -> BufferRef<Val<'a>> {
+        assert_eq!(self.tag, EncodingType::Val);
+        self.buffer.val()
+    }
+
     pub fn const_i64(&self) -> BufferRef<i64> {
         // assert_eq!(self.tag, EncodingType::I64);
         self.buffer.i64()
@@ -155,12 +190,14 @@ impl BufferRef<Any> {
     pub fn premerge(self) -> BufferRef<typed_vec::Premerge> { self.transmute() }
     pub fn raw_val(self) -> BufferRef<RawVal> { self.transmute() }
     pub fn i64(self) -> BufferRef<i64> { self.transmute() }
+    pub fn i128(self) -> BufferRef<i128> { self.transmute() }
     pub fn u64(self) -> BufferRef<u64> { self.transmute() }
     pub fn u32(self) -> BufferRef<u32> { self.transmute() }
     pub fn u16(self) -> BufferRef<u16> { self.transmute() }
     pub fn u8(self) -> BufferRef<u8> { self.transmute() }
     pub fn string(self) -> BufferRef<String> { self.transmute() }
     pub fn str<'a>(self) -> BufferRef<&'a str> { self.transmute() }
+    pub fn val<'a>(self) -> BufferRef<Val<'a>> { self.transmute() }
     pub fn usize(self) -> BufferRef<usize> { self.transmute() }
     fn transmute<T>(self) -> BufferRef<T> { unsafe { mem::transmute(self) } }
 }
@@ -236,6 +273,8 @@ pub struct Scratchpad<'a> {
     buffers: Vec<RefCell<BoxedVec<'a>>>,
     columns: HashMap<String, Vec<&'a AnyVec<'a>>>,
     pinned: Vec<bool>,
+    released: Vec<bool>,
+    peak_bytes: usize,
 }
 
 impl<'a> Scratchpad<'a> {
@@ -248,6 +287,8 @@ impl<'a> Scratchpad<'a> {
             buffers,
             columns,
             pinned: vec![false; count],
+            released: vec![false; count],
+            peak_bytes: 0,
         }
     }
 
@@ -292,6 +333,7 @@ impl<'a> Scratchpad<'a> {
     }
 
     pub fn collect(&mut self, index: BufferRef<Any>) -> BoxedVec<'a> {
+        assert!(!self.released[index.i], "Trying to collect released buffer {}", index);
         let owned = mem::replace(&mut self.buffers[index.i], RefCell::new(AnyVec::empty(0)));
         owned.into_inner()
     }
@@ -314,6 +356,24 @@ impl<'a> Scratchpad<'a> {
         self.pinned[index.i] = false;
     }
 
+    /// Frees the allocation backing a buffer that is not read by any remaining operators.
+    /// Pinned buffers are referenced by other buffers and are kept alive.
+    pub fn release(&mut self, index: BufferRef<Any>) {
+        if !self.pinned[index.i] {
+            self.buffers[index.i] = RefCell::new(AnyVec::empty(0));
+            self.released[index.i] = true;
+        }
+    }
+
+    /// Records the number of bytes currently held by all buffers.
+    /// Summing the size of all buffers after every stage is not free, so executors only do so in tests.
+    pub fn record_allocation(&mut self) {
+        let allocated = self.buffers.iter().map(|b| b.borrow().heap_size_bytes()).sum();
+        self.peak_bytes = cmp::max(self.peak_bytes, allocated);
+    }
+
+    pub fn peak_bytes(&self) -> usize { self.peak_bytes }
+
     pub fn collect_pinned(self) -> Vec<BoxedVec<'a>> {
         self.buffers
             .into_iter()
@@ -354,6 +414,27 @@ impl<'a> VecOperator<'a> {
         ]
     }
 
+    pub fn dict_prefix_match(indices: TypedBufferRef,
+                             dict_indices: BufferRef<u64>,
+                             dict_data: BufferRef<u8>,
+                             prefix: String,
+                             output: BufferRef<u8>) -> BoxedOperator<'a> {
+        reify_types![
+            "dict_prefix_match";
+            indices: Integer;
+            Box::new(DictPrefixMatch { indices, dict_indices, dict_data, prefix, range: None, output });
+        ]
+    }
+
+    pub fn like(input: BufferRef<&'a str>, pattern: &str, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(Like { input, pattern: like_to_regex(pattern), output })
+    }
+
+    pub fn glob(input: BufferRef<&'a str>, pattern: &str, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        let pattern = glob_to_regex(pattern).expect("glob pattern is validated during planning");
+        Box::new(Like { input, pattern, output })
+    }
+
     #[cfg(feature = "enable_lz4")]
     pub fn lz4_decode(encoded: BufferRef<u8>,
                       decoded: TypedBufferRef,
@@ -375,6 +456,10 @@ impl<'a> VecOperator<'a> {
         panic!("LZ4 is not enabled in this build of LocustDB. Recompile with `features enable_lz4`")
     }
 
+    pub fn unpack_bits(packed: BufferRef<u8>, unpacked: BufferRef<u8>, width: u8, decoded_len: usize) -> BoxedOperator<'a> {
+        Box::new(UnpackBits { packed, unpacked, width, decoded_len, batch_size: 0, position: 0, has_more: true })
+    }
+
     pub fn unpack_strings(packed: BufferRef<u8>, unpacked: BufferRef<&'a str>) -> BoxedOperator<'a> {
         Box::new(UnpackStrings::<'a> { packed, unpacked, iterator: None, has_more: true })
     }
@@ -421,6 +506,12 @@ impl<'a> VecOperator<'a> {
     pub fn select(input: TypedBufferRef,
                   indices: BufferRef<usize>,
                   output: TypedBufferRef) -> BoxedOperator<'a> {
+        if let EncodingType::USize = input.tag {
+            return Box::new(Select { input: input.usize(), indices, output: output.usize() });
+        }
+        if let EncodingType::AggregateState = input.tag {
+            return Box::new(SelectAggregateStates { input: input.any(), indices, output: output.any() });
+        }
         reify_types! {
             "select";
             input, output: Primitive;
@@ -444,6 +535,14 @@ impl<'a> VecOperator<'a> {
         }
     }
 
+    pub fn greater_than_vs(lhs: TypedBufferRef, rhs: BufferRef<i64>, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        reify_types! {
+            "greater_than_vs";
+            lhs: IntegerNoU64;
+            Box::new(VecConstBoolOperator::<_, i64, GreaterThanInt<_>> { lhs, rhs, output, op: PhantomData });
+        }
+    }
+
     pub fn equals_vs(lhs: TypedBufferRef,
                      rhs: TypedBufferRef,
                      output: BufferRef<u8>) -> BoxedOperator<'a> {
@@ -458,6 +557,14 @@ impl<'a> VecOperator<'a> {
         }
     }
 
+    pub fn equals_no_case_vs(lhs: BufferRef<&'a str>, rhs: BufferRef<String>, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(VecConstBoolOperator { lhs, rhs, output, op: PhantomData::<EqualsStringNoCase> })
+    }
+
+    pub fn not_equals_no_case_vs(lhs: BufferRef<&'a str>, rhs: BufferRef<String>, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(VecConstBoolOperator { lhs, rhs, output, op: PhantomData::<NotEqualsStringNoCase> })
+    }
+
     pub fn not_equals_vs(lhs: TypedBufferRef,
                          rhs: TypedBufferRef,
                          output: BufferRef<u8>) -> BoxedOperator<'a> {
@@ -471,12 +578,79 @@ impl<'a> VecOperator<'a> {
         }
     }
 
+    pub fn less_than_vv(lhs: TypedBufferRef, rhs: TypedBufferRef, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        if let EncodingType::Str = lhs.tag {
+            return Box::new(VecVecBoolOperator { lhs: lhs.str(), rhs: rhs.str(), output, op: PhantomData::<LessThanVV> });
+        }
+        Box::new(VecVecBoolOperator { lhs: lhs.i64(), rhs: rhs.i64(), output, op: PhantomData::<LessThanVV> })
+    }
+
+    pub fn equals_vv(lhs: TypedBufferRef, rhs: TypedBufferRef, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        if let EncodingType::Str = lhs.tag {
+            return Box::new(VecVecBoolOperator { lhs: lhs.str(), rhs: rhs.str(), output, op: PhantomData::<EqualsVV> });
+        }
+        Box::new(VecVecBoolOperator { lhs: lhs.i64(), rhs: rhs.i64(), output, op: PhantomData::<EqualsVV> })
+    }
+
+    pub fn not_equals_vv(lhs: TypedBufferRef, rhs: TypedBufferRef, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        if let EncodingType::Str = lhs.tag {
+            return Box::new(VecVecBoolOperator { lhs: lhs.str(), rhs: rhs.str(), output, op: PhantomData::<NotEqualsVV> });
+        }
+        Box::new(VecVecBoolOperator { lhs: lhs.i64(), rhs: rhs.i64(), output, op: PhantomData::<NotEqualsVV> })
+    }
+
     pub fn divide_vs(lhs: BufferRef<i64>,
                      rhs: BufferRef<i64>,
                      output: BufferRef<i64>) -> BoxedOperator<'a> {
         Box::new(DivideVS { lhs, rhs, output })
     }
 
+    pub fn modulo_vs(lhs: BufferRef<i64>,
+                     rhs: BufferRef<i64>,
+                     output: BufferRef<i64>) -> BoxedOperator<'a> {
+        Box::new(ModuloVS { lhs, rhs, output })
+    }
+
+    pub fn divide_vv(lhs: BufferRef<i64>,
+                     rhs: BufferRef<i64>,
+                     output: BufferRef<Val<'a>>) -> BoxedOperator<'a> {
+        Box::new(CheckedDivisionVV::<Division> { lhs, rhs, output, op: PhantomData })
+    }
+
+    pub fn modulo_vv(lhs: BufferRef<i64>,
+                     rhs: BufferRef<i64>,
+                     output: BufferRef<Val<'a>>) -> BoxedOperator<'a> {
+        Box::new(CheckedDivisionVV::<Modulo> { lhs, rhs, output, op: PhantomData })
+    }
+
+    pub fn divide_sv(lhs: BufferRef<i64>,
+                     rhs: BufferRef<i64>,
+                     output: BufferRef<Val<'a>>) -> BoxedOperator<'a> {
+        Box::new(CheckedDivisionSV::<Division> { lhs, rhs, output, op: PhantomData })
+    }
+
+    pub fn modulo_sv(lhs: BufferRef<i64>,
+                     rhs: BufferRef<i64>,
+                     output: BufferRef<Val<'a>>) -> BoxedOperator<'a> {
+        Box::new(CheckedDivisionSV::<Modulo> { lhs, rhs, output, op: PhantomData })
+    }
+
+    pub fn fuse_nulls(input: TypedBufferRef, nulls: BufferRef<u8>, output: BufferRef<Val<'a>>) -> BoxedOperator<'a> {
+        if let EncodingType::Str = input.tag {
+            Box::new(FuseNulls { input: input.str(), nulls, output })
+        } else {
+            Box::new(FuseNulls { input: input.i64(), nulls, output })
+        }
+    }
+
+    pub fn null_vec(input: BufferRef<Any>, output: BufferRef<Any>) -> BoxedOperator<'a> {
+        Box::new(NullVec { input, output })
+    }
+
+    pub fn constant_bool_vec(input: BufferRef<Any>, value: bool, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(ConstantBoolVec { input, value, output })
+    }
+
     pub fn addition_vs(lhs: TypedBufferRef,
                        rhs: BufferRef<i64>,
                        output: BufferRef<i64>) -> BoxedOperator<'a> {
@@ -495,6 +669,18 @@ impl<'a> VecOperator<'a> {
         BooleanOperator::<BooleanAnd>::compare(lhs, rhs)
     }
 
+    pub fn not(input: BufferRef<u8>, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(Not { input, output })
+    }
+
+    pub fn is_null(input: BufferRef<Val<'a>>, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(IsNull { input, output })
+    }
+
+    pub fn is_not_null(input: BufferRef<Val<'a>>, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(IsNotNull { input, output })
+    }
+
     pub fn bit_shift_left_add(lhs: BufferRef<i64>,
                               rhs: BufferRef<i64>,
                               output: BufferRef<i64>,
@@ -536,6 +722,34 @@ impl<'a> VecOperator<'a> {
         }
     }
 
+    pub fn int_to_string(input: BufferRef<i64>, output: BufferRef<&'a str>, stringstore: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(IntToString { input, output, stringstore })
+    }
+
+    pub fn string_to_int(input: BufferRef<&'a str>, output: BufferRef<i64>) -> BoxedOperator<'a> {
+        Box::new(StringToInt { input, output })
+    }
+
+    pub fn string_is_not_int(input: BufferRef<&'a str>, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(StringIsNotInt { input, output })
+    }
+
+    pub fn string_to_float(input: BufferRef<&'a str>, output: BufferRef<Val<'a>>) -> BoxedOperator<'a> {
+        Box::new(StringToFloat { input, output })
+    }
+
+    pub fn int_to_float(input: BufferRef<i64>, output: BufferRef<Val<'a>>) -> BoxedOperator<'a> {
+        Box::new(IntToFloat { input, output })
+    }
+
+    pub fn cast_vals(input: BufferRef<Val<'a>>, output: BufferRef<Val<'a>>, target: BasicType) -> BoxedOperator<'a> {
+        Box::new(CastVals { input, output, target })
+    }
+
+    pub fn val_to_string(input: BufferRef<Val<'a>>, output: BufferRef<Val<'a>>, stringstore: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(ValToString { input, output, stringstore })
+    }
+
     #[allow(clippy::wrong_self_convention)]
     pub fn to_year(input: BufferRef<i64>, output: BufferRef<i64>) -> BoxedOperator<'a> {
         Box::new(ToYear { input, output })
@@ -543,12 +757,91 @@ impl<'a> VecOperator<'a> {
 
     pub fn summation(input: TypedBufferRef,
                      grouping: TypedBufferRef,
-                     output: BufferRef<i64>,
-                     max_index: BufferRef<i64>) -> BoxedOperator<'a> {
+                     output: BufferRef<i128>,
+                     max_index: BufferRef<i64>,
+                     offset: i64) -> BoxedOperator<'a> {
         reify_types! {
             "summation";
             input: IntegerNoU64, grouping: Integer;
-            Box::new(VecSum { input, grouping, output, max_index });
+            Box::new(VecSum { input, grouping, output, max_index, offset, counts: Vec::new() });
+        }
+    }
+
+    /// Each aggregate is a sum, minimum, maximum or count given as `(aggregator, input, output, offset)`,
+    /// see `CombinedAggregate`.
+    pub fn combined_aggregation(grouping: TypedBufferRef,
+                                max_index: BufferRef<i64>,
+                                aggregates: Vec<(Aggregator, Option<TypedBufferRef>, TypedBufferRef, i64)>) -> BoxedOperator<'a> {
+        let aggregates = aggregates.into_iter()
+            .map(|(aggregator, input, output, offset)| CombinedAggregate { aggregator, input, output, offset, accumulators: Vec::new() })
+            .collect::<Vec<_>>();
+        reify_types! {
+            "combined_aggregation";
+            grouping: Integer;
+            Box::new(VecCombinedAggregation { grouping, max_index, aggregates, counts: Vec::new() });
+        }
+    }
+
+    pub fn minimum(input: TypedBufferRef,
+                   grouping: TypedBufferRef,
+                   output: BufferRef<i64>,
+                   max_index: BufferRef<i64>) -> BoxedOperator<'a> {
+        reify_types! {
+            "minimum";
+            input: IntegerNoU64, grouping: Integer;
+            Box::new(VecMinMax { input, grouping, output, max_index, c: PhantomData::<CmpLessThan> });
+        }
+    }
+
+    pub fn maximum(input: TypedBufferRef,
+                   grouping: TypedBufferRef,
+                   output: BufferRef<i64>,
+                   max_index: BufferRef<i64>) -> BoxedOperator<'a> {
+        reify_types! {
+            "maximum";
+            input: IntegerNoU64, grouping: Integer;
+            Box::new(VecMinMax { input, grouping, output, max_index, c: PhantomData::<CmpGreaterThan> });
+        }
+    }
+
+    pub fn hyperloglog(input: TypedBufferRef,
+                       grouping: TypedBufferRef,
+                       registers: BufferRef<u8>,
+                       packed: Vec<BufferRef<i64>>,
+                       max_index: BufferRef<i64>,
+                       precision: u8) -> BoxedOperator<'a> {
+        reify_types! {
+            "hyperloglog";
+            input: Primitive, grouping: Integer;
+            Box::new(HyperLogLog { input, grouping, registers, packed, max_index, precision });
+        }
+    }
+
+    pub fn moments(input: TypedBufferRef,
+                   grouping: TypedBufferRef,
+                   count: BufferRef<i64>,
+                   mean: BufferRef<i64>,
+                   m2: BufferRef<i64>,
+                   max_index: BufferRef<i64>) -> BoxedOperator<'a> {
+        reify_types! {
+            "moments";
+            input: IntegerNoU64, grouping: Integer;
+            Box::new(VecMoments { input, grouping, count, mean, m2, max_index, moments: Vec::new() });
+        }
+    }
+
+    pub fn aggregate_states(input: TypedBufferRef,
+                            ordering: Option<TypedBufferRef>,
+                            grouping: TypedBufferRef,
+                            output: BufferRef<Any>,
+                            max_index: BufferRef<i64>,
+                            aggregator: Aggregator) -> BoxedOperator<'a> {
+        let input = input.any();
+        let ordering = ordering.map(|ordering| ordering.any());
+        reify_types! {
+            "aggregate_states";
+            grouping: Integer;
+            Box::new(VecAggregateStates { input, ordering, grouping, output, max_index, aggregator, states: Vec::new() });
         }
     }
 
@@ -560,6 +853,10 @@ impl<'a> VecOperator<'a> {
         }
     }
 
+    pub fn null_count(output: BufferRef<u32>, max_index: BufferRef<i64>) -> BoxedOperator<'a> {
+        Box::new(NullCount { output, max_index })
+    }
+
     pub fn exists(input: TypedBufferRef, output: BufferRef<u8>, max_index: BufferRef<i64>) -> BoxedOperator<'a> {
         reify_types! {
             "exists";
@@ -576,6 +873,14 @@ impl<'a> VecOperator<'a> {
         }
     }
 
+    pub fn filter_indices(filter: BufferRef<u8>, indices: BufferRef<usize>) -> BoxedOperator<'a> {
+        Box::new(FilterIndices { filter, indices, offset: 0 })
+    }
+
+    pub fn filter_count(filter: BufferRef<u8>, count: BufferRef<usize>) -> BoxedOperator<'a> {
+        Box::new(FilterCount { filter, count })
+    }
+
     pub fn nonzero_indices(input: TypedBufferRef, output: TypedBufferRef) -> BoxedOperator<'a> {
         reify_types! {
             "nonzero_indices";
@@ -585,6 +890,17 @@ impl<'a> VecOperator<'a> {
     }
 
     pub fn compact(data: TypedBufferRef, select: TypedBufferRef) -> BoxedOperator<'a> {
+        if let EncodingType::AggregateState = data.tag {
+            return VecOperator::compact_aggregate_states(data.any(), select);
+        }
+        if let EncodingType::I128 = data.tag {
+            let data = data.i128();
+            reify_types! {
+                "compact_i128";
+                select: Integer;
+                Compact::boxed(data, select);
+            }
+        }
         reify_types! {
             "compact";
             data: Integer, select: Integer;
@@ -592,25 +908,62 @@ impl<'a> VecOperator<'a> {
         }
     }
 
+    fn compact_aggregate_states(data: BufferRef<Any>, select: TypedBufferRef) -> BoxedOperator<'a> {
+        reify_types! {
+            "compact_aggregate_states";
+            select: Integer;
+            Box::new(CompactAggregateStates { data, select });
+        }
+    }
+
     // TODO(clemens): allow different types on raw input grouping key and output grouping key
     pub fn hash_map_grouping(raw_grouping_key: TypedBufferRef,
                              unique_out: TypedBufferRef,
                              grouping_key_out: BufferRef<u32>,
                              cardinality_out: BufferRef<i64>,
+                             count_out: Option<BufferRef<u32>>,
                              max_cardinality: usize) -> BoxedOperator<'a> {
         if let EncodingType::ByteSlices(columns) = raw_grouping_key.tag {
             return HashMapGroupingByteSlices::boxed(
-                raw_grouping_key.buffer, unique_out.buffer, grouping_key_out, cardinality_out, columns);
+                raw_grouping_key.buffer, unique_out.buffer, grouping_key_out, cardinality_out, count_out, columns);
         }
         reify_types! {
             "hash_map_grouping";
             raw_grouping_key, unique_out: Primitive;
-            HashMapGrouping::boxed(raw_grouping_key, unique_out, grouping_key_out, cardinality_out, max_cardinality);
+            HashMapGrouping::boxed(raw_grouping_key, unique_out, grouping_key_out, cardinality_out, count_out, max_cardinality);
         }
     }
 
-    pub fn sort_indices(input: BufferRef<Any>, output: BufferRef<usize>, descending: bool) -> BoxedOperator<'a> {
-        Box::new(SortIndices { input, output, descending })
+    pub fn sort_indices(input: BufferRef<Any>, output: BufferRef<usize>, descending: bool, collation: Collation) -> BoxedOperator<'a> {
+        Box::new(SortIndices { input, output, descending, collation })
+    }
+
+    pub fn sort_by(ranking: TypedBufferRef,
+                   indices: BufferRef<usize>,
+                   output: BufferRef<usize>,
+                   limit: usize,
+                   desc: bool) -> BoxedOperator<'a> {
+        if let EncodingType::Val = ranking.tag {
+            let ranking = ranking.val();
+            return if desc {
+                Box::new(SortBy { ranking, indices, output, limit, c: PhantomData::<CmpGreaterThan> })
+            } else {
+                Box::new(SortBy { ranking, indices, output, limit, c: PhantomData::<CmpLessThan> })
+            };
+        }
+        if desc {
+            reify_types! {
+                "sort_by_desc";
+                ranking: Primitive;
+                Box::new(SortBy { ranking, indices, output, limit, c: PhantomData::<CmpGreaterThan> });
+            }
+        } else {
+            reify_types! {
+                "sort_by";
+                ranking: Primitive;
+                Box::new(SortBy { ranking, indices, output, limit, c: PhantomData::<CmpLessThan> });
+            }
+        }
     }
 
     pub fn top_n(input: TypedBufferRef,
@@ -646,11 +999,28 @@ impl<'a> VecOperator<'a> {
     pub fn partition(left: TypedBufferRef,
                      right: TypedBufferRef,
                      partition_out: BufferRef<typed_vec::Premerge>,
-                     limit: usize) -> BoxedOperator<'a> {
-        reify_types! {
-            "partition";
-            left, right: Primitive;
-            Box::new(Partition { left, right, partitioning: partition_out, limit });
+                     limit: usize,
+                     desc: bool) -> BoxedOperator<'a> {
+        if let EncodingType::Val = left.tag {
+            let (left, right) = (left.val(), right.val());
+            return if desc {
+                Box::new(Partition { left, right, partitioning: partition_out, limit, c: PhantomData::<CmpGreaterThan> })
+            } else {
+                Box::new(Partition { left, right, partitioning: partition_out, limit, c: PhantomData::<CmpLessThan> })
+            };
+        }
+        if desc {
+            reify_types! {
+                "partition_desc";
+                left, right: Primitive;
+                Box::new(Partition { left, right, partitioning: partition_out, limit, c: PhantomData::<CmpGreaterThan> });
+            }
+        } else {
+            reify_types! {
+                "partition";
+                left, right: Primitive;
+                Box::new(Partition { left, right, partitioning: partition_out, limit, c: PhantomData::<CmpLessThan> });
+            }
         }
     }
 
@@ -658,11 +1028,58 @@ impl<'a> VecOperator<'a> {
     pub fn subpartition(partitioning: BufferRef<typed_vec::Premerge>,
                         left: TypedBufferRef,
                         right: TypedBufferRef,
-                        subpartition_out: BufferRef<typed_vec::Premerge>) -> BoxedOperator<'a> {
-        reify_types! {
-            "subpartition";
-            left, right: Primitive;
-            Box::new(SubPartition { partitioning, left, right, sub_partitioning: subpartition_out });
+                        subpartition_out: BufferRef<typed_vec::Premerge>,
+                        desc: bool) -> BoxedOperator<'a> {
+        if let EncodingType::Val = left.tag {
+            let (left, right, sub_partitioning) = (left.val(), right.val(), subpartition_out);
+            return if desc {
+                Box::new(SubPartition { partitioning, left, right, sub_partitioning, c: PhantomData::<CmpGreaterThan> })
+            } else {
+                Box::new(SubPartition { partitioning, left, right, sub_partitioning, c: PhantomData::<CmpLessThan> })
+            };
+        }
+        if desc {
+            reify_types! {
+                "subpartition_desc";
+                left, right: Primitive;
+                Box::new(SubPartition { partitioning, left, right, sub_partitioning: subpartition_out, c: PhantomData::<CmpGreaterThan> });
+            }
+        } else {
+            reify_types! {
+                "subpartition";
+                left, right: Primitive;
+                Box::new(SubPartition { partitioning, left, right, sub_partitioning: subpartition_out, c: PhantomData::<CmpLessThan> });
+            }
+        }
+    }
+
+    pub fn merge_partitioned(partitioning: BufferRef<typed_vec::Premerge>,
+                             left: TypedBufferRef,
+                             right: TypedBufferRef,
+                             merged_out: TypedBufferRef,
+                             take_left_out: BufferRef<u8>,
+                             limit: usize,
+                             desc: bool) -> BoxedOperator<'a> {
+        if let EncodingType::Val = left.tag {
+            let (left, right, merged) = (left.val(), right.val(), merged_out.val());
+            return if desc {
+                Box::new(MergePartitioned { partitioning, left, right, merged, take_left: take_left_out, limit, c: PhantomData::<CmpGreaterThan> })
+            } else {
+                Box::new(MergePartitioned { partitioning, left, right, merged, take_left: take_left_out, limit, c: PhantomData::<CmpLessThan> })
+            };
+        }
+        if desc {
+            reify_types! {
+                "merge_partitioned_desc";
+                left, right, merged_out: Primitive;
+                Box::new(MergePartitioned { partitioning, left, right, merged: merged_out, take_left: take_left_out, limit, c: PhantomData::<CmpGreaterThan> });
+            }
+        } else {
+            reify_types! {
+                "merge_partitioned";
+                left, right, merged_out: Primitive;
+                Box::new(MergePartitioned { partitioning, left, right, merged: merged_out, take_left: take_left_out, limit, c: PhantomData::<CmpLessThan> });
+            }
         }
     }
 
@@ -690,11 +1107,29 @@ impl<'a> VecOperator<'a> {
     }
 
     pub fn merge_aggregate(merge_ops: BufferRef<typed_vec::MergeOp>,
-                           left: BufferRef<i64>,
-                           right: BufferRef<i64>,
-                           aggregated_out: BufferRef<i64>,
+                           left: TypedBufferRef,
+                           right: TypedBufferRef,
+                           aggregated_out: TypedBufferRef,
                            aggregator: Aggregator) -> BoxedOperator<'a> {
-        Box::new(MergeAggregate { merge_ops, left, right, aggregated: aggregated_out, aggregator })
+        if let EncodingType::I128 = left.tag {
+            Box::new(MergeAggregate { merge_ops, left: left.i128(), right: right.i128(), aggregated: aggregated_out.i128(), aggregator })
+        } else {
+            Box::new(MergeAggregate { merge_ops, left: left.i64(), right: right.i64(), aggregated: aggregated_out.i64(), aggregator })
+        }
+    }
+
+    pub fn merge_moments(merge_ops: BufferRef<typed_vec::MergeOp>,
+                         left: [BufferRef<i64>; 3],
+                         right: [BufferRef<i64>; 3],
+                         merged: [BufferRef<i64>; 3]) -> BoxedOperator<'a> {
+        Box::new(MergeMoments { merge_ops, left, right, merged })
+    }
+
+    pub fn merge_aggregate_states(merge_ops: BufferRef<typed_vec::MergeOp>,
+                                  left: BufferRef<Any>,
+                                  right: BufferRef<Any>,
+                                  merged: BufferRef<Any>) -> BoxedOperator<'a> {
+        Box::new(MergeAggregateStates { merge_ops, left, right, merged })
     }
 
     pub fn merge(left: TypedBufferRef,
@@ -702,7 +1137,25 @@ impl<'a> VecOperator<'a> {
                  merged_out: TypedBufferRef,
                  ops_out: BufferRef<u8>,
                  limit: usize,
-                 desc: bool) -> BoxedOperator<'a> {
+                 desc: bool,
+                 collation: Collation) -> BoxedOperator<'a> {
+        if let (EncodingType::Str, Collation::NoCase) = (left.tag, collation) {
+            let (left, right, merged) = (left.str(), right.str(), merged_out.str());
+            return if desc {
+                Box::new(Merge { left, right, merged, merge_ops: ops_out, limit, c: PhantomData::<CmpGreaterThanNoCase> })
+            } else {
+                Box::new(Merge { left, right, merged, merge_ops: ops_out, limit, c: PhantomData::<CmpLessThanNoCase> })
+            };
+        }
+        // Columns that contain nulls in some partitions are merged as `Val`
+        if let EncodingType::Val = left.tag {
+            let (left, right, merged) = (left.val(), right.val(), merged_out.val());
+            return if desc {
+                Box::new(Merge { left, right, merged, merge_ops: ops_out, limit, c: PhantomData::<CmpGreaterThan> })
+            } else {
+                Box::new(Merge { left, right, merged, merge_ops: ops_out, limit, c: PhantomData::<CmpLessThan> })
+            };
+        }
         if desc {
             reify_types! {
                 "merge_desc";
@@ -722,6 +1175,9 @@ impl<'a> VecOperator<'a> {
                       left: TypedBufferRef,
                       right: TypedBufferRef,
                       merged_out: TypedBufferRef) -> BoxedOperator<'a> {
+        if let EncodingType::Val = left.tag {
+            return Box::new(MergeKeep { merge_ops, left: left.val(), right: right.val(), merged: merged_out.val() });
+        }
         reify_types! {
                 "merge_keep";
                 left, right, merged_out: Primitive;
@@ -1,3 +1,5 @@
+use std::io;
+
 #[derive(Fail, Debug)]
 pub enum QueryError {
     #[fail(display = "Failed to parser query. Chars remaining: {}", _0)]
@@ -10,8 +12,47 @@ pub enum QueryError {
     FatalError(String),
     #[fail(display = "Not implemented: {}", _0)]
     NotImplemented(String),
-    #[fail(display = "Type error: {}", _0)]
-    TypeError(String),
+    #[fail(display = "Unknown column: {}", _0)]
+    UnknownColumn(String),
+    #[fail(display = "Type mismatch: {}", _0)]
+    TypeMismatch(String),
+    #[fail(display = "Query timed out: {}", _0)]
+    Timeout(String),
+    #[fail(display = "Query was cancelled after executing {} vector operators", _0)]
+    Cancelled(usize),
+}
+
+/// Error encountered while loading data into a table.
+#[derive(Fail, Debug)]
+pub enum IngestError {
+    /// The input could not be opened or read
+    #[fail(display = "Failed to read input: {}", _0)]
+    Io(#[cause] io::Error),
+    /// The input could not be decoded as CSV
+    #[fail(display = "Invalid CSV: {}", _0)]
+    Csv(String),
+    /// The record on line `row` could not be parsed, starting at the field with (zero-based) index `col`
+    #[fail(display = "Failed to parse field {} on line {}", col, row)]
+    Parse { row: u64, col: usize },
+    /// The input does not match the expected columns
+    #[fail(display = "Schema mismatch: {}", _0)]
+    Schema(String),
+    /// The load options ask for something this build can't do, e.g. reading a compression that is not enabled
+    #[fail(display = "Unsupported option: {}", _0)]
+    Unsupported(String),
+    /// The values of a column can't be stored with the encoding requested for it
+    #[fail(display = "Invalid encoding: {}", _0)]
+    Encoding(String),
+    /// An ingested partition could not be appended to the write-ahead log
+    #[fail(display = "Failed to write to write-ahead log: {}", _0)]
+    Wal(#[cause] io::Error),
+    /// The input could not be decoded as Arrow IPC file
+    #[fail(display = "Invalid Arrow file: {}", _0)]
+    Arrow(String),
+}
+
+impl From<io::Error> for IngestError {
+    fn from(err: io::Error) -> IngestError { IngestError::Io(err) }
 }
 
 #[macro_export]
@@ -0,0 +1,362 @@
+//! Reader for the Arrow IPC file format (also known as Feather v2), see
+//! https://arrow.apache.org/docs/format/Columnar.html#ipc-file-format.
+//! The flatbuffers metadata is decoded directly, which supports flat schemas of null, integer, floating point,
+//! boolean and UTF-8 columns without dictionary encoding or compression.
+use std::str;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use errors::IngestError;
+
+const MAGIC: &[u8] = b"ARROW1";
+/// Precedes the metadata length of encapsulated messages written by Arrow 0.15 and later
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+/// `MessageHeader::RecordBatch`
+const RECORD_BATCH: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataType {
+    Null,
+    Int { bits: usize, signed: bool },
+    Float { bits: usize },
+    Bool,
+    Utf8,
+}
+
+impl DataType {
+    /// Number of buffers of arrays of this type, including the validity bitmap.
+    fn buffer_count(self) -> usize {
+        match self {
+            DataType::Null => 0,
+            DataType::Int { .. } | DataType::Float { .. } | DataType::Bool => 2,
+            DataType::Utf8 => 3,
+        }
+    }
+
+    /// Minimum length of the values buffer of an array with `len` values of this type.
+    fn values_bytes(self, len: usize) -> usize {
+        match self {
+            DataType::Null | DataType::Utf8 => 0,
+            DataType::Int { bits, .. } | DataType::Float { bits } => len * bits / 8,
+            DataType::Bool => (len + 7) / 8,
+        }
+    }
+}
+
+pub struct Field {
+    pub name: String,
+    pub data_type: DataType,
+}
+
+/// Location of a record batch message within the file.
+struct Block {
+    offset: usize,
+    metadata_len: usize,
+    body_len: usize,
+}
+
+pub struct ArrowFile {
+    data: Vec<u8>,
+    pub fields: Vec<Field>,
+    record_batches: Vec<Block>,
+}
+
+impl ArrowFile {
+    /// Parses the footer of the Arrow IPC file `data`, record batches are decoded by `record_batch`.
+    pub fn read(data: Vec<u8>) -> Result<ArrowFile, IngestError> {
+        let (fields, record_batches) = {
+            if data.len() < 2 * MAGIC.len() + 4 || &data[..MAGIC.len()] != MAGIC || &data[data.len() - MAGIC.len()..] != MAGIC {
+                return Err(invalid("file does not start and end with ARROW1"));
+            }
+            let footer_end = data.len() - MAGIC.len() - 4;
+            let footer_len = to_usize(i64::from(LittleEndian::read_i32(&data[footer_end..])))?;
+            if footer_len > footer_end {
+                return Err(invalid("footer length exceeds file size"));
+            }
+            let footer = Table::root(&data[footer_end - footer_len..footer_end])?;
+            let schema = footer.table(1)?.ok_or_else(|| invalid("footer has no schema"))?;
+            let fields = schema.tables(1)?.iter().map(read_field).collect::<Result<Vec<_>, _>>()?;
+            let mut record_batches = Vec::new();
+            for block in footer.structs(3, 24)? {
+                record_batches.push(Block {
+                    offset: to_usize(LittleEndian::read_i64(&block[0..8]))?,
+                    metadata_len: to_usize(i64::from(LittleEndian::read_i32(&block[8..12])))?,
+                    body_len: to_usize(LittleEndian::read_i64(&block[16..24]))?,
+                });
+            }
+            (fields, record_batches)
+        };
+        Ok(ArrowFile { data, fields, record_batches })
+    }
+
+    pub fn record_batch_count(&self) -> usize {
+        self.record_batches.len()
+    }
+
+    /// Decodes the record batch with index `index` into one array for each of `fields`.
+    pub fn record_batch(&self, index: usize) -> Result<Vec<Array>, IngestError> {
+        let block = &self.record_batches[index];
+        let mut pos = block.offset;
+        if u32_at(&self.data, pos)? == CONTINUATION {
+            pos += 4;
+        }
+        let metadata_len = to_usize(i64::from(LittleEndian::read_i32(slice(&self.data, pos, 4)?)))?;
+        let message = Table::root(slice(&self.data, pos + 4, metadata_len)?)?;
+        if message.u8(1, 0)? != RECORD_BATCH {
+            return Err(invalid("block does not point to a record batch"));
+        }
+        let batch = message.table(2)?.ok_or_else(|| invalid("record batch message has no header"))?;
+        if batch.table(3)?.is_some() {
+            return Err(IngestError::Unsupported("compressed Arrow record batches".to_string()));
+        }
+        let body = slice(&self.data, block.offset + block.metadata_len, block.body_len)?;
+        let nodes = batch.structs(1, 16)?;
+        if nodes.len() != self.fields.len() {
+            return Err(invalid("number of arrays in record batch does not match schema"));
+        }
+        let mut buffers = batch.structs(2, 16)?.into_iter();
+        let mut arrays = Vec::with_capacity(nodes.len());
+        for (field, node) in self.fields.iter().zip(nodes) {
+            let mut array_buffers = Vec::with_capacity(field.data_type.buffer_count());
+            for _ in 0..field.data_type.buffer_count() {
+                let buffer = buffers.next().ok_or_else(|| invalid("record batch has too few buffers"))?;
+                let offset = to_usize(LittleEndian::read_i64(&buffer[0..8]))?;
+                let len = to_usize(LittleEndian::read_i64(&buffer[8..16]))?;
+                array_buffers.push(slice(body, offset, len)?);
+            }
+            let array = Array {
+                data_type: field.data_type,
+                len: to_usize(LittleEndian::read_i64(&node[0..8]))?,
+                null_count: to_usize(LittleEndian::read_i64(&node[8..16]))?,
+                buffers: array_buffers,
+            };
+            array.validate(&field.name)?;
+            arrays.push(array);
+        }
+        Ok(arrays)
+    }
+}
+
+/// Values of one column of a record batch.
+pub struct Array<'a> {
+    pub data_type: DataType,
+    pub len: usize,
+    pub null_count: usize,
+    /// Validity bitmap followed by the buffers of the values, see `DataType::buffer_count`
+    buffers: Vec<&'a [u8]>,
+}
+
+impl<'a> Array<'a> {
+    fn validate(&self, name: &str) -> Result<(), IngestError> {
+        if self.data_type == DataType::Null {
+            return Ok(());
+        }
+        let validity = self.buffers[0];
+        if self.null_count > 0 && !validity.is_empty() && validity.len() < (self.len + 7) / 8 {
+            bail!(IngestError::Arrow, "validity bitmap of column `{}` is too short", name);
+        }
+        if self.buffers[1].len() < self.data_type.values_bytes(self.len) {
+            bail!(IngestError::Arrow, "values of column `{}` are too short", name);
+        }
+        if self.data_type == DataType::Utf8 && self.buffers[1].len() < 4 * (self.len + 1) {
+            bail!(IngestError::Arrow, "offsets of column `{}` are too short", name);
+        }
+        Ok(())
+    }
+
+    pub fn is_null(&self, i: usize) -> bool {
+        match self.data_type {
+            DataType::Null => true,
+            _ => {
+                let validity = self.buffers[0];
+                // Arrays without nulls may omit the validity bitmap
+                self.null_count > 0 && !validity.is_empty() && (validity[i / 8] >> (i % 8)) & 1 == 0
+            }
+        }
+    }
+
+    /// Value at index `i` of an integer or floating point array. Floats are truncated.
+    pub fn int(&self, i: usize) -> i64 {
+        let values = self.buffers[1];
+        match self.data_type {
+            DataType::Int { bits: 8, signed: true } => i64::from(values[i] as i8),
+            DataType::Int { bits: 8, signed: false } => i64::from(values[i]),
+            DataType::Int { bits: 16, signed: true } => i64::from(LittleEndian::read_i16(&values[2 * i..])),
+            DataType::Int { bits: 16, signed: false } => i64::from(LittleEndian::read_u16(&values[2 * i..])),
+            DataType::Int { bits: 32, signed: true } => i64::from(LittleEndian::read_i32(&values[4 * i..])),
+            DataType::Int { bits: 32, signed: false } => i64::from(LittleEndian::read_u32(&values[4 * i..])),
+            DataType::Int { bits: 64, signed: true } => LittleEndian::read_i64(&values[8 * i..]),
+            DataType::Int { bits: 64, signed: false } => LittleEndian::read_u64(&values[8 * i..]) as i64,
+            DataType::Float { bits: 32 } => LittleEndian::read_f32(&values[4 * i..]) as i64,
+            DataType::Float { bits: 64 } => LittleEndian::read_f64(&values[8 * i..]) as i64,
+            t => unreachable!("Array of type {:?} has no integer values", t),
+        }
+    }
+
+    /// Value at index `i` of a boolean array.
+    pub fn bool(&self, i: usize) -> bool {
+        (self.buffers[1][i / 8] >> (i % 8)) & 1 == 1
+    }
+
+    /// Value at index `i` of a UTF-8 array.
+    pub fn str(&self, i: usize) -> Result<&'a str, IngestError> {
+        let offsets = self.buffers[1];
+        let start = to_usize(i64::from(LittleEndian::read_i32(&offsets[4 * i..])))?;
+        let end = to_usize(i64::from(LittleEndian::read_i32(&offsets[4 * (i + 1)..])))?;
+        if end < start {
+            return Err(invalid("string offsets are not increasing"));
+        }
+        str::from_utf8(slice(self.buffers[2], start, end - start)?).map_err(|_| invalid("string is not valid UTF-8"))
+    }
+}
+
+fn read_field(field: &Table) -> Result<Field, IngestError> {
+    let name = field.string(0)?.unwrap_or("").to_string();
+    if field.table(4)?.is_some() {
+        bail!(IngestError::Schema, "Dictionary encoded Arrow column `{}` is not supported", name);
+    }
+    if !field.tables(5)?.is_empty() {
+        bail!(IngestError::Schema, "Nested Arrow column `{}` is not supported", name);
+    }
+    let type_table = field.table(3)?;
+    let data_type = match (field.u8(2, 0)?, type_table) {
+        (1, _) => DataType::Null,
+        (2, Some(t)) => match t.i32(0, 0)? {
+            bits @ 8 | bits @ 16 | bits @ 32 | bits @ 64 => DataType::Int { bits: bits as usize, signed: t.u8(1, 0)? != 0 },
+            bits => bail!(IngestError::Schema, "Arrow column `{}` has unsupported integer width {}", name, bits),
+        },
+        (3, Some(t)) => match t.i16(0, 0)? {
+            1 => DataType::Float { bits: 32 },
+            2 => DataType::Float { bits: 64 },
+            _ => bail!(IngestError::Schema, "Arrow column `{}` has unsupported half precision floats", name),
+        },
+        (5, _) => DataType::Utf8,
+        (6, _) => DataType::Bool,
+        (t, _) => bail!(IngestError::Schema, "Arrow column `{}` has unsupported type {}", name, t),
+    };
+    Ok(Field { name, data_type })
+}
+
+/// Flatbuffers table at offset `pos` of `buf`.
+#[derive(Clone, Copy)]
+struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Table<'a> {
+    fn root(buf: &'a [u8]) -> Result<Table<'a>, IngestError> {
+        Ok(Table { buf, pos: u32_at(buf, 0)? as usize })
+    }
+
+    /// Position of the field with index `index`, or `None` if the field is absent.
+    fn field(&self, index: usize) -> Result<Option<usize>, IngestError> {
+        let vtable = self.pos as i64 - i64::from(LittleEndian::read_i32(slice(self.buf, self.pos, 4)?));
+        let vtable = to_usize(vtable)?;
+        let slot = 4 + 2 * index;
+        if slot + 2 > u16_at(self.buf, vtable)? as usize {
+            return Ok(None);
+        }
+        match u16_at(self.buf, vtable + slot)? {
+            0 => Ok(None),
+            offset => Ok(Some(self.pos + offset as usize)),
+        }
+    }
+
+    fn scalar(&self, index: usize, size: usize) -> Result<Option<&'a [u8]>, IngestError> {
+        match self.field(index)? {
+            Some(pos) => Ok(Some(slice(self.buf, pos, size)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn u8(&self, index: usize, default: u8) -> Result<u8, IngestError> {
+        Ok(self.scalar(index, 1)?.map_or(default, |bytes| bytes[0]))
+    }
+
+    fn i16(&self, index: usize, default: i16) -> Result<i16, IngestError> {
+        Ok(self.scalar(index, 2)?.map_or(default, LittleEndian::read_i16))
+    }
+
+    fn i32(&self, index: usize, default: i32) -> Result<i32, IngestError> {
+        Ok(self.scalar(index, 4)?.map_or(default, LittleEndian::read_i32))
+    }
+
+    /// Position of the object referenced by the offset stored in field `index`.
+    fn indirect(&self, index: usize) -> Result<Option<usize>, IngestError> {
+        match self.field(index)? {
+            Some(pos) => Ok(Some(pos + u32_at(self.buf, pos)? as usize)),
+            None => Ok(None),
+        }
+    }
+
+    fn table(&self, index: usize) -> Result<Option<Table<'a>>, IngestError> {
+        Ok(self.indirect(index)?.map(|pos| Table { buf: self.buf, pos }))
+    }
+
+    /// Start and length of the vector in field `index` with elements of `size` bytes.
+    fn vector(&self, index: usize, size: usize) -> Result<Option<(usize, usize)>, IngestError> {
+        match self.indirect(index)? {
+            Some(pos) => {
+                let len = u32_at(self.buf, pos)? as usize;
+                slice(self.buf, pos + 4, len * size)?;
+                Ok(Some((pos + 4, len)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn string(&self, index: usize) -> Result<Option<&'a str>, IngestError> {
+        match self.vector(index, 1)? {
+            Some((start, len)) => str::from_utf8(&self.buf[start..start + len])
+                .map(Some)
+                .map_err(|_| invalid("string is not valid UTF-8")),
+            None => Ok(None),
+        }
+    }
+
+    fn tables(&self, index: usize) -> Result<Vec<Table<'a>>, IngestError> {
+        let (start, len) = match self.vector(index, 4)? {
+            Some(vector) => vector,
+            None => return Ok(Vec::new()),
+        };
+        (0..len).map(|i| {
+            let pos = start + 4 * i;
+            Ok(Table { buf: self.buf, pos: pos + u32_at(self.buf, pos)? as usize })
+        }).collect()
+    }
+
+    fn structs(&self, index: usize, size: usize) -> Result<Vec<&'a [u8]>, IngestError> {
+        let (start, len) = match self.vector(index, size)? {
+            Some(vector) => vector,
+            None => return Ok(Vec::new()),
+        };
+        Ok((0..len).map(|i| &self.buf[start + i * size..start + (i + 1) * size]).collect())
+    }
+}
+
+fn slice(buf: &[u8], pos: usize, len: usize) -> Result<&[u8], IngestError> {
+    pos.checked_add(len)
+        .and_then(|end| buf.get(pos..end))
+        .ok_or_else(|| invalid("offset out of bounds"))
+}
+
+fn u16_at(buf: &[u8], pos: usize) -> Result<u16, IngestError> {
+    Ok(LittleEndian::read_u16(slice(buf, pos, 2)?))
+}
+
+fn u32_at(buf: &[u8], pos: usize) -> Result<u32, IngestError> {
+    Ok(LittleEndian::read_u32(slice(buf, pos, 4)?))
+}
+
+fn to_usize(x: i64) -> Result<usize, IngestError> {
+    if x < 0 {
+        Err(invalid("negative offset or length"))
+    } else {
+        Ok(x as usize)
+    }
+}
+
+fn invalid(msg: &str) -> IngestError {
+    IngestError::Arrow(msg.to_string())
+}
@@ -0,0 +1,64 @@
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+
+use errors::IngestError;
+use ingest::arrow_ipc::{Array, ArrowFile, DataType};
+use mem_store::booleans::BooleanColumn;
+use mem_store::column::Column;
+use mem_store::column_builder::*;
+use mem_store::strings::fast_build_string_column;
+use scheduler::*;
+
+
+/// Ingests the Arrow IPC (Feather v2) file at `path` into `tablename`, storing each record batch as one partition.
+/// Columns keep the types of the Arrow schema instead of being inferred from their values.
+/// Null integers and strings are marked in the null map of their column, null booleans are stored as false.
+/// Columns that contain only nulls become null columns. As with CSV, floats are truncated to integers.
+pub fn ingest_arrow_file(ldb: &InnerLocustDB, path: &str, tablename: &str) -> Result<(), IngestError> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    let file = ArrowFile::read(data)?;
+    for i in 0..file.record_batch_count() {
+        let arrays = file.record_batch(i)?;
+        if arrays.first().map_or(true, |array| array.len == 0) { continue; }
+        let partition = file.fields.iter().zip(&arrays)
+            .map(|(field, array)| build_column(&field.name, array))
+            .collect::<Result<Vec<_>, _>>()?;
+        ldb.store_partition(tablename, partition, None).map_err(IngestError::Wal)?;
+    }
+    Ok(())
+}
+
+fn build_column(name: &str, array: &Array) -> Result<Arc<Column>, IngestError> {
+    if array.data_type == DataType::Null || array.null_count == array.len {
+        return Ok(Arc::new(Column::null(name, array.len)));
+    }
+    let mut column = match array.data_type {
+        DataType::Bool => {
+            let values = (0..array.len).map(|i| !array.is_null(i) && array.bool(i)).collect();
+            return Ok(BooleanColumn::new_boxed(name, values));
+        }
+        DataType::Utf8 => {
+            let strings = (0..array.len)
+                .map(|i| if array.is_null(i) { Ok("") } else { array.str(i) })
+                .collect::<Result<Vec<_>, _>>()?;
+            let total_bytes = strings.iter().map(|s| s.len()).sum();
+            fast_build_string_column(name, strings.iter().cloned(), strings.len(), false, false, total_bytes, None)
+        }
+        _ => {
+            let mut builder = IntColBuilder::default();
+            for i in 0..array.len {
+                builder.push(&if array.is_null(i) { 0 } else { array.int(i) });
+            }
+            builder.finalize(name)
+        }
+    };
+    if array.null_count > 0 {
+        // The column was just built, so it isn't shared yet
+        if let Some(column) = Arc::get_mut(&mut column) {
+            column.set_nulls((0..array.len).map(|i| array.is_null(i) as u8).collect());
+        }
+    }
+    Ok(column)
+}
@@ -1,3 +1,4 @@
+use std::io;
 use std::marker::PhantomData;
 use std::sync::Arc;
 
@@ -246,12 +247,12 @@ pub struct GenTable {
 }
 
 impl GenTable {
-    pub fn gen(&self, db: &InnerLocustDB, partition_number: u64) {
+    pub fn gen(&self, db: &InnerLocustDB, partition_number: u64) -> io::Result<()> {
         let partition = self.columns
             .iter()
             .map(|(name, c)| c.generate(self.partition_size, &name, partition_number))
             .collect();
-        db.store_partition(&self.name, partition);
+        db.store_partition(&self.name, partition, None)
     }
 }
 
@@ -1,17 +1,29 @@
 extern crate csv;
 extern crate flate2;
 
+use crypto::digest::Digest;
+use crypto::md5::Md5;
+use engine::types::{BasicType, EncodingType};
+use errors::IngestError;
+use heapsize::HeapSizeOf;
+use mem_store::booleans::BooleanColumn;
 use mem_store::column::*;
 use mem_store::column_builder::*;
+use mem_store::shared_dictionary::SharedDictionaries;
 use mem_store::strings::fast_build_string_column;
 use scheduler::*;
 use self::flate2::read::GzDecoder;
+use std::cmp;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::mem;
 use std::ops::BitOr;
 use std::str;
 use std::sync::Arc;
 use super::extractor;
+#[cfg(feature = "enable_zstd")]
+use zstd;
 use stringpack::*;
 
 type IngestionTransform = HashMap<String, extractor::Extractor>;
@@ -20,11 +32,58 @@ pub struct Options {
     filename: String,
     tablename: String,
     partition_size: usize,
+    /// If set, the partition size is chosen such that partitions take up roughly this many bytes
+    partition_memory_budget: Option<usize>,
     colnames: Option<Vec<String>>,
+    /// Column names and optional types read from a schema file, take precedence over `colnames` and the header
+    schema: Option<Vec<(String, Option<BasicType>)>>,
+    /// Maps column names from the header (or `colnames`) to the names under which the columns are stored
+    renames: HashMap<String, String>,
     extractors: IngestionTransform,
+    derived_cols: Vec<DerivedColumn>,
     ignore_cols: HashSet<String>,
     always_string: HashSet<String>,
-    unzip: bool,
+    /// Types that columns are ingested as regardless of the types inferred from their values
+    column_types: HashMap<String, BasicType>,
+    /// Values that denote missing values in addition to empty fields
+    null_tokens: HashSet<String>,
+    /// Columns in which empty fields are empty strings rather than missing values
+    empty_strings: HashSet<String>,
+    /// Encodings that integer columns are stored with instead of choosing the smallest encoding that fits
+    encodings: HashMap<String, EncodingType>,
+    /// Store integer columns with a range of less than 8 bits with fewer bits per value
+    bit_packing: bool,
+    /// Compression of the file, detected automatically if `None`
+    compression: Option<Compression>,
+    shared_dictionaries: bool,
+    duplicate_cols: DuplicateColumns,
+    /// Skip the rows stored by the previous ingestion of the same file into the same table
+    resume: bool,
+    /// Skip batches whose contents are identical to a batch previously ingested into the same table
+    deduplicate_batches: bool,
+}
+
+struct DerivedColumn {
+    name: String,
+    inputs: Vec<String>,
+    derivation: extractor::Derivation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    /// Requires the `enable_zstd` feature
+    Zstd,
+}
+
+/// How to handle a header that contains the same column name more than once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuplicateColumns {
+    /// Appends `_1`, `_2`, ... to repeated names (e.g. `id`, `id_1`)
+    Rename,
+    /// Fails ingestion with `IngestError::Schema`
+    Error,
 }
 
 impl Options {
@@ -33,16 +92,37 @@ impl Options {
             filename: filename.to_owned(),
             tablename: tablename.to_owned(),
             partition_size: 1 << 16,
+            partition_memory_budget: None,
             colnames: None,
+            schema: None,
+            renames: HashMap::new(),
             extractors: HashMap::new(),
+            derived_cols: Vec::new(),
             ignore_cols: HashSet::new(),
             always_string: HashSet::new(),
-            unzip: filename.ends_with(".gz"),
+            column_types: HashMap::new(),
+            null_tokens: HashSet::new(),
+            empty_strings: HashSet::new(),
+            encodings: HashMap::new(),
+            bit_packing: false,
+            compression: None,
+            shared_dictionaries: false,
+            duplicate_cols: DuplicateColumns::Rename,
+            resume: false,
+            deduplicate_batches: false,
         }
     }
 
     pub fn with_partition_size(mut self, chunk_size: usize) -> Options {
         self.partition_size = chunk_size;
+        self.partition_memory_budget = None;
+        self
+    }
+
+    /// Chooses the partition size automatically to make each partition take up roughly `bytes` of memory.
+    /// The size per row is estimated from a small first partition. Overridden by `with_partition_size`.
+    pub fn with_partition_memory_budget(mut self, bytes: usize) -> Options {
+        self.partition_memory_budget = Some(bytes);
         self
     }
 
@@ -51,11 +131,41 @@ impl Options {
         self
     }
 
+    /// Reads column names from a schema file instead of the header or `with_column_names`, the data file is assumed to have no header.
+    /// Each line of the schema file names one column, optionally followed by a colon and one of the types
+    /// `string`, `integer`, `boolean` or `null` (e.g. `zip:string`). Empty lines and lines starting with `#` are skipped.
+    /// Types set by `with_column_types` take precedence over types from the schema file.
+    pub fn with_schema_file(mut self, path: &str) -> Result<Options, IngestError> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        self.schema = Some(parse_schema(&contents)?);
+        Ok(self)
+    }
+
+    /// Stores columns under a different name than given by the header.
+    /// Other options (e.g. `with_ignore_cols`) refer to columns by their new name.
+    pub fn with_renamed_cols(mut self, renames: &[(&str, &str)]) -> Options {
+        self.renames = renames.iter().map(|&(from, to)| (from.to_owned(), to.to_owned())).collect();
+        self
+    }
+
     pub fn with_extractors(mut self, extractors: &[(&str, extractor::Extractor)]) -> Options {
         self.extractors = extractors.iter().map(|&(col, extractor)| (col.to_owned(), extractor)).collect();
         self
     }
 
+    /// Adds a column `name` computed from the raw values of the columns `inputs` of each row.
+    /// The type of the column is inferred from the computed values, like for any other column.
+    pub fn with_derived_col<F>(mut self, name: &str, inputs: &[&str], derivation: F) -> Options
+        where F: Fn(&[&str]) -> String + Send + Sync + 'static {
+        self.derived_cols.push(DerivedColumn {
+            name: name.to_owned(),
+            inputs: inputs.iter().map(|&x| x.to_owned()).collect(),
+            derivation: Box::new(derivation),
+        });
+        self
+    }
+
     pub fn with_ignore_cols(mut self, ignore: &[String]) -> Options {
         self.ignore_cols = ignore.into_iter().map(|x| x.to_owned()).collect();
         self
@@ -65,85 +175,552 @@ impl Options {
         self.always_string = always_string.into_iter().map(|&x| x.to_owned()).collect();
         self
     }
+
+    /// Ingests columns as the given type instead of inferring it.
+    /// Supported types are `String`, `Integer`, `Boolean` and `Null`, values that are not valid for the type fail ingestion.
+    /// Integer columns accept floats (which are truncated) and boolean columns accept `0` and `1`, empty values are always accepted.
+    pub fn with_column_types(mut self, column_types: HashMap<String, BasicType>) -> Options {
+        self.column_types = column_types;
+        self
+    }
+
+    /// Treats fields equal to any of `tokens` (e.g. `NULL` or `\N`) as missing values, just like empty fields.
+    pub fn with_null_tokens(mut self, tokens: &[&str]) -> Options {
+        self.null_tokens = tokens.iter().map(|&x| x.to_owned()).collect();
+        self
+    }
+
+    /// Ingests empty fields of the given columns as empty strings instead of missing values,
+    /// so that only the tokens set by `with_null_tokens` denote missing values.
+    pub fn with_empty_strings(mut self, columns: &[&str]) -> Options {
+        self.empty_strings = columns.iter().map(|&x| x.to_owned()).collect();
+        self
+    }
+
+    /// Stores integer columns with the given encoding instead of choosing the smallest encoding that fits their values.
+    /// Supported encodings are `U8`, `U16` and `U32` (values are offset by the smallest value if necessary) and plain `I64`.
+    /// Ingestion fails with `IngestError::Encoding` if the encoding can't represent the values of a column,
+    /// or if the column is not an integer column.
+    pub fn with_encodings(mut self, encodings: HashMap<String, EncodingType>) -> Options {
+        self.encodings = encodings;
+        self
+    }
+
+    /// Stores integer columns whose values span a range of less than 8 bits with as few bits per value as possible
+    /// instead of one byte per value (default false). This reduces the memory usage of such columns by up to 8x,
+    /// but every query has to unpack the bits, which makes scanning them slower (see the `bit_packed` benchmarks).
+    /// Columns with an encoding set by `with_encodings` are never bit-packed.
+    pub fn with_bit_packing(mut self, bit_packing: bool) -> Options {
+        self.bit_packing = bit_packing;
+        self
+    }
+
+    fn forced_type(&self, colname: &str) -> Option<BasicType> {
+        match self.column_types.get(colname) {
+            Some(&t) => Some(t),
+            None if self.always_string.contains(colname) => Some(BasicType::String),
+            None => self.schema_type(colname),
+        }
+    }
+
+    fn schema_type(&self, colname: &str) -> Option<BasicType> {
+        self.schema.as_ref()?.iter()
+            .find(|&&(ref name, _)| self.renames.get(name).unwrap_or(name) == colname)
+            .and_then(|&(_, t)| t)
+    }
+
+    /// Whether dictionary encoded string columns use a dictionary shared by all partitions of the table (default false).
+    /// The dictionary of a column is created from the first partition, partitions with other strings are encoded
+    /// with a dictionary of their own.
+    pub fn with_shared_dictionaries(mut self, shared_dictionaries: bool) -> Options {
+        self.shared_dictionaries = shared_dictionaries;
+        self
+    }
+
+    /// How duplicate column names in the header are handled (default `DuplicateColumns::Rename`).
+    /// Duplicates are detected after applying `with_renamed_cols`.
+    pub fn with_duplicate_cols(mut self, duplicate_cols: DuplicateColumns) -> Options {
+        self.duplicate_cols = duplicate_cols;
+        self
+    }
+
+    /// Continues a previous ingestion of the same file into the same table that failed partway through.
+    /// Rows of all partitions stored by the previous attempt are skipped, rows of the partition that was being built
+    /// when it failed are ingested again. Without this option, ingestion always starts from the first row.
+    pub fn with_resume(mut self, resume: bool) -> Options {
+        self.resume = resume;
+        self
+    }
+
+    /// Skips batches with the same contents as a batch that was ingested into the table by a previous load with this
+    /// option enabled, so that loading the same data twice does not duplicate rows (default false).
+    /// Batches are identified by a hash over the values of all their rows, which requires the same partition size.
+    /// Identical batches within a single load are all stored. The hashes are persisted with the write-ahead log and
+    /// by `LocustDB::save`.
+    pub fn with_deduplicate_batches(mut self, deduplicate_batches: bool) -> Options {
+        self.deduplicate_batches = deduplicate_batches;
+        self
+    }
+
+    /// Overrides automatic detection of gzip compressed files.
+    pub fn with_gzip(self, gzipped: bool) -> Options {
+        self.with_compression(if gzipped { Compression::Gzip } else { Compression::None })
+    }
+
+    /// Overrides automatic detection of the compression format.
+    pub fn with_compression(mut self, compression: Compression) -> Options {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Compression format of the file.
+    /// Unless set explicitly, this is determined from the first bytes of the file,
+    /// or from the file extension if the file cannot be read.
+    fn compression(&self) -> Compression {
+        if let Some(compression) = self.compression { return compression; }
+        let mut magic = [0u8; 4];
+        match File::open(&self.filename).and_then(|mut f| f.read(&mut magic)) {
+            Ok(len) => Compression::from_magic(&magic[..len]),
+            Err(_) if self.filename.ends_with(".gz") => Compression::Gzip,
+            Err(_) if self.filename.ends_with(".zst") => Compression::Zstd,
+            Err(_) => Compression::None,
+        }
+    }
+}
+
+impl Compression {
+    fn from_magic(magic: &[u8]) -> Compression {
+        if magic.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if magic.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+pub fn ingest_file(ldb: &InnerLocustDB, opts: &Options) -> Result<(), IngestError> {
+    let f = File::open(&opts.filename)?;
+    ingest_compressed(ldb, f, opts.compression(), opts)
+}
+
+/// Ingests CSV data read from `input`, e.g. stdin.
+/// Unless set explicitly, compression is detected from the first bytes of the stream.
+pub fn ingest_stream<R: Read>(ldb: &InnerLocustDB, input: R, opts: &Options) -> Result<(), IngestError> {
+    let mut input = BufReader::new(input);
+    let compression = match opts.compression {
+        Some(compression) => compression,
+        None => Compression::from_magic(input.fill_buf()?),
+    };
+    ingest_compressed(ldb, input, compression, opts)
+}
+
+fn ingest_compressed<R: Read>(ldb: &InnerLocustDB, input: R, compression: Compression, opts: &Options) -> Result<(), IngestError> {
+    let (mut reader, headers) = csv_reader(decompress(input, compression, opts)?, opts)?;
+    auto_ingest(ldb, reader.records(), &headers, opts)
+}
+
+/// Ingests a fixed-width file where each field is given as `(name, start, len)` in bytes.
+/// Fields are trimmed of surrounding whitespace and parts of a field that extend past the end of a line are empty.
+pub fn ingest_fixed_width<R: Read>(ldb: &InnerLocustDB,
+                                   input: R,
+                                   fields: &[(String, usize, usize)],
+                                   opts: &Options) -> Result<(), IngestError> {
+    let mut input = BufReader::new(input);
+    let compression = match opts.compression {
+        Some(compression) => compression,
+        None => Compression::from_magic(input.fill_buf()?),
+    };
+    let input = BufReader::new(decompress(input, compression, opts)?);
+    let headers = fields.iter()
+        .map(|&(ref name, _, _)| opts.renames.get(name).cloned().unwrap_or_else(|| name.clone()))
+        .collect::<Vec<_>>();
+    let headers = deduplicate_headers(headers, opts.duplicate_cols)?;
+    let fields = fields.to_vec();
+    let records = input.lines()
+        .enumerate()
+        .filter(|&(_, ref line)| line.as_ref().map(|line| !line.trim().is_empty()).unwrap_or(true))
+        .map(move |(i, line)| -> csv::Result<csv::StringRecord> {
+            let line = line.map_err(csv::Error::from)?;
+            let mut record = fields.iter()
+                .map(|&(_, start, len)| slice_field(&line, start, len))
+                .collect::<csv::StringRecord>();
+            let mut position = csv::Position::new();
+            position.set_line(i as u64 + 1);
+            record.set_position(Some(position));
+            Ok(record)
+        });
+    auto_ingest(ldb, records, &headers, opts)
+}
+
+fn slice_field(line: &str, start: usize, len: usize) -> &str {
+    let line = line.trim_right_matches('\r');
+    let end = cmp::min(start + len, line.len());
+    if start >= end { return ""; }
+    let mut start = start;
+    while !line.is_char_boundary(start) { start += 1; }
+    let mut end = end;
+    while !line.is_char_boundary(end) { end -= 1; }
+    if start >= end { "" } else { line[start..end].trim() }
+}
+
+/// Identifies a batch ingested with `Options::with_deduplicate_batches` by the hash of its rows, and by how many batches
+/// of the same load up to and including it have the same hash (starting at 1).
+/// Batches with the same rows inside a single load therefore have different keys.
+pub type BatchKey = ([u8; 16], usize);
+
+/// Progress of ingesting a file, see `Options::with_resume`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IngestCheckpoint {
+    /// Number of rows stored in partitions
+    pub rows: usize,
+    /// Number of partitions stored
+    pub batches: usize,
 }
 
-pub fn ingest_file(ldb: &InnerLocustDB, opts: &Options) -> Result<(), String> {
-    // Can't combine these two branches because csv::Reader takes a type param which differs for creating from Reader/File
-    if opts.unzip {
-        let f = File::open(&opts.filename).map_err(|x| x.to_string())?;
-        let decoded = GzDecoder::new(f);
-        let mut reader = csv::ReaderBuilder::new()
-            .has_headers(opts.colnames.is_none())
-            .from_reader(decoded);
-        let headers = match opts.colnames {
-            Some(ref colnames) => colnames.clone(),
-            None => reader.headers().unwrap().iter().map(str::to_owned).collect()
+/// Progress of all ingestion tasks for a table, see `LocustDB::ingestion_status`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IngestionStatus {
+    /// Number of rows stored in partitions
+    pub rows: usize,
+    /// Number of scheduled ingestion tasks that have not finished yet
+    pub pending_tasks: usize,
+    /// Number of ingestion tasks that finished successfully
+    pub completed_tasks: usize,
+    /// Number of ingestion tasks that returned an error
+    pub failed_tasks: usize,
+}
+
+/// Type that a column would be ingested as, determined from a sample of rows by `infer_schema`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferredColumn {
+    pub name: String,
+    pub basic_type: BasicType,
+    /// Fraction of sampled values that are missing
+    pub null_fraction: f64,
+}
+
+/// Determines the type of each column from the first `sample_rows` rows of the file without ingesting it.
+/// Floats are ingested as integers and are reported as such.
+pub fn infer_schema(opts: &Options, sample_rows: usize) -> Result<Vec<InferredColumn>, IngestError> {
+    let f = File::open(&opts.filename)?;
+    let (mut reader, colnames) = csv_reader(decompress(f, opts.compression(), opts)?, opts)?;
+    let mut raw_cols = colnames.iter().map(|name| RawCol::new(opts.empty_strings.contains(name))).collect::<Vec<_>>();
+    let mut nulls = vec![0; colnames.len()];
+    let mut rows = 0;
+    for row in reader.records().take(sample_rows) {
+        let row = row.map_err(csv_error)?;
+        for (i, val) in row.iter().enumerate().take(colnames.len()) {
+            if opts.null_tokens.contains(val) || (val.is_empty() && !raw_cols[i].empty_is_string) {
+                raw_cols[i].push_null();
+                nulls[i] += 1;
+            } else {
+                raw_cols[i].push(val);
+            }
+        }
+        rows += 1;
+    }
+    Ok(colnames.iter().enumerate()
+        .filter(|&(_, name)| !opts.ignore_cols.contains(name))
+        .map(|(i, name)| InferredColumn {
+            name: name.clone(),
+            basic_type: if opts.extractors.contains_key(name) {
+                BasicType::Integer
+            } else {
+                raw_cols[i].basic_type(opts.forced_type(name))
+            },
+            null_fraction: if rows == 0 { 0.0 } else { nulls[i] as f64 / rows as f64 },
+        })
+        .collect())
+}
+
+fn decompress<'a, R: Read + 'a>(input: R, compression: Compression, opts: &Options) -> Result<Box<Read + 'a>, IngestError> {
+    Ok(match compression {
+        Compression::None => Box::new(input),
+        Compression::Gzip => Box::new(GzDecoder::new(input)),
+        Compression::Zstd => zstd_decoder(input, opts)?,
+    })
+}
+
+#[cfg(feature = "enable_zstd")]
+fn zstd_decoder<'a, R: Read + 'a>(input: R, _: &Options) -> Result<Box<Read + 'a>, IngestError> {
+    Ok(Box::new(zstd::Decoder::new(input)?))
+}
+
+#[cfg(not(feature = "enable_zstd"))]
+fn zstd_decoder<'a, R: Read + 'a>(_: R, opts: &Options) -> Result<Box<Read + 'a>, IngestError> {
+    Err(IngestError::Unsupported(format!("Failed to ingest {}: zstd not supported in this build of LocustDB. Recompile with --features enable_zstd.", opts.filename)))
+}
+
+/// Returns a CSV reader positioned at the first record and the (renamed) column names.
+fn csv_reader<R: Read>(input: R, opts: &Options) -> Result<(csv::Reader<R>, Vec<String>), IngestError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(opts.colnames.is_none() && opts.schema.is_none())
+        .from_reader(input);
+    let headers = match (&opts.schema, &opts.colnames) {
+        (&Some(ref schema), _) => schema.iter().map(|&(ref name, _)| name.clone()).collect(),
+        (&None, &Some(ref colnames)) => colnames.clone(),
+        (&None, &None) => reader.headers().map_err(csv_error)?.iter().map(str::to_owned).collect()
+    };
+    let headers = headers.into_iter()
+        .map(|colname| opts.renames.get(&colname).cloned().unwrap_or(colname))
+        .collect::<Vec<_>>();
+    let headers = deduplicate_headers(headers, opts.duplicate_cols)?;
+    Ok((reader, headers))
+}
+
+fn parse_schema(contents: &str) -> Result<Vec<(String, Option<BasicType>)>, IngestError> {
+    let mut schema = Vec::new();
+    for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap().trim().to_owned();
+        let basic_type = match parts.next().map(|t| t.trim().to_lowercase()) {
+            None => None,
+            Some(ref t) if t == "string" => Some(BasicType::String),
+            Some(ref t) if t == "integer" => Some(BasicType::Integer),
+            Some(ref t) if t == "boolean" => Some(BasicType::Boolean),
+            Some(ref t) if t == "null" => Some(BasicType::Null),
+            Some(t) => return Err(IngestError::Schema(format!("Unknown type `{}` for column `{}` in schema file", t, name))),
         };
-        auto_ingest(ldb, reader.records().map(|r| r.unwrap()), &headers, opts)
-    } else {
-        let mut reader = csv::ReaderBuilder::new()
-            .has_headers(opts.colnames.is_none())
-            .from_path(&opts.filename)
-            .map_err(|x| x.to_string())?;
-        let headers = match opts.colnames {
-            Some(ref colnames) => colnames.clone(),
-            None => reader.headers().unwrap().iter().map(str::to_owned).collect()
+        schema.push((name, basic_type));
+    }
+    Ok(schema)
+}
+
+fn deduplicate_headers(headers: Vec<String>, mode: DuplicateColumns) -> Result<Vec<String>, IngestError> {
+    let mut taken = headers.iter().cloned().collect::<HashSet<_>>();
+    let mut seen = HashSet::new();
+    let mut result = Vec::with_capacity(headers.len());
+    for colname in headers {
+        if seen.insert(colname.clone()) {
+            result.push(colname);
+            continue;
+        }
+        if mode == DuplicateColumns::Error {
+            return Err(IngestError::Schema(format!("Duplicate column name `{}` in header", colname)));
+        }
+        let renamed = (1..).map(|i| format!("{}_{}", colname, i))
+            .find(|candidate| !taken.contains(candidate))
+            .unwrap();
+        taken.insert(renamed.clone());
+        seen.insert(renamed.clone());
+        result.push(renamed);
+    }
+    Ok(result)
+}
+
+fn csv_error(err: csv::Error) -> IngestError {
+    if let csv::ErrorKind::UnequalLengths { ref pos, expected_len, len } = *err.kind() {
+        return IngestError::Parse {
+            row: pos.as_ref().map_or(0, |pos| pos.line()),
+            col: cmp::min(expected_len, len) as usize,
         };
-        auto_ingest(ldb, reader.records().map(|r| r.unwrap()), &headers, opts)
+    }
+    if !err.is_io_error() {
+        return IngestError::Csv(err.to_string());
+    }
+    match err.into_kind() {
+        csv::ErrorKind::Io(err) => IngestError::Io(err),
+        _ => unreachable!(),
     }
 }
 
-fn auto_ingest<T>(ldb: &InnerLocustDB, records: T, colnames: &[String], opts: &Options) -> Result<(), String>
-    where T: Iterator<Item=csv::StringRecord> {
+fn auto_ingest<T>(ldb: &InnerLocustDB, records: T, source_colnames: &[String], opts: &Options) -> Result<(), IngestError>
+    where T: Iterator<Item=csv::Result<csv::StringRecord>> {
+    let mut derived_inputs = Vec::with_capacity(opts.derived_cols.len());
+    for derived in &opts.derived_cols {
+        let mut inputs = Vec::with_capacity(derived.inputs.len());
+        for input in &derived.inputs {
+            match source_colnames.iter().position(|colname| colname == input) {
+                Some(index) => inputs.push(index),
+                None => return Err(IngestError::Schema(format!("Column {} used by derived column {} does not exist", input, derived.name))),
+            }
+        }
+        derived_inputs.push(inputs);
+    }
+    let colnames = &source_colnames.iter().cloned()
+        .chain(opts.derived_cols.iter().map(|derived| derived.name.clone()))
+        .collect::<Vec<_>>();
     let ignore = colnames.iter().map(|x| opts.ignore_cols.contains(x)).collect::<Vec<_>>();
-    let string = colnames.iter().map(|x| opts.always_string.contains(x)).collect::<Vec<_>>();
-    let mut raw_cols = (0..colnames.len()).map(|_| RawCol::new()).collect::<Vec<_>>();
+    let forced = colnames.iter().map(|x| opts.forced_type(x)).collect::<Vec<_>>();
+    for (colname, t) in colnames.iter().zip(&forced) {
+        match t {
+            Some(BasicType::Float) | Some(BasicType::Val) =>
+                return Err(IngestError::Unsupported(format!("Can't ingest column {} as {:?}", colname, t.unwrap()))),
+            _ => {}
+        }
+    }
+    let encodings = colnames.iter().map(|x| opts.encodings.get(x).cloned()).collect::<Vec<_>>();
+    for (colname, encoding) in colnames.iter().zip(&encodings) {
+        match *encoding {
+            None | Some(EncodingType::U8) | Some(EncodingType::U16) | Some(EncodingType::U32) | Some(EncodingType::I64) => {}
+            Some(t) => return Err(IngestError::Encoding(format!("Can't store column {} with encoding {:?}", colname, t))),
+        }
+    }
+    let mut checkpoint = if opts.resume {
+        ldb.ingest_checkpoint(&opts.tablename, &opts.filename)
+    } else {
+        IngestCheckpoint::default()
+    };
+    ldb.set_ingest_checkpoint(&opts.tablename, &opts.filename, checkpoint);
+    let mut raw_cols = colnames.iter().map(|name| RawCol::new(opts.empty_strings.contains(name))).collect::<Vec<_>>();
+    let mut batch_hasher = if opts.deduplicate_batches { Some(Md5::new()) } else { None };
+    // Batches are identified by the names of the stored columns in addition to their values
+    let stored_colnames = colnames.iter().zip(&ignore)
+        .filter(|&(_, &ignored)| !ignored)
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    let mut batch_counts = HashMap::new();
+    let dictionaries = if opts.shared_dictionaries { Some(ldb.string_dictionaries(&opts.tablename)) } else { None };
     let mut row_num = 0usize;
-    for row in records {
+    let mut batch_rows = 0usize;
+    let mut partition_size = match opts.partition_memory_budget {
+        Some(_) => cmp::min(opts.partition_size, AUTO_PARTITION_SAMPLE_ROWS),
+        None => opts.partition_size,
+    };
+    for row in records.skip(checkpoint.rows) {
+        let row = row.map_err(csv_error)?;
+        if row.len() != source_colnames.len() {
+            return Err(IngestError::Schema(format!(
+                "Expected {} columns ({}), found {} on line {}",
+                source_colnames.len(), source_colnames.join(", "), row.len(),
+                row.position().map_or(0, |pos| pos.line()))));
+        }
         for (i, val) in row.iter().enumerate() {
-            if !ignore[i] {
+            if ignore[i] {
+                continue;
+            } else if opts.null_tokens.contains(val) {
+                raw_cols[i].push_null();
+            } else if !is_valid(val, forced[i]) {
+                return Err(IngestError::Parse { row: row.position().map_or(0, |pos| pos.line()), col: i });
+            } else {
                 raw_cols[i].push(val);
             }
         }
-
-        if row_num % opts.partition_size == opts.partition_size - 1 {
-            let partition = create_batch(&mut raw_cols, colnames, &opts.extractors, &ignore, &string);
-            ldb.store_partition(&opts.tablename, partition);
+        if let Some(ref mut hasher) = batch_hasher {
+            for (val, &ignored) in row.iter().zip(&ignore) {
+                if ignored { continue; }
+                hasher.input(&(val.len() as u64).to_ne_bytes());
+                hasher.input_str(val);
+            }
+        }
+        for (i, (derived, inputs)) in opts.derived_cols.iter().zip(&derived_inputs).enumerate() {
+            let values = inputs.iter().map(|&input| &row[input]).collect::<Vec<_>>();
+            raw_cols[source_colnames.len() + i].push(&(derived.derivation)(&values));
         }
+
         row_num += 1;
+        batch_rows += 1;
+        if batch_rows == partition_size {
+            let batch_key = batch_key(&mut batch_hasher, &stored_colnames, &mut batch_counts);
+            let duplicate = batch_key.map_or(false, |key| ldb.is_duplicate_batch(&opts.tablename, key));
+            let tune_partition_size = opts.partition_memory_budget.is_some() && row_num == batch_rows;
+            if duplicate && !tune_partition_size {
+                raw_cols.iter_mut().for_each(RawCol::clear);
+            } else {
+                let partition = create_batch(&mut raw_cols, colnames, opts, &ignore, &forced, &encodings, &dictionaries)?;
+                if let (Some(budget), true) = (opts.partition_memory_budget, tune_partition_size) {
+                    // Partition boundaries have to match those of the duplicated ingestion
+                    partition_size = tuned_partition_size(&partition, batch_rows, budget);
+                }
+                if !duplicate {
+                    ldb.store_partition(&opts.tablename, partition, batch_key).map_err(IngestError::Wal)?;
+                }
+            }
+            checkpoint = IngestCheckpoint { rows: checkpoint.rows + batch_rows, batches: checkpoint.batches + 1 };
+            ldb.set_ingest_checkpoint(&opts.tablename, &opts.filename, checkpoint);
+            batch_rows = 0;
+        }
     }
 
-    if row_num % opts.partition_size != 0 {
-        let partition = create_batch(&mut raw_cols, colnames, &opts.extractors, &ignore, &string);
-        ldb.store_partition(&opts.tablename, partition);
+    if batch_rows > 0 {
+        let batch_key = batch_key(&mut batch_hasher, &stored_colnames, &mut batch_counts);
+        if !batch_key.map_or(false, |key| ldb.is_duplicate_batch(&opts.tablename, key)) {
+            let partition = create_batch(&mut raw_cols, colnames, opts, &ignore, &forced, &encodings, &dictionaries)?;
+            ldb.store_partition(&opts.tablename, partition, batch_key).map_err(IngestError::Wal)?;
+        }
+        checkpoint = IngestCheckpoint { rows: checkpoint.rows + batch_rows, batches: checkpoint.batches + 1 };
+        ldb.set_ingest_checkpoint(&opts.tablename, &opts.filename, checkpoint);
+    }
+    if row_num == 0 {
+        // Queries against tables created from empty files return no rows
+        ldb.create_if_empty(&opts.tablename);
     }
     Ok(())
 }
 
-fn create_batch(cols: &mut [RawCol], colnames: &[String], extractors: &IngestionTransform, ignore: &[bool], string: &[bool]) -> Vec<Arc<Column>> {
+/// Key of the batch formed by the rows hashed by `hasher` and stored in columns `colnames`, `counts` tracks the number
+/// of batches with each hash in the current load. Always `None` if deduplication is disabled, otherwise `hasher` is
+/// reset for the next batch.
+fn batch_key(hasher: &mut Option<Md5>, colnames: &[&str], counts: &mut HashMap<[u8; 16], usize>) -> Option<BatchKey> {
+    let hasher = hasher.as_mut()?;
+    for name in colnames {
+        hasher.input(&(name.len() as u64).to_ne_bytes());
+        hasher.input_str(name);
+    }
+    let mut hash = [0; 16];
+    hasher.result(&mut hash);
+    hasher.reset();
+    let count = counts.entry(hash).or_insert(0);
+    *count += 1;
+    Some((hash, *count))
+}
+
+/// Whether `value` can be ingested into a column of type `forced`.
+fn is_valid(value: &str, forced: Option<BasicType>) -> bool {
+    match forced {
+        _ if value.is_empty() => true,
+        Some(BasicType::Integer) => value.parse::<i64>().is_ok() || value.parse::<f64>().is_ok(),
+        Some(BasicType::Boolean) => BooleanColumn::is_boolean(value),
+        Some(BasicType::Null) => false,
+        _ => true,
+    }
+}
+
+/// Number of rows in the first partition, which determines the partition size when tuning for a memory budget.
+const AUTO_PARTITION_SAMPLE_ROWS: usize = 1 << 12;
+
+fn tuned_partition_size(sample: &[Arc<Column>], rows: usize, budget: usize) -> usize {
+    let bytes = sample.iter().map(|col| col.as_ref().heap_size_of_children()).sum::<usize>();
+    let bytes_per_row = cmp::max(1, bytes / rows);
+    cmp::max(1, budget / bytes_per_row)
+}
+
+fn create_batch(cols: &mut [RawCol],
+                colnames: &[String],
+                opts: &Options,
+                ignore: &[bool],
+                forced: &[Option<BasicType>],
+                encodings: &[Option<EncodingType>],
+                dictionaries: &Option<Arc<SharedDictionaries>>) -> Result<Vec<Arc<Column>>, IngestError> {
     let mut mem_store = Vec::new();
     for (i, col) in cols.iter_mut().enumerate() {
         if !ignore[i] {
-            let new_column = match extractors.get(&colnames[i]) {
-                Some(extractor) => col.extract(&colnames[i], *extractor),
-                None => col.finalize(&colnames[i], string[i]),
+            let new_column = match opts.extractors.get(&colnames[i]) {
+                Some(extractor) => col.extract(&colnames[i], *extractor, encodings[i], opts.bit_packing)?,
+                None => col.finalize(&colnames[i], forced[i], encodings[i], opts.bit_packing, dictionaries.as_ref().map(|d| &**d))?,
             };
             mem_store.push(new_column);
         }
     }
-    mem_store
+    Ok(mem_store)
 }
 
 pub struct CSVIngestionTask {
     options: Options,
     locustdb: Arc<InnerLocustDB>,
-    sender: SharedSender<Result<(), String>>,
+    sender: SharedSender<Result<(), IngestError>>,
 }
 
 impl CSVIngestionTask {
     pub fn new(options: Options,
                locustdb: Arc<InnerLocustDB>,
-               sender: SharedSender<Result<(), String>>) -> CSVIngestionTask {
+               sender: SharedSender<Result<(), IngestError>>) -> CSVIngestionTask {
+        locustdb.start_ingestion_task(&options.tablename);
         CSVIngestionTask {
             options,
             locustdb,
@@ -154,7 +731,9 @@ impl CSVIngestionTask {
 
 impl Task for CSVIngestionTask {
     fn execute(&self) {
-        self.sender.send(ingest_file(&self.locustdb, &self.options))
+        let result = ingest_file(&self.locustdb, &self.options);
+        self.locustdb.finish_ingestion_task(&self.options.tablename, result.is_ok());
+        self.sender.send(result)
     }
     fn completed(&self) -> bool { false }
     fn multithreaded(&self) -> bool { false }
@@ -162,70 +741,135 @@ impl Task for CSVIngestionTask {
 
 
 struct RawCol {
+    /// Whether empty values are empty strings rather than missing values
+    empty_is_string: bool,
     types: ColType,
     values: IndexedPackedStrings,
+    /// 1 for each missing value, see `Column::set_nulls`
+    nulls: Vec<u8>,
     lhex: bool,
     uhex: bool,
+    boolean: bool,
     string_bytes: usize,
 }
 
 impl RawCol {
-    fn new() -> RawCol {
+    fn new(empty_is_string: bool) -> RawCol {
         RawCol {
+            empty_is_string,
             types: ColType::nothing(),
             values: IndexedPackedStrings::default(),
+            nulls: Vec::new(),
             lhex: true,
             uhex: true,
+            boolean: true,
             string_bytes: 0,
         }
     }
 
     fn push(&mut self, elem: &str) {
-        self.types = self.types | ColType::determine(elem);
+        let t = if elem.is_empty() && self.empty_is_string { ColType::string() } else { ColType::determine(elem) };
+        self.push_typed(elem, t);
+    }
+
+    fn push_null(&mut self) {
+        self.push_typed("", ColType::null());
+    }
+
+    fn push_typed(&mut self, elem: &str, t: ColType) {
+        self.types = self.types | t;
         self.lhex = self.lhex && is_lowercase_hex(elem);
         self.uhex = self.uhex && is_uppercase_hex(elem);
+        self.boolean = self.boolean && BooleanColumn::is_boolean(elem);
         self.string_bytes += elem.as_bytes().len();
         self.values.push(elem);
+        self.nulls.push(t.contains_null as u8);
     }
 
-    fn finalize(&mut self, name: &str, string: bool) -> Arc<Column> {
-        let result = if self.types.contains_string || string {
-            fast_build_string_column(name, self.values.iter(), self.values.len(),
-                                     self.lhex, self.uhex, self.string_bytes)
+    /// Type of the column built by `finalize`.
+    fn basic_type(&self, forced: Option<BasicType>) -> BasicType {
+        if let Some(t) = forced {
+            t
+        } else if self.types.contains_string || (self.types.contains_bool && !self.boolean) {
+            BasicType::String
+        } else if self.types.contains_bool {
+            BasicType::Boolean
         } else if self.types.contains_int {
-            let mut builder = IntColBuilder::default();
-            for s in self.values.iter() {
-                let int = if s.is_empty() {
-                    0
-                } else if let Ok(int) = s.parse::<i64>() {
-                    int
-                } else if let Ok(float) = s.parse::<f64>() {
-                    float as i64
-                } else {
-                    unreachable!("{} should be parseable as int or float. {} {:?}", s, name, self.types)
-                };
-                builder.push(&int);
-            }
-            builder.finalize(name)
+            BasicType::Integer
         } else {
-            Arc::new(Column::null(name, self.values.len()))
+            BasicType::Null
+        }
+    }
+
+    fn finalize(&mut self,
+                name: &str,
+                forced: Option<BasicType>,
+                encoding: Option<EncodingType>,
+                bit_packing: bool,
+                dictionaries: Option<&SharedDictionaries>) -> Result<Arc<Column>, IngestError> {
+        let basic_type = self.basic_type(forced);
+        match (encoding, basic_type) {
+            (Some(t), BasicType::String) | (Some(t), BasicType::Boolean) => {
+                self.clear();
+                return Err(IngestError::Encoding(format!("Can't store {:?} column {} with encoding {:?}", basic_type, name, t)));
+            }
+            _ => {}
+        }
+        let mut result = match basic_type {
+            BasicType::String => fast_build_string_column(name, self.values.iter(), self.values.len(),
+                                                          self.lhex, self.uhex, self.string_bytes, dictionaries),
+            BasicType::Boolean => BooleanColumn::new_boxed(name, self.values.iter().map(BooleanColumn::parse).collect()),
+            BasicType::Integer => {
+                let mut builder = IntColBuilder::default();
+                for s in self.values.iter() {
+                    let int = extractor::parse_int(s).unwrap_or_else(||
+                        unreachable!("{} should be parseable as int or float. {} {:?}", s, name, self.types));
+                    builder.push(&int);
+                }
+                finalize_int(builder, name, encoding, bit_packing)?
+            }
+            _ => Arc::new(Column::null(name, self.values.len())),
         };
+        if self.types.contains_null && (basic_type == BasicType::String || basic_type == BasicType::Integer) {
+            // Missing values are stored as empty strings or zero and marked in the null map.
+            // The column was just built, so it isn't shared yet.
+            if let Some(column) = Arc::get_mut(&mut result) {
+                column.set_nulls(mem::replace(&mut self.nulls, Vec::new()));
+            }
+        }
         self.clear();
-        result
+        Ok(result)
     }
 
-    fn extract(&mut self, name: &str, extractor: extractor::Extractor) -> Arc<Column> {
+    fn extract(&mut self,
+               name: &str,
+               extractor: extractor::Extractor,
+               encoding: Option<EncodingType>,
+               bit_packing: bool) -> Result<Arc<Column>, IngestError> {
         let mut builder = IntColBuilder::default();
         for s in self.values.iter() {
             builder.push(&extractor(s));
         }
         self.clear();
-        builder.finalize(name)
+        finalize_int(builder, name, encoding, bit_packing)
     }
 
     fn clear(&mut self) {
         self.types = ColType::nothing();
+        self.boolean = true;
         self.values.clear();
+        self.nulls.clear();
+    }
+}
+
+fn finalize_int(builder: IntColBuilder,
+                name: &str,
+                encoding: Option<EncodingType>,
+                bit_packing: bool) -> Result<Arc<Column>, IngestError> {
+    match encoding {
+        Some(t) => builder.finalize_with_encoding(name, t)
+            .ok_or_else(|| IngestError::Encoding(format!("Values of column {} can't be represented with encoding {:?}", name, t))),
+        None => Ok(builder.finalize_with_bit_packing(name, bit_packing)),
     }
 }
 
@@ -253,27 +897,33 @@ struct ColType {
     contains_string: bool,
     contains_int: bool,
     contains_null: bool,
+    /// Contains `true` or `false`
+    contains_bool: bool,
 }
 
 impl ColType {
-    fn new(string: bool, int: bool, null: bool) -> ColType {
-        ColType { contains_string: string, contains_int: int, contains_null: null }
+    fn new(string: bool, int: bool, null: bool, boolean: bool) -> ColType {
+        ColType { contains_string: string, contains_int: int, contains_null: null, contains_bool: boolean }
     }
 
     fn string() -> ColType {
-        ColType::new(true, false, false)
+        ColType::new(true, false, false, false)
     }
 
     fn int() -> ColType {
-        ColType::new(false, true, false)
+        ColType::new(false, true, false, false)
     }
 
     fn null() -> ColType {
-        ColType::new(false, false, true)
+        ColType::new(false, false, true, false)
+    }
+
+    fn bool() -> ColType {
+        ColType::new(false, false, false, true)
     }
 
     fn nothing() -> ColType {
-        ColType::new(false, false, false)
+        ColType::new(false, false, false, false)
     }
 
     fn determine(s: &str) -> ColType {
@@ -281,6 +931,8 @@ impl ColType {
             ColType::null()
         } else if s.parse::<i64>().is_ok() || s.parse::<f64>().is_ok() {
             ColType::int()
+        } else if BooleanColumn::is_boolean_literal(s) {
+            ColType::bool()
         } else {
             ColType::string()
         }
@@ -294,6 +946,7 @@ impl BitOr for ColType {
             contains_string: self.contains_string | rhs.contains_string,
             contains_int: self.contains_int | rhs.contains_int,
             contains_null: self.contains_null | rhs.contains_null,
+            contains_bool: self.contains_bool | rhs.contains_bool,
         }
     }
 }
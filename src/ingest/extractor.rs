@@ -1,7 +1,12 @@
+use std::i64;
+
 use chrono::prelude::*;
 
 pub type Extractor = fn(&str) -> i64;
 
+/// Computes the value of a derived column from the values of its source columns.
+pub type Derivation = Box<Fn(&[&str]) -> String + Send + Sync>;
+
 pub fn multiply_by_100(field: &str) -> i64 {
     if let Ok(int) = field.parse::<i64>() {
         int * 100
@@ -26,6 +31,29 @@ pub fn multiply_by_1000(field: &str) -> i64 {
     }
 }
 
+/// Parses a value the way integer columns are ingested: empty values are 0 and floats are truncated.
+pub fn parse_int(field: &str) -> Option<i64> {
+    if field.is_empty() {
+        Some(0)
+    } else if let Ok(int) = field.parse::<i64>() {
+        Some(int)
+    } else if let Ok(float) = field.parse::<f64>() {
+        float_to_int(float)
+    } else {
+        None
+    }
+}
+
+/// Truncates `float` to an integer, returns `None` for NaN and floats outside the range of i64.
+pub fn float_to_int(float: f64) -> Option<i64> {
+    // i64::MIN is -2^63 which is exactly representable as f64, the largest i64 is not
+    if float >= i64::MIN as f64 && float < -(i64::MIN as f64) {
+        Some(float as i64)
+    } else {
+        None
+    }
+}
+
 pub fn int(field: &str) -> i64 {
     if let Ok(int) = field.parse::<i64>() {
         int
@@ -1,8 +1,10 @@
 pub mod csv_loader;
+pub mod arrow_loader;
 pub mod raw_val;
 pub mod input_column;
 pub mod buffer;
 pub mod extractor;
 pub mod nyc_taxi_data;
 pub mod colgen;
-mod alias_method_fork;
\ No newline at end of file
+mod alias_method_fork;
+mod arrow_ipc;
\ No newline at end of file
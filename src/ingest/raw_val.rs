@@ -1,22 +1,67 @@
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use engine::types::BasicType;
+use heapsize::HeapSizeOf;
+use ingest::extractor;
 
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, HeapSizeOf)]
+#[derive(Debug, Clone)]
 pub enum RawVal {
     Int(i64),
+    /// Integer outside the range of i64, which is only produced by sums. Construct with `RawVal::from_i128`.
+    BigInt(i128),
+    Float(f64),
     Str(String),
     Null,
 }
 
 impl RawVal {
+    /// Returns `Int` if `value` fits into i64 and `BigInt` otherwise.
+    pub fn from_i128(value: i128) -> RawVal {
+        if value >= i128::from(i64::MIN) && value <= i128::from(i64::MAX) {
+            RawVal::Int(value as i64)
+        } else {
+            RawVal::BigInt(value)
+        }
+    }
+
+    /// Value of `Int` and `BigInt`.
+    pub fn as_i128(&self) -> Option<i128> {
+        match *self {
+            RawVal::Int(i) => Some(i128::from(i)),
+            RawVal::BigInt(i) => Some(i),
+            _ => None,
+        }
+    }
+
     pub fn get_type(&self) -> BasicType {
         match *self {
-            RawVal::Int(_) => BasicType::Integer,
+            RawVal::Int(_) | RawVal::BigInt(_) => BasicType::Integer,
+            RawVal::Float(_) => BasicType::Float,
             RawVal::Str(_) => BasicType::String,
             RawVal::Null => BasicType::Null,
         }
     }
+
+    /// Converts the value to `target`, floats are truncated when converted to integers and strings are parsed
+    /// like values of integer columns during ingestion. Returns `None` if the conversion is not possible.
+    pub fn cast(&self, target: BasicType) -> Option<RawVal> {
+        match (self, target) {
+            (&RawVal::Null, _) => Some(RawVal::Null),
+            (&RawVal::Int(i), BasicType::Integer) => Some(RawVal::Int(i)),
+            (&RawVal::Int(i), BasicType::Float) => Some(RawVal::Float(i as f64)),
+            (&RawVal::BigInt(i), BasicType::Integer) => Some(RawVal::BigInt(i)),
+            (&RawVal::BigInt(i), BasicType::Float) => Some(RawVal::Float(i as f64)),
+            (&RawVal::Float(f), BasicType::Integer) => extractor::float_to_int(f).map(RawVal::Int),
+            (&RawVal::Float(f), BasicType::Float) => Some(RawVal::Float(f)),
+            (&RawVal::Str(ref s), BasicType::Integer) => extractor::parse_int(s).map(RawVal::Int),
+            (&RawVal::Str(ref s), BasicType::Float) => s.parse::<f64>().ok().map(RawVal::Float),
+            (&RawVal::Str(ref s), BasicType::String) => Some(RawVal::Str(s.clone())),
+            (value, BasicType::String) => Some(RawVal::Str(value.to_string())),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for RawVal {
@@ -24,13 +69,88 @@ impl fmt::Display for RawVal {
         match *self {
             RawVal::Null => write!(f, "null"),
             RawVal::Int(i) => write!(f, "{}", i),
+            RawVal::BigInt(i) => write!(f, "{}", i),
+            // Precision (e.g. `{:.2}`) only applies to floats
+            RawVal::Float(x) => match f.precision() {
+                Some(precision) => write!(f, "{:.*}", precision, x),
+                None => write!(f, "{}", x),
+            },
             RawVal::Str(ref s) => write!(f, "\"{}\"", s),
         }
     }
 }
 
+// Floats are compared by their total order (as defined by IEEE 754) so that RawVal can be used as a key.
+impl PartialEq for RawVal {
+    fn eq(&self, other: &RawVal) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RawVal {}
+
+impl PartialOrd for RawVal {
+    fn partial_cmp(&self, other: &RawVal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RawVal {
+    fn cmp(&self, other: &RawVal) -> Ordering {
+        match (self, other) {
+            (RawVal::Int(a), RawVal::Int(b)) => a.cmp(b),
+            (RawVal::Int(a), RawVal::BigInt(b)) => i128::from(*a).cmp(b),
+            (RawVal::BigInt(a), RawVal::Int(b)) => a.cmp(&i128::from(*b)),
+            (RawVal::BigInt(a), RawVal::BigInt(b)) => a.cmp(b),
+            (RawVal::Float(a), RawVal::Float(b)) => total_order_key(*a).cmp(&total_order_key(*b)),
+            (RawVal::Str(a), RawVal::Str(b)) => a.cmp(b),
+            (RawVal::Null, RawVal::Null) => Ordering::Equal,
+            _ => self.variant_index().cmp(&other.variant_index()),
+        }
+    }
+}
+
+impl Hash for RawVal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.variant_index().hash(state);
+        match *self {
+            RawVal::Int(i) => i.hash(state),
+            RawVal::BigInt(i) => i.hash(state),
+            RawVal::Float(x) => total_order_key(x).hash(state),
+            RawVal::Str(ref s) => s.hash(state),
+            RawVal::Null => {}
+        }
+    }
+}
+
+impl HeapSizeOf for RawVal {
+    fn heap_size_of_children(&self) -> usize {
+        match *self {
+            RawVal::Str(ref s) => s.heap_size_of_children(),
+            _ => 0,
+        }
+    }
+}
+
+impl RawVal {
+    /// `Int` and `BigInt` never hold the same value, so they are ordered by value rather than by variant.
+    fn variant_index(&self) -> u8 {
+        match *self {
+            RawVal::Int(_) | RawVal::BigInt(_) => 0,
+            RawVal::Float(_) => 1,
+            RawVal::Str(_) => 2,
+            RawVal::Null => 3,
+        }
+    }
+}
+
+pub fn total_order_key(x: f64) -> i64 {
+    let bits = x.to_bits() as i64;
+    bits ^ (((bits >> 63) as u64) >> 1) as i64
+}
+
 pub mod syntax {
-    pub use super::RawVal::{Int, Null};
+    pub use super::RawVal::{Int, Float, Null};
 
     #[allow(non_snake_case)]
     pub fn Str(s: &str) -> super::RawVal { super::RawVal::Str(s.to_string()) }
@@ -13,6 +13,7 @@ extern crate futures_executor;
 #[macro_use]
 extern crate heapsize_derive;
 extern crate heapsize;
+extern crate bit_vec;
 extern crate itertools;
 extern crate num;
 extern crate num_cpus;
@@ -27,6 +28,8 @@ extern crate crypto;
 extern crate hex;
 #[cfg(feature = "enable_rocksdb")]
 extern crate capnp;
+#[cfg(feature = "enable_zstd")]
+extern crate zstd;
 extern crate std_semaphore;
 extern crate aliasmethod;
 extern crate rand;
@@ -44,11 +47,26 @@ mod scheduler;
 mod locustdb;
 mod disk_store;
 mod stringpack;
+#[cfg(feature = "enable_http")]
+mod server;
 pub mod unit_fmt;
 
+pub use engine::aggregator::Aggregator;
+pub use engine::query::Query;
 pub use engine::query_task::QueryOutput;
+pub use engine::query_task::QueryStats;
+pub use engine::types::BasicType;
+pub use engine::types::EncodingType;
+pub use errors::IngestError;
+pub use ingest::buffer::Buffer as Batch;
 pub use errors::QueryError;
 pub use ingest::csv_loader::Options as LoadOptions;
+pub use ingest::csv_loader::Compression;
+pub use ingest::csv_loader::DuplicateColumns;
+pub use ingest::csv_loader::IngestCheckpoint;
+pub use ingest::csv_loader::IngestionStatus;
+pub use ingest::csv_loader::InferredColumn;
+pub use ingest::csv_loader::infer_schema;
 pub use ingest::extractor;
 pub use ingest::nyc_taxi_data;
 pub use ingest::raw_val::RawVal as Value;
@@ -56,8 +74,13 @@ pub use ingest::raw_val::syntax as value_syntax;
 pub use ingest::colgen;
 pub use locustdb::LocustDB as LocustDB;
 pub use locustdb::Options as Options;
-pub use mem_store::table::TableStats;
+pub use locustdb::QueryStream;
+pub use mem_store::table::{ColumnStats, TableStats};
+pub use syntax::expression::{Expr, Func1Type, Func2Type};
+pub use syntax::query_builder::QueryBuilder;
 pub use disk_store::noop_storage::NoopStorage;
+#[cfg(feature = "enable_http")]
+pub use server::HttpServer;
 
 pub type QueryResult = Result<QueryOutput, QueryError>;
 
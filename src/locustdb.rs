@@ -1,5 +1,13 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::mem;
 use std::str;
+use std::u64;
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 
 use futures_channel::oneshot;
 use futures_core::*;
@@ -8,15 +16,22 @@ use futures_util;
 use futures_executor::block_on;
 use num_cpus;
 
+use IngestError;
 use QueryError;
 use QueryResult;
 use disk_store::interface::*;
 use disk_store::noop_storage::NoopStorage;
-use engine::query_task::QueryTask;
+use engine::query::Query;
+use engine::query_task::{self, QueryTask};
+use engine::types::BasicType;
 use ingest::colgen::GenTable;
-use ingest::csv_loader::{CSVIngestionTask, Options as LoadOptions};
+use ingest::csv_loader::{CSVIngestionTask, IngestCheckpoint, IngestionStatus, Options as LoadOptions};
 use mem_store::*;
+use mem_store::partition::Partition;
 use scheduler::*;
+use scheduler::inner_locustdb::INFORMATION_SCHEMA;
+use syntax::expression::Expr;
+use syntax::limit::LimitClause;
 use syntax::parser;
 use trace::{Trace, TraceBuilder};
 
@@ -27,39 +42,197 @@ pub struct LocustDB {
 
 impl LocustDB {
     pub fn memory_only() -> LocustDB {
-        LocustDB::new(&Options::default())
+        LocustDB::start(Arc::new(InnerLocustDB::new(Arc::new(NoopStorage), &Options::default())))
     }
 
+    /// Does not open the write-ahead log at `Options::wal_path`, use `with_wal` instead.
     pub fn new(opts: &Options) -> LocustDB {
+        LocustDB::start(LocustDB::create(opts))
+    }
+
+    /// Like `new`, but restores all partitions recorded in the write-ahead log at `Options::wal_path` (if set) and
+    /// appends new partitions to it. Fails if the write-ahead log cannot be restored or opened.
+    pub fn with_wal(opts: &Options) -> io::Result<LocustDB> {
+        let locustdb = LocustDB::create(opts);
+        if let Some(ref path) = opts.wal_path {
+            locustdb.open_wal(path)?;
+        }
+        Ok(LocustDB::start(locustdb))
+    }
+
+    fn create(opts: &Options) -> Arc<InnerLocustDB> {
         let disk_store = opts.db_path.as_ref()
             .map(|path| LocustDB::persistent_storage(path))
             .unwrap_or_else(|| Arc::new(NoopStorage));
-        let locustdb = Arc::new(InnerLocustDB::new(disk_store, opts));
+        assert!(opts.threads > 0, "Options::threads must be positive");
+        Arc::new(InnerLocustDB::new(disk_store, opts))
+    }
+
+    fn start(locustdb: Arc<InnerLocustDB>) -> LocustDB {
         InnerLocustDB::start_worker_threads(&locustdb);
         LocustDB { inner_locustdb: locustdb }
     }
 
+    /// Runs `query`, which may combine several queries with `UNION ALL`. The ORDER BY, LIMIT and OFFSET clauses
+    /// following the last of these queries apply to the combined rows, they are rejected after any other query.
     pub fn run_query(&self, query: &str, explain: bool, show: Vec<usize>) -> Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> {
-        let (sender, receiver) = oneshot::channel();
+        self.run_query_with_timeout(query, explain, show, None)
+    }
+
+    /// Like `run_query`, but fails with `QueryError::Timeout` once the query has been running for longer than `timeout`.
+    /// The timeout is checked before processing each partition.
+    pub fn run_query_with_timeout(&self, query: &str, explain: bool, show: Vec<usize>, timeout: Option<Duration>) -> Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> {
+        self.run_query_cancellable(query, explain, show, timeout, None)
+    }
+
+    /// Like `run_query_with_timeout`, but additionally fails with `QueryError::Cancelled` once `cancel` is set.
+    /// Cancellation is checked before processing each partition, so partitions already in flight will still run to completion.
+    pub fn run_query_cancellable(&self, query: &str, explain: bool, show: Vec<usize>,
+                                 timeout: Option<Duration>,
+                                 cancel: Option<Arc<AtomicBool>>) -> Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> {
+        self.run_query_restricted(query, explain, show, timeout, cancel, None)
+    }
 
+    /// Runs the next page of the ordered query `query`, following the page whose result returned `cursor`.
+    pub fn run_query_after(&self, query: &str, cursor: &str, explain: bool, show: Vec<usize>) -> Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> {
+        match parser::parse_query(query).and_then(|mut query| query.resume_after(cursor).map(|_| query)) {
+            Ok(query) => self.run_parsed_query(query, explain, show, None, None, None),
+            Err(err) => Box::new(future::ok(
+                (Err(err),
+                 TraceBuilder::new("empty".to_owned()).finalize()))),
+        }
+    }
+
+    /// Runs a query constructed programmatically, e.g. with `QueryBuilder`, instead of parsing it from SQL.
+    pub fn run_query_ast(&self, query: Query, explain: bool, show: Vec<usize>) -> Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> {
+        self.run_parsed_query(query, explain, show, None, None, None)
+    }
+
+    /// Runs `query` only on the partitions whose values of the integer column `time_column` overlap `[start, end]`.
+    /// All other partitions are skipped before execution, but rows outside the window that belong to an overlapping partition are still included.
+    pub fn run_query_in_time_range(&self, query: &str, explain: bool, show: Vec<usize>,
+                                   time_column: &str, start: i64, end: i64) -> Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> {
+        self.run_query_restricted(query, explain, show, None, None, Some((time_column, start, end)))
+    }
+
+    fn run_query_restricted(&self, query: &str, explain: bool, show: Vec<usize>,
+                            timeout: Option<Duration>,
+                            cancel: Option<Arc<AtomicBool>>,
+                            time_range: Option<(&str, i64, i64)>) -> Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> {
         // TODO(clemens): perform compilation and table snapshot in asynchronous task?
-        let query = match parser::parse_query(query) {
-            Ok(query) => query,
-            Err(err) => {
-                return Box::new(future::ok(
-                    (Err(err),
-                     TraceBuilder::new("empty".to_owned()).finalize())));
+        let parsed = parser::split_union_all(query)
+            .and_then(|queries| queries.iter().map(|query| parser::parse_query(query)).collect::<Result<Vec<_>, _>>());
+        let mut queries = match parsed.and_then(|queries| {
+            if queries.len() > 1 && queries.last().unwrap().group_limit.is_some() {
+                bail!(QueryError::NotImplemented, "LIMIT ... PER is not supported for queries combined by UNION ALL");
+            }
+            Ok(queries)
+        }) {
+            Ok(queries) => queries,
+            Err(err) => return Box::new(future::ok(
+                (Err(err),
+                 TraceBuilder::new("empty".to_owned()).finalize()))),
+        };
+        if queries.len() == 1 {
+            return self.run_parsed_query(queries.pop().unwrap(), explain, show, timeout, cancel, time_range);
+        }
+
+        // The ORDER BY, LIMIT and OFFSET clauses of the last query apply to the combined rows of all queries
+        let (order_by, collation, limit) = {
+            let last = queries.last_mut().unwrap();
+            let desc = last.order_desc;
+            let mut order_by = last.order_by.take().map(|column| (column, desc)).into_iter().collect::<Vec<_>>();
+            order_by.extend(mem::replace(&mut last.then_order_by, Vec::new()));
+            (order_by, last.collation, last.limit.clone())
+        };
+        let results = queries.into_iter()
+            .map(|mut query| {
+                query.limit = LimitClause { limit: u64::MAX, offset: 0 };
+                self.run_parsed_query(query, explain, show.clone(), timeout, cancel.clone(), time_range)
+            })
+            .collect::<Vec<_>>();
+        // Queries combined by UNION ALL run concurrently, their rows are concatenated in order
+        let union = results.into_iter()
+            .fold(None, |union: Option<Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>>>, result| match union {
+                None => Some(result),
+                Some(union) => Some(Box::new(union.join(result).map(|((union, union_trace), (result, trace))| {
+                    (union.and_then(|union| result.and_then(|result| union.union_all(result))), union_trace.merge(trace, "union all"))
+                }))),
+            })
+            .unwrap();
+        Box::new(union.map(move |(result, trace)| {
+            (result.and_then(|output| output.order_and_limit(&order_by, collation, &limit)), trace)
+        }))
+    }
+
+    fn run_parsed_query(&self, mut query: Query, explain: bool, show: Vec<usize>,
+                        timeout: Option<Duration>,
+                        cancel: Option<Arc<AtomicBool>>,
+                        time_range: Option<(&str, i64, i64)>) -> Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> {
+        let cache_key = if self.inner_locustdb.opts().query_cache_size > 0 && !explain && show.is_empty()
+            && time_range.is_none() && query.sample.is_none() && query.table != INFORMATION_SCHEMA {
+            Some(format!("{:?}", query))
+        } else {
+            None
+        };
+        if let Some(ref key) = cache_key {
+            if let Some(output) = self.inner_locustdb.cached_query_result(key, &query.table) {
+                return Box::new(future::ok((Ok(output), TraceBuilder::new("cached".to_owned()).finalize())));
             }
+        }
+        // Read before taking the snapshot so that writes that happen concurrently invalidate the cached result
+        let table_version = self.inner_locustdb.table_version(&query.table);
+
+        let data = match self.prepare_query(&mut query, time_range) {
+            Ok(data) => data,
+            Err(err) => return Box::new(future::ok((Err(err), TraceBuilder::new("empty".to_owned()).finalize()))),
         };
+        let result = self.schedule_query(query, explain, show, data, timeout, cancel);
+        match cache_key {
+            Some(key) => {
+                let inner_locustdb = self.inner_locustdb.clone();
+                Box::new(result.map(move |(result, trace)| {
+                    if let Ok(ref output) = result {
+                        inner_locustdb.cache_query_result(key, table_version, output);
+                    }
+                    (result, trace)
+                }))
+            }
+            None => Box::new(result),
+        }
+    }
 
+    /// Returns the partitions of the table queried by `query` that overlap `time_range`,
+    /// after resolving and type checking the columns referenced by `query`.
+    fn prepare_query(&self, query: &mut Query, time_range: Option<(&str, i64, i64)>) -> Result<Vec<Arc<Partition>>, QueryError> {
         let mut data = match self.inner_locustdb.snapshot(&query.table) {
             Some(data) => data,
             // TODO(clemens): A table may not exist on all nodes, so querying empty table is valid and should return empty result.
-            None => return Box::new(future::ok((
-                Err(QueryError::NotImplemented(format!("Table {} does not exist!", &query.table))),
-                TraceBuilder::new("empty".to_owned()).finalize()))),
+            None => bail!(QueryError::NotImplemented, "Table {} does not exist!", &query.table),
         };
 
+        if let Some((time_column, start, end)) = time_range {
+            data.retain(|partition| partition.may_overlap(time_column, start, end));
+        }
+
+        if self.inner_locustdb.opts().case_insensitive_columns {
+            query.resolve_colnames_case_insensitive(&query_task::find_all_cols(&data));
+        }
+
+        if let Some(col) = query_task::find_unknown_col(query, &data) {
+            return Err(QueryError::UnknownColumn(col));
+        }
+
+        query.type_check(&query_task::find_col_types(&data))?;
+        Ok(data)
+    }
+
+    /// Runs `query` on the partitions `data` on the worker threads.
+    fn schedule_query(&self, query: Query, explain: bool, show: Vec<usize>,
+                      mut data: Vec<Arc<Partition>>,
+                      timeout: Option<Duration>,
+                      cancel: Option<Arc<AtomicBool>>) -> Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> {
+        let (sender, receiver) = oneshot::channel();
         if self.inner_locustdb.opts().seq_disk_read {
             self.inner_locustdb.disk_read_scheduler()
                 .schedule_sequential_read(&mut data,
@@ -70,15 +243,53 @@ impl LocustDB {
             let _ = self.inner_locustdb.schedule(read_data);
         }
 
-        let task = QueryTask::new(
+        let task = match QueryTask::new(
             query, explain, show, data,
+            timeout.map(|t| t.as_secs() * 1_000_000_000 + u64::from(t.subsec_nanos())),
+            cancel,
+            self.inner_locustdb.opts().detailed_stats,
+            self.inner_locustdb.opts().combine_aggregates,
             self.inner_locustdb.disk_read_scheduler().clone(),
-            SharedSender::new(sender));
+            SharedSender::new(sender)) {
+            Ok(task) => task,
+            Err(err) => return Box::new(future::ok((Err(err), TraceBuilder::new("empty".to_owned()).finalize()))),
+        };
         let trace_receiver = self.schedule(task);
         Box::new(receiver.join(trace_receiver))
     }
 
-    pub fn load_csv(&self, options: LoadOptions) -> impl Future<Item=Result<(), String>, Error=oneshot::Canceled> {
+    /// Runs `query` and returns its rows in chunks as soon as they are available, see `QueryStream`.
+    /// Rows of queries without aggregates, ORDER BY, DISTINCT and TABLESAMPLE are returned one partition at a time
+    /// in the order in which the partitions were stored, all other queries return their rows in a single chunk
+    /// once the query completes.
+    pub fn run_query_streaming(&self, query: &str) -> QueryStream {
+        let parsed = parser::split_union_all(query).and_then(|queries| match queries.len() {
+            1 => parser::parse_query(&queries[0]).map(Some),
+            _ => Ok(None),
+        });
+        let mut query = match parsed {
+            Ok(Some(ref query)) if query.is_streamable() => query.clone(),
+            Ok(_) => return QueryStream::single(self, self.run_query(query, false, vec![])),
+            Err(err) => return QueryStream::failed(self, err),
+        };
+        let mut data = match self.prepare_query(&mut query, None) {
+            Ok(data) => data,
+            Err(err) => return QueryStream::failed(self, err),
+        };
+        data.sort_by_key(|partition| partition.id());
+        if query.is_select_star() {
+            // Partitions may have different columns, every chunk has all columns of the table
+            query.select = query_task::find_all_cols(&data).into_iter().map(Expr::ColName).collect();
+        }
+        QueryStream::partitioned(self, query, data)
+    }
+
+    /// Number of queries that were answered from the result cache, see `Options::query_cache_size`.
+    pub fn query_cache_hits(&self) -> usize {
+        self.inner_locustdb.query_cache_hits()
+    }
+
+    pub fn load_csv(&self, options: LoadOptions) -> impl Future<Item=Result<(), IngestError>, Error=oneshot::Canceled> {
         let (sender, receiver) = oneshot::channel();
         let task = CSVIngestionTask::new(
             options,
@@ -88,7 +299,41 @@ impl LocustDB {
         receiver
     }
 
-    pub fn gen_table(&self, opts: GenTable) -> impl Future<Item=(), Error=oneshot::Canceled> {
+    /// Ingests CSV data from `input` (e.g. stdin) on the calling thread.
+    /// `options` determines the target table, and its filename is only used in error messages.
+    /// Each partition becomes queryable as soon as its rows have been read, only the rows of the partition that
+    /// is currently being built are buffered.
+    pub fn load_csv_stream<R: Read>(&self, input: R, options: LoadOptions) -> Result<(), IngestError> {
+        self.inner_locustdb.ingest_csv_stream(input, &options)
+    }
+
+    /// Ingests the Arrow IPC file at `path` into `table` on the calling thread, using the column types of the Arrow schema.
+    pub fn load_arrow_file(&self, path: &str, table: &str) -> Result<(), IngestError> {
+        self.inner_locustdb.ingest_arrow_file(path, table)
+    }
+
+    /// Ingests a fixed-width (positional) file from `input` on the calling thread.
+    /// Each of `fields` is a `(name, start, len)` triple giving the byte range of a column within every line.
+    /// Values are trimmed and then typed in the same way as CSV fields, `options` determines the target table.
+    pub fn load_fixed_width_stream<R: Read>(&self,
+                                            input: R,
+                                            fields: &[(String, usize, usize)],
+                                            options: LoadOptions) -> Result<(), IngestError> {
+        self.inner_locustdb.ingest_fixed_width_stream(input, fields, &options)
+    }
+
+    /// Writes all tables to the file at `path`, preserving the encodings of all columns.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        self.inner_locustdb.save(path)
+    }
+
+    /// Loads tables previously written by `save` from the file at `path`.
+    pub fn load(&self, path: &str) -> io::Result<()> {
+        self.inner_locustdb.load(path)
+    }
+
+    /// Fails if a generated partition could not be appended to the write-ahead log.
+    pub fn gen_table(&self, opts: GenTable) -> impl Future<Item=io::Result<()>, Error=oneshot::Canceled> {
         let mut receivers = Vec::new();
         let opts = Arc::new(opts);
         for partition in 0..opts.partitions {
@@ -98,7 +343,7 @@ impl LocustDB {
             self.schedule(task);
             receivers.push(receiver);
         }
-        futures_util::future::join_all(receivers).map(|_| ())
+        futures_util::future::join_all(receivers).map(|results| results.into_iter().collect())
     }
 
     pub fn ast(&self, query: &str) -> String {
@@ -139,6 +384,31 @@ impl LocustDB {
         receiver
     }
 
+    /// Largest number of worker threads that have executed tasks at the same time, which never exceeds `Options::threads`.
+    pub fn max_busy_workers(&self) -> usize {
+        self.inner_locustdb.max_busy_workers()
+    }
+
+    /// Number of rows and partitions of `source` (the filename given to `LoadOptions`) that the most recent
+    /// ingestion into `table` stored before it completed or failed. Ingestion with `LoadOptions::with_resume` continues from here.
+    pub fn ingest_checkpoint(&self, table: &str, source: &str) -> IngestCheckpoint {
+        self.inner_locustdb.ingest_checkpoint(table, source)
+    }
+
+    /// Returns the `(code, string)` entries of the dictionary of each partition in which the string `column` of `table`
+    /// is dictionary encoded, or `None` if `table` does not exist.
+    /// Partitions may reference different versions of the shared dictionary of a column, so codes are only consistent
+    /// within the dictionary of a single partition.
+    pub fn column_dictionaries(&self, table: &str, column: &str) -> Option<Vec<Vec<(u64, String)>>> {
+        self.inner_locustdb.column_dictionaries(table, column)
+    }
+
+    /// Number of rows stored in `table` since startup and the number of pending, completed and failed tasks
+    /// scheduled with `load_csv` that ingest into `table`.
+    pub fn ingestion_status(&self, table: &str) -> IngestionStatus {
+        self.inner_locustdb.ingestion_status(table)
+    }
+
     pub fn table_stats(&self) -> impl Future<Item=Vec<TableStats>, Error=oneshot::Canceled> {
         let inner = self.inner_locustdb.clone();
         let (task, receiver) = Task::from_fn(move || inner.stats());
@@ -146,6 +416,23 @@ impl LocustDB {
         receiver
     }
 
+    /// Names of all tables, including internal tables prefixed with `_meta`.
+    pub fn tables(&self) -> impl Future<Item=Vec<String>, Error=oneshot::Canceled> {
+        let inner = self.inner_locustdb.clone();
+        let (task, receiver) = Task::from_fn(move || inner.tables());
+        self.schedule(task);
+        receiver
+    }
+
+    /// Name and decoded type of each column in `table`, or `None` if the table does not exist.
+    pub fn schema(&self, table: &str) -> impl Future<Item=Option<Vec<(String, BasicType)>>, Error=oneshot::Canceled> {
+        let inner = self.inner_locustdb.clone();
+        let table = table.to_string();
+        let (task, receiver) = Task::from_fn(move || inner.schema(&table));
+        self.schedule(task);
+        receiver
+    }
+
     fn schedule<T: Task + 'static>(&self, task: T) -> impl Future<Item=Trace, Error=oneshot::Canceled> {
         self.inner_locustdb.schedule(task)
     }
@@ -168,8 +455,122 @@ impl Drop for LocustDB {
     }
 }
 
+/// Rows of a query returned by `LocustDB::run_query_streaming`, one chunk at a time.
+/// Each chunk has the column names and types of the query and the statistics of the partitions it was read from.
+/// Partitions are processed concurrently by up to `Options::threads` worker threads, but chunks are returned in order.
+/// Dropping the stream stops scheduling further partitions.
+pub struct QueryStream<'a> {
+    locustdb: &'a LocustDB,
+    query: Option<Query>,
+    /// Partitions that have not been scheduled yet
+    partitions: VecDeque<Arc<Partition>>,
+    /// Results of scheduled partitions in partition order
+    in_flight: VecDeque<Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>>>,
+    /// Number of rows that remain to be skipped for the OFFSET clause
+    offset: u64,
+    /// Number of rows that remain to be returned for the LIMIT clause
+    remaining: u64,
+    /// Whether a chunk has been returned yet
+    started: bool,
+}
+
+impl<'a> QueryStream<'a> {
+    fn single(locustdb: &'a LocustDB,
+              result: Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>>) -> QueryStream<'a> {
+        QueryStream {
+            locustdb,
+            query: None,
+            partitions: VecDeque::new(),
+            in_flight: vec![result].into_iter().collect(),
+            offset: 0,
+            remaining: u64::MAX,
+            started: false,
+        }
+    }
+
+    fn failed(locustdb: &'a LocustDB, err: QueryError) -> QueryStream<'a> {
+        QueryStream::single(locustdb, Box::new(future::ok((Err(err), TraceBuilder::new("empty".to_owned()).finalize()))))
+    }
+
+    fn partitioned(locustdb: &'a LocustDB, mut query: Query, partitions: Vec<Arc<Partition>>) -> QueryStream<'a> {
+        let limit = mem::replace(&mut query.limit, LimitClause { limit: u64::MAX, offset: 0 });
+        // The rows skipped by OFFSET may all come from the same partition
+        query.limit.limit = limit.limit.saturating_add(limit.offset);
+        let mut stream = QueryStream {
+            locustdb,
+            query: Some(query),
+            partitions: partitions.into_iter().collect(),
+            in_flight: VecDeque::new(),
+            offset: limit.offset,
+            remaining: limit.limit,
+            started: false,
+        };
+        if stream.partitions.is_empty() {
+            // Returns a single empty chunk with the column names of the query
+            let query = stream.query.take().unwrap();
+            stream.in_flight.push_back(locustdb.schedule_query(query, false, vec![], vec![], None, None));
+        }
+        stream
+    }
+
+    fn schedule_partitions(&mut self) {
+        let query = match self.query {
+            Some(ref query) => query,
+            None => return,
+        };
+        let threads = self.locustdb.inner_locustdb.opts().threads;
+        while self.in_flight.len() < threads {
+            match self.partitions.pop_front() {
+                Some(partition) => self.in_flight.push_back(
+                    self.locustdb.schedule_query(query.clone(), false, vec![], vec![partition], None, None)),
+                None => break,
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for QueryStream<'a> {
+    type Item = QueryResult;
+
+    fn next(&mut self) -> Option<QueryResult> {
+        loop {
+            self.schedule_partitions();
+            let chunk = self.in_flight.pop_front()?;
+            let mut output = match block_on(chunk) {
+                Ok((Ok(output), _)) => output,
+                Ok((Err(err), _)) => {
+                    self.partitions.clear();
+                    self.in_flight.clear();
+                    return Some(Err(err));
+                }
+                Err(_) => {
+                    self.partitions.clear();
+                    self.in_flight.clear();
+                    return Some(Err(QueryError::FatalError("Query execution was canceled".to_string())));
+                }
+            };
+            let skipped = cmp::min(self.offset, output.rows.len() as u64);
+            output.rows.drain(..skipped as usize);
+            self.offset -= skipped;
+            if output.rows.len() as u64 >= self.remaining {
+                output.rows.truncate(self.remaining as usize);
+                self.partitions.clear();
+                self.in_flight.clear();
+            }
+            self.remaining -= output.rows.len() as u64;
+            let last = self.partitions.is_empty() && self.in_flight.is_empty();
+            // Chunks without rows are skipped, unless no chunk would be returned otherwise
+            if !output.rows.is_empty() || (last && !self.started) {
+                self.started = true;
+                return Some(Ok(output));
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Options {
+    /// Number of worker threads that execute queries and ingestion tasks, defaults to the number of logical CPUs
     pub threads: usize,
     pub read_threads: usize,
     pub db_path: Option<String>,
@@ -177,6 +578,20 @@ pub struct Options {
     pub mem_lz4: bool,
     pub readahead: usize,
     pub seq_disk_read: bool,
+    /// Record time spent in each phase of query execution in `QueryStats::breakdown`
+    pub detailed_stats: bool,
+    /// Path of a write-ahead log that records all ingested partitions and is replayed on startup.
+    /// Only used by `LocustDB::with_wal`. The log is truncated whenever the database is saved with `LocustDB::save`.
+    pub wal_path: Option<String>,
+    /// Maximum number of query results that are cached until the queried table is modified, 0 disables the cache.
+    /// Queries run with `explain`, `show`, a time range or `TABLESAMPLE` are never cached.
+    pub query_cache_size: usize,
+    /// Resolve column names in queries case-insensitively, e.g. `userid` refers to a column named `UserID`.
+    /// Exact matches take precedence, and names that match several columns case-insensitively are not resolved.
+    pub case_insensitive_columns: bool,
+    /// Compute all sums, minimums, maximums and counts of an aggregation query in a single pass over the grouping key,
+    /// instead of one pass for each aggregate.
+    pub combine_aggregates: bool,
 }
 
 impl Default for Options {
@@ -189,6 +604,11 @@ impl Default for Options {
             mem_lz4: true,
             readahead: 256 * 1024 * 1024, // 256 MiB
             seq_disk_read: false,
+            detailed_stats: false,
+            wal_path: None,
+            query_cache_size: 0,
+            case_insensitive_columns: false,
+            combine_aggregates: true,
         }
     }
 }
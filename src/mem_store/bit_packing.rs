@@ -0,0 +1,46 @@
+/// Number of bits required to represent all integers between 0 and `max` (at least 1).
+pub fn bit_width(max: u64) -> u8 {
+    (64 - max.leading_zeros()).max(1) as u8
+}
+
+/// Packs `values - offset` into `width` bits per value.
+/// The result is padded with an extra byte so that every value can be read with a single two byte load.
+pub fn pack(values: &[i64], offset: i64, width: u8) -> Vec<u8> {
+    assert!(width > 0 && width < 8);
+    let mut packed = vec![0u8; (values.len() * width as usize + 7) / 8 + 1];
+    for (i, &v) in values.iter().enumerate() {
+        let bit = i * width as usize;
+        let shifted = ((v - offset) as u16) << (bit & 7);
+        packed[bit >> 3] |= shifted as u8;
+        packed[(bit >> 3) + 1] |= (shifted >> 8) as u8;
+    }
+    packed
+}
+
+/// Returns the value at `index` of values packed with `pack`.
+#[inline]
+pub fn unpack(packed: &[u8], width: u8, index: usize) -> u8 {
+    let bit = index * width as usize;
+    let word = u16::from(packed[bit >> 3]) | (u16::from(packed[(bit >> 3) + 1]) << 8);
+    ((word >> (bit & 7)) & ((1 << width) - 1)) as u8
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_unpack() {
+        let values = vec![3, 9, 4, 10, 3, 3, 5, 6, 7, 8, 9, 10, 4];
+        for width in 3..8 {
+            let packed = pack(&values, 3, width);
+            let unpacked = (0..values.len()).map(|i| i64::from(unpack(&packed, width, i)) + 3).collect::<Vec<_>>();
+            assert_eq!(unpacked, values);
+        }
+        assert_eq!(bit_width(0), 1);
+        assert_eq!(bit_width(1), 1);
+        assert_eq!(bit_width(7), 3);
+        assert_eq!(bit_width(8), 4);
+    }
+}
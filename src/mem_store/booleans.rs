@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use bit_vec::BitVec;
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use mem_store::*;
+
+pub struct BooleanColumn;
+
+impl BooleanColumn {
+    /// Stores `values` as one bit per row.
+    /// Bits are packed in the same layout as 1 bit wide `bit_packing::pack`, so they decode into the `u8` representation used by filters.
+    pub fn new_boxed(name: &str, values: BitVec) -> Arc<Column> {
+        let len = values.len();
+        let mut packed = Vec::with_capacity(values.storage().len() * 4 + 1);
+        for block in values.blocks() {
+            packed.write_u32::<LittleEndian>(block).unwrap();
+        }
+        packed.push(0);
+        let mut column = Column::new(
+            name,
+            len,
+            Some((0, 1)),
+            vec![CodecOp::UnpackBools(len)],
+            vec![DataSection::U8(packed)]);
+        column.lz4_encode();
+        Arc::new(column)
+    }
+
+    /// Whether `s` is a valid value for a boolean column.
+    pub fn is_boolean(s: &str) -> bool {
+        s == "0" || s == "1" || BooleanColumn::is_boolean_literal(s)
+    }
+
+    pub fn is_boolean_literal(s: &str) -> bool {
+        s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("false")
+    }
+
+    pub fn parse(s: &str) -> bool {
+        s == "1" || s.eq_ignore_ascii_case("true")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine::types::BasicType;
+    use mem_store::bit_packing;
+
+    #[test]
+    fn test_boolean_column_layout() {
+        let values = (0..100).map(|i| i % 3 == 0 || i == 70).collect::<BitVec>();
+        let column = BooleanColumn::new_boxed("flag", values.clone());
+        assert_eq!(column.basic_type(), BasicType::Boolean);
+        match column.data()[0] {
+            DataSection::U8(ref packed) => {
+                let unpacked = (0..100).map(|i| bit_packing::unpack(packed, 1, i) == 1).collect::<Vec<_>>();
+                assert_eq!(unpacked, values.iter().collect::<Vec<_>>());
+            }
+            ref section => panic!("Expected u8 data section, got {:?}", section.encoding_type()),
+        }
+    }
+}
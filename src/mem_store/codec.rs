@@ -47,6 +47,13 @@ impl Codec {
         }
     }
 
+    /// Identity codec for the 128 bit integers produced by summation.
+    pub fn wide_integer() -> Codec {
+        let mut codec = Codec::identity(BasicType::Integer);
+        codec.section_types = vec![EncodingType::I128];
+        codec
+    }
+
     pub fn integer_offset(t: EncodingType, offset: i64) -> Codec {
         Codec::new(vec![CodecOp::Add(t, offset)], vec![t])
     }
@@ -154,6 +161,10 @@ impl Codec {
                 }
                 CodecOp::LZ4(t, decoded_length) =>
                     Box::new(QueryPlan::LZ4Decode(stack.pop().unwrap(), decoded_length, t)),
+                CodecOp::UnpackBits(width, decoded_length) =>
+                    Box::new(QueryPlan::UnpackBits(stack.pop().unwrap(), width, decoded_length)),
+                CodecOp::UnpackBools(decoded_length) =>
+                    Box::new(QueryPlan::UnpackBits(stack.pop().unwrap(), 1, decoded_length)),
                 CodecOp::UnpackStrings =>
                     Box::new(QueryPlan::UnpackStrings(stack.pop().unwrap())),
                 CodecOp::UnhexpackStrings(upper, total_bytes) =>
@@ -186,6 +197,16 @@ impl Codec {
     pub fn is_elementwise_decodable(&self) -> bool { self.is_fixed_width }
     pub fn is_identity(&self) -> bool { self.ops.is_empty() }
 
+    /// Whether the column consists of (possibly compressed) dictionary indices in section 0,
+    /// dictionary offsets in section 1 and dictionary strings in section 2.
+    pub fn is_dictionary(&self) -> bool {
+        if self.ops.len() < 3 { return false; }
+        match self.ops[self.ops.len() - 3..] {
+            [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::DictLookup(_)] => true,
+            _ => false,
+        }
+    }
+
     pub fn encode_str(&self, string_const: Box<QueryPlan>) -> Box<QueryPlan> {
         match self.ops[..] {
             [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::DictLookup(_)] =>
@@ -199,6 +220,22 @@ impl Codec {
         }
     }
 
+    /// Returns a plan that evaluates `LIKE 'prefix%'` on the dictionary indices, or `None` if this is not a dictionary codec.
+    pub fn dict_prefix_match(&self, indices: Box<QueryPlan>, prefix: &str) -> Option<Box<QueryPlan>> {
+        match self.ops[..] {
+            [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::DictLookup(t)] =>
+                Some(Box::new(QueryPlan::DictPrefixMatch(
+                    indices,
+                    t,
+                    Box::new(QueryPlan::ReadColumnSection(
+                        self.column_name.to_string(), 1, None, EncodingType::U64)),
+                    Box::new(QueryPlan::ReadColumnSection(
+                        self.column_name.to_string(), 2, None, EncodingType::U8)),
+                    prefix.to_string()))),
+            _ => None,
+        }
+    }
+
     pub fn encode_int(&self, x: i64) -> RawVal {
         if let CodecOp::Add(_, y) = self.ops[0] {
             assert_eq!(self.ops.len(), 1);
@@ -282,6 +319,10 @@ pub enum CodecOp {
     PushDataSection(usize),
     DictLookup(EncodingType),
     LZ4(EncodingType, usize),
+    /// Unpacks the given number of values stored with the given number of bits each into `u8`s
+    UnpackBits(u8, usize),
+    /// Unpacks the given number of booleans stored as one bit each
+    UnpackBools(usize),
     UnpackStrings,
     UnhexpackStrings(bool, usize),
     Unknown,
@@ -295,6 +336,8 @@ impl CodecOp {
             CodecOp::ToI64(_) => BasicType::Integer,
             CodecOp::DictLookup(_) => BasicType::String,
             CodecOp::LZ4(_, _) => BasicType::Integer,
+            CodecOp::UnpackBits(_, _) => BasicType::Integer,
+            CodecOp::UnpackBools(_) => BasicType::Boolean,
             CodecOp::UnpackStrings => BasicType::String,
             CodecOp::UnhexpackStrings(_, _) => BasicType::String,
             CodecOp::PushDataSection(_) => panic!("PushDataSection.input_type()"),
@@ -310,6 +353,8 @@ impl CodecOp {
             CodecOp::PushDataSection(_) => true,
             CodecOp::DictLookup(_) => false,
             CodecOp::LZ4(_, _) => false,
+            CodecOp::UnpackBits(_, _) => false,
+            CodecOp::UnpackBools(_) => false,
             CodecOp::UnpackStrings => false,
             CodecOp::UnhexpackStrings(_, _) => false,
             CodecOp::Unknown => panic!("Unknown.is_summation_preserving()"),
@@ -324,6 +369,8 @@ impl CodecOp {
             CodecOp::PushDataSection(_) => true,
             CodecOp::DictLookup(_) => true,
             CodecOp::LZ4(_, _) => false,
+            CodecOp::UnpackBits(_, _) => false,
+            CodecOp::UnpackBools(_) => false,
             CodecOp::UnpackStrings => false,
             CodecOp::UnhexpackStrings(_, _) => false,
             CodecOp::Unknown => panic!("Unknown.is_order_preserving()"),
@@ -338,6 +385,8 @@ impl CodecOp {
             CodecOp::PushDataSection(_) => true,
             CodecOp::DictLookup(_) => true,
             CodecOp::LZ4(_, _) => false,
+            CodecOp::UnpackBits(_, _) => false,
+            CodecOp::UnpackBools(_) => false,
             CodecOp::UnpackStrings => false,
             CodecOp::UnhexpackStrings(_, _) => false,
             CodecOp::Unknown => panic!("Unknown.is_positive_integer()"),
@@ -352,6 +401,8 @@ impl CodecOp {
             CodecOp::PushDataSection(_) => true,
             CodecOp::DictLookup(_) => true,
             CodecOp::LZ4(_, _) => false,
+            CodecOp::UnpackBits(_, _) => false,
+            CodecOp::UnpackBools(_) => false,
             CodecOp::UnpackStrings => false,
             CodecOp::UnhexpackStrings(_, _) => false,
             CodecOp::Unknown => panic!("Unknown.is_fixed_width()"),
@@ -366,6 +417,8 @@ impl CodecOp {
             CodecOp::PushDataSection(_) => 0,
             CodecOp::DictLookup(_) => 3,
             CodecOp::LZ4(_, _) => 1,
+            CodecOp::UnpackBits(_, _) => 1,
+            CodecOp::UnpackBools(_) => 1,
             CodecOp::UnpackStrings => 1,
             CodecOp::UnhexpackStrings(_, _) => 1,
             CodecOp::Unknown => panic!("Unknown.is_fixed_width()"),
@@ -388,6 +441,8 @@ impl CodecOp {
             } else {
                 format!("LZ4({:?})", t)
             }
+            CodecOp::UnpackBits(width, _) => format!("BitUnpack({})", width),
+            CodecOp::UnpackBools(_) => "BoolUnpack".to_string(),
             CodecOp::UnpackStrings => "StrUnpack".to_string(),
             CodecOp::UnhexpackStrings(_, _) => "StrHexUnpack".to_string(),
             CodecOp::Unknown => "Unknown".to_string(),
@@ -1,6 +1,8 @@
 use std::fmt;
+use std::sync::Arc;
 
 use mem_store::*;
+use engine::query_plan::QueryPlan;
 use engine::typed_vec::AnyVec;
 use engine::types::*;
 use mem_store::lz4;
@@ -15,6 +17,9 @@ pub struct Column {
     range: Option<(i64, i64)>,
     codec: Codec,
     data: Vec<DataSection>,
+    /// One byte per row that is 1 for missing values, which are stored as zero or the empty string in `data`.
+    /// `None` if no value is missing.
+    nulls: Option<Vec<u8>>,
 }
 
 impl Column {
@@ -35,6 +40,7 @@ impl Column {
             range,
             codec,
             data,
+            nulls: None,
         }
     }
 
@@ -45,9 +51,16 @@ impl Column {
             range: None,
             codec: Codec::identity(BasicType::Null),
             data: vec![DataSection::Null(len)],
+            nulls: None,
         }
     }
 
+    /// Marks the rows for which `nulls` is 1 as missing values.
+    pub fn set_nulls(&mut self, nulls: Vec<u8>) {
+        assert_eq!(nulls.len(), self.len, "Null map of column {} has wrong length", self.name);
+        self.nulls = Some(nulls);
+    }
+
     pub fn lz4_encode(&mut self) {
         if cfg!(feature = "enable_lz4") {
             let (encoded, worth_it) = self.data[0].lz4_encode();
@@ -60,9 +73,9 @@ impl Column {
 
     #[cfg(feature = "enable_lz4")]
     pub fn lz4_decode(&mut self) {
-        if let Some(CodecOp::LZ4(decoded_type, _)) = self.codec.ops().get(0).map(|c| *c) {
+        if let Some(CodecOp::LZ4(decoded_type, decoded_len)) = self.codec.ops().get(0).map(|c| *c) {
             self.codec = self.codec.without_lz4();
-            self.data[0] = self.data[0].lz4_decode(decoded_type, self.len);
+            self.data[0] = self.data[0].lz4_decode(decoded_type, decoded_len);
         }
     }
 
@@ -74,11 +87,78 @@ impl Column {
     pub fn encoding_type(&self) -> EncodingType { self.codec.encoding_type() }
     pub fn section_encoding_type(&self, section: usize) -> EncodingType { self.data[section].encoding_type() }
     pub fn range(&self) -> Option<(i64, i64)> { self.range }
+    pub fn nulls(&self) -> Option<&[u8]> { self.nulls.as_ref().map(|nulls| &nulls[..]) }
+    /// Index of the data section that holds the null map, which follows all sections of `data`.
+    pub fn null_section(&self) -> Option<usize> { self.nulls.as_ref().map(|_| self.data.len()) }
+    /// Smallest and largest decoded value of integer columns, if known.
+    pub fn value_range(&self) -> Option<(i64, i64)> {
+        if self.basic_type() != BasicType::Integer { return None; }
+        let plan = QueryPlan::ReadColumnSection(self.name.clone(), 0, self.range, self.encoding_type());
+        self.codec.decode(Box::new(plan)).encoding_range()
+    }
     pub fn full_type(&self) -> Type {
         Type::new(self.basic_type(), Some(self.codec()))
     }
+    /// Returns the code and string of each dictionary entry, or `None` if the column is not dictionary encoded.
+    pub fn dictionary(&self) -> Option<Vec<(u64, String)>> {
+        if !self.codec.is_dictionary() { return None; }
+        let dict_indices = self.data[1].to_any_vec().cast_ref_u64();
+        let dict_data = self.data[2].to_any_vec().cast_ref_u8();
+        Some(dict_indices.iter().enumerate().map(|(code, offset_len)| {
+            let offset = (offset_len >> 24) as usize;
+            let len = (offset_len & 0x00ff_ffff) as usize;
+            (code as u64, String::from_utf8_lossy(&dict_data[offset..(offset + len)]).into_owned())
+        }).collect())
+    }
+
     pub fn data_sections(&self) -> Vec<&AnyVec> {
-        self.data.iter().map(|d| d.to_any_vec()).collect()
+        self.data.iter()
+            .map(|d| d.to_any_vec())
+            .chain(self.nulls.iter().map(|nulls| nulls as &AnyVec))
+            .collect()
+    }
+
+    /// Size of the column without any encoding, assuming 8 bytes per integer and float
+    /// and the length of each string. String lengths are derived from the encoded data without decoding the column,
+    /// dictionary encoded columns with compressed indices are estimated from the mean length of dictionary entries.
+    pub fn raw_size_bytes(&self) -> usize {
+        match self.basic_type() {
+            BasicType::Integer | BasicType::Float | BasicType::Val => self.len * 8,
+            BasicType::Boolean => self.len,
+            BasicType::Null => 0,
+            BasicType::String => self.raw_string_bytes(),
+        }
+    }
+
+    fn raw_string_bytes(&self) -> usize {
+        if self.codec.is_dictionary() {
+            let entry_lens = self.data[1].to_any_vec().cast_ref_u64().iter()
+                .map(|offset_len| (offset_len & 0x00ff_ffff) as usize)
+                .collect::<Vec<_>>();
+            if entry_lens.is_empty() { return 0; }
+            return match (self.codec.ops()[0], self.data[0].encoding_type()) {
+                (CodecOp::LZ4(_, _), _) => self.len * entry_lens.iter().sum::<usize>() / entry_lens.len(),
+                (_, EncodingType::U8) =>
+                    self.data[0].to_any_vec().cast_ref_u8().iter().map(|&i| entry_lens[i as usize]).sum(),
+                (_, EncodingType::U16) =>
+                    self.data[0].to_any_vec().cast_ref_u16().iter().map(|&i| entry_lens[i as usize]).sum(),
+                (_, EncodingType::U32) =>
+                    self.data[0].to_any_vec().cast_ref_u32().iter().map(|&i| entry_lens[i as usize]).sum(),
+                (_, t) => panic!("Unexpected dictionary index type {:?} for column {}", t, self.name),
+            };
+        }
+        let mut packed_len = self.data[0].len();
+        for op in self.codec.ops() {
+            match *op {
+                CodecOp::LZ4(_, decoded_len) => packed_len = decoded_len,
+                // Total length of all strings is recorded by the codec
+                CodecOp::UnhexpackStrings(_, total_bytes) => return total_bytes,
+                // Each string is followed by a terminating zero byte
+                CodecOp::UnpackStrings => return packed_len - self.len,
+                _ => {}
+            }
+        }
+        panic!("Unexpected codec {} for string column {}", self.codec.signature(false), self.name)
     }
 
     pub fn mem_tree(&self, tree: &mut MemTreeColumn, depth: usize) {
@@ -134,6 +214,9 @@ pub enum DataSection {
     U64(Vec<u64>),
     I64(Vec<i64>),
     Null(usize),
+    /// Data shared with the columns of other partitions, e.g. a `SharedDictionary`
+    SharedU8(Arc<Vec<u8>>),
+    SharedU64(Arc<Vec<u64>>),
 }
 
 impl DataSection {
@@ -145,6 +228,8 @@ impl DataSection {
             DataSection::U64(ref x) => x,
             DataSection::I64(ref x) => x,
             DataSection::Null(ref x) => x,
+            DataSection::SharedU8(ref x) => &**x,
+            DataSection::SharedU64(ref x) => &**x,
         }
     }
 
@@ -156,6 +241,8 @@ impl DataSection {
             DataSection::U64(ref x) => x.len(),
             DataSection::I64(ref x) => x.len(),
             DataSection::Null(ref x) => *x,
+            DataSection::SharedU8(ref x) => x.len(),
+            DataSection::SharedU64(ref x) => x.len(),
         }
     }
 
@@ -167,6 +254,8 @@ impl DataSection {
             DataSection::U64(ref x) => x.capacity(),
             DataSection::I64(ref x) => x.capacity(),
             DataSection::Null(ref x) => *x,
+            DataSection::SharedU8(ref x) => x.capacity(),
+            DataSection::SharedU64(ref x) => x.capacity(),
         }
     }
 
@@ -178,6 +267,8 @@ impl DataSection {
             DataSection::U64(_) => EncodingType::U64,
             DataSection::I64(_) => EncodingType::I64,
             DataSection::Null(_) => EncodingType::Null,
+            DataSection::SharedU8(_) => EncodingType::U8,
+            DataSection::SharedU64(_) => EncodingType::U64,
         }
     }
 
@@ -214,7 +305,9 @@ impl DataSection {
                 let len = encoded.len();
                 (DataSection::U8(encoded), len * 100 < x.len() * 8 * min_reduction)
             }
-            DataSection::Null(ref x) => (DataSection::Null(*x), false)
+            DataSection::Null(ref x) => (DataSection::Null(*x), false),
+            DataSection::SharedU8(ref x) => (DataSection::SharedU8(x.clone()), false),
+            DataSection::SharedU64(ref x) => (DataSection::SharedU64(x.clone()), false),
         }
     }
 
@@ -261,7 +354,7 @@ impl DataSection {
                 DataSection::U32(ref mut x) => x.shrink_to_fit(),
                 DataSection::U64(ref mut x) => x.shrink_to_fit(),
                 DataSection::I64(ref mut x) => x.shrink_to_fit(),
-                DataSection::Null(_) => {}
+                DataSection::Null(_) | DataSection::SharedU8(_) | DataSection::SharedU64(_) => {}
             }
         }
     }
@@ -276,6 +369,8 @@ impl HeapSizeOf for DataSection {
             DataSection::U64(ref x) => x.heap_size_of_children(),
             DataSection::I64(ref x) => x.heap_size_of_children(),
             DataSection::Null(_) => 0,
+            // Shared data is accounted for once by its owner, e.g. `SharedDictionaries`
+            DataSection::SharedU8(_) | DataSection::SharedU64(_) => 0,
         }
     }
 }
@@ -5,6 +5,7 @@ use std::collections::hash_set::HashSet;
 use std::rc::Rc;
 use std::sync::Arc;
 
+use engine::types::EncodingType;
 use mem_store::integers::*;
 use mem_store::column::*;
 use mem_store::strings::*;
@@ -80,10 +81,23 @@ impl ColumnBuilder<i64> for IntColBuilder {
     }
 
     fn finalize(self, name: &str) -> Arc<Column> {
+        self.finalize_with_bit_packing(name, false)
+    }
+}
+
+impl IntColBuilder {
+    /// Builds the column with the smallest encoding that fits, values with a range of less than 8 bits are
+    /// bit-packed if `bit_pack` is set.
+    pub fn finalize_with_bit_packing(self, name: &str, bit_pack: bool) -> Arc<Column> {
         // TODO(clemens): heuristic for deciding delta encoding could probably be improved
         let delta_encode = self.allow_delta_encode &&
             (self.increasing * 10 > self.data.len() as u64 * 9 && cfg!(feature = "enable_lz4"));
-        IntegerColumn::new_boxed(name, self.data, self.min, self.max, delta_encode)
+        IntegerColumn::new_boxed(name, self.data, self.min, self.max, delta_encode, bit_pack)
+    }
+
+    /// Builds the column with encoding `t`, or returns `None` if `t` can't represent the values.
+    pub fn finalize_with_encoding(self, name: &str, t: EncodingType) -> Option<Arc<Column>> {
+        IntegerColumn::new_boxed_with_encoding(name, self.data, self.min, self.max, t)
     }
 }
 
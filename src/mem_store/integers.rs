@@ -5,11 +5,14 @@ use std::sync::Arc;
 use engine::*;
 use engine::types::*;
 use mem_store::*;
+use mem_store::bit_packing;
 
 pub struct IntegerColumn;
 
 impl IntegerColumn {
-    pub fn new_boxed(name: &str, mut values: Vec<i64>, mut min: i64, mut max: i64, delta_encode: bool) -> Arc<Column> {
+    /// Stores `values` with the smallest encoding that fits. If `bit_pack` is set, values that fit into less than 8 bits
+    /// are packed into fewer bits per value, which reduces memory usage at the cost of decoding the bits in every query.
+    pub fn new_boxed(name: &str, mut values: Vec<i64>, mut min: i64, mut max: i64, delta_encode: bool, bit_pack: bool) -> Arc<Column> {
         let original_range = Some((min, max));
         let min0 = min;
         let max0 = max;
@@ -25,7 +28,10 @@ impl IntegerColumn {
                 if min > *curr { min = *curr }
             }
         }
-        let mut column = if min >= 0 && max <= From::from(u8::MAX) {
+        let bit_packing = if bit_pack { IntegerColumn::bit_packing(min, max) } else { None };
+        let mut column = if let Some((offset, width)) = bit_packing {
+            IntegerColumn::create_bitpacked_col(name, values, offset, width, min0, max0, delta_encode)
+        } else if min >= 0 && max <= From::from(u8::MAX) {
             IntegerColumn::create_col::<u8>(name, values, 0, min0, max0, delta_encode, EncodingType::U8)
         } else if max - min <= From::from(u8::MAX) {
             IntegerColumn::create_col::<u8>(name, values, min, min0, max0, delta_encode, EncodingType::U8)
@@ -59,23 +65,71 @@ impl IntegerColumn {
         Arc::new(column)
     }
 
+    /// Stores `values` with encoding `t` instead of choosing the smallest encoding that fits.
+    /// Supported encodings are `U8`, `U16` and `U32` (offset by `min` if necessary) and plain `I64`.
+    /// Returns `None` if the encoding is not supported or can't represent the values.
+    pub fn new_boxed_with_encoding(name: &str, values: Vec<i64>, min: i64, max: i64, t: EncodingType) -> Option<Arc<Column>> {
+        let offset = |type_max: i64| if min >= 0 && max <= type_max {
+            Some(0)
+        } else if max.checked_sub(min).map_or(false, |range| range <= type_max) {
+            Some(min)
+        } else {
+            None
+        };
+        let column = match t {
+            EncodingType::U8 => IntegerColumn::create_col::<u8>(name, values, offset(From::from(u8::MAX))?, min, max, false, t),
+            EncodingType::U16 => IntegerColumn::create_col::<u16>(name, values, offset(From::from(u16::MAX))?, min, max, false, t),
+            EncodingType::U32 => IntegerColumn::create_col::<u32>(name, values, offset(From::from(u32::MAX))?, min, max, false, t),
+            EncodingType::I64 => Column::new(name, values.len(), Some((min, max)), vec![], vec![DataSection::I64(values)]),
+            _ => return None,
+        };
+        Some(Arc::new(column))
+    }
+
     pub fn create_col<T>(name: &str, values: Vec<i64>, offset: i64, min: i64, max: i64, delta_encode: bool, t: EncodingType) -> Column
         where T: GenericIntVec<T>, Vec<T>: Into<DataSection> {
         let values = IntegerColumn::encode::<T>(values, offset);
         let len = values.len();
-        let codec = match (offset == 0, delta_encode) {
-            (true, true) => vec![CodecOp::Delta(t)],
-            (true, false) => vec![CodecOp::ToI64(t)],
-            (false, true) => vec![CodecOp::Add(t, offset), CodecOp::Delta(EncodingType::I64)],
-            (false, false) => vec![CodecOp::Add(t, offset)],
-        };
+        Column::new(
+            name,
+            len,
+            Some((min - offset, max - offset)),
+            IntegerColumn::codec(offset, delta_encode, t),
+            vec![values.into()])
+    }
 
+    /// Stores each value in `width` bits, which must be less than 8.
+    pub fn create_bitpacked_col(name: &str, values: Vec<i64>, offset: i64, width: u8, min: i64, max: i64, delta_encode: bool) -> Column {
+        let len = values.len();
+        let packed = bit_packing::pack(&values, offset, width);
+        let mut codec = vec![CodecOp::UnpackBits(width, len)];
+        codec.extend(IntegerColumn::codec(offset, delta_encode, EncodingType::U8));
         Column::new(
             name,
             len,
             Some((min - offset, max - offset)),
             codec,
-            vec![values.into()])
+            vec![DataSection::U8(packed)])
+    }
+
+    /// Returns the offset and number of bits per value for values between `min` and `max` if they fit into less than 8 bits.
+    fn bit_packing(min: i64, max: i64) -> Option<(i64, u8)> {
+        if min >= 0 && max < 128 {
+            Some((0, bit_packing::bit_width(max as u64)))
+        } else if max.checked_sub(min).map_or(false, |range| range < 128) {
+            Some((min, bit_packing::bit_width((max - min) as u64)))
+        } else {
+            None
+        }
+    }
+
+    fn codec(offset: i64, delta_encode: bool, t: EncodingType) -> Vec<CodecOp> {
+        match (offset == 0, delta_encode) {
+            (true, true) => vec![CodecOp::Delta(t)],
+            (true, false) => vec![CodecOp::ToI64(t)],
+            (false, true) => vec![CodecOp::Add(t, offset), CodecOp::Delta(EncodingType::I64)],
+            (false, false) => vec![CodecOp::Add(t, offset)],
+        }
     }
 
     pub fn encode<T: GenericIntVec<T>>(values: Vec<i64>, offset: i64) -> Vec<T> {
@@ -90,3 +144,43 @@ impl IntegerColumn {
         encoded_vals
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapsize::HeapSizeOf;
+
+    fn decode(column: &Column) -> Vec<i64> {
+        let (width, offset) = match column.codec().ops() {
+            [CodecOp::UnpackBits(width, _), CodecOp::ToI64(EncodingType::U8)] => (*width, 0),
+            [CodecOp::UnpackBits(width, _), CodecOp::Add(EncodingType::U8, offset)] => (*width, *offset),
+            ops => panic!("Expected bit packed column, got {:?}", ops),
+        };
+        match column.data()[0] {
+            DataSection::U8(ref packed) =>
+                (0..column.len()).map(|i| i64::from(bit_packing::unpack(packed, width, i)) + offset).collect(),
+            ref section => panic!("Expected u8 data section, got {:?}", section.encoding_type()),
+        }
+    }
+
+    #[test]
+    fn test_1_bit_packing() {
+        let values = (0..100_000).map(|i| (i % 3 == 0) as i64).collect::<Vec<_>>();
+        let column = IntegerColumn::create_bitpacked_col("flag", values.clone(), 0, 1, 0, 1, false);
+        assert_eq!(decode(&column), values);
+        let u8_column = IntegerColumn::create_col::<u8>("flag", values, 0, 0, 1, false, EncodingType::U8);
+        assert!(column.heap_size_of_children() * 7 < u8_column.heap_size_of_children());
+    }
+
+    #[test]
+    fn test_3_bit_packing() {
+        let values = (0..100_000).map(|i| 100 + i * 7 % 8).collect::<Vec<_>>();
+        let (offset, width) = IntegerColumn::bit_packing(100, 107).unwrap();
+        assert_eq!((offset, width), (100, 3));
+        let column = IntegerColumn::create_bitpacked_col("enum", values.clone(), offset, width, 100, 107, false);
+        assert_eq!(decode(&column), values);
+        let u8_column = IntegerColumn::create_col::<u8>("enum", values, 100, 100, 107, false, EncodingType::U8);
+        assert!(column.heap_size_of_children() * 2 < u8_column.heap_size_of_children());
+    }
+}
@@ -2,11 +2,13 @@ use ingest::raw_val::RawVal;
 use mem_store::value::Val;
 
 impl RawVal {
-    pub fn to_val(&self) -> Val {
+    /// Floats and integers outside the range of i64 have no representation as `Val` and return `None`.
+    pub fn to_val(&self) -> Option<Val> {
         match *self {
-            RawVal::Null => Val::Null,
-            RawVal::Int(i) => Val::Integer(i),
-            RawVal::Str(ref string) => Val::Str(string),
+            RawVal::Null => Some(Val::Null),
+            RawVal::Int(i) => Some(Val::Integer(i)),
+            RawVal::Float(_) | RawVal::BigInt(_) => None,
+            RawVal::Str(ref string) => Some(Val::Str(string)),
         }
     }
 }
@@ -1,9 +1,12 @@
+pub mod bit_packing;
+pub mod booleans;
 pub mod codec;
 pub mod column;
 pub mod column_builder;
 pub mod integers;
 pub mod partition;
 pub mod raw_col;
+pub mod shared_dictionary;
 pub mod strings;
 pub mod table;
 pub mod tree;
@@ -16,7 +19,7 @@ pub(crate) mod lru;
 pub use self::column::{Column, DataSection};
 pub use self::codec::{Codec, CodecOp};
 pub use self::tree::*;
-pub use self::table::TableStats;
+pub use self::table::{ColumnStats, TableStats};
 pub use self::lru::LRU;
 
 
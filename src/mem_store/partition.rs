@@ -4,6 +4,7 @@ use std::sync::{Arc, Mutex, MutexGuard};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use disk_store::interface::*;
+use engine::types::{BasicType, EncodingType};
 use heapsize::HeapSizeOf;
 use ingest::buffer::Buffer;
 use mem_store::*;
@@ -57,6 +58,8 @@ impl Partition {
             lru)
     }
 
+    /// Returns all columns in `referenced_cols`.
+    /// Columns that don't exist in this partition (e.g. because they were added to the table later) are all null.
     pub fn get_cols(&self, referenced_cols: &HashSet<String>, drs: &DiskReadScheduler) -> HashMap<String, Arc<Column>> {
         let mut columns = HashMap::new();
         for handle in &self.cols {
@@ -65,6 +68,11 @@ impl Partition {
                 columns.insert(handle.name().to_string(), column);
             }
         }
+        for name in referenced_cols {
+            if !columns.contains_key(name) {
+                columns.insert(name.to_string(), Arc::new(Column::null(name, self.len)));
+            }
+        }
         columns
     }
 
@@ -76,6 +84,26 @@ impl Partition {
         names
     }
 
+    /// Returns the decoded type of each column, or `None` for columns that are not currently resident.
+    pub fn col_types(&self) -> Vec<(&str, Option<BasicType>)> {
+        self.cols.iter()
+            .map(|handle| (handle.name(), handle.try_get().as_ref().map(|c| c.basic_type())))
+            .collect()
+    }
+
+    /// Returns false if no value of the integer column `col` can lie within `[start, end]`.
+    /// Partitions without the column contain only nulls and never overlap.
+    /// If the column is not resident or its range is unknown, the partition is assumed to overlap.
+    pub fn may_overlap(&self, col: &str, start: i64, end: i64) -> bool {
+        match self.cols.iter().find(|handle| handle.name() == col) {
+            Some(handle) => match *handle.try_get() {
+                Some(ref column) => column.value_range().map_or(true, |(min, max)| min <= end && start <= max),
+                None => true,
+            },
+            None => false,
+        }
+    }
+
     pub fn non_residents(&self, cols: &HashSet<String>) -> HashSet<String> {
         let mut non_residents = HashSet::new();
         for handle in &self.cols {
@@ -161,6 +189,17 @@ impl Partition {
         }
     }
 
+    /// Returns the unencoded and encoded size in bytes of all resident columns.
+    pub fn compression_per_column(&self) -> Vec<(String, usize, usize, EncodingType)> {
+        self.cols.iter()
+            .filter_map(|handle| {
+                // Clone the column so the lock is not held while computing its size
+                let c = handle.col.lock().unwrap().clone();
+                c.map(|c| (handle.name().to_string(), c.raw_size_bytes(), c.heap_size_of_children(), c.encoding_type()))
+            })
+            .collect()
+    }
+
     pub fn heap_size_per_column(&self) -> Vec<(String, usize)> {
         self.cols.iter()
             .map(|handle| {
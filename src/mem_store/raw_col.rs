@@ -45,12 +45,29 @@ impl MixedCol {
     }
 
     pub fn finalize(self, name: &str) -> Arc<Column> {
+        let nulls = if self.types.contains_null && (self.types.contains_string || self.types.contains_int) {
+            Some(self.data.iter().map(|v| if let RawVal::Null = *v { 1 } else { 0 }).collect::<Vec<u8>>())
+        } else {
+            None
+        };
+        let mut column = self.finalize_values(name);
+        // Missing values are stored as empty strings or zero and marked in the null map.
+        // The column was just built, so it isn't shared yet.
+        if let (Some(nulls), Some(col)) = (nulls, Arc::get_mut(&mut column)) {
+            col.set_nulls(nulls);
+        }
+        column
+    }
+
+    fn finalize_values(self, name: &str) -> Arc<Column> {
         if self.types.contains_string {
             let mut builder = StringColBuilder::default();
             for v in self.data {
                 match v {
                     RawVal::Str(s) => builder.push(&s),
                     RawVal::Int(i) => builder.push(&i.to_string()),
+                    RawVal::BigInt(i) => builder.push(&i.to_string()),
+                    RawVal::Float(x) => builder.push(&x.to_string()),
                     RawVal::Null => builder.push(&""),
                 }
             }
@@ -59,9 +76,10 @@ impl MixedCol {
             let mut builder = IntColBuilder::default();
             for v in self.data {
                 match v {
-                    RawVal::Str(_) => panic!("Unexpected string in int column!"),
                     RawVal::Int(i) => builder.push(&i),
                     RawVal::Null => builder.push(&0),
+                    // Columns that contain floats are stored as strings, see `ColType::determine`
+                    ref other => unreachable!("Unexpected {} in int column", other),
                 }
             }
             builder.finalize(name)
@@ -111,7 +129,8 @@ impl ColType {
     fn determine(v: &RawVal) -> ColType {
         match *v {
             RawVal::Null => ColType::null(),
-            RawVal::Str(_) => ColType::string(),
+            // There are no float columns yet, floats and integers that don't fit into i64 are stored as strings
+            RawVal::Str(_) | RawVal::Float(_) | RawVal::BigInt(_) => ColType::string(),
             RawVal::Int(_) => ColType::int()
         }
     }
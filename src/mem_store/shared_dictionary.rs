@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+use std::sync::{Arc, Mutex};
+
+use heapsize::HeapSizeOf;
+use seahash::SeaHasher;
+
+use stringpack::IndexedPackedStrings;
+
+
+type HashMapSea<K, V> = HashMap<K, V, BuildHasherDefault<SeaHasher>>;
+
+/// Sorted string dictionary that is shared by the dictionary encoded columns of all partitions of a table.
+/// The dictionary is created from the strings of the first partition and never changes afterwards, so all columns
+/// reference the same copy. Partitions that contain strings which are not part of the dictionary use their own.
+pub struct SharedDictionary {
+    codes: HashMapSea<String, u32>,
+    dictionary_indices: Arc<Vec<u64>>,
+    dictionary_data: Arc<Vec<u8>>,
+}
+
+impl SharedDictionary {
+    pub fn new<'a, T>(strings: T) -> SharedDictionary where T: Iterator<Item=&'a str> {
+        let mut sorted = strings.collect::<Vec<_>>();
+        sorted.sort();
+        sorted.dedup();
+        let mut codes = HashMapSea::default();
+        let mut packed = IndexedPackedStrings::default();
+        for (code, s) in sorted.into_iter().enumerate() {
+            packed.push(s);
+            codes.insert(s.to_string(), code as u32);
+        }
+        let (dictionary_indices, dictionary_data) = packed.into_parts();
+        SharedDictionary {
+            codes,
+            dictionary_indices: Arc::new(dictionary_indices),
+            dictionary_data: Arc::new(dictionary_data),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    pub fn code(&self, s: &str) -> Option<u32> {
+        self.codes.get(s).cloned()
+    }
+
+    /// The dictionary indices and data sections referenced by columns encoded with this dictionary.
+    pub fn sections(&self) -> (Arc<Vec<u64>>, Arc<Vec<u8>>) {
+        (self.dictionary_indices.clone(), self.dictionary_data.clone())
+    }
+}
+
+impl HeapSizeOf for SharedDictionary {
+    fn heap_size_of_children(&self) -> usize {
+        self.codes.heap_size_of_children()
+            + (*self.dictionary_indices).heap_size_of_children()
+            + (*self.dictionary_data).heap_size_of_children()
+    }
+}
+
+/// The shared dictionaries of all string columns of a table, keyed by column name.
+#[derive(Default)]
+pub struct SharedDictionaries {
+    dictionaries: Mutex<HashMap<String, Arc<SharedDictionary>>>,
+}
+
+impl SharedDictionaries {
+    /// Returns the dictionary of column `colname`, which is created from `strings` if the column does not have one yet.
+    /// No dictionary is created if it would contain more than `max_len` strings.
+    pub fn get_or_create<'a, T>(&self, colname: &str, strings: T, max_len: usize) -> Option<Arc<SharedDictionary>>
+        where T: Iterator<Item=&'a str> {
+        let mut dictionaries = self.dictionaries.lock().unwrap();
+        if let Some(dictionary) = dictionaries.get(colname) {
+            return Some(dictionary.clone());
+        }
+        let dictionary = SharedDictionary::new(strings);
+        if dictionary.len() > max_len { return None; }
+        let dictionary = Arc::new(dictionary);
+        dictionaries.insert(colname.to_string(), dictionary.clone());
+        Some(dictionary)
+    }
+
+    /// Size of the dictionary of each column. Columns only reference their dictionary, so it is accounted for here.
+    pub fn heap_size_per_column(&self) -> Vec<(String, usize)> {
+        let dictionaries = self.dictionaries.lock().unwrap();
+        dictionaries.iter().map(|(colname, dictionary)| (colname.clone(), dictionary.heap_size_of_children())).collect()
+    }
+}
+
+impl HeapSizeOf for SharedDictionaries {
+    fn heap_size_of_children(&self) -> usize {
+        self.heap_size_per_column().iter().map(|&(_, size)| size).sum()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dictionary_is_shared() {
+        let dictionaries = SharedDictionaries::default();
+        let dictionary1 = dictionaries.get_or_create("col", ["b", "a", "b"].iter().cloned(), 10).unwrap();
+        let dictionary2 = dictionaries.get_or_create("col", ["c"].iter().cloned(), 10).unwrap();
+        assert!(Arc::ptr_eq(&dictionary1, &dictionary2));
+        assert_eq!(dictionary1.len(), 2);
+        assert_eq!(dictionary1.code("a"), Some(0));
+        assert_eq!(dictionary1.code("b"), Some(1));
+        assert_eq!(dictionary1.code("c"), None);
+
+        assert!(dictionaries.get_or_create("other", ["c", "d"].iter().cloned(), 1).is_none());
+        assert_eq!(dictionaries.heap_size_per_column().len(), 1);
+    }
+}
@@ -14,6 +14,7 @@ use stringpack::*;
 use engine::types::*;
 use mem_store::*;
 use mem_store::column_builder::UniqueValues;
+use mem_store::shared_dictionary::SharedDictionaries;
 
 
 type HashMapSea<K, V> = HashMap<K, V, BuildHasherDefault<SeaHasher>>;
@@ -26,7 +27,8 @@ pub fn fast_build_string_column<'a, T>(name: &str,
                                        len: usize,
                                        lhex: bool,
                                        uhex: bool,
-                                       total_bytes: usize)
+                                       total_bytes: usize,
+                                       shared_dictionaries: Option<&SharedDictionaries>)
                                        -> Arc<Column> where T: Iterator<Item=&'a str> + Clone {
     let mut unique_values = HashSetSea::default();
     for s in strings.clone() {
@@ -52,6 +54,29 @@ pub fn fast_build_string_column<'a, T>(name: &str,
             return Arc::new(column);
         }
     }
+    let shared_dictionary = shared_dictionaries
+        .and_then(|d| d.get_or_create(name, unique_values.iter().cloned(), From::from(u16::MAX)))
+        // Partitions with strings that are not part of the shared dictionary use a dictionary of their own
+        .filter(|d| unique_values.iter().all(|s| d.code(s).is_some()));
+    if let Some(dictionary) = shared_dictionary {
+        let (dictionary_indices, dictionary_data) = dictionary.sections();
+        let dict_size = dictionary.len();
+        let (indices, index_type) = if dict_size <= From::from(u8::MAX) {
+            (DataSection::U8(strings.map(|s| dictionary.code(s).unwrap() as u8).collect()), EncodingType::U8)
+        } else {
+            (DataSection::U16(strings.map(|s| dictionary.code(s).unwrap() as u16).collect()), EncodingType::U16)
+        };
+        let mut column = Column::new(
+            name,
+            len,
+            Some((0, dict_size as i64)),
+            dict_codec(index_type),
+            vec![indices,
+                 DataSection::SharedU64(dictionary_indices),
+                 DataSection::SharedU8(dictionary_data)]);
+        column.lz4_encode();
+        return Arc::new(column);
+    }
     let dict_size = unique_values.len();
     let mut mapping = unique_values.into_iter().collect::<Vec<_>>();
     mapping.sort();
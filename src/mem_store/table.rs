@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::mem;
 use std::ops::DerefMut;
@@ -6,11 +7,13 @@ use std::sync::Arc;
 use std::sync::{Mutex, RwLock};
 
 use disk_store::interface::*;
+use engine::types::{BasicType, EncodingType};
 use heapsize::HeapSizeOf;
 use ingest::buffer::Buffer;
 use ingest::input_column::InputColumn;
 use ingest::raw_val::RawVal;
 use mem_store::partition::{Partition, ColumnKey};
+use mem_store::shared_dictionary::SharedDictionaries;
 use mem_store::*;
 
 
@@ -20,6 +23,7 @@ pub struct Table {
     partitions: RwLock<HashMap<PartitionID, Arc<Partition>>>,
     buffer: Mutex<Buffer>,
     lru: LRU,
+    string_dictionaries: Arc<SharedDictionaries>,
 }
 
 impl Table {
@@ -30,6 +34,7 @@ impl Table {
             partitions: RwLock::new(HashMap::new()),
             buffer: Mutex::new(Buffer::default()),
             lru,
+            string_dictionaries: Arc::default(),
         }
     }
 
@@ -37,6 +42,11 @@ impl Table {
         &self.name
     }
 
+    /// Dictionaries shared by the dictionary encoded string columns of all partitions, keyed by column name.
+    pub fn string_dictionaries(&self) -> Arc<SharedDictionaries> {
+        self.string_dictionaries.clone()
+    }
+
     pub fn snapshot(&self) -> Vec<Arc<Partition>> {
         let partitions = self.partitions.read().unwrap();
         partitions.values().cloned().collect()
@@ -135,17 +145,37 @@ impl Table {
 
     pub fn stats(&self) -> TableStats {
         let partitions = self.snapshot();
-        let size_per_column = Table::size_per_column(&partitions);
+        let size_per_column = Table::size_per_column(&partitions, &self.string_dictionaries);
         let buffer = self.buffer.lock().unwrap();
         TableStats {
             name: self.name().to_string(),
             rows: partitions.iter().map(|p| p.len()).sum(),
             batches: partitions.len(),
-            batches_bytes: partitions.heap_size_of_children(),
+            batches_bytes: partitions.heap_size_of_children() + self.string_dictionaries.heap_size_of_children(),
             buffer_length: buffer.len(),
             buffer_bytes: buffer.heap_size_of_children(),
             size_per_column,
+            columns: Table::column_stats(&partitions),
+        }
+    }
+
+    /// Returns the decoded type of each column, sorted by column name.
+    /// Columns with different types in different partitions have type `BasicType::Val`.
+    pub fn schema(&self) -> Vec<(String, BasicType)> {
+        let mut types = BTreeMap::<String, Option<BasicType>>::new();
+        for partition in self.snapshot() {
+            for (colname, t) in partition.col_types() {
+                let unified = match (types.get(colname).cloned().unwrap_or(None), t) {
+                    (None, t) | (t, None) => t,
+                    (Some(t1), Some(t2)) if t1 == t2 => Some(t1),
+                    (Some(BasicType::Null), t) | (t, Some(BasicType::Null)) => t,
+                    _ => Some(BasicType::Val),
+                };
+                types.insert(colname.to_string(), unified);
+            }
         }
+        // The type of columns that are not resident in any partition is unknown
+        types.into_iter().map(|(colname, t)| (colname, t.unwrap_or(BasicType::Val))).collect()
     }
 
     pub fn max_partition_id(&self) -> u64 {
@@ -153,13 +183,33 @@ impl Table {
         partitions.keys().max().cloned().unwrap_or(0)
     }
 
-    fn size_per_column(partitions: &[Arc<Partition>]) -> Vec<(String, usize)> {
+    fn column_stats(partitions: &[Arc<Partition>]) -> Vec<ColumnStats> {
+        let mut sizes = BTreeMap::<String, (usize, usize, Vec<EncodingType>)>::new();
+        for partition in partitions {
+            for (colname, raw_bytes, encoded_bytes, encoding) in partition.compression_per_column() {
+                let size = sizes.entry(colname).or_insert((0, 0, Vec::new()));
+                size.0 += raw_bytes;
+                size.1 += encoded_bytes;
+                if !size.2.contains(&encoding) {
+                    size.2.push(encoding);
+                }
+            }
+        }
+        sizes.into_iter()
+            .map(|(name, (raw_bytes, encoded_bytes, encodings))| ColumnStats { name, raw_bytes, encoded_bytes, encodings })
+            .collect()
+    }
+
+    fn size_per_column(partitions: &[Arc<Partition>], dictionaries: &SharedDictionaries) -> Vec<(String, usize)> {
         let mut sizes: HashMap<String, usize> = HashMap::default();
         for partition in partitions {
             for (colname, size) in partition.heap_size_per_column() {
                 *sizes.entry(colname).or_insert(0) += size;
             }
         }
+        for (colname, size) in dictionaries.heap_size_per_column() {
+            *sizes.entry(colname).or_insert(0) += size;
+        }
         sizes.iter().map(|(name, size)| (name.to_string(), *size)).collect()
     }
 }
@@ -172,7 +222,7 @@ impl HeapSizeOf for Table {
     fn heap_size_of_children(&self) -> usize {
         let batches_size = {
             let batches = self.partitions.read().unwrap();
-            batches.heap_size_of_children()
+            batches.heap_size_of_children() + self.string_dictionaries.heap_size_of_children()
         };
         let buffer_size = {
             let buffer = self.buffer.lock().unwrap();
@@ -197,6 +247,27 @@ pub struct TableStats {
     pub buffer_length: usize,
     pub buffer_bytes: usize,
     pub size_per_column: Vec<(String, usize)>,
+    /// Compression of each column, sorted by column name. Only includes data resident in memory.
+    pub columns: Vec<ColumnStats>,
+}
+
+#[derive(Debug)]
+pub struct ColumnStats {
+    pub name: String,
+    /// Size without any encoding, with 8 bytes per integer and the length of each string
+    pub raw_bytes: usize,
+    /// Size in memory
+    pub encoded_bytes: usize,
+    /// Distinct encodings of the stored data, in the order in which they first occur in partitions
+    pub encodings: Vec<EncodingType>,
+}
+
+impl ColumnStats {
+    /// Ratio of encoded to raw size, smaller values indicate better compression.
+    pub fn compression_ratio(&self) -> f64 {
+        if self.raw_bytes == 0 { return 1.0; }
+        self.encoded_bytes as f64 / self.raw_bytes as f64
+    }
 }
 
 
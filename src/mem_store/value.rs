@@ -1,16 +1,72 @@
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use heapsize::HeapSizeOf;
 use std::convert::From;
-use ingest::raw_val::RawVal;
+use ingest::raw_val::{self, RawVal};
 
-#[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Clone, Copy, Hash)]
+#[derive(Debug, Clone, Copy)]
 pub enum Val<'a> {
     Null,
     Bool(bool),
     Integer(i64),
+    /// Only produced by casts, there are no float columns
+    Float(f64),
     Str(&'a str),
 }
 
+impl<'a> Val<'a> {
+    fn variant_index(&self) -> u8 {
+        match *self {
+            Val::Null => 0,
+            Val::Bool(_) => 1,
+            Val::Integer(_) => 2,
+            Val::Float(_) => 3,
+            Val::Str(_) => 4,
+        }
+    }
+}
+
+// Floats are compared by their total order like in `RawVal`, so that values can be sorted and grouped by.
+impl<'a> PartialEq for Val<'a> {
+    fn eq(&self, other: &Val<'a>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a> Eq for Val<'a> {}
+
+impl<'a> PartialOrd for Val<'a> {
+    fn partial_cmp(&self, other: &Val<'a>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for Val<'a> {
+    fn cmp(&self, other: &Val<'a>) -> Ordering {
+        match (*self, *other) {
+            (Val::Bool(a), Val::Bool(b)) => a.cmp(&b),
+            (Val::Integer(a), Val::Integer(b)) => a.cmp(&b),
+            (Val::Float(a), Val::Float(b)) => raw_val::total_order_key(a).cmp(&raw_val::total_order_key(b)),
+            (Val::Str(a), Val::Str(b)) => a.cmp(b),
+            _ => self.variant_index().cmp(&other.variant_index()),
+        }
+    }
+}
+
+impl<'a> Hash for Val<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.variant_index().hash(state);
+        match *self {
+            Val::Null => {}
+            Val::Bool(b) => b.hash(state),
+            Val::Integer(i) => i.hash(state),
+            Val::Float(x) => raw_val::total_order_key(x).hash(state),
+            Val::Str(s) => s.hash(state),
+        }
+    }
+}
+
 
 impl<'a> fmt::Display for Val<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -18,6 +74,7 @@ impl<'a> fmt::Display for Val<'a> {
             Val::Null => write!(f, "null"),
             Val::Bool(b) => write!(f, "{}", b),
             Val::Integer(i) => write!(f, "{}", i),
+            Val::Float(x) => write!(f, "{}", x),
             Val::Str(s) => write!(f, "\"{}\"", s),
         }
     }
@@ -27,7 +84,7 @@ impl<'a> HeapSizeOf for Val<'a> {
     fn heap_size_of_children(&self) -> usize {
         use self::Val::*;
         match *self {
-            Null | Bool(_) | Integer(_) => 0,
+            Null | Bool(_) | Integer(_) | Float(_) => 0,
             Str(r) => r.heap_size_of_children(),
         }
     }
@@ -72,6 +129,7 @@ impl<'a, 'b> From<&'a Val<'b>> for RawVal {
     fn from(val: &Val) -> RawVal {
         match *val {
             Val::Integer(b) => RawVal::Int(b),
+            Val::Float(x) => RawVal::Float(x),
             Val::Str(s) => RawVal::Str(s.to_string()),
             Val::Null | Val::Bool(_) => RawVal::Null,
         }
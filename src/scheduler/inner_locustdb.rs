@@ -1,5 +1,10 @@
-use std::collections::{HashMap, VecDeque};
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::{BufReader, BufWriter, Read};
 use std::mem;
+use std::path::Path;
 use std::str;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Condvar, Mutex, RwLock};
@@ -9,21 +14,33 @@ use std::time::Duration;
 use futures_core::*;
 use futures_channel::oneshot;
 use heapsize::HeapSizeOf;
+use lru::LruCache;
 use time;
 
+use disk_store::file_format;
 use disk_store::interface::*;
+use errors::IngestError;
+use engine::query_task::QueryOutput;
+use engine::types::BasicType;
+use ingest::arrow_loader;
+use ingest::buffer::Buffer;
 use ingest::colgen::GenTable;
+use ingest::csv_loader::{self, BatchKey, IngestCheckpoint, IngestionStatus, Options as LoadOptions};
 use ingest::input_column::InputColumn;
 use ingest::raw_val::RawVal;
 use locustdb::Options;
 use mem_store::*;
 use mem_store::partition::Partition;
+use mem_store::shared_dictionary::SharedDictionaries;
 use mem_store::table::*;
 use scheduler::*;
 use scheduler::disk_read_scheduler::DiskReadScheduler;
 use trace::*;
 
 
+/// Name of the virtual table that describes the columns of all tables.
+pub const INFORMATION_SCHEMA: &str = "information_schema";
+
 pub struct InnerLocustDB {
     tables: RwLock<HashMap<String, Table>>,
     lru: LRU,
@@ -31,8 +48,26 @@ pub struct InnerLocustDB {
     disk_read_scheduler: Arc<DiskReadScheduler>,
 
     opts: Options,
+    /// Write-ahead log that all new partitions are appended to, if enabled by `Options::wal_path`
+    wal: Mutex<Option<File>>,
 
     next_partition_id: AtomicUsize,
+    /// Progress of the most recent ingestion of each (table, source file) pair
+    ingest_checkpoints: Mutex<HashMap<(String, String), IngestCheckpoint>>,
+    /// For each content hash of batches ingested into a table with batch deduplication enabled, the largest number of
+    /// batches with that hash stored by a single load (see `BatchKey`)
+    batch_hashes: Mutex<HashMap<String, HashMap<[u8; 16], usize>>>,
+    /// Rows stored and ingestion tasks scheduled for each table
+    ingestion_status: Mutex<HashMap<String, IngestionStatus>>,
+    /// Number of writes to each table, used to invalidate cached query results
+    table_versions: Mutex<HashMap<String, u64>>,
+    /// Results of recently run queries with the version of the queried table they were computed from,
+    /// see `Options::query_cache_size`
+    query_cache: Mutex<LruCache<String, (u64, QueryOutput)>>,
+    query_cache_hits: AtomicUsize,
+    /// Number of worker threads currently executing a task
+    busy_workers: AtomicUsize,
+    max_busy_workers: AtomicUsize,
     running: AtomicBool,
     idle_queue: Condvar,
     task_queue: Mutex<VecDeque<Arc<TaskState>>>,
@@ -70,13 +105,44 @@ impl InnerLocustDB {
             running: AtomicBool::new(true),
 
             opts: opts.clone(),
+            wal: Mutex::new(None),
 
             next_partition_id: AtomicUsize::new(max_pid as usize + 1),
+            ingest_checkpoints: Mutex::new(HashMap::new()),
+            batch_hashes: Mutex::new(HashMap::new()),
+            ingestion_status: Mutex::new(HashMap::new()),
+            table_versions: Mutex::new(HashMap::new()),
+            query_cache: Mutex::new(LruCache::new(cmp::max(opts.query_cache_size, 1))),
+            query_cache_hits: AtomicUsize::new(0),
+            busy_workers: AtomicUsize::new(0),
+            max_busy_workers: AtomicUsize::new(0),
             idle_queue: Condvar::new(),
             task_queue: Mutex::new(VecDeque::new()),
         }
     }
 
+    /// Restores all partitions and batch keys recorded in the write-ahead log at `path` and appends new partitions to it.
+    /// A partially written record at the end of the log is truncated, so that new records follow the last complete one.
+    pub fn open_wal(&self, path: &str) -> io::Result<()> {
+        if let Ok(file) = File::open(path) {
+            let log_len = file.metadata()?.len();
+            let (records, complete_len) = file_format::read_log(&mut BufReader::new(file), log_len)?;
+            for (tablename, partition, batch_key) in records {
+                self.insert_partition(&tablename, partition);
+                if let Some(key) = batch_key {
+                    self.register_batch(&tablename, key);
+                }
+            }
+            if complete_len < log_len {
+                warn!("Truncating partially written record at the end of write-ahead log {}", path);
+                OpenOptions::new().write(true).open(path)?.set_len(complete_len)?;
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        *self.wal.lock().unwrap() = Some(file);
+        Ok(())
+    }
+
     pub fn start_worker_threads(locustdb: &Arc<InnerLocustDB>) {
         for id in 0..locustdb.opts.threads {
             let cloned = locustdb.clone();
@@ -87,10 +153,38 @@ impl InnerLocustDB {
     }
 
     pub fn snapshot(&self, table: &str) -> Option<Vec<Arc<Partition>>> {
+        if table == INFORMATION_SCHEMA { return Some(self.information_schema()); }
         let tables = self.tables.read().unwrap();
         tables.get(table).map(|t| t.snapshot())
     }
 
+    /// Materializes a virtual table with one row for each column of every table.
+    fn information_schema(&self) -> Vec<Arc<Partition>> {
+        let tables = self.tables.read().unwrap();
+        let mut names = tables.keys().collect::<Vec<_>>();
+        names.sort();
+        let mut buffer = Buffer::default();
+        for name in names {
+            let table = &tables[name];
+            let stats = table.stats();
+            let size_per_column = stats.size_per_column.into_iter().collect::<HashMap<_, _>>();
+            for (column, t) in table.schema() {
+                let size_bytes = size_per_column.get(&column).cloned().unwrap_or(0);
+                buffer.push_row(vec![
+                    ("table_name".to_string(), RawVal::Str(name.to_string())),
+                    ("column_name".to_string(), RawVal::Str(column)),
+                    ("column_type".to_string(), RawVal::Str(format!("{:?}", t))),
+                    ("row_count".to_string(), RawVal::Int(stats.rows as i64)),
+                    ("size_bytes".to_string(), RawVal::Int(size_bytes as i64)),
+                ]);
+            }
+        }
+        if buffer.len() == 0 { return Vec::new(); }
+        // Partition is not registered with the LRU since it is dropped once the query completes
+        let (partition, _) = Partition::from_buffer(0, buffer, LRU::default());
+        vec![Arc::new(partition)]
+    }
+
     pub fn full_snapshot(&self) -> Vec<Vec<Arc<Partition>>> {
         let tables = self.tables.read().unwrap();
         tables.values().map(|t| t.snapshot()).collect()
@@ -110,10 +204,12 @@ impl InnerLocustDB {
                 if let Some(ref tb) = *task.trace_builder.read().unwrap() {
                     tb.activate();
                 }
+                locustdb.start_busy();
                 {
                     trace_start!("Worker thread {}", thread_id);
                     task.task.execute();
                 }
+                locustdb.busy_workers.fetch_sub(1, Ordering::SeqCst);
                 if let Some(ref mut tb) = *task.trace_builder.write().unwrap() {
                     tb.collect();
                 }
@@ -122,6 +218,20 @@ impl InnerLocustDB {
         drop(locustdb) // Make clippy happy
     }
 
+    fn start_busy(&self) {
+        let busy = self.busy_workers.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut max = self.max_busy_workers.load(Ordering::SeqCst);
+        while busy > max {
+            let previous = self.max_busy_workers.compare_and_swap(max, busy, Ordering::SeqCst);
+            if previous == max { break; }
+            max = previous;
+        }
+    }
+
+    pub fn max_busy_workers(&self) -> usize {
+        self.max_busy_workers.load(Ordering::SeqCst)
+    }
+
     fn await_task(ldb: &Arc<InnerLocustDB>) -> Option<Arc<TaskState>> {
         let mut task_queue = ldb.task_queue.lock().unwrap();
         while task_queue.is_empty() {
@@ -158,7 +268,33 @@ impl InnerLocustDB {
         trace_receiver
     }
 
-    pub fn store_partition(&self, tablename: &str, partition: Vec<Arc<Column>>) {
+    /// Adds `partition` to `tablename`, after appending it to the write-ahead log if there is one.
+    /// The partition is not added if it could not be written to the log.
+    /// `batch_key` is recorded together with the partition if the batch was ingested with deduplication enabled.
+    /// The partition is dropped if a batch with the same key was already stored in `tablename`. Checking and recording
+    /// the key happens under the lock of the write-ahead log, so concurrent loads never store the same batch twice.
+    pub fn store_partition(&self,
+                           tablename: &str,
+                           partition: Vec<Arc<Column>>,
+                           batch_key: Option<BatchKey>) -> io::Result<()> {
+        let mut wal = self.wal.lock().unwrap();
+        if let Some(key) = batch_key {
+            if self.is_duplicate_batch(tablename, key) { return Ok(()); }
+        }
+        if let Some(ref mut wal) = *wal {
+            file_format::append_partition(wal, tablename, &partition, batch_key)?;
+            wal.sync_data()?;
+        }
+        if let Some(key) = batch_key {
+            self.register_batch(tablename, key);
+        }
+        let rows = partition.first().map_or(0, |col| col.len());
+        self.update_ingestion_status(tablename, |status| status.rows += rows);
+        self.insert_partition(tablename, partition);
+        Ok(())
+    }
+
+    fn insert_partition(&self, tablename: &str, partition: Vec<Arc<Column>>) {
         self.create_if_empty(tablename);
         let tables = self.tables.read().unwrap();
         let table = tables.get(tablename).unwrap();
@@ -167,12 +303,175 @@ impl InnerLocustDB {
         let (new_partition, keys) = Partition::new(pid, partition, self.lru.clone());
         table.load_partition(new_partition);
         for key in keys { self.lru.put(key); }
+        self.bump_table_version(tablename);
+    }
+
+    /// Writes all partitions of all tables to `path` in their compressed encodings and truncates the write-ahead log.
+    /// The tables are written to `<path>.tmp` first, which then replaces `path`.
+    /// Rows that are still buffered and have not been batched into a partition yet are not included.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        // Holding the lock prevents new partitions from being added before the log is truncated
+        let mut wal = self.wal.lock().unwrap();
+        let mut tables = Vec::new();
+        for name in self.tables() {
+            let mut partitions = Vec::new();
+            for partition in self.snapshot(&name).unwrap_or_default() {
+                let names = partition.col_names().into_iter().map(str::to_string).collect();
+                let mut columns = partition.get_cols(&names, &self.disk_read_scheduler)
+                    .into_iter()
+                    .map(|(_, col)| col)
+                    .collect::<Vec<_>>();
+                columns.sort_by(|a, b| a.name().cmp(b.name()));
+                if !columns.is_empty() { partitions.push(columns); }
+            }
+            tables.push((name, partitions));
+        }
+        let batch_keys = self.batch_hashes.lock().unwrap().iter()
+            .flat_map(|(name, hashes)| hashes.iter().map(move |(&hash, &count)| (name.clone(), (hash, count))))
+            .collect::<Vec<_>>();
+        // The previous snapshot is only replaced once the new one is completely written,
+        // and the log is only truncated once the new snapshot is in place
+        let tmp_path = format!("{}.tmp", path);
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        file_format::write_tables(&mut writer, &tables, &batch_keys)?;
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        if let Some(dir) = Path::new(path).parent() {
+            // Persists the rename
+            if let Ok(dir) = File::open(if dir.as_os_str().is_empty() { Path::new(".") } else { dir }) {
+                dir.sync_all()?;
+            }
+        }
+        if let Some(ref mut wal) = *wal {
+            wal.set_len(0)?;
+            wal.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Loads the partitions of all tables stored in `path` by `save` as new partitions, along with the keys of
+    /// deduplicated batches. Loaded partitions are not added to the write-ahead log, since they are already
+    /// persisted in `path`.
+    pub fn load(&self, path: &str) -> io::Result<()> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let (tables, batch_keys) = file_format::read_tables(&mut reader)?;
+        for (name, partitions) in tables {
+            self.create_if_empty(&name);
+            for columns in partitions {
+                self.insert_partition(&name, columns);
+            }
+        }
+        for (name, key) in batch_keys {
+            self.register_batch(&name, key);
+        }
+        Ok(())
+    }
+
+    pub fn ingest_csv_stream<R: Read>(&self, input: R, opts: &LoadOptions) -> Result<(), IngestError> {
+        csv_loader::ingest_stream(self, input, opts)
+    }
+
+    pub fn ingest_arrow_file(&self, path: &str, tablename: &str) -> Result<(), IngestError> {
+        arrow_loader::ingest_arrow_file(self, path, tablename)
+    }
+
+    pub fn ingest_fixed_width_stream<R: Read>(&self,
+                                              input: R,
+                                              fields: &[(String, usize, usize)],
+                                              opts: &LoadOptions) -> Result<(), IngestError> {
+        csv_loader::ingest_fixed_width(self, input, fields, opts)
+    }
+
+    pub fn ingest_checkpoint(&self, tablename: &str, source: &str) -> IngestCheckpoint {
+        let checkpoints = self.ingest_checkpoints.lock().unwrap();
+        checkpoints.get(&(tablename.to_string(), source.to_string())).cloned().unwrap_or_default()
+    }
+
+    pub fn set_ingest_checkpoint(&self, tablename: &str, source: &str, checkpoint: IngestCheckpoint) {
+        let mut checkpoints = self.ingest_checkpoints.lock().unwrap();
+        checkpoints.insert((tablename.to_string(), source.to_string()), checkpoint);
+    }
+
+    pub fn ingestion_status(&self, tablename: &str) -> IngestionStatus {
+        let status = self.ingestion_status.lock().unwrap();
+        status.get(tablename).cloned().unwrap_or_default()
+    }
+
+    pub fn start_ingestion_task(&self, tablename: &str) {
+        self.update_ingestion_status(tablename, |status| status.pending_tasks += 1);
+    }
+
+    pub fn finish_ingestion_task(&self, tablename: &str, success: bool) {
+        self.update_ingestion_status(tablename, |status| {
+            status.pending_tasks -= 1;
+            if success { status.completed_tasks += 1 } else { status.failed_tasks += 1 }
+        });
+    }
+
+    fn update_ingestion_status<F: FnOnce(&mut IngestionStatus)>(&self, tablename: &str, f: F) {
+        let mut status = self.ingestion_status.lock().unwrap();
+        f(status.entry(tablename.to_string()).or_insert_with(IngestionStatus::default));
+    }
+
+    pub fn table_version(&self, table: &str) -> u64 {
+        let versions = self.table_versions.lock().unwrap();
+        versions.get(table).cloned().unwrap_or(0)
+    }
+
+    /// Invalidates cached query results of `table`.
+    /// Must be called after the modification has been applied and while the lock on `tables` is still held,
+    /// so that queries cannot cache results computed from the old data under the new version.
+    fn bump_table_version(&self, table: &str) {
+        let mut versions = self.table_versions.lock().unwrap();
+        *versions.entry(table.to_string()).or_insert(0) += 1;
+    }
+
+    /// Returns the cached result of the query with key `key` on `table` if `table` has not been modified since.
+    pub fn cached_query_result(&self, key: &str, table: &str) -> Option<QueryOutput> {
+        let version = self.table_version(table);
+        let mut cache = self.query_cache.lock().unwrap();
+        let output = match cache.get(&key.to_string()) {
+            Some(&(cached_version, ref output)) if cached_version == version => output.clone(),
+            _ => return None,
+        };
+        self.query_cache_hits.fetch_add(1, Ordering::SeqCst);
+        Some(output)
+    }
+
+    pub fn cache_query_result(&self, key: String, table_version: u64, output: &QueryOutput) {
+        let mut cache = self.query_cache.lock().unwrap();
+        cache.put(key, (table_version, output.clone()));
+    }
+
+    pub fn query_cache_hits(&self) -> usize {
+        self.query_cache_hits.load(Ordering::SeqCst)
+    }
+
+    /// Whether a previous load stored a batch with the same key in `tablename`.
+    /// Batches are only registered by `store_partition`, which repeats the check atomically.
+    pub fn is_duplicate_batch(&self, tablename: &str, (hash, count): BatchKey) -> bool {
+        let batch_hashes = self.batch_hashes.lock().unwrap();
+        batch_hashes.get(tablename).and_then(|hashes| hashes.get(&hash)).map_or(false, |&stored| stored >= count)
+    }
+
+    /// Records that the batch identified by `key` was stored in `tablename`.
+    fn register_batch(&self, tablename: &str, (hash, count): BatchKey) {
+        let mut batch_hashes = self.batch_hashes.lock().unwrap();
+        let stored = batch_hashes.entry(tablename.to_string()).or_insert_with(HashMap::new).entry(hash).or_insert(0);
+        *stored = cmp::max(*stored, count);
+    }
+
+    pub fn string_dictionaries(&self, tablename: &str) -> Arc<SharedDictionaries> {
+        self.create_if_empty(tablename);
+        let tables = self.tables.read().unwrap();
+        tables[tablename].string_dictionaries()
     }
 
     pub fn ingest(&self, table: &str, row: Vec<(String, RawVal)>) {
         self.create_if_empty(table);
         let tables = self.tables.read().unwrap();
-        tables.get(table).unwrap().ingest(row)
+        tables.get(table).unwrap().ingest(row);
+        self.bump_table_version(table);
     }
 
     pub fn restore(&self, id: PartitionID, column: Column) {
@@ -186,14 +485,16 @@ impl InnerLocustDB {
     pub fn ingest_homogeneous(&self, table: &str, columns: HashMap<String, InputColumn>) {
         self.create_if_empty(table);
         let tables = self.tables.read().unwrap();
-        tables.get(table).unwrap().ingest_homogeneous(columns)
+        tables.get(table).unwrap().ingest_homogeneous(columns);
+        self.bump_table_version(table);
     }
 
     #[allow(dead_code)]
     pub fn ingest_heterogeneous(&self, table: &str, columns: HashMap<String, Vec<RawVal>>) {
         self.create_if_empty(table);
         let tables = self.tables.read().unwrap();
-        tables.get(table).unwrap().ingest_heterogeneous(columns)
+        tables.get(table).unwrap().ingest_heterogeneous(columns);
+        self.bump_table_version(table);
     }
 
     pub fn drop_pending_tasks(&self) {
@@ -211,11 +512,33 @@ impl InnerLocustDB {
         tables.values().map(|table| table.stats()).collect()
     }
 
-    pub fn gen_partition(&self, opts: &GenTable, p: u64) {
-        opts.gen(&self, p);
+    pub fn tables(&self) -> Vec<String> {
+        let tables = self.tables.read().unwrap();
+        let mut names = tables.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    pub fn schema(&self, table: &str) -> Option<Vec<(String, BasicType)>> {
+        let tables = self.tables.read().unwrap();
+        tables.get(table).map(|t| t.schema())
+    }
+
+    /// Dictionaries of all partitions in which `column` is dictionary encoded.
+    pub fn column_dictionaries(&self, table: &str, column: &str) -> Option<Vec<Vec<(u64, String)>>> {
+        let partitions = self.snapshot(table)?;
+        let cols = [column.to_string()].iter().cloned().collect::<HashSet<_>>();
+        Some(partitions.iter()
+            .filter_map(|partition| partition.get_cols(&cols, &self.disk_read_scheduler).remove(column))
+            .filter_map(|col| col.dictionary())
+            .collect())
+    }
+
+    pub fn gen_partition(&self, opts: &GenTable, p: u64) -> io::Result<()> {
+        opts.gen(&self, p)
     }
 
-    fn create_if_empty(&self, table: &str) {
+    pub fn create_if_empty(&self, table: &str) {
         let exists = {
             let tables = self.tables.read().unwrap();
             tables.contains_key(table)
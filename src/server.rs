@@ -0,0 +1,101 @@
+extern crate tiny_http;
+
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use futures_executor::block_on;
+
+use self::tiny_http::{Header, Method, Request, Response, Server};
+use QueryResult;
+use engine::query_task::json_string;
+use locustdb::LocustDB;
+
+
+/// HTTP server that accepts SQL queries as the body of `POST /query` requests and responds with the results as JSON.
+pub struct HttpServer {
+    addr: SocketAddr,
+    running: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl HttpServer {
+    /// Starts serving queries against `locustdb` on `addr`, e.g. "127.0.0.1:8080".
+    pub fn start(locustdb: Arc<LocustDB>, addr: &str) -> Result<HttpServer, String> {
+        let server = Server::http(addr).map_err(|e| e.to_string())?;
+        let addr = server.server_addr();
+        let running = Arc::new(AtomicBool::new(true));
+        let handle = {
+            let running = running.clone();
+            thread::spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    match server.recv_timeout(Duration::from_millis(100)) {
+                        Ok(Some(request)) => handle_request(&locustdb, request),
+                        Ok(None) => {}
+                        Err(err) => warn!("Failed to receive HTTP request: {}", err),
+                    }
+                }
+            })
+        };
+        Ok(HttpServer { addr, running, handle: Some(handle) })
+    }
+
+    pub fn addr(&self) -> SocketAddr { self.addr }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn handle_request(locustdb: &LocustDB, mut request: Request) {
+    let (status, body) = if request.url() != "/query" {
+        (404, error_json(&format!("Unknown path {}", request.url())))
+    } else if *request.method() != Method::Post {
+        (405, error_json("Queries must be sent with POST"))
+    } else {
+        let mut query = String::new();
+        match request.as_reader().read_to_string(&mut query) {
+            Ok(_) => match block_on(locustdb.run_query(&query, false, vec![])) {
+                Ok((result, _)) => query_result_json(result),
+                Err(_) => (500, error_json("Query was cancelled")),
+            },
+            Err(err) => (400, error_json(&err.to_string())),
+        }
+    };
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header("Content-Type: application/json".parse::<Header>().unwrap());
+    if let Err(err) = request.respond(response) {
+        warn!("Failed to send HTTP response: {}", err);
+    }
+}
+
+fn query_result_json(result: QueryResult) -> (u16, String) {
+    match result {
+        Ok(output) => (200, output.to_json()),
+        Err(err) => (400, error_json(&err.to_string())),
+    }
+}
+
+fn error_json(message: &str) -> String {
+    format!("{{\"error\":{}}}", json_string(message))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+}
@@ -0,0 +1,33 @@
+use std::cmp::Ordering;
+
+
+/// Determines how strings are compared in filters and ordered by `ORDER BY`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Collation {
+    /// Compares the UTF-8 encoded bytes, which is equivalent to ordering by Unicode code points
+    Binary,
+    /// Orders by Unicode code points after converting each character to lowercase, e.g. `"Émile" = "émile"`
+    NoCase,
+}
+
+impl Collation {
+    pub fn from_name(name: &str) -> Option<Collation> {
+        match name.to_lowercase().as_ref() {
+            "binary" => Some(Collation::Binary),
+            "nocase" => Some(Collation::NoCase),
+            _ => None,
+        }
+    }
+
+    pub fn cmp(self, left: &str, right: &str) -> Ordering {
+        match self {
+            Collation::Binary => left.cmp(right),
+            Collation::NoCase => left.chars().flat_map(char::to_lowercase)
+                .cmp(right.chars().flat_map(char::to_lowercase)),
+        }
+    }
+}
+
+impl Default for Collation {
+    fn default() -> Collation { Collation::Binary }
+}
@@ -1,6 +1,8 @@
+use engine::types::BasicType;
 use ingest::raw_val::RawVal;
 use self::Expr::*;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::i64;
 
 
 #[derive(Debug, Clone)]
@@ -23,13 +25,25 @@ pub enum Func2Type {
     Subtract,
     Multiply,
     Divide,
+    Modulo,
     RegexMatch,
+    Like,
+    /// Shell-style pattern match with `*`, `?` and `[...]`, see `glob_to_regex`
+    Glob,
+    /// Like `Equals`, but null is equal to null and not equal to any other value
+    NullSafeEquals,
+    /// Case insensitive string equality, see `Collation::NoCase`
+    EqualsNoCase,
+    NotEqualsNoCase,
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum Func1Type {
     Negate,
+    Not,
     ToYear,
+    /// Converts values to the given type, casts of constants are evaluated by the parser
+    Cast(BasicType),
 }
 
 impl Expr {
@@ -47,6 +61,26 @@ impl Expr {
         }
     }
 
+    pub fn col(name: &str) -> Expr { ColName(name.to_string()) }
+
+    pub fn int(value: i64) -> Expr { Const(RawVal::Int(value)) }
+
+    pub fn string(value: &str) -> Expr { Const(RawVal::Str(value.to_string())) }
+
+    pub fn eq(lhs: Expr, rhs: Expr) -> Expr { Expr::func(Func2Type::Equals, lhs, rhs) }
+
+    pub fn neq(lhs: Expr, rhs: Expr) -> Expr { Expr::func(Func2Type::NotEquals, lhs, rhs) }
+
+    pub fn lt(lhs: Expr, rhs: Expr) -> Expr { Expr::func(Func2Type::LT, lhs, rhs) }
+
+    pub fn gt(lhs: Expr, rhs: Expr) -> Expr { Expr::func(Func2Type::GT, lhs, rhs) }
+
+    pub fn and(lhs: Expr, rhs: Expr) -> Expr { Expr::func(Func2Type::And, lhs, rhs) }
+
+    pub fn or(lhs: Expr, rhs: Expr) -> Expr { Expr::func(Func2Type::Or, lhs, rhs) }
+
+    pub fn not(expr: Expr) -> Expr { Expr::func1(Func1Type::Not, expr) }
+
     pub fn func(ftype: Func2Type, expr1: Expr, expr2: Expr) -> Expr {
         Func2(ftype, Box::new(expr1), Box::new(expr2))
     }
@@ -54,5 +88,49 @@ impl Expr {
     pub fn func1(ftype: Func1Type, expr: Expr) -> Expr {
         Func1(ftype, Box::new(expr))
     }
+
+    /// Replaces the columns named in `replacements` by the corresponding expressions.
+    pub fn replace_colnames(self, replacements: &HashMap<String, Expr>) -> Expr {
+        match self {
+            ColName(name) => replacements.get(&name).cloned().unwrap_or(ColName(name)),
+            Func2(ftype, lhs, rhs) =>
+                Expr::func(ftype, (*lhs).replace_colnames(replacements), (*rhs).replace_colnames(replacements)),
+            Func1(ftype, expr) => Expr::func1(ftype, (*expr).replace_colnames(replacements)),
+            expr => expr,
+        }
+    }
+
+    /// Evaluates arithmetic on integer constants, e.g. `1 - 1` becomes `0` and `1 / 0` becomes null.
+    /// Subexpressions that overflow are left unchanged.
+    pub fn fold_constants(self) -> Expr {
+        match self {
+            Func2(ftype, lhs, rhs) => {
+                let lhs = (*lhs).fold_constants();
+                let rhs = (*rhs).fold_constants();
+                if let (&Const(RawVal::Int(l)), &Const(RawVal::Int(r))) = (&lhs, &rhs) {
+                    // Same semantics as the division operators: zero divisors yield null, i64::MIN / -1 saturates
+                    let value = match ftype {
+                        Func2Type::Add => l.checked_add(r).map(RawVal::Int),
+                        Func2Type::Subtract => l.checked_sub(r).map(RawVal::Int),
+                        Func2Type::Multiply => l.checked_mul(r).map(RawVal::Int),
+                        Func2Type::Divide | Func2Type::Modulo if r == 0 => Some(RawVal::Null),
+                        Func2Type::Divide => Some(RawVal::Int(l.checked_div(r).unwrap_or(i64::MAX))),
+                        Func2Type::Modulo => Some(RawVal::Int(l.checked_rem(r).unwrap_or(0))),
+                        _ => None,
+                    };
+                    if let Some(value) = value {
+                        return Const(value);
+                    }
+                }
+                Expr::func(ftype, lhs, rhs)
+            }
+            Func1(Func1Type::Negate, expr) => match (*expr).fold_constants() {
+                Const(RawVal::Int(value)) if value != i64::MIN => Const(RawVal::Int(-value)),
+                expr => Expr::func1(Func1Type::Negate, expr),
+            },
+            Func1(ftype, expr) => Expr::func1(ftype, (*expr).fold_constants()),
+            expr => expr,
+        }
+    }
 }
 
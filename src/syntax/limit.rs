@@ -3,3 +3,19 @@ pub struct LimitClause {
     pub limit: u64,
     pub offset: u64,
 }
+
+/// Keeps only the first `limit` result rows for each combination of values of the result columns `columns`.
+#[derive(Clone, Debug, Hash, PartialEq)]
+pub struct GroupLimit {
+    pub limit: u64,
+    pub columns: Vec<String>,
+}
+
+/// Restricts a query to a random sample of partitions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TableSample {
+    /// Fraction of partitions that are scanned, in (0, 1]
+    pub fraction: f64,
+    /// Seed that determines which partitions are sampled, chosen randomly if not specified
+    pub seed: Option<u64>,
+}
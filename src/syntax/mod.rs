@@ -1,3 +1,5 @@
+pub mod collation;
 pub mod expression;
 pub mod limit;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod query_builder;
\ No newline at end of file
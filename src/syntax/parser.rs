@@ -1,44 +1,590 @@
 extern crate sqlparser;
 
+use std::collections::HashMap;
+use std::u64;
+
+use hex;
+use regex::{self, Captures, Regex};
 use sqlparser::sqlparser::*;
 use sqlparser::sqlast::*;
 use engine::query::Query;
 use syntax::expression::*;
 use engine::aggregator::*;
+use engine::types::BasicType;
+use engine::vector_op::hyperloglog;
 use ingest::raw_val::RawVal;
+use syntax::collation::Collation;
 use syntax::limit::*;
 use sqlparser::dialect::GenericSqlDialect;
 use QueryError;
 
 // Convert sqlparser-rs `ASTNode` to LocustDB's `Query`
-pub fn parse_query(query: &str) -> Result<Query, QueryError> {
+pub fn parse_query(sql: &str) -> Result<Query, QueryError> {
+    check_syntax(sql)?;
+    let query = encode_quoted_identifiers(sql)?;
+    let query = normalize_numbers(&query)?;
+    check_limit_offset(&query)?;
+    let query = rewrite_null_safe_equals(&query)?;
+    let query = rewrite_casts(&query)?;
+    let query = rewrite_ordered_aggregates(&query);
+    let (query, aliases) = split_aliases(&query)?;
+    let (query, offset) = split_offset(&query)?;
+    let (query, group_limit) = split_limit_per(query)?;
+    let (query, mut distinct) = split_distinct(query);
+    let (query, sample) = split_tablesample(&query)?;
+    let (query, collation) = split_collate(&query)?;
+    let (query, rollup) = split_rollup(&query);
     let dialect = GenericSqlDialect {};
     let ast = Parser::parse_sql(&dialect, query.to_string())
         .map_err(|e| match e {
-            ParserError::ParserError(e_str) => QueryError::ParseError(e_str),
+            ParserError::ParserError(e_str) => QueryError::ParseError(locate_parser_error(sql, e_str)),
             _ => QueryError::FatalError(format!("{:?}", e)),
         })?;
 
-    let (projection, relation, selection, order_by, limit) = get_query_components(ast)?;
+    let (projection, relation, selection, order_by, group_by, having, limit) = get_query_components(ast)?;
+    let (projection, top_k) = split_top_k(projection)?;
+    if top_k.is_some() && (group_by.is_some() || order_by.is_some() || limit.is_some() || offset.is_some() || group_limit.is_some()) {
+        bail!(QueryError::NotImplemented, "TOP_K can not be combined with GROUP BY, ORDER BY, LIMIT or OFFSET");
+    }
+    let select_aliases = get_select_aliases(&projection, aliases)?;
     let (select, aggregate) = get_select_aggregate(projection)?;
+    if rollup {
+        if group_by.is_none() {
+            bail!(QueryError::ParseError, "WITH ROLLUP requires a GROUP BY clause");
+        }
+        if aggregate.is_empty() {
+            bail!(QueryError::NotImplemented, "WITH ROLLUP is only supported for queries with aggregates");
+        }
+    }
+    if let Some(group_by) = group_by {
+        let group_by = group_by.iter().map(|node| expr(node).map(|e| *e)).collect::<Result<Vec<_>, _>>()?;
+        check_group_by(&select, &group_by, rollup)?;
+        // Without aggregates, grouping just removes duplicate rows
+        distinct = distinct || aggregate.is_empty();
+    }
     let table = get_table_name(relation)?;
     let filter = match selection {
         Some(ref s) => *expr(s)?,
         None => Expr::Const(RawVal::Int(1)),
     };
-    let (order_by_str, order_desc) = get_order_by(order_by)?;
-    let limit_clause = LimitClause { limit: get_limit(limit)?, offset: 0 };
+    let order_by = get_order_by(order_by, &aggregate)?;
+    // A standalone OFFSET or a limit per group returns all remaining rows, TOP_K summarizes all rows
+    let default_limit = if offset.is_some() || group_limit.is_some() || top_k.is_some() { u64::MAX } else { 100 };
+    let limit_clause = LimitClause { limit: get_limit(limit, default_limit)?, offset: offset.unwrap_or(0) };
 
-    Ok(Query {
+    let mut query = Query {
         select,
+        select_aliases,
+        distinct,
+        rollup,
         table,
+        sample,
         filter,
         aggregate,
-        order_by: order_by_str,
-        order_desc,
+        order_by: None,
+        order_desc: false,
+        then_order_by: vec![],
+        collation,
         limit: limit_clause,
         order_by_index: None,
-    })
+        then_order_by_indices: vec![],
+        group_limit,
+        top_k,
+    };
+    if let Some(having) = having {
+        if !query.aggregate.is_empty() {
+            bail!(QueryError::NotImplemented, "HAVING is only supported for queries without aggregates");
+        }
+        // Without aggregates, filtering the result rows is equivalent to filtering on the selected expressions
+        let selected = query.result_column_names().into_iter()
+            .zip(query.select.iter().cloned())
+            .collect::<HashMap<_, _>>();
+        let having = (*expr(&having)?).replace_colnames(&selected);
+        query.filter = match query.filter {
+            Expr::Const(RawVal::Int(1)) => having,
+            filter => Expr::and(filter, having),
+        };
+    }
+    if !order_by.is_empty() {
+        let result_columns = query.result_column_names();
+        let mut keys = order_by.into_iter()
+            .map(|(name, aggregate_index, desc)| match (name, aggregate_index) {
+                (Some(name), _) => (name, desc),
+                (None, Some(index)) => (result_columns[query.select.len() + index].clone(), desc),
+                (None, None) => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        let then_order_by = keys.split_off(1);
+        let (order_by, order_desc) = keys.pop().unwrap();
+        if !query.is_select_star() {
+            if let Some(&(ref column, _)) = then_order_by.iter().find(|&&(ref c, _)| !result_columns.contains(c)) {
+                bail!(QueryError::ParseError, "Column `{}` in ORDER BY must be selected", column);
+            }
+        }
+        query.order_by = Some(order_by);
+        query.order_desc = order_desc;
+        query.then_order_by = then_order_by;
+    }
+    // Columns selected by `*` are only known once the table is queried
+    if let (&Some(ref group_limit), false) = (&query.group_limit, query.is_select_star()) {
+        let result_columns = query.result_column_names();
+        if let Some(column) = group_limit.columns.iter().find(|c| !result_columns.contains(c)) {
+            bail!(QueryError::ParseError, "Column `{}` in LIMIT ... PER must be selected", column);
+        }
+    }
+    Ok(query)
+}
+
+// Detects common mistakes for which sqlparser-rs reports only a generic error, pointing at the offending position.
+fn check_syntax(query: &str) -> Result<(), QueryError> {
+    let tokens = Regex::new(r#"(?i)'[^']*'?|"[^"]*"?|`[^`]*`?|\bwhere\b|[()]"#).unwrap();
+    let mut open_parens = Vec::new();
+    let mut where_clause = false;
+    for m in tokens.find_iter(query) {
+        let token = m.as_str();
+        match token {
+            "(" => open_parens.push(m.start()),
+            ")" => if open_parens.pop().is_none() {
+                bail!(QueryError::ParseError, "Unmatched `)` {}", location(query, m.start()));
+            },
+            // Unterminated quoted identifiers are reported by `encode_quoted_identifiers`
+            _ if token.starts_with('`') => {}
+            _ if token.starts_with('\'') || token.starts_with('"') => {
+                if token.len() == 1 || !token.ends_with(&token[..1]) {
+                    bail!(QueryError::ParseError, "Unterminated string literal {}", location(query, m.start()));
+                }
+            }
+            _ => {
+                if where_clause {
+                    bail!(QueryError::ParseError, "Unexpected second WHERE clause {}, combine conditions with AND instead", location(query, m.start()));
+                }
+                where_clause = true;
+            }
+        }
+    }
+    if let Some(&start) = open_parens.last() {
+        bail!(QueryError::ParseError, "Unclosed `(` {}", location(query, start));
+    }
+    Ok(())
+}
+
+// Errors of sqlparser-rs name the unexpected token, which is located in the query to give its position.
+fn locate_parser_error(query: &str, error: String) -> String {
+    let found = Regex::new(r#"found: \w+\("([^"]+)"\)"#).unwrap();
+    let token = match found.captures(&error) {
+        Some(captures) => captures[1].to_string(),
+        None => return error,
+    };
+    let re = Regex::new(&format!(r"(?i)(^|\W){}(\W|$)", regex::escape(&token))).unwrap();
+    match re.captures(query) {
+        Some(captures) => format!("{} {}", error, location(query, captures.get(0).unwrap().start() + captures[1].len())),
+        None => error,
+    }
+}
+
+// Describes the position of the byte `offset` in `query` for error messages.
+fn location(query: &str, offset: usize) -> String {
+    let snippet = query[offset..].chars().take(20).collect::<String>();
+    format!("at position {} near `{}`", query[..offset].chars().count() + 1, snippet.trim_right())
+}
+
+const QUOTED_IDENTIFIER_PREFIX: &str = "__quoted_";
+
+// sqlparser-rs only supports identifiers made of alphanumeric characters and underscores, so identifiers quoted with
+// backticks (e.g. `Total Amount` or `2020`) are hex encoded into a valid identifier before parsing.
+// Double quotes already denote string literals and can't be used to quote identifiers.
+fn encode_quoted_identifiers(query: &str) -> Result<String, QueryError> {
+    let re = Regex::new(r#"'[^']*'|"[^"]*"|`([^`]*)`"#).unwrap();
+    let mut empty = false;
+    let encoded = re.replace_all(query, |captures: &Captures| match captures.get(1) {
+        Some(identifier) => {
+            empty |= identifier.as_str().is_empty();
+            format!("{}{}", QUOTED_IDENTIFIER_PREFIX, hex::encode(identifier.as_str()))
+        }
+        None => captures[0].to_string(),
+    }).into_owned();
+    if empty {
+        bail!(QueryError::ParseError, "Empty quoted identifier");
+    }
+    if re.replace_all(&encoded, "").contains('`') {
+        bail!(QueryError::ParseError, "Unterminated quoted identifier");
+    }
+    Ok(encoded)
+}
+
+fn identifier(identifier: &str) -> String {
+    if identifier.starts_with(QUOTED_IDENTIFIER_PREFIX) {
+        if let Some(decoded) = hex::decode(&identifier[QUOTED_IDENTIFIER_PREFIX.len()..]).ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok()) {
+            return decoded;
+        }
+    }
+    identifier.to_string()
+}
+
+// sqlparser-rs only supports plain integer and decimal literals, so numbers with `_` digit separators are
+// rewritten without separators and numbers in scientific notation are expanded into decimal literals.
+// String literals are left unchanged.
+fn normalize_numbers(query: &str) -> Result<String, QueryError> {
+    let tokens = Regex::new(r#"'[^']*'|"[^"]*"|\b\d(?:[eE][+-]|[\w.])*"#).unwrap();
+    let number = Regex::new(r"^\d+(?:_\d+)*(?:\.\d+(?:_\d+)*)?(?:[eE][+-]?\d+)?$").unwrap();
+    let mut normalized = String::with_capacity(query.len());
+    let mut last = 0;
+    for m in tokens.find_iter(query) {
+        let token = m.as_str();
+        if token.starts_with('\'') || token.starts_with('"') || token.chars().all(|c| c.is_digit(10) || c == '.') {
+            continue;
+        }
+        if !number.is_match(token) {
+            bail!(QueryError::ParseError, "Invalid numeric literal: {}", token);
+        }
+        normalized.push_str(&query[last..m.start()]);
+        let digits = token.replace('_', "");
+        if digits.contains(|c| c == 'e' || c == 'E') {
+            let float = match digits.parse::<f64>() {
+                Ok(float) if float.is_finite() => float.to_string(),
+                _ => bail!(QueryError::ParseError, "Invalid numeric literal: {}", token),
+            };
+            normalized.push_str(&float);
+            if !float.contains('.') { normalized.push_str(".0"); }
+        } else {
+            normalized.push_str(&digits);
+        }
+        last = m.end();
+    }
+    normalized.push_str(&query[last..]);
+    Ok(normalized)
+}
+
+// Negative or fractional numbers after LIMIT or OFFSET would otherwise be reported as unexpected tokens.
+fn check_limit_offset(query: &str) -> Result<(), QueryError> {
+    let re = Regex::new(r#"(?i)'[^']*'|"[^"]*"|\b(limit|offset)\s+(-?[\d.][\w.]*)"#).unwrap();
+    for captures in re.captures_iter(query) {
+        if let (Some(keyword), Some(value)) = (captures.get(1), captures.get(2)) {
+            if !value.as_str().chars().all(|c| c.is_digit(10)) {
+                bail!(QueryError::ParseError, "{} must be a non-negative integer, found `{}` {}",
+                      keyword.as_str().to_uppercase(), value.as_str(), location(query, value.start()))
+            }
+        }
+    }
+    Ok(())
+}
+
+const NULL_SAFE_EQUALS_FUNCTION: &str = "__NULL_SAFE_EQUALS";
+
+// sqlparser-rs does not support the `<=>` operator, so null-safe comparisons of columns and constants
+// are rewritten into a call of an internal function before parsing.
+fn rewrite_null_safe_equals(query: &str) -> Result<String, QueryError> {
+    // Literals that are not an operand of `<=>` are matched as a whole, so that their contents are never rewritten
+    let re = Regex::new(r"('[^']*'|-?[\w.]+)\s*<=>\s*('[^']*'|-?[\w.]+)|'[^']*'|\x22[^\x22]*\x22|`[^`]*`").unwrap();
+    let rewritten = re.replace_all(query, |captures: &Captures| match captures.get(1) {
+        Some(_) => format!("{}({}, {})", NULL_SAFE_EQUALS_FUNCTION, &captures[1], &captures[2]),
+        None => captures[0].to_string(),
+    }).into_owned();
+    let literals = Regex::new(r#"'[^']*'|"[^"]*""#).unwrap();
+    if literals.replace_all(&rewritten, "").contains("<=>") {
+        bail!(QueryError::ParseError, "<=> operator is only supported between columns and constants");
+    }
+    Ok(rewritten)
+}
+
+const CAST_FUNCTION_PREFIX: &str = "__CAST_";
+
+// sqlparser-rs does not support CAST, so every `CAST(expr AS type)` is rewritten into a call of an internal
+// function named after the target type before parsing.
+fn rewrite_casts(query: &str) -> Result<String, QueryError> {
+    let tokens = Regex::new(r#"(?i)'[^']*'|"[^"]*"|[()]|\bcast\s*\(|\s+as\s+(\w+)\s*\)"#).unwrap();
+    // Position of each enclosing CAST and the depth of parentheses outside of it
+    let mut casts = Vec::new();
+    let mut replacements = Vec::new();
+    let mut depth = 0;
+    for captures in tokens.captures_iter(query) {
+        let token = captures.get(0).unwrap();
+        let text = token.as_str();
+        if text.starts_with('\'') || text.starts_with('"') {
+            continue;
+        } else if text == "(" {
+            depth += 1;
+        } else if text.ends_with('(') {
+            casts.push((token.start(), token.end(), depth));
+            depth += 1;
+        } else {
+            depth -= 1;
+            if casts.last().map(|&(_, _, d)| d) != Some(depth) {
+                continue;
+            }
+            let (start, open, _) = casts.pop().unwrap();
+            let target = match captures.get(1) {
+                Some(target) => match target.as_str().to_uppercase().as_ref() {
+                    "INT" | "INTEGER" | "BIGINT" => "INTEGER",
+                    "FLOAT" | "DOUBLE" | "REAL" => "FLOAT",
+                    "STRING" | "VARCHAR" | "TEXT" => "STRING",
+                    _ => bail!(QueryError::ParseError, "Unsupported type `{}` in CAST, expected INT, FLOAT or STRING", target.as_str()),
+                },
+                None => bail!(QueryError::ParseError, "Expected CAST(expression AS type) {}", location(query, start)),
+            };
+            replacements.push((start, open, format!("{}{}(", CAST_FUNCTION_PREFIX, target)));
+            replacements.push((token.start(), token.end(), ")".to_string()));
+        }
+    }
+    if let Some(&(start, _, _)) = casts.last() {
+        bail!(QueryError::ParseError, "Unclosed CAST {}", location(query, start))
+    }
+    replacements.sort_by_key(|&(start, _, _)| start);
+    let mut rewritten = query.to_string();
+    for (start, end, replacement) in replacements.into_iter().rev() {
+        rewritten.replace_range(start..end, &replacement);
+    }
+    Ok(rewritten)
+}
+
+// sqlparser-rs does not support clauses within function calls, so the ordering of `FIRST(expr ORDER_BY key)` and
+// `LAST(expr ORDER_BY key)` is rewritten into a second argument before parsing.
+fn rewrite_ordered_aggregates(query: &str) -> String {
+    let tokens = Regex::new(r#"(?i)'[^']*'|"[^"]*"|[()]|\b(?:first|last)\s*\(|\s+order(?:_|\s+)by\s+"#).unwrap();
+    // Depth of parentheses outside of each enclosing FIRST or LAST call
+    let mut aggregates = Vec::new();
+    let mut orderings = Vec::new();
+    let mut depth = 0;
+    for m in tokens.find_iter(query) {
+        let token = m.as_str().to_lowercase();
+        if token == "(" {
+            depth += 1;
+        } else if token == ")" {
+            depth -= 1;
+            if aggregates.last() == Some(&depth) {
+                aggregates.pop();
+            }
+        } else if token.ends_with('(') {
+            aggregates.push(depth);
+            depth += 1;
+        } else if token.trim_left().starts_with("order") && aggregates.last() == Some(&(depth - 1)) {
+            orderings.push((m.start(), m.end()));
+        }
+    }
+    let mut rewritten = query.to_string();
+    for (start, end) in orderings.into_iter().rev() {
+        rewritten.replace_range(start..end, ", ");
+    }
+    rewritten
+}
+
+// sqlparser-rs does not support OFFSET, so a trailing `OFFSET n` is removed before parsing
+fn split_offset(query: &str) -> Result<(&str, Option<u64>), QueryError> {
+    let re = Regex::new(r"(?i)\s+offset\s+(\w+)\s*;?\s*$").unwrap();
+    match re.captures(query) {
+        Some(captures) => {
+            let offset = captures[1].parse::<u64>()
+                .map_err(|_| QueryError::ParseError(format!("Invalid offset: {}", &captures[1])))?;
+            Ok((&query[..captures.get(0).unwrap().start()], Some(offset)))
+        }
+        None => Ok((query, None)),
+    }
+}
+
+// A trailing `LIMIT n PER col1, col2, ...` limits the number of rows per group and is removed before parsing
+fn split_limit_per(query: &str) -> Result<(&str, Option<GroupLimit>), QueryError> {
+    let re = Regex::new(r"(?i)\s+limit\s+(\w+)\s+per\s+(\w+(?:\s*,\s*\w+)*)\s*;?\s*$").unwrap();
+    match re.captures(query) {
+        Some(captures) => {
+            let limit = captures[1].parse::<u64>()
+                .map_err(|_| QueryError::ParseError(format!("Invalid limit: {}", &captures[1])))?;
+            let columns = captures[2].split(',').map(|column| identifier(column.trim())).collect();
+            Ok((&query[..captures.get(0).unwrap().start()], Some(GroupLimit { limit, columns })))
+        }
+        None => Ok((query, None)),
+    }
+}
+
+// sqlparser-rs does not support SELECT DISTINCT, so the DISTINCT keyword is removed before parsing
+fn split_distinct(query: &str) -> (String, bool) {
+    let re = Regex::new(r"(?i)^\s*select\s+distinct\s").unwrap();
+    match re.find(query) {
+        Some(m) => (format!("SELECT {}", &query[m.end()..]), true),
+        None => (query.to_string(), false),
+    }
+}
+
+// sqlparser-rs does not support TABLESAMPLE, so a `TABLESAMPLE p PERCENT [REPEATABLE (seed)]` clause is removed before parsing
+fn split_tablesample(query: &str) -> Result<(String, Option<TableSample>), QueryError> {
+    let tokens = Regex::new(r"(?i)'[^']*'|\x22[^\x22]*\x22|`[^`]*`|\s+tablesample\s+([\w.]+)\s+percent(\s+repeatable\s*\(\s*(\w+)\s*\))?").unwrap();
+    match tokens.captures_iter(query).find(|captures| captures.get(1).is_some()) {
+        Some(captures) => {
+            let percent = captures[1].parse::<f64>()
+                .map_err(|_| QueryError::ParseError(format!("Invalid sample percentage: {}", &captures[1])))?;
+            if !(percent > 0.0 && percent <= 100.0) {
+                bail!(QueryError::ParseError, "Sample percentage must be in (0, 100], got {}", percent);
+            }
+            let seed = match captures.get(3) {
+                Some(seed) => Some(seed.as_str().parse::<u64>()
+                    .map_err(|_| QueryError::ParseError(format!("Invalid sample seed: {}", seed.as_str())))?),
+                None => None,
+            };
+            let whole_match = captures.get(0).unwrap();
+            let query = format!("{} {}", &query[..whole_match.start()], &query[whole_match.end()..]);
+            Ok((query, Some(TableSample { fraction: percent / 100.0, seed })))
+        }
+        None => Ok((query.to_string(), None)),
+    }
+}
+
+const COLLATE_NOCASE_FUNCTION: &str = "__COLLATE_NOCASE";
+
+// sqlparser-rs does not support COLLATE, so `COLLATE name` clauses are removed before parsing.
+// A collation following the first ORDER BY expression applies to the ordering and is returned. A collation following a
+// string constant applies to the comparison with that constant, `'a' COLLATE NOCASE` is rewritten into a call of an
+// internal function that marks the constant.
+fn split_collate(query: &str) -> Result<(String, Collation), QueryError> {
+    let tokens = Regex::new(r#"(?i)'[^']*'|"[^"]*"|[(),]|\border\s+by\b|\b(?:limit|offset)\b|\s+collate\s+(\w+)"#).unwrap();
+    let mut collation = None;
+    let mut rewritten = String::with_capacity(query.len());
+    let mut last = 0;
+    let mut literal = None;
+    let mut depth = 0;
+    // Index of the current ORDER BY expression
+    let mut order_by = None;
+    for captures in tokens.captures_iter(query) {
+        let m = captures.get(0).unwrap();
+        let token = m.as_str().to_lowercase();
+        let name = match captures.get(1) {
+            Some(name) => name.as_str(),
+            None => {
+                match token.as_ref() {
+                    "(" => depth += 1,
+                    ")" => depth -= 1,
+                    "," if depth == 0 => order_by = order_by.map(|i| i + 1),
+                    "limit" | "offset" if depth == 0 => order_by = None,
+                    _ if depth == 0 && token.starts_with("order") => order_by = Some(0),
+                    _ => {}
+                }
+                literal = if token.starts_with('\'') || token.starts_with('"') { Some(m) } else { None };
+                continue;
+            }
+        };
+        let c = Collation::from_name(name)
+            .ok_or_else(|| QueryError::ParseError(format!("Unknown collation: {}", name)))?;
+        match (literal, order_by) {
+            (Some(constant), _) if constant.end() == m.start() => {
+                rewritten.push_str(&query[last..constant.start()]);
+                match c {
+                    Collation::Binary => rewritten.push_str(constant.as_str()),
+                    Collation::NoCase => rewritten.push_str(&format!("{}({})", COLLATE_NOCASE_FUNCTION, constant.as_str())),
+                }
+            }
+            (_, Some(0)) if depth == 0 => {
+                rewritten.push_str(&query[last..m.start()]);
+                collation = Some(c);
+            }
+            (_, Some(_)) if depth == 0 =>
+                bail!(QueryError::NotImplemented, "COLLATE is only supported for the first ORDER BY expression {}", location(query, m.start())),
+            _ => bail!(QueryError::ParseError, "COLLATE has to follow an ORDER BY expression or a string constant {}", location(query, m.start())),
+        }
+        last = m.end();
+        literal = None;
+    }
+    rewritten.push_str(&query[last..]);
+    Ok((rewritten, collation.unwrap_or_default()))
+}
+
+/// Splits `query` into the queries combined by `UNION ALL`, returns `query` itself if there is no union.
+/// ORDER BY, LIMIT and OFFSET clauses apply to the combined rows and may only follow the last query.
+pub fn split_union_all(query: &str) -> Result<Vec<String>, QueryError> {
+    let tokens = Regex::new(r"(?i)'[^']*'|\x22[^\x22]*\x22|`[^`]*`|[()]|\bunion\b(\s+all\b)?|\border\s+by\b|\blimit\b|\boffset\b").unwrap();
+    let mut queries = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let mut ordered_at = None;
+    for captures in tokens.captures_iter(query) {
+        let m = captures.get(0).unwrap();
+        let token = m.as_str().to_lowercase();
+        match token.as_str() {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            token if depth == 0 && token.starts_with("union") => {
+                if captures.get(1).is_none() {
+                    bail!(QueryError::NotImplemented, "UNION without ALL {}, only UNION ALL is supported", location(query, m.start()));
+                }
+                if let Some(offset) = ordered_at {
+                    bail!(QueryError::ParseError, "ORDER BY, LIMIT and OFFSET have to follow the last query combined by UNION ALL {}",
+                          location(query, offset));
+                }
+                queries.push(query[start..m.start()].to_string());
+                start = m.end();
+            }
+            token if depth == 0 && (token.starts_with("order") || token == "limit" || token == "offset") => {
+                ordered_at = ordered_at.or(Some(m.start()));
+            }
+            _ => {}
+        }
+    }
+    queries.push(query[start..].to_string());
+    Ok(queries)
+}
+
+// sqlparser-rs does not support WITH ROLLUP, so the modifier following the GROUP BY clause is removed before parsing
+fn split_rollup(query: &str) -> (String, bool) {
+    let tokens = Regex::new(r"(?i)'[^']*'|\x22[^\x22]*\x22|`[^`]*`|\s+with\s+rollup\b").unwrap();
+    for m in tokens.find_iter(query) {
+        if !m.as_str().starts_with(|c: char| c == '\'' || c == '"' || c == '`') {
+            return (format!("{} {}", &query[..m.start()], &query[m.end()..]), true);
+        }
+    }
+    (query.to_string(), false)
+}
+
+// sqlparser-rs does not support aliases, so `expr AS name` in the select clause is replaced by `expr` before parsing.
+// Returns the alias of each expression in the select clause.
+fn split_aliases(query: &str) -> Result<(String, Vec<Option<String>>), QueryError> {
+    let tokens = Regex::new(r"(?i)'[^']*'|\x22[^\x22]*\x22|[(),]|\bselect\b|\bfrom\b|\s+as\s+(\w+)").unwrap();
+    let mut aliases = vec![None];
+    let mut removed = Vec::new();
+    let mut depth = 0;
+    let mut in_select = false;
+    for captures in tokens.captures_iter(query) {
+        let m = captures.get(0).unwrap();
+        match m.as_str().to_lowercase().as_ref() {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            "," if depth == 0 && in_select => aliases.push(None),
+            "select" if depth == 0 => in_select = true,
+            "from" if depth == 0 && in_select => break,
+            _ => if let (Some(alias), 0, true) = (captures.get(1), depth, in_select) {
+                let alias_index = aliases.len() - 1;
+                if aliases[alias_index].is_some() {
+                    bail!(QueryError::ParseError, "Unexpected second alias `{}` {}", identifier(alias.as_str()), location(query, m.start()));
+                }
+                aliases[alias_index] = Some(identifier(alias.as_str()));
+                removed.push((m.start(), m.end()));
+            },
+        }
+    }
+    let mut stripped = String::with_capacity(query.len());
+    let mut last = 0;
+    for (start, end) in removed {
+        stripped.push_str(&query[last..start]);
+        last = end;
+    }
+    stripped.push_str(&query[last..]);
+    Ok((stripped, aliases))
+}
+
+// Assigns the aliases of the select clause to the non-aggregate select expressions
+fn get_select_aliases(projection: &[ASTNode], aliases: Vec<Option<String>>) -> Result<Vec<Option<String>>, QueryError> {
+    if aliases.iter().all(Option::is_none) { return Ok(vec![]); }
+    if aliases.len() != projection.len() {
+        bail!(QueryError::ParseError, "Could not match aliases to the expressions in the select clause");
+    }
+    let mut select_aliases = Vec::new();
+    for (node, alias) in projection.iter().zip(aliases) {
+        let (select, aggregate) = get_select_aggregate(vec![node.clone()])?;
+        if !aggregate.is_empty() && alias.is_some() {
+            bail!(QueryError::NotImplemented, "Alias `{}` for an aggregate", alias.unwrap());
+        }
+        for _ in select {
+            select_aliases.push(alias.clone());
+        }
+    }
+    Ok(select_aliases)
 }
 
 fn get_query_components(ast: ASTNode)
@@ -47,26 +593,73 @@ fn get_query_components(ast: ASTNode)
                             Option<Box<ASTNode>>,
                             Option<Box<ASTNode>>,
                             Option<Vec<SQLOrderByExpr>>,
+                            Option<Vec<ASTNode>>,
+                            Option<Box<ASTNode>>,
                             Option<Box<ASTNode>>),
                             QueryError>
 {
     match ast {
-        ASTNode::SQLSelect { projection, relation, selection, order_by, group_by, having, limit } => {
-            if group_by.is_some() {
-                Err(QueryError::NotImplemented(format!("Group By")))
-            } else if having.is_some() {
-                Err(QueryError::NotImplemented(format!("Having")))
-            } else {
-                Ok((projection, relation, selection, order_by, limit))
-            }
-        }
+        ASTNode::SQLSelect { projection, relation, selection, order_by, group_by, having, limit } =>
+            Ok((projection, relation, selection, order_by, group_by, having, limit)),
         _ => Err(QueryError::NotImplemented(format!("{:?}", ast))),
     }
 }
 
-fn get_select_aggregate(projection: Vec<ASTNode>) -> Result<(Vec<Expr>, Vec<(Aggregator, Expr)>), QueryError> {
+// Results are always grouped by all non-aggregate select expressions,
+// so an explicit GROUP BY clause has to list exactly those expressions.
+// Subtotals of WITH ROLLUP are computed for prefixes of the select clause, which therefore has to match the GROUP BY order.
+pub(crate) fn check_group_by(select: &[Expr], group_by: &[Expr], rollup: bool) -> Result<(), QueryError> {
+    let select = select.iter().map(|e| format!("{:?}", e)).collect::<Vec<_>>();
+    let mut group_by_exprs = Vec::with_capacity(group_by.len());
+    for e in group_by {
+        let e = format!("{:?}", e);
+        if !select.contains(&e) {
+            return Err(QueryError::NotImplemented(
+                format!("Grouping by {} which is not part of the select clause", e)));
+        }
+        group_by_exprs.push(e);
+    }
+    for e in &select {
+        if !group_by_exprs.contains(e) {
+            return Err(QueryError::ParseError(
+                format!("{} must appear in the GROUP BY clause or be used in an aggregate function", e)));
+        }
+    }
+    if rollup && group_by_exprs != select {
+        bail!(QueryError::NotImplemented, "WITH ROLLUP requires the GROUP BY columns in the order of the select clause");
+    }
+    Ok(())
+}
+
+// `SELECT TOP_K(expr, k)` is only valid as the sole select expression and is replaced by `expr`
+fn split_top_k(projection: Vec<ASTNode>) -> Result<(Vec<ASTNode>, Option<usize>), QueryError> {
+    let is_top_k = |node: &ASTNode| match *node {
+        ASTNode::SQLFunction { ref id, .. } => id.to_uppercase() == "TOP_K",
+        _ => false,
+    };
+    if !projection.iter().any(is_top_k) {
+        return Ok((projection, None));
+    }
+    if projection.len() != 1 {
+        bail!(QueryError::NotImplemented, "TOP_K must be the only expression in the select clause");
+    }
+    match projection.into_iter().next() {
+        Some(ASTNode::SQLFunction { mut args, .. }) => {
+            let k = match args.get(1) {
+                Some(ASTNode::SQLValue(Value::Long(k))) if args.len() == 2 && *k > 0 => *k as usize,
+                _ => return Err(QueryError::ParseError(
+                    "Expected two arguments in TOP_K function, the second a positive integer".to_string())),
+            };
+            args.truncate(1);
+            Ok((args, Some(k)))
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn get_select_aggregate(projection: Vec<ASTNode>) -> Result<(Vec<Expr>, Vec<(Aggregator, Expr, Option<Expr>)>), QueryError> {
     let mut select = Vec::<Expr>::new();
-    let mut aggregate = Vec::<(Aggregator, Expr)>::new();
+    let mut aggregate = Vec::<(Aggregator, Expr, Option<Expr>)>::new();
     for elem in &projection {
         match elem {
             ASTNode::SQLFunction { id, args } => {
@@ -76,7 +669,12 @@ fn get_select_aggregate(projection: Vec<ASTNode>) -> Result<(Vec<Expr>, Vec<(Agg
                             return Err(QueryError::ParseError(
                                 "Expected one argument in COUNT function".to_string()));
                         }
-                        aggregate.push((Aggregator::Count, *expr(&args[0])?));
+                        // `count(*)` counts all rows, `count(expr)` only the rows where `expr` is not null
+                        let counted = match args[0] {
+                            ASTNode::SQLWildcard => Expr::Const(RawVal::Int(1)),
+                            ref arg => *expr(arg)?,
+                        };
+                        aggregate.push((Aggregator::Count, counted, None));
                         continue;
                     }
                     "SUM" => {
@@ -84,7 +682,90 @@ fn get_select_aggregate(projection: Vec<ASTNode>) -> Result<(Vec<Expr>, Vec<(Agg
                             return Err(QueryError::ParseError(
                                 "Expected one argument in SUM function".to_string()));
                         }
-                        aggregate.push((Aggregator::Sum, *expr(&args[0])?));
+                        aggregate.push((Aggregator::Sum, *expr(&args[0])?, None));
+                    }
+                    "MIN" => {
+                        if args.len() != 1 {
+                            return Err(QueryError::ParseError(
+                                "Expected one argument in MIN function".to_string()));
+                        }
+                        aggregate.push((Aggregator::Min, *expr(&args[0])?, None));
+                    }
+                    "MAX" => {
+                        if args.len() != 1 {
+                            return Err(QueryError::ParseError(
+                                "Expected one argument in MAX function".to_string()));
+                        }
+                        aggregate.push((Aggregator::Max, *expr(&args[0])?, None));
+                    }
+                    "PERCENTILE" => {
+                        let percentage = match args.get(1) {
+                            Some(ASTNode::SQLValue(Value::Long(p))) if args.len() == 2 && *p >= 0 && *p <= 100 => *p as u8,
+                            _ => return Err(QueryError::ParseError(
+                                "Expected two arguments in PERCENTILE function, the second an integer between 0 and 100".to_string())),
+                        };
+                        aggregate.push((Aggregator::Percentile(percentage), *expr(&args[0])?, None));
+                    }
+                    "PERCENTILE_CONT" | "PERCENTILE_DISC" => {
+                        let fraction = match args.get(1) {
+                            Some(ASTNode::SQLValue(Value::Double(f))) if args.len() == 2 && *f >= 0.0 && *f <= 1.0 => *f,
+                            Some(ASTNode::SQLValue(Value::Long(f))) if args.len() == 2 && (*f == 0 || *f == 1) => *f as f64,
+                            _ => return Err(QueryError::ParseError(format!(
+                                "Expected two arguments in {} function, the second a number between 0 and 1", id.to_uppercase()))),
+                        };
+                        let aggregator = if id.to_uppercase() == "PERCENTILE_CONT" {
+                            Aggregator::PercentileCont(fraction)
+                        } else {
+                            Aggregator::PercentileDisc(fraction)
+                        };
+                        aggregate.push((aggregator, *expr(&args[0])?, None));
+                    }
+                    "MEDIAN" => {
+                        if args.len() != 1 {
+                            return Err(QueryError::ParseError(
+                                "Expected one argument in MEDIAN function".to_string()));
+                        }
+                        aggregate.push((Aggregator::Percentile(50), *expr(&args[0])?, None));
+                    }
+                    "VAR" | "VARIANCE" => {
+                        if args.len() != 1 {
+                            return Err(QueryError::ParseError(
+                                "Expected one argument in VAR function".to_string()));
+                        }
+                        aggregate.push((Aggregator::Variance, *expr(&args[0])?, None));
+                    }
+                    "STDDEV" => {
+                        if args.len() != 1 {
+                            return Err(QueryError::ParseError(
+                                "Expected one argument in STDDEV function".to_string()));
+                        }
+                        aggregate.push((Aggregator::StdDev, *expr(&args[0])?, None));
+                    }
+                    "MODE" => {
+                        if args.len() != 1 {
+                            return Err(QueryError::ParseError(
+                                "Expected one argument in MODE function".to_string()));
+                        }
+                        aggregate.push((Aggregator::Mode, *expr(&args[0])?, None));
+                    }
+                    "FIRST" | "LAST" => {
+                        if args.len() != 2 {
+                            return Err(QueryError::ParseError(format!(
+                                "Expected {}(expression ORDER_BY key) or {}(expression, key)", id.to_uppercase(), id.to_uppercase())));
+                        }
+                        let aggregator = if id.to_uppercase() == "FIRST" { Aggregator::First } else { Aggregator::Last };
+                        aggregate.push((aggregator, *expr(&args[0])?, Some(*expr(&args[1])?)));
+                    }
+                    "APPROX_COUNT_DISTINCT" => {
+                        let precision = match args.get(1) {
+                            None if args.len() == 1 => 8,
+                            Some(ASTNode::SQLValue(Value::Long(p)))
+                            if args.len() == 2 && *p >= i64::from(hyperloglog::MIN_PRECISION) && *p <= i64::from(hyperloglog::MAX_PRECISION) => *p as u8,
+                            _ => return Err(QueryError::ParseError(format!(
+                                "Expected one argument and optional precision between {} and {} in APPROX_COUNT_DISTINCT function",
+                                hyperloglog::MIN_PRECISION, hyperloglog::MAX_PRECISION))),
+                        };
+                        aggregate.push((Aggregator::ApproxCountDistinct(precision), *expr(&args[0])?, None));
                     }
                     _ => select.push(*expr(elem)?),
                 }
@@ -99,57 +780,124 @@ fn get_select_aggregate(projection: Vec<ASTNode>) -> Result<(Vec<Expr>, Vec<(Agg
 
 fn get_table_name(relation: Option<Box<ASTNode>>) -> Result<String, QueryError> {
     match relation {
-        Some(box ASTNode::SQLIdentifier(table_name)) => Ok(table_name),
+        Some(box ASTNode::SQLIdentifier(table_name)) => Ok(identifier(&table_name)),
         Some(s) => Err(QueryError::ParseError(format!("Invalid expression for table name: {:?}", s))),
         None => Err(QueryError::ParseError("Table name missing.".to_string())),
     }
 }
 
-fn get_order_by(order_by: Option<Vec<SQLOrderByExpr>>) -> Result<(Option<String>, bool), QueryError> {
-    match order_by {
-        Some(sql_order_by_exprs) => {
-            // Remove when `QueryTask` supports multiple columns in `order_by`
-            if sql_order_by_exprs.len() > 1 {
-                return Err(QueryError::NotImplemented(format!("Mutliple columns in order by")));
-            }
-            if let ASTNode::SQLIdentifier(ref identifier) = *sql_order_by_exprs[0].expr {
-                Ok((Some(identifier.to_string()), !sql_order_by_exprs[0].asc))
-            } else {
-                Err(QueryError::NotImplemented(format!("{:?}", sql_order_by_exprs)))
+// Returns the name of each order by column, or alternatively the index of the aggregate it refers to,
+// and whether it is descending
+fn get_order_by(order_by: Option<Vec<SQLOrderByExpr>>,
+                aggregate: &[(Aggregator, Expr, Option<Expr>)])
+                -> Result<Vec<(Option<String>, Option<usize>, bool)>, QueryError> {
+    let mut keys = Vec::new();
+    for sql_order_by_expr in order_by.unwrap_or_default() {
+        let desc = !sql_order_by_expr.asc;
+        match *sql_order_by_expr.expr {
+            ASTNode::SQLIdentifier(ref name) => keys.push((Some(identifier(name)), None, desc)),
+            ref function @ ASTNode::SQLFunction { .. } => {
+                let (_, order_aggregate) = get_select_aggregate(vec![function.clone()])?;
+                let index = order_aggregate.first().and_then(|order_aggregate| {
+                    let order_aggregate = format!("{:?}", order_aggregate);
+                    aggregate.iter().position(|a| format!("{:?}", a) == order_aggregate)
+                });
+                match index {
+                    Some(index) => keys.push((None, Some(index), desc)),
+                    None => bail!(QueryError::NotImplemented,
+                                  "ORDER BY expression that is not an aggregate in the select clause: {:?}", function),
+                }
             }
+            ref other => bail!(QueryError::NotImplemented, "{:?}", other),
         }
-        None => Ok((None, false)),
     }
+    Ok(keys)
 }
 
-fn get_limit(limit: Option<Box<ASTNode>>) -> Result<u64, QueryError> {
+fn get_limit(limit: Option<Box<ASTNode>>, default: u64) -> Result<u64, QueryError> {
     match limit {
-        Some(box ASTNode::SQLValue(Value::Long(int))) => Ok(int as u64),
-        None => Ok(100),
+        Some(box ASTNode::SQLValue(Value::Long(int))) if int >= 0 => Ok(int as u64),
+        None => Ok(default),
         _ => Err(QueryError::NotImplemented(format!("Invalid expression in limit clause: {:?}", limit))),
     }
 }
 
 fn expr(node: &ASTNode) -> Result<Box<Expr>, QueryError> {
     Ok(Box::new(match node {
-        ASTNode::SQLBinaryExpr { ref left, ref op, ref right } =>
-            Expr::Func2(map_operator(op)?, expr(left)?, expr(right)?),
+        ASTNode::SQLBinaryExpr { ref left, ref op, ref right } => match (collate_nocase(left), collate_nocase(right)) {
+            (None, None) => Expr::Func2(map_operator(op)?, expr(left)?, expr(right)?),
+            (left_constant, right_constant) => {
+                let ftype = match map_operator(op)? {
+                    Func2Type::Equals => Func2Type::EqualsNoCase,
+                    Func2Type::NotEquals => Func2Type::NotEqualsNoCase,
+                    _ => bail!(QueryError::NotImplemented, "COLLATE NOCASE is only supported for = and <> comparisons"),
+                };
+                Expr::Func2(ftype, expr(left_constant.unwrap_or(left))?, expr(right_constant.unwrap_or(right))?)
+            }
+        },
         ASTNode::SQLValue(ref literal) => Expr::Const(get_raw_val(literal)?),
-        ASTNode::SQLIdentifier(ref identifier) => Expr::ColName(identifier.to_string()),
+        ASTNode::SQLIdentifier(ref name) => Expr::ColName(identifier(name)),
+        ASTNode::SQLUnary { operator: SQLOperator::Not, ref rex } => Expr::Func1(Func1Type::Not, expr(rex)?),
         ASTNode::SQLFunction { id, args } => match id.to_uppercase().as_ref() {
             "TO_YEAR" => {
                 if args.len() != 1 {
                     return Err(QueryError::ParseError(
-                        "Expected one argument in COUNT function".to_string()));
+                        "Expected one argument in TO_YEAR function".to_string()));
                 }
                 Expr::Func1(Func1Type::ToYear, expr(&args[0])?)
             }
-            _ => return Err(QueryError::NotImplemented(format!("{:?}", id))),
+            "GLOB" => {
+                if args.len() != 2 {
+                    return Err(QueryError::ParseError(
+                        "Expected two arguments in GLOB function".to_string()));
+                }
+                Expr::Func2(Func2Type::Glob, expr(&args[0])?, expr(&args[1])?)
+            }
+            ref function if function.starts_with(CAST_FUNCTION_PREFIX) && args.len() == 1 => {
+                let target = match &function[CAST_FUNCTION_PREFIX.len()..] {
+                    "INTEGER" => BasicType::Integer,
+                    "FLOAT" => BasicType::Float,
+                    _ => BasicType::String,
+                };
+                match *expr(&args[0])? {
+                    // Like column values, constants that can not be converted become null
+                    Expr::Const(ref value) => Expr::Const(value.cast(target).unwrap_or(RawVal::Null)),
+                    inner => Expr::Func1(Func1Type::Cast(target), Box::new(inner)),
+                }
+            }
+            COLLATE_NOCASE_FUNCTION =>
+                bail!(QueryError::NotImplemented, "COLLATE NOCASE is only supported for = and <> comparisons"),
+            NULL_SAFE_EQUALS_FUNCTION if args.len() == 2 =>
+                Expr::Func2(Func2Type::NullSafeEquals, expr(&args[0])?, expr(&args[1])?),
+            _ => return Err(unknown_function(id)),
         }
         _ => return Err(QueryError::NotImplemented(format!("{:?}", node))),
     }))
 }
 
+// Returns the string constant marked by `COLLATE NOCASE`, see `split_collate`
+fn collate_nocase(node: &ASTNode) -> Option<&ASTNode> {
+    match *node {
+        ASTNode::SQLFunction { ref id, ref args } if id.to_uppercase() == COLLATE_NOCASE_FUNCTION && args.len() == 1 =>
+            Some(&args[0]),
+        _ => None,
+    }
+}
+
+// Functions accepted in the select clause, aggregates are only valid at the top level of a select expression
+const SUPPORTED_FUNCTIONS: &[&str] = &[
+    "APPROX_COUNT_DISTINCT", "COUNT", "FIRST", "GLOB", "LAST", "MAX", "MEDIAN", "MIN", "MODE", "PERCENTILE",
+    "PERCENTILE_CONT", "PERCENTILE_DISC", "STDDEV", "SUM", "TOP_K", "TO_YEAR", "VAR", "VARIANCE",
+];
+
+fn unknown_function(id: &str) -> QueryError {
+    if SUPPORTED_FUNCTIONS.contains(&id.to_uppercase().as_ref()) {
+        QueryError::NotImplemented(format!("Function `{}` is not supported in this position", id))
+    } else {
+        QueryError::ParseError(format!("Unknown function `{}`. Supported functions: {}", id, SUPPORTED_FUNCTIONS.join(", ")))
+    }
+}
+
 fn map_operator(o: &SQLOperator) -> Result<Func2Type, QueryError> {
     Ok(match o {
         SQLOperator::And => Func2Type::And,
@@ -157,11 +905,13 @@ fn map_operator(o: &SQLOperator) -> Result<Func2Type, QueryError> {
         SQLOperator::Minus => Func2Type::Subtract,
         SQLOperator::Multiply => Func2Type::Multiply,
         SQLOperator::Divide => Func2Type::Divide,
+        SQLOperator::Modulus => Func2Type::Modulo,
         SQLOperator::Gt => Func2Type::GT,
         SQLOperator::Lt => Func2Type::LT,
         SQLOperator::Eq => Func2Type::Equals,
         SQLOperator::NotEq => Func2Type::NotEquals,
         SQLOperator::Or => Func2Type::Or,
+        SQLOperator::Like => Func2Type::Like,
         _ => return Err(QueryError::NotImplemented(format!("Unsupported operator {:?}", o))),
     })
 }
@@ -174,6 +924,7 @@ fn get_raw_val(constant: &Value) -> Result<RawVal, QueryError> {
         Value::String(string)
         | Value::SingleQuotedString(string)
         | Value::DoubleQuotedString(string) => Ok(RawVal::Str(string.to_string())),
+        Value::Double(float) => Ok(RawVal::Float(*float)),
         Value::Null => Ok(RawVal::Null),
         _ => {
             return Err(QueryError::NotImplemented(format!("{:?}", constant)));
@@ -189,13 +940,426 @@ mod tests {
     fn test_select_star() {
         assert_eq!(
             format!("{:?}", parse_query("select * from default")),
-            "Ok(Query { select: [ColName(\"*\")], table: \"default\", filter: Const(Int(1)), aggregate: [], order_by: None, order_desc: false, limit: LimitClause { limit: 100, offset: 0 }, order_by_index: None })");
+            "Ok(Query { select: [ColName(\"*\")], select_aliases: [], distinct: false, rollup: false, table: \"default\", sample: None, filter: Const(Int(1)), aggregate: [], order_by: None, order_desc: false, then_order_by: [], collation: Binary, limit: LimitClause { limit: 100, offset: 0 }, order_by_index: None, then_order_by_indices: [], group_limit: None, top_k: None })");
     }
 
     #[test]
     fn test_to_year() {
         assert_eq!(
             format!("{:?}", parse_query("select to_year(ts) from default")),
-            "Ok(Query { select: [Func1(ToYear, ColName(\"ts\"))], table: \"default\", filter: Const(Int(1)), aggregate: [], order_by: None, order_desc: false, limit: LimitClause { limit: 100, offset: 0 }, order_by_index: None })");
+            "Ok(Query { select: [Func1(ToYear, ColName(\"ts\"))], select_aliases: [], distinct: false, rollup: false, table: \"default\", sample: None, filter: Const(Int(1)), aggregate: [], order_by: None, order_desc: false, then_order_by: [], collation: Binary, limit: LimitClause { limit: 100, offset: 0 }, order_by_index: None, then_order_by_indices: [], group_limit: None, top_k: None })");
+    }
+
+    #[test]
+    fn test_group_by() {
+        let query = parse_query("select id % 100, count(0) from default group by id % 100").unwrap();
+        assert_eq!(format!("{:?}", query.select),
+                   format!("{:?}", vec![Expr::func(Func2Type::Modulo, col("id"), int(100))]));
+        assert!(!query.distinct);
+        assert!(parse_query("select a, b, sum(c) from default group by b, a").is_ok());
+        assert!(parse_query("select a from default group by a").unwrap().distinct);
+        assert!(parse_query("select a, b, count(0) from default group by a").is_err());
+        assert!(parse_query("select a, count(0) from default group by a, b").is_err());
+    }
+
+    #[test]
+    fn test_rollup() {
+        let query = parse_query("select a, b, sum(c) from default group by a, b with rollup limit 10").unwrap();
+        assert!(query.rollup);
+        assert_eq!(query.limit.limit, 10);
+        assert!(!parse_query("select a, sum(c) from default group by a").unwrap().rollup);
+        assert!(parse_query("select a, b, sum(c) from default group by b, a with rollup").is_err());
+        assert!(parse_query("select a, sum(c) from default with rollup").is_err());
+        assert!(parse_query("select a from default group by a with rollup").is_err());
+        assert!(!parse_query("select a, count(0) from default where b = ' with rollup' group by a").unwrap().rollup);
+    }
+
+    #[test]
+    fn test_union_all() {
+        let queries = split_union_all("SELECT a FROM t WHERE s = 'union' UNION ALL SELECT a FROM u union all select b from v;").unwrap();
+        assert_eq!(queries, vec!["SELECT a FROM t WHERE s = 'union' ", " SELECT a FROM u ", " select b from v;"]);
+        assert_eq!(split_union_all("SELECT reunion FROM t").unwrap(), vec!["SELECT reunion FROM t"]);
+        assert!(split_union_all("SELECT a FROM t UNION SELECT a FROM u").is_err());
+        assert!(split_union_all("SELECT a FROM t ORDER BY a UNION ALL SELECT a FROM u").is_err());
+        assert!(split_union_all("SELECT a FROM t LIMIT 1 UNION ALL SELECT a FROM u").is_err());
+        assert_eq!(split_union_all("SELECT a FROM t WHERE s = 'limit' UNION ALL SELECT a FROM u ORDER BY a LIMIT 1").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_distinct() {
+        let query = parse_query("SELECT DISTINCT city, country FROM default").unwrap();
+        assert!(query.distinct);
+        assert_eq!(format!("{:?}", query.select), format!("{:?}", vec![col("city"), col("country")]));
+        assert!(!parse_query("select distinct_count from default").unwrap().distinct);
+        assert!(!parse_query("select city from default").unwrap().distinct);
+    }
+
+    #[test]
+    fn test_collate() {
+        let query = parse_query("SELECT name FROM default WHERE name = 'A' COLLATE NOCASE ORDER BY name COLLATE nocase").unwrap();
+        assert_eq!(query.collation, Collation::NoCase);
+        assert_eq!(query.order_by, Some("name".to_string()));
+        assert_eq!(format!("{:?}", query.filter),
+                   format!("{:?}", Expr::func(Func2Type::EqualsNoCase, col("name"), Expr::Const(RawVal::Str("A".to_string())))));
+        assert_eq!(parse_query("SELECT name FROM default").unwrap().collation, Collation::Binary);
+        assert!(parse_query("SELECT name FROM default ORDER BY name COLLATE klingon").is_err());
+
+        // Each collation applies only to the expression it follows
+        let query = parse_query("SELECT name FROM default WHERE name = 'A' COLLATE NOCASE ORDER BY name COLLATE BINARY").unwrap();
+        assert_eq!(query.collation, Collation::Binary);
+        assert_eq!(format!("{:?}", query.filter),
+                   format!("{:?}", Expr::func(Func2Type::EqualsNoCase, col("name"), Expr::Const(RawVal::Str("A".to_string())))));
+        let query = parse_query("SELECT name FROM default WHERE name = 'A' ORDER BY name COLLATE NOCASE").unwrap();
+        assert_eq!(query.collation, Collation::NoCase);
+        assert_eq!(format!("{:?}", query.filter),
+                   format!("{:?}", Expr::func(Func2Type::Equals, col("name"), Expr::Const(RawVal::Str("A".to_string())))));
+        let query = parse_query("SELECT name FROM default WHERE name = 'a collate nocase'").unwrap();
+        assert_eq!(query.collation, Collation::Binary);
+        assert_eq!(format!("{:?}", query.filter),
+                   format!("{:?}", Expr::func(Func2Type::Equals, col("name"), Expr::Const(RawVal::Str("a collate nocase".to_string())))));
+
+        assert!(parse_query("SELECT name FROM default WHERE name COLLATE NOCASE = 'A'").is_err());
+        assert!(parse_query("SELECT name FROM default WHERE name > 'A' COLLATE NOCASE").is_err());
+        assert!(parse_query("SELECT name, city FROM default ORDER BY city, name COLLATE NOCASE").is_err());
+    }
+
+    #[test]
+    fn test_order_by_aggregate() {
+        let query = parse_query("SELECT city, count(0), sum(x) FROM default ORDER BY sum(x) DESC LIMIT 10").unwrap();
+        assert_eq!(query.order_by, Some("sum_1".to_string()));
+        assert!(query.order_desc);
+        assert!(parse_query("SELECT city, count(0) FROM default ORDER BY sum(x)").is_err());
+    }
+
+    #[test]
+    fn test_aliases_and_having() {
+        let query = parse_query("SELECT a + b AS s, c, cast(d as integer) FROM default WHERE c > 1 HAVING s > 10").unwrap();
+        assert_eq!(query.result_column_names(), vec!["s".to_string(), "c".to_string(), "col_0".to_string()]);
+        assert_eq!(format!("{:?}", query.filter),
+                   format!("{:?}", Expr::and(
+                       Expr::gt(col("c"), Expr::int(1)),
+                       Expr::gt(Expr::func(Func2Type::Add, col("a"), col("b")), Expr::int(10)))));
+        let query = parse_query("SELECT 'x as y' AS `quoted name` FROM default HAVING `quoted name` = 'x as y'").unwrap();
+        assert_eq!(query.result_column_names(), vec!["quoted name".to_string()]);
+        assert!(parse_query("SELECT a, count(0) FROM default HAVING count(0) > 1").is_err());
+        assert!(parse_query("SELECT a, count(0) AS n FROM default").is_err());
+    }
+
+    #[test]
+    fn test_order_by_multiple_columns() {
+        let query = parse_query("SELECT city, score, name FROM default ORDER BY city ASC, score DESC, name").unwrap();
+        assert_eq!(query.order_by, Some("city".to_string()));
+        assert!(!query.order_desc);
+        assert_eq!(query.then_order_by, vec![("score".to_string(), true), ("name".to_string(), false)]);
+        let query = parse_query("SELECT city, count(0) FROM default ORDER BY count(0) DESC, city").unwrap();
+        assert_eq!(query.order_by, Some("count_0".to_string()));
+        assert_eq!(query.then_order_by, vec![("city".to_string(), false)]);
+        assert!(parse_query("SELECT city FROM default ORDER BY city, score").is_err());
+    }
+
+    #[test]
+    fn test_unknown_function() {
+        let err = parse_query("select foo(x) from default").unwrap_err();
+        assert_eq!(err.to_string(),
+                   "Failed to parser query: Unknown function `foo`. Supported functions: APPROX_COUNT_DISTINCT, COUNT, \
+                    FIRST, GLOB, LAST, MAX, MEDIAN, MIN, MODE, PERCENTILE, PERCENTILE_CONT, PERCENTILE_DISC, STDDEV, SUM, \
+                    TOP_K, TO_YEAR, VAR, VARIANCE");
+        match parse_query("select x from default where sum(x) > 1") {
+            Err(QueryError::NotImplemented(_)) => {}
+            other => panic!("Expected NotImplemented, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_numeric_literals() {
+        assert_eq!(filter("x = 1_000"), format!("{:?}", eq("x", 1000)));
+        assert_eq!(filter("x = 1000"), format!("{:?}", eq("x", 1000)));
+        assert_eq!(filter("x = 1e3"), format!("{:?}", Expr::func(Func2Type::Equals, col("x"), float(1000.0))));
+        assert_eq!(filter("x = 2.5E-1"), format!("{:?}", Expr::func(Func2Type::Equals, col("x"), float(0.25))));
+        assert_eq!(filter("x = 1_0.5e+1"), format!("{:?}", Expr::func(Func2Type::Equals, col("x"), float(105.0))));
+        assert_eq!(filter("x = 1.5"), format!("{:?}", Expr::func(Func2Type::Equals, col("x"), float(1.5))));
+        assert_eq!(filter("x = '1e3' OR x = \"1_000\""),
+                   format!("{:?}", Expr::func(Func2Type::Or,
+                                              Expr::func(Func2Type::Equals, col("x"), Expr::Const(RawVal::Str("1e3".to_string()))),
+                                              Expr::func(Func2Type::Equals, col("x"), Expr::Const(RawVal::Str("1_000".to_string()))))));
+        assert_eq!(limit("select x from default limit 1_000 offset 2_0"), LimitClause { limit: 1000, offset: 20 });
+        for malformed in &["1__0", "1e", "1_", "1e+", "1._5", "1_e3", "12abc"] {
+            assert!(parse_query(&format!("select x from default where x = {}", malformed)).is_err(), "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn test_quoted_identifiers() {
+        let query = parse_query("SELECT `Total Amount`, `2020` FROM default WHERE `Total Amount` > 10 ORDER BY `2020`").unwrap();
+        assert_eq!(format!("{:?}", query.select), format!("{:?}", vec![col("Total Amount"), col("2020")]));
+        assert_eq!(format!("{:?}", query.filter), format!("{:?}", Expr::func(Func2Type::GT, col("Total Amount"), int(10))));
+        assert_eq!(query.order_by, Some("2020".to_string()));
+        let query = parse_query("SELECT x FROM default WHERE x = '`a b`'").unwrap();
+        assert_eq!(format!("{:?}", query.filter),
+                   format!("{:?}", Expr::func(Func2Type::Equals, col("x"), Expr::Const(RawVal::Str("`a b`".to_string())))));
+        assert!(parse_query("SELECT `` FROM default").is_err());
+        assert!(parse_query("SELECT `x FROM default").is_err());
+    }
+
+    #[test]
+    fn test_count_star() {
+        assert_eq!(aggregate("count(*)"), format!("{:?}", vec![(Aggregator::Count, int(1), None::<Expr>)]));
+        assert_eq!(aggregate("count(x)"), format!("{:?}", vec![(Aggregator::Count, col("x"), None::<Expr>)]));
+    }
+
+    #[test]
+    fn test_first_last_order_by() {
+        let expected = format!("{:?}", vec![(Aggregator::First, col("url"), Some(col("ts"))),
+                                            (Aggregator::Last, col("url"), Some(col("ts")))]);
+        assert_eq!(aggregate("first(url order_by ts), LAST(url ORDER BY ts)"), expected);
+        assert_eq!(aggregate("first(url, ts), last(url, ts)"), expected);
+        assert_eq!(aggregate("first(url order_by (ts + 1))"),
+                   format!("{:?}", vec![(Aggregator::First, col("url"), Some(Expr::func(Func2Type::Add, col("ts"), int(1))))]));
+        assert_eq!(format!("{:?}", parse_query("select first(url order_by ts) from default where url = 'a order_by b'").unwrap().filter),
+                   format!("{:?}", Expr::func(Func2Type::Equals, col("url"), Expr::Const(RawVal::Str("a order_by b".to_string())))));
+        assert!(parse_query("select first(url) from default").is_err());
+    }
+
+    #[test]
+    fn test_null_safe_equals() {
+        let query = parse_query("SELECT x FROM default WHERE x <=> NULL AND y<=>'a b'").unwrap();
+        assert_eq!(format!("{:?}", query.filter),
+                   format!("{:?}", Expr::func(Func2Type::And,
+                                              Expr::func(Func2Type::NullSafeEquals, col("x"), Expr::Const(RawVal::Null)),
+                                              Expr::func(Func2Type::NullSafeEquals, col("y"), Expr::Const(RawVal::Str("a b".to_string()))))));
+        let query = parse_query("SELECT x FROM default WHERE x <=> y").unwrap();
+        assert_eq!(format!("{:?}", query.filter), format!("{:?}", Expr::func(Func2Type::NullSafeEquals, col("x"), col("y"))));
+        assert!(parse_query("SELECT x FROM default WHERE (x) <=> y").is_err());
+        assert!(parse_query("SELECT x FROM default WHERE x = '<=>'").is_ok());
+        let query = parse_query("SELECT x FROM default WHERE x = 'a <=> b'").unwrap();
+        assert_eq!(format!("{:?}", query.filter),
+                   format!("{:?}", Expr::func(Func2Type::Equals, col("x"), Expr::Const(RawVal::Str("a <=> b".to_string())))));
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        fn error(query: &str) -> String {
+            match parse_query(query) {
+                Err(QueryError::ParseError(msg)) => msg,
+                other => panic!("Expected parse error, got {:?}", other),
+            }
+        }
+        assert_eq!(error("SELECT a FROM t WHERE a = 1 WHERE b = 2"),
+                   "Unexpected second WHERE clause at position 29 near `WHERE b = 2`, combine conditions with AND instead");
+        assert_eq!(error("SELECT a FROM t WHERE (a = 1 OR b = 2"),
+                   "Unclosed `(` at position 23 near `(a = 1 OR b = 2`");
+        assert_eq!(error("SELECT a FROM t WHERE a = 1)"),
+                   "Unmatched `)` at position 28 near `)`");
+        assert_eq!(error("SELECT a FROM t WHERE b = 'x"),
+                   "Unterminated string literal at position 27 near `'x`");
+        assert!(parse_query("SELECT a FROM t WHERE b = 'where (' AND c = \"where )\"").is_ok());
+    }
+
+    #[test]
+    fn test_glob() {
+        let query = parse_query("SELECT name FROM default WHERE glob(name, 'a?[a-c]*')").unwrap();
+        assert_eq!(format!("{:?}", query.filter),
+                   format!("{:?}", Expr::func(Func2Type::Glob, col("name"), Expr::Const(RawVal::Str("a?[a-c]*".to_string())))));
+        assert!(parse_query("SELECT name FROM default WHERE glob(name)").is_err());
+    }
+
+    #[test]
+    fn test_like() {
+        let query = parse_query("SELECT name FROM default WHERE name LIKE 'ab%'").unwrap();
+        assert_eq!(format!("{:?}", query.filter),
+                   format!("{:?}", Expr::func(Func2Type::Like, col("name"), Expr::Const(RawVal::Str("ab%".to_string())))));
+    }
+
+    #[test]
+    fn test_tablesample() {
+        let query = parse_query("SELECT count(0) FROM default TABLESAMPLE 2.5 PERCENT WHERE x < 3").unwrap();
+        assert_eq!(query.sample, Some(TableSample { fraction: 0.025, seed: None }));
+        assert_eq!(format!("{:?}", query.filter), format!("{:?}", Expr::func(Func2Type::LT, col("x"), int(3))));
+        let query = parse_query("select x from default tablesample 10 percent repeatable (42);").unwrap();
+        assert_eq!(query.sample, Some(TableSample { fraction: 0.1, seed: Some(42) }));
+        assert_eq!(parse_query("select x from default").unwrap().sample, None);
+        assert!(parse_query("select x from default tablesample 0 percent").is_err());
+        assert!(parse_query("select x from default tablesample 101 percent").is_err());
+        assert_eq!(parse_query("select x from default where y = ' tablesample 5 percent'").unwrap().sample, None);
+    }
+
+    #[test]
+    fn test_limit_per() {
+        let query = parse_query("SELECT city, name, score FROM default ORDER BY score DESC LIMIT 3 PER city OFFSET 1;").unwrap();
+        assert_eq!(query.group_limit, Some(GroupLimit { limit: 3, columns: vec!["city".to_string()] }));
+        assert_eq!(query.limit, LimitClause { limit: u64::MAX, offset: 1 });
+        let query = parse_query("SELECT a, b, c FROM default LIMIT 1 per a, b").unwrap();
+        assert_eq!(query.group_limit, Some(GroupLimit { limit: 1, columns: vec!["a".to_string(), "b".to_string()] }));
+        assert_eq!(parse_query("SELECT a FROM default LIMIT 1").unwrap().group_limit, None);
+        assert!(parse_query("SELECT a FROM default LIMIT 1 PER b").is_err());
+    }
+
+    #[test]
+    fn test_top_k() {
+        let query = parse_query("SELECT top_k(city, 5) FROM default WHERE x < 3").unwrap();
+        assert_eq!(query.top_k, Some(5));
+        assert_eq!(format!("{:?}", query.select), format!("{:?}", vec![col("city")]));
+        assert_eq!(query.result_column_names(), vec!["city".to_string(), "approx_count".to_string()]);
+        assert!(parse_query("SELECT top_k(city) FROM default").is_err());
+        assert!(parse_query("SELECT top_k(city, 0) FROM default").is_err());
+        assert!(parse_query("SELECT x, top_k(city, 5) FROM default").is_err());
+        assert!(parse_query("SELECT top_k(city, 5) FROM default LIMIT 3").is_err());
+    }
+
+    #[test]
+    fn test_cast() {
+        fn select(query: &str) -> String {
+            format!("{:?}", parse_query(query).unwrap().select)
+        }
+        assert_eq!(select("SELECT cast(3 as float) FROM default"), format!("{:?}", vec![Expr::Const(RawVal::Float(3.0))]));
+        assert_eq!(select("SELECT CAST(3.7 AS INT) FROM default"), format!("{:?}", vec![int(3)]));
+        assert_eq!(select("SELECT cast(12 as string) FROM default"), format!("{:?}", vec![Expr::Const(RawVal::Str("12".to_string()))]));
+        assert_eq!(select("SELECT cast('4.5' as int) FROM default"), format!("{:?}", vec![int(4)]));
+        assert_eq!(select("SELECT cast(cast(x as string) as integer) FROM default"),
+                   format!("{:?}", vec![Expr::func1(Func1Type::Cast(BasicType::Integer),
+                                                    Expr::func1(Func1Type::Cast(BasicType::String), col("x")))]));
+        assert_eq!(select("SELECT cast(x + 1 AS varchar) FROM default WHERE y = 'cast(z as int)'"),
+                   format!("{:?}", vec![Expr::func1(Func1Type::Cast(BasicType::String), Expr::func(Func2Type::Add, col("x"), int(1)))]));
+        assert!(parse_query("SELECT cast(x as date) FROM default").is_err());
+        assert!(parse_query("SELECT cast(x) FROM default").is_err());
+        assert_eq!(select("SELECT cast('abc' as int), cast('x' as float) FROM default"),
+                   format!("{:?}", vec![Expr::Const(RawVal::Null), Expr::Const(RawVal::Null)]));
+        assert_eq!(select("SELECT cast(x AS int), 'a)' FROM default"),
+                   format!("{:?}", vec![Expr::func1(Func1Type::Cast(BasicType::Integer), col("x")),
+                                        Expr::Const(RawVal::Str("a)".to_string()))]));
+        assert_eq!(select("SELECT cast((x) as int) FROM default"),
+                   format!("{:?}", vec![Expr::func1(Func1Type::Cast(BasicType::Integer), col("x"))]));
+        assert!(parse_query("SELECT cast(x as int FROM default").is_err());
+    }
+
+    fn limit(query: &str) -> LimitClause {
+        parse_query(query).unwrap().limit
+    }
+
+    fn parse_error(query: &str) -> String {
+        match parse_query(query) {
+            Err(QueryError::ParseError(message)) => message,
+            other => panic!("Expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invalid_limit() {
+        assert_eq!(parse_error("select * from default limit -1"),
+                   "LIMIT must be a non-negative integer, found `-1` at position 29 near `-1`");
+        assert_eq!(parse_error("select * from default LIMIT 2.5;"),
+                   "LIMIT must be a non-negative integer, found `2.5` at position 29 near `2.5;`");
+        assert_eq!(parse_error("select * from default limit 10 offset -5"),
+                   "OFFSET must be a non-negative integer, found `-5` at position 39 near `-5`");
+        assert_eq!(parse_error("select x from default limit 0.5 per x"),
+                   "LIMIT must be a non-negative integer, found `0.5` at position 29 near `0.5 per x`");
+        assert_eq!(limit("select * from default limit 0"), LimitClause { limit: 0, offset: 0 });
+    }
+
+    #[test]
+    fn test_limit_offset() {
+        assert_eq!(limit("select * from default"), LimitClause { limit: 100, offset: 0 });
+        assert_eq!(limit("select * from default limit 0"), LimitClause { limit: 0, offset: 0 });
+        assert_eq!(limit("select * from default limit 10 offset 20"), LimitClause { limit: 10, offset: 20 });
+        assert_eq!(limit("select * from default LIMIT 10 OFFSET 20;"), LimitClause { limit: 10, offset: 20 });
+        assert_eq!(limit("select * from default offset 20"), LimitClause { limit: u64::MAX, offset: 20 });
+        assert_eq!(limit("select * from default where x = 'offset 3'"), LimitClause { limit: 100, offset: 0 });
+        assert!(parse_query("select * from default offset x").is_err());
+        assert!(parse_query("select * from default limit -1").is_err());
+        assert!(parse_query("select * from default where x = 'limit -1'").is_ok());
+        assert_eq!(limit("select * from default offset 18446744073709551615"),
+                   LimitClause { limit: u64::MAX, offset: u64::MAX });
+        assert!(parse_query("select * from default offset 18446744073709551616").is_err());
+    }
+
+    fn filter(condition: &str) -> String {
+        format!("{:?}", parse_query(&format!("select * from default where {}", condition)).unwrap().filter)
+    }
+
+    fn col(name: &str) -> Expr { Expr::ColName(name.to_string()) }
+
+    fn int(i: i64) -> Expr { Expr::Const(RawVal::Int(i)) }
+
+    fn float(x: f64) -> Expr { Expr::Const(RawVal::Float(x)) }
+
+    fn eq(name: &str, i: i64) -> Expr { Expr::func(Func2Type::Equals, col(name), int(i)) }
+
+    #[test]
+    fn test_operator_precedence() {
+        assert_eq!(
+            filter("a = 1 and b = 2 or c = 3"),
+            format!("{:?}", Expr::func(Func2Type::Or,
+                                       Expr::func(Func2Type::And, eq("a", 1), eq("b", 2)),
+                                       eq("c", 3))));
+        assert_eq!(
+            filter("a = 1 or b = 2 and c = 3"),
+            format!("{:?}", Expr::func(Func2Type::Or,
+                                       eq("a", 1),
+                                       Expr::func(Func2Type::And, eq("b", 2), eq("c", 3)))));
+        assert_eq!(
+            filter("a = 1 and (b = 2 or c = 3)"),
+            format!("{:?}", Expr::func(Func2Type::And,
+                                       eq("a", 1),
+                                       Expr::func(Func2Type::Or, eq("b", 2), eq("c", 3)))));
+        assert_eq!(
+            filter("a + 1 > b * 2 and c < 3"),
+            format!("{:?}", Expr::func(Func2Type::And,
+                                       Expr::func(Func2Type::GT,
+                                                  Expr::func(Func2Type::Add, col("a"), int(1)),
+                                                  Expr::func(Func2Type::Multiply, col("b"), int(2))),
+                                       Expr::func(Func2Type::LT, col("c"), int(3)))));
+    }
+
+    #[test]
+    fn test_modulo() {
+        assert_eq!(
+            filter("id % 10 = 3"),
+            format!("{:?}", Expr::func(Func2Type::Equals,
+                                       Expr::func(Func2Type::Modulo, col("id"), int(10)),
+                                       int(3))));
+        assert_eq!(
+            filter("a / b + a % b > 0"),
+            format!("{:?}", Expr::func(Func2Type::GT,
+                                       Expr::func(Func2Type::Add,
+                                                  Expr::func(Func2Type::Divide, col("a"), col("b")),
+                                                  Expr::func(Func2Type::Modulo, col("a"), col("b"))),
+                                       int(0))));
+    }
+
+    #[test]
+    fn test_not() {
+        assert_eq!(
+            filter("not (x = 1)"),
+            format!("{:?}", Expr::func1(Func1Type::Not, eq("x", 1))));
+        assert_eq!(
+            filter("a = 1 and not (b = 2 or c = 3)"),
+            format!("{:?}", Expr::func(Func2Type::And,
+                                       eq("a", 1),
+                                       Expr::func1(Func1Type::Not,
+                                                   Expr::func(Func2Type::Or, eq("b", 2), eq("c", 3))))));
+    }
+
+    fn aggregate(select: &str) -> String {
+        format!("{:?}", parse_query(&format!("select {} from default", select)).unwrap().aggregate)
+    }
+
+    #[test]
+    fn test_nested_aggregate_arguments() {
+        assert_eq!(
+            aggregate("sum(a + b)"),
+            format!("{:?}", vec![(Aggregator::Sum, Expr::func(Func2Type::Add, col("a"), col("b")), None::<Expr>)]));
+        assert_eq!(
+            aggregate("sum((a))"),
+            format!("{:?}", vec![(Aggregator::Sum, col("a"), None::<Expr>)]));
+        assert_eq!(
+            aggregate("count(a > 0)"),
+            format!("{:?}", vec![(Aggregator::Count, Expr::func(Func2Type::GT, col("a"), int(0)), None::<Expr>)]));
+        assert_eq!(
+            aggregate("max((a + 1) * (b - 2))"),
+            format!("{:?}", vec![(Aggregator::Max,
+                                  Expr::func(Func2Type::Multiply,
+                                             Expr::func(Func2Type::Add, col("a"), int(1)),
+                                             Expr::func(Func2Type::Subtract, col("b"), int(2))),
+                                  None::<Expr>)]));
     }
 }
@@ -0,0 +1,135 @@
+use engine::aggregator::Aggregator;
+use engine::query::Query;
+use engine::vector_op::hyperloglog;
+use ingest::raw_val::RawVal;
+use syntax::collation::Collation;
+use syntax::expression::Expr;
+use syntax::limit::LimitClause;
+use syntax::parser;
+use QueryError;
+
+
+/// Assembles a `Query` from expressions instead of parsing SQL, e.g.
+/// `QueryBuilder::select(vec![Expr::col("city")]).aggregate(Aggregator::Count, Expr::int(1)).from("trips").build()`.
+/// Like in SQL, rows are grouped by all selected columns if the query contains aggregates.
+#[derive(Debug, Clone)]
+pub struct QueryBuilder {
+    select: Vec<Expr>,
+    aggregate: Vec<(Aggregator, Expr, Option<Expr>)>,
+    table: Option<String>,
+    filter: Expr,
+    group_by: Option<Vec<Expr>>,
+    distinct: bool,
+    order_by: Option<(String, bool)>,
+    limit: LimitClause,
+}
+
+impl QueryBuilder {
+    pub fn select(select: Vec<Expr>) -> QueryBuilder {
+        QueryBuilder {
+            select,
+            aggregate: Vec::new(),
+            table: None,
+            filter: Expr::Const(RawVal::Int(1)),
+            group_by: None,
+            distinct: false,
+            order_by: None,
+            limit: LimitClause { limit: 100, offset: 0 },
+        }
+    }
+
+    /// Adds an aggregate of `expr`, which follows all select columns in the result.
+    /// `First` and `Last` additionally require the expression that orders values, see `aggregate_ordered`.
+    pub fn aggregate(self, aggregator: Aggregator, expr: Expr) -> QueryBuilder {
+        self.aggregate_ordered(aggregator, expr, None)
+    }
+
+    pub fn aggregate_ordered(mut self, aggregator: Aggregator, expr: Expr, ordering: Option<Expr>) -> QueryBuilder {
+        self.aggregate.push((aggregator, expr, ordering));
+        self
+    }
+
+    pub fn from(mut self, table: &str) -> QueryBuilder {
+        self.table = Some(table.to_string());
+        self
+    }
+
+    pub fn filter(mut self, filter: Expr) -> QueryBuilder {
+        self.filter = filter;
+        self
+    }
+
+    /// Must contain exactly the select columns. Grouping a query without aggregates removes duplicate rows.
+    pub fn group_by(mut self, group_by: Vec<Expr>) -> QueryBuilder {
+        self.group_by = Some(group_by);
+        self
+    }
+
+    pub fn distinct(mut self) -> QueryBuilder {
+        self.distinct = true;
+        self
+    }
+
+    /// Orders by the result column with name `column`.
+    pub fn order_by(mut self, column: &str, desc: bool) -> QueryBuilder {
+        self.order_by = Some((column.to_string(), desc));
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> QueryBuilder {
+        self.limit.limit = limit;
+        self
+    }
+
+    pub fn offset(mut self, offset: u64) -> QueryBuilder {
+        self.limit.offset = offset;
+        self
+    }
+
+    pub fn build(self) -> Result<Query, QueryError> {
+        let table = match self.table {
+            Some(table) => table,
+            None => bail!(QueryError::ParseError, "Table name missing."),
+        };
+        let mut distinct = self.distinct;
+        if let Some(group_by) = self.group_by {
+            parser::check_group_by(&self.select, &group_by, false)?;
+            distinct = distinct || self.aggregate.is_empty();
+        }
+        for &(aggregator, _, ref ordering) in &self.aggregate {
+            let ordered = aggregator == Aggregator::First || aggregator == Aggregator::Last;
+            if ordered != ordering.is_some() {
+                bail!(QueryError::ParseError, "Ordering expression is required for FIRST and LAST aggregates only, got {:?}", aggregator);
+            }
+            if let Aggregator::ApproxCountDistinct(precision) = aggregator {
+                if precision < hyperloglog::MIN_PRECISION || precision > hyperloglog::MAX_PRECISION {
+                    bail!(QueryError::ParseError, "Precision of {:?} must be between {} and {}",
+                          aggregator, hyperloglog::MIN_PRECISION, hyperloglog::MAX_PRECISION);
+                }
+            }
+        }
+        let (order_by, order_desc) = match self.order_by {
+            Some((column, desc)) => (Some(column), desc),
+            None => (None, false),
+        };
+        Ok(Query {
+            select: self.select,
+            select_aliases: vec![],
+            distinct,
+            rollup: false,
+            table,
+            sample: None,
+            filter: self.filter,
+            aggregate: self.aggregate,
+            order_by,
+            order_desc,
+            then_order_by: vec![],
+            collation: Collation::Binary,
+            limit: self.limit,
+            order_by_index: None,
+            then_order_by_indices: vec![],
+            group_limit: None,
+            top_k: None,
+        })
+    }
+}
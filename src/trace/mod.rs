@@ -86,19 +86,44 @@ pub struct Span {
 }
 
 impl Trace {
+    /// Combines the traces of queries that ran concurrently into a single trace with a toplevel span named `name`,
+    /// which spans both traces and contains their toplevel spans.
+    pub fn merge(self, other: Trace, name: &str) -> Trace {
+        let mut children = if self.toplevel_span.name == name {
+            self.toplevel_span.children
+        } else {
+            vec![self.toplevel_span]
+        };
+        children.push(other.toplevel_span);
+        Trace {
+            toplevel_span: Span {
+                name: name.to_owned(),
+                start_time: children.iter().map(|span| span.start_time).min().unwrap(),
+                end_time: children.iter().map(|span| span.end_time).max().unwrap(),
+                children,
+            }
+        }
+    }
+
     pub fn print(&self) {
-        Trace::_print(&self.toplevel_span, 0, self.toplevel_span.start_time);
+        print!("{}", self.format());
+    }
+
+    pub fn format(&self) -> String {
+        let mut result = String::new();
+        Trace::_format(&self.toplevel_span, 0, self.toplevel_span.start_time, &mut result);
+        result
     }
 
-    fn _print(span: &Span, depth: usize, offset: u64) {
-        println!("{}{} {} [{}, {}]",
-                 " ".repeat(depth * 2),
-                 span.name,
-                 Trace::format_duration(span.end_time - span.start_time),
-                 span.start_time - offset,
-                 span.end_time - offset);
+    fn _format(span: &Span, depth: usize, offset: u64, result: &mut String) {
+        *result += &format!("{}{} {} [{}, {}]\n",
+                            " ".repeat(depth * 2),
+                            span.name,
+                            Trace::format_duration(span.end_time - span.start_time),
+                            span.start_time - offset,
+                            span.end_time - offset);
         for child in &span.children {
-            Trace::_print(child, depth + 1, offset);
+            Trace::_format(child, depth + 1, offset, result);
         }
     }
 
@@ -2,6 +2,7 @@ extern crate env_logger;
 extern crate futures_executor;
 extern crate locustdb;
 extern crate log;
+extern crate num_cpus;
 extern crate tempdir;
 
 use futures_executor::block_on;
@@ -22,15 +23,42 @@ fn test_query(query: &str, expected_rows: &[Vec<Value>]) {
 }
 
 fn test_query_ec(query: &str, expected_rows: &[Vec<Value>]) {
+    let locustdb = load_edge_cases();
+    assert_eq!(run_query(&locustdb, query).rows, expected_rows);
+}
+
+/// Runs `query` against `csv` split into partitions of a single row, a few rows and the whole table, so that columns
+/// which are null in entire partitions are covered as well as partitions that mix nulls and values.
+fn test_query_csv(csv: &str, query: &str, expected_rows: &[Vec<Value>]) {
+    for &partition_size in &[1, 2, 3, 1 << 16] {
+        let locustdb = load_csv_str(csv, partition_size);
+        assert_eq!(run_query(&locustdb, query).rows, expected_rows, "{} (partition size {})", query, partition_size);
+    }
+}
+
+fn load_edge_cases() -> LocustDB {
     let _ = env_logger::try_init();
-    #[allow(unused_mut)]
-    let mut opts = Options::default();
-    let locustdb = LocustDB::new(&opts);
-    let _ = block_on(locustdb.load_csv(
+    let locustdb = LocustDB::memory_only();
+    block_on(locustdb.load_csv(
         LoadOptions::new("test_data/edge_cases.csv", "default")
-            .with_partition_size(3)));
-    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
-    assert_eq!(result.0.unwrap().rows, expected_rows);
+            .with_partition_size(3))).unwrap().unwrap();
+    locustdb
+}
+
+/// Loads `csv` into the table `default` of an in-memory database with `partition_size` rows per partition.
+fn load_csv_str(csv: &str, partition_size: usize) -> LocustDB {
+    load_csv_str_with_options(csv, partition_size, &Options::default())
+}
+
+fn load_csv_str_with_options(csv: &str, partition_size: usize, opts: &Options) -> LocustDB {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(opts);
+    locustdb.load_csv_stream(csv.as_bytes(), LoadOptions::new("stdin", "default").with_partition_size(partition_size)).unwrap();
+    locustdb
+}
+
+fn run_query(locustdb: &LocustDB, query: &str) -> QueryOutput {
+    block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap()
 }
 
 fn test_query_nyc(query: &str, expected_rows: &[Vec<Value>]) {
@@ -159,6 +187,25 @@ fn group_by_col_and_aliasing_const_cols() {
     )
 }
 
+#[test]
+fn test_not() {
+    use Value::*;
+    test_query_ec(
+        "select enum, count(0) from default where not (non_dense_ints = 2);",
+        &[
+            vec![Str("aa".to_string()), Int(4)],
+            vec![Str("bb".to_string()), Int(3)],
+        ],
+    );
+    test_query_ec(
+        "select enum, count(0) from default where not ((enum = \"aa\") or (non_dense_ints < 2));",
+        &[
+            vec![Str("bb".to_string()), Int(2)],
+            vec![Str("cc".to_string()), Int(2)],
+        ],
+    );
+}
+
 #[test]
 fn test_string_packed_column() {
     test_query_ec(
@@ -197,6 +244,30 @@ fn test_sum_2() {
     )
 }
 
+#[test]
+fn test_aggregates_merged_across_partitions() {
+    test_query_ec(
+        "select enum, min(negative), max(negative), sum(negative), count(1) from default;",
+        &[
+            vec![Str("aa"), Int(-199), Int(39), Int(-348), Int(5)],
+            vec![Str("bb"), Int(-40), Int(4031), Int(4025), Int(3)],
+            vec![Str("cc"), Int(-130), Int(4010), Int(3880), Int(2)],
+        ],
+    )
+}
+
+#[test]
+fn test_min_max_offset_encoded() {
+    test_query_ec(
+        "select enum, min(u8_offset_encoded), max(u8_offset_encoded) from default;",
+        &[
+            vec!["aa".into(), 256.into(), 500.into()],
+            vec!["bb".into(), 257.into(), 500.into()],
+            vec!["cc".into(), 343.into(), 511.into()],
+        ],
+    )
+}
+
 #[test]
 fn test_multiple_group_by() {
     test_query(
@@ -450,6 +521,2395 @@ fn test_group_by_string() {
     assert_eq!(result.rows, expected_rows);
 }
 
+#[test]
+fn test_approx_count_distinct() {
+    use Value::*;
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.gen_table(
+        locustdb::colgen::GenTable {
+            name: "test".to_string(),
+            partitions: 10,
+            partition_size: 2000,
+            columns: vec![
+                ("id".to_string(), locustdb::colgen::incrementing_int()),
+                ("zero".to_string(), locustdb::colgen::int_uniform(0, 1)),
+            ],
+        }
+    ));
+
+    let query = "SELECT zero, approx_count_distinct(id, 10), count(1) FROM test;";
+    let result = run_query(&locustdb, query);
+    assert_eq!(result.rows.len(), 1);
+    assert_eq!(result.rows[0][0], Int(0));
+    assert_eq!(result.rows[0][2], Int(20_000));
+    match result.rows[0][1] {
+        Int(estimate) => assert!((estimate - 20_000).abs() < 1_500, "estimate: {}", estimate),
+        ref other => panic!("Expected integer, got {:?}", other),
+    }
+    let query = "SELECT zero, approx_count_distinct(id, 13) FROM test;";
+    assert!(block_on(locustdb.run_query(query, false, vec![])).unwrap().0.is_err());
+}
+
+#[test]
+fn test_percentile() {
+    test_query_ec(
+        "select enum, median(negative), percentile(negative, 95), count(1) from default;",
+        &[vec![Str("aa"), Int(-100), Int(39), Int(5)],
+            vec![Str("bb"), Int(34), Int(4031), Int(3)],
+            vec![Str("cc"), Int(-130), Int(4010), Int(2)]],
+    );
+}
+
+#[test]
+fn test_percentile_across_partitions() {
+    use Value::*;
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.gen_table(
+        locustdb::colgen::GenTable {
+            name: "test".to_string(),
+            partitions: 4,
+            partition_size: 250,
+            columns: vec![
+                ("id".to_string(), locustdb::colgen::incrementing_int()),
+                ("zero".to_string(), locustdb::colgen::int_uniform(0, 1)),
+            ],
+        }
+    ));
+
+    let query = "SELECT zero, median(id), percentile(id, 90), percentile(id, 99) FROM test;";
+    let result = run_query(&locustdb, query);
+    assert_eq!(result.rows, vec![vec![Int(0), Int(499), Int(899), Int(989)]]);
+}
+
+#[test]
+fn test_percentile_cont_disc() {
+    use Value::*;
+    let csv = "g,x\na,40\nb,100\na,10\na,\na,30\nb,5\na,20\n";
+    let query = "SELECT g, percentile_cont(x, 0.5), percentile_disc(x, 0.5), percentile_cont(x, 0.75), percentile_disc(x, 0.75), \
+                 percentile_disc(x, 0), percentile_cont(x, 1) FROM default ORDER BY g;";
+    test_query_csv(csv, query, &[
+        vec![Str("a".to_string()), Float(25.0), Int(20), Float(32.5), Int(30), Int(10), Float(40.0)],
+        vec![Str("b".to_string()), Float(52.5), Int(5), Float(76.25), Int(100), Int(5), Float(100.0)],
+    ]);
+    let locustdb = load_csv_str(csv, 2);
+    assert!(block_on(locustdb.run_query("SELECT percentile_cont(x, 95) FROM default;", false, vec![])).unwrap().0.is_err());
+}
+
+#[test]
+fn test_percentile_skips_nulls() {
+    let csv = "g,x\na,4\na,\nb,\na,1\na,\na,3\nb,7\n";
+    // The null rows of group a are not part of its distribution
+    test_query_csv(csv, "SELECT g, median(x), percentile(x, 100) FROM default ORDER BY g;", &[
+        vec![Str("a"), Int(3), Int(4)],
+        vec![Str("b"), Int(7), Int(7)],
+    ]);
+}
+
+#[test]
+fn test_variance_stddev() {
+    use Value::*;
+    let locustdb = load_edge_cases();
+    let result = run_query(&locustdb, "select enum, var(negative), stddev(negative) from default;");
+
+    let groups: [(&str, &[f64]); 3] = [
+        ("aa", &[-199.0, 39.0, -100.0, 32.0, -120.0]),
+        ("bb", &[34.0, 4031.0, -40.0]),
+        ("cc", &[-130.0, 4010.0]),
+    ];
+    assert_eq!(result.rows.len(), groups.len());
+    for (row, &(group, values)) in result.rows.iter().zip(groups.iter()) {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let expected = values.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1.0);
+        assert_eq!(row[0], Str(group.to_string()));
+        match (&row[1], &row[2]) {
+            (&Float(var), &Float(stddev)) => {
+                assert!((var - expected).abs() < 1e-6 * expected, "{} != {}", var, expected);
+                assert!((stddev - expected.sqrt()).abs() < 1e-6 * expected.sqrt(), "{} != {}", stddev, expected.sqrt());
+            }
+            other => panic!("Expected floats, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_variance_merged_across_partitions() {
+    use Value::*;
+    let csv = "g,x\na,1000000004\na,1000000007\nb,5\na,1000000013\na,1000000016\n";
+    // Each value is in its own partition, so all moments are merged from single values
+    let locustdb = load_csv_str(csv, 1);
+    let mut result = run_query(&locustdb, "SELECT g, var(x) FROM default;");
+    result.rows.sort();
+    assert_eq!(result.rows.len(), 2);
+    match result.rows[0][1] {
+        Float(var) => assert!((var - 30.0).abs() < 1e-6, "{} != 30", var),
+        ref other => panic!("Expected float, got {:?}", other),
+    }
+    // Sample variance of a single value is undefined
+    assert_eq!(result.rows[1], vec![Str("b".to_string()), Null]);
+
+    let result = run_query(&locustdb, "SELECT g, var(x) FROM default GROUP BY g WITH ROLLUP;");
+    assert_eq!(result.rows.len(), 3);
+    match result.rows[2][1] {
+        Float(var) => {
+            let values = [1_000_000_004.0, 1_000_000_007.0, 5.0, 1_000_000_013.0, 1_000_000_016.0];
+            let mean = values.iter().sum::<f64>() / 5.0;
+            let expected = values.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / 4.0;
+            assert!((var - expected).abs() < 1e-9 * expected, "{} != {}", var, expected);
+        }
+        ref other => panic!("Expected float, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_first_last() {
+    test_query_ec(
+        "select enum, first(string_packed order_by u8_offset_encoded), last(string_packed order_by u8_offset_encoded) from default;",
+        &[vec![Str("aa"), Str("xyz"), Str("sss")],
+            vec![Str("bb"), Str("axy"), Str("g")],
+            vec![Str("cc"), Str("asd"), Str("t")]],
+    );
+}
+
+#[test]
+fn test_first_last_skips_null_keys() {
+    let csv = "user,url,ts\n\
+               a,/home,5\nb,/cart,9\na,/search,\na,/login,2\nb,/pay,\nb,/home,3\na,/logout,8\n";
+    test_query_csv(csv, "SELECT user, first(url ORDER_BY ts), last(url ORDER_BY ts) FROM default ORDER BY user;", &[
+        vec![Str("a"), Str("/login"), Str("/logout")],
+        vec![Str("b"), Str("/home"), Str("/cart")],
+    ]);
+}
+
+#[test]
+fn test_mode() {
+    let csv = "team,city,score\n\
+               a,Paris,3\na,Berlin,7\na,Rome,3\na,Berlin,7\nb,Paris,1\na,Paris,2\n\
+               b,Oslo,2\na,Berlin,3\nb,Paris,2\nb,Oslo,1\nb,Oslo,\nb,Paris,\n";
+    // Team b has a tie between Oslo and Paris and between 1 and 2 (missing scores are skipped), the smaller value wins
+    test_query_csv(csv, "SELECT team, mode(city), mode(score) FROM default ORDER BY team;", &[
+        vec![Str("a"), Str("Berlin"), Int(3)],
+        vec![Str("b"), Str("Oslo"), Int(1)],
+    ]);
+    test_query_csv(csv, "SELECT mode(city) FROM default;", &[vec![Str("Paris")]]);
+}
+
+#[test]
+fn test_tables_and_schema() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/edge_cases.csv", "edge")
+            .with_partition_size(3)));
+
+    let tables = block_on(locustdb.tables()).unwrap();
+    assert!(tables.contains(&"default".to_string()), "{:?}", tables);
+    assert!(tables.contains(&"edge".to_string()), "{:?}", tables);
+
+    let schema = block_on(locustdb.schema("edge")).unwrap().unwrap();
+    assert_eq!(schema, vec![
+        ("constant0".to_string(), BasicType::Integer),
+        ("constant0_2".to_string(), BasicType::Integer),
+        ("enum".to_string(), BasicType::String),
+        ("negative".to_string(), BasicType::Integer),
+        ("non_dense_ints".to_string(), BasicType::Integer),
+        ("string_packed".to_string(), BasicType::String),
+        ("u8_offset_encoded".to_string(), BasicType::Integer),
+    ]);
+    let schema = block_on(locustdb.schema("default")).unwrap().unwrap();
+    assert!(schema.contains(&("first_name".to_string(), BasicType::String)), "{:?}", schema);
+    assert!(schema.contains(&("num".to_string(), BasicType::Integer)), "{:?}", schema);
+    assert_eq!(block_on(locustdb.schema("does_not_exist")).unwrap(), None);
+}
+
+#[test]
+fn test_information_schema() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/edge_cases.csv", "edge")
+            .with_partition_size(3)));
+
+    let query = "select column_name, column_type, row_count from information_schema where table_name = \"edge\";";
+    let result = run_query(&locustdb, query);
+    assert_eq!(result.rows, vec![
+        vec![Str("constant0"), Str("Integer"), Int(10)],
+        vec![Str("constant0_2"), Str("Integer"), Int(10)],
+        vec![Str("enum"), Str("String"), Int(10)],
+        vec![Str("negative"), Str("Integer"), Int(10)],
+        vec![Str("non_dense_ints"), Str("Integer"), Int(10)],
+        vec![Str("string_packed"), Str("String"), Int(10)],
+        vec![Str("u8_offset_encoded"), Str("Integer"), Int(10)],
+    ]);
+
+    let query = "select table_name, count(1) from information_schema where table_name = \"default\";";
+    let result = run_query(&locustdb, query);
+    assert_eq!(result.rows, vec![vec![Str("default"), Int(9)]]);
+}
+
+#[test]
+fn test_gzip_detection() {
+    use std::fs;
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("gzip_detection").unwrap();
+    let gzipped = tmp_dir.path().join("nyc-taxi.csv");
+    let plain = tmp_dir.path().join("tiny.csv.gz");
+    fs::copy("test_data/nyc-taxi.csv.gz", &gzipped).unwrap();
+    fs::copy("test_data/tiny.csv", &plain).unwrap();
+
+    let count = |filename: &str| {
+        let locustdb = LocustDB::memory_only();
+        block_on(locustdb.load_csv(LoadOptions::new(filename, "default"))).unwrap().unwrap();
+        let result = block_on(locustdb.run_query("select count(1) from default;", false, vec![])).unwrap().0.unwrap();
+        result.rows[0][0].clone()
+    };
+    // Compressed file without .gz extension
+    assert_eq!(count(gzipped.to_str().unwrap()), count("test_data/nyc-taxi.csv.gz"));
+    // Uncompressed file with .gz extension
+    assert_eq!(count(plain.to_str().unwrap()), count("test_data/tiny.csv"));
+}
+
+#[test]
+fn test_load_csv_stream() {
+    use std::fs::File;
+    use std::io::Read;
+    let _ = env_logger::try_init();
+    let count = |input: &[u8]| {
+        let locustdb = LocustDB::memory_only();
+        locustdb.load_csv_stream(input, LoadOptions::new("stdin", "default").with_partition_size(40)).unwrap();
+        let result = block_on(locustdb.run_query("select count(1) from default;", false, vec![])).unwrap().0.unwrap();
+        result.rows[0][0].clone()
+    };
+    let mut plain = Vec::new();
+    File::open("test_data/tiny.csv").unwrap().read_to_end(&mut plain).unwrap();
+    assert_eq!(count(&plain), Int(100));
+    let mut gzipped = Vec::new();
+    File::open("test_data/nyc-taxi.csv.gz").unwrap().read_to_end(&mut gzipped).unwrap();
+    let locustdb = LocustDB::memory_only();
+    block_on(locustdb.load_csv(LoadOptions::new("test_data/nyc-taxi.csv.gz", "default"))).unwrap().unwrap();
+    let expected = block_on(locustdb.run_query("select count(1) from default;", false, vec![])).unwrap().0.unwrap();
+    assert_eq!(count(&gzipped), expected.rows[0][0]);
+}
+
+#[test]
+fn test_run_query_streaming() {
+    use Value::*;
+    let csv = format!("n,s\n{}", (0..10).map(|i| format!("{},x{}\n", i, i)).collect::<String>());
+    let locustdb = load_csv_str(&csv, 3);
+    let chunks = |query: &str| locustdb.run_query_streaming(query)
+        .map(|chunk| chunk.unwrap().rows.into_iter().map(|row| row[0].clone()).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    assert_eq!(chunks("select n from default;"), vec![
+        vec![Int(0), Int(1), Int(2)],
+        vec![Int(3), Int(4), Int(5)],
+        vec![Int(6), Int(7), Int(8)],
+        vec![Int(9)],
+    ]);
+    assert_eq!(chunks("select n from default where n > 4;"), vec![
+        vec![Int(5)],
+        vec![Int(6), Int(7), Int(8)],
+        vec![Int(9)],
+    ]);
+    assert_eq!(chunks("select n from default limit 4 offset 2;"), vec![
+        vec![Int(2)],
+        vec![Int(3), Int(4), Int(5)],
+    ]);
+    assert_eq!(chunks("select n from default where n > 100;"), vec![Vec::<Value>::new()]);
+    assert_eq!(chunks("select count(0) from default;"), vec![vec![Int(10)]]);
+    let star = locustdb.run_query_streaming("select * from default limit 1;").next().unwrap().unwrap();
+    assert_eq!(star.colnames, vec!["n".to_string(), "s".to_string()]);
+    let mut failed = locustdb.run_query_streaming("select nope from default;");
+    match failed.next() {
+        Some(Err(QueryError::UnknownColumn(ref col))) => assert_eq!(col, "nope"),
+        _ => panic!("Expected unknown column error"),
+    }
+    assert!(failed.next().is_none());
+}
+
+#[cfg(feature = "enable_zstd")]
+#[test]
+fn test_zstd_ingestion() {
+    let _ = env_logger::try_init();
+    let query = "select first_name, last_name, num from default;";
+    let run = |filename: &str| {
+        let locustdb = LocustDB::memory_only();
+        block_on(locustdb.load_csv(
+            LoadOptions::new(filename, "default")
+                .with_partition_size(40))).unwrap().unwrap();
+        let mut rows = run_query(&locustdb, query).rows;
+        rows.sort();
+        rows
+    };
+    let expected = run("test_data/tiny.csv");
+    assert!(!expected.is_empty());
+    assert_eq!(run("test_data/tiny.csv.zst"), expected);
+}
+
+#[test]
+fn test_shared_string_dictionary() {
+    let _ = env_logger::try_init();
+    let load = |shared_dictionaries: Option<bool>| {
+        let locustdb = LocustDB::memory_only();
+        let options = LoadOptions::new("test_data/tiny.csv", "default").with_partition_size(10);
+        let options = match shared_dictionaries {
+            Some(shared) => options.with_shared_dictionaries(shared),
+            None => options,
+        };
+        block_on(locustdb.load_csv(options)).unwrap().unwrap();
+        locustdb
+    };
+    let tld_bytes = |locustdb: &LocustDB| {
+        let stats = block_on(locustdb.table_stats()).unwrap();
+        let table = stats.iter().find(|t| t.name == "default").unwrap();
+        table.size_per_column.iter().find(|&&(ref col, _)| col == "tld").unwrap().1
+    };
+    let shared = load(Some(true));
+    let per_batch = load(Some(false));
+    // The shared dictionary is counted once for the table rather than for each partition
+    assert!(tld_bytes(&shared) < tld_bytes(&per_batch),
+            "shared: {}, per batch: {}", tld_bytes(&shared), tld_bytes(&per_batch));
+    // Shared dictionaries are opt-in
+    assert_eq!(tld_bytes(&load(None)), tld_bytes(&per_batch));
+
+    for locustdb in &[shared, per_batch] {
+        let query = "select tld, count(1) from default where tld = \"name\";";
+        let result = run_query(&locustdb, query);
+        assert_eq!(result.rows, vec![vec![Str("name"), Int(17)]]);
+        let query = "select tld, count(1) from default where tld = \"gov\" OR tld = \"org\";";
+        let result = run_query(&locustdb, query);
+        assert_eq!(result.rows, vec![vec![Str("gov"), Int(5)], vec![Str("org"), Int(8)]]);
+    }
+}
+
+#[cfg(feature = "enable_http")]
+#[test]
+fn test_http_query() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    let _ = env_logger::try_init();
+    let locustdb = Arc::new(LocustDB::memory_only());
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/edge_cases.csv", "default")
+            .with_partition_size(3)));
+    let server = HttpServer::start(locustdb.clone(), "127.0.0.1:0").unwrap();
+
+    let post = |query: &str| {
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        write!(stream,
+               "POST /query HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+               query.len(), query).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+
+    let response = post("select enum, count(1) from default;");
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("{\"colnames\":[\"enum\",\"count_0\"],\"rows\":[[\"aa\",5],[\"bb\",3],[\"cc\",2]],"),
+            "{}", response);
+
+    let response = post("select from where;");
+    assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+    assert!(response.contains("\"error\":"), "{}", response);
+}
+
+#[test]
+fn test_csv_and_json_output() {
+    let locustdb = load_edge_cases();
+    let output = run_query(&locustdb, "select enum, count(1) from default;");
+    assert_eq!(output.to_csv(), "enum,count_0\naa,5\nbb,3\ncc,2\n");
+    assert!(output.to_json().starts_with("{\"colnames\":[\"enum\",\"count_0\"],\"rows\":[[\"aa\",5],[\"bb\",3],[\"cc\",2]],"),
+            "{}", output.to_json());
+}
+
+#[test]
+fn test_addition_saturates() {
+    test_query_ec(
+        "SELECT negative, negative + 9223372036854775000 FROM default ORDER BY negative DESC LIMIT 3;",
+        &[vec![Int(4031), Int(std::i64::MAX)],
+            vec![Int(4010), Int(std::i64::MAX)],
+            vec![Int(39), Int(9223372036854775039)]],
+    );
+    test_query_ec(
+        "SELECT negative + 100 FROM default WHERE negative < -150;",
+        &[vec![Int(-99)]],
+    );
+}
+
+#[test]
+fn test_greater_than() {
+    test_query_ec("SELECT negative FROM default WHERE negative > 1000 ORDER BY negative;",
+                  &[vec![Int(4010)], vec![Int(4031)]]);
+    test_query_ec("SELECT u8_offset_encoded FROM default WHERE u8_offset_encoded > 500;",
+                  &[vec![Int(511)]]);
+    test_query_ec("SELECT count(0) FROM default WHERE non_dense_ints > 2;",
+                  &[vec![Int(3)]]);
+}
+
+#[test]
+fn test_division_by_zero() {
+    let locustdb = load_edge_cases();
+    let rows = run_query(&locustdb, "SELECT negative / 0, negative % 0 FROM default;").rows;
+    assert!(!rows.is_empty());
+    for row in rows {
+        assert_eq!(row, vec![Value::Null, Value::Null]);
+    }
+}
+
+#[test]
+fn test_modulo_and_division() {
+    use Value::*;
+    let locustdb = load_csv_str("id,a,b\n7,7,2\n12,-7,2\n25,9,0\n-13,10,-3\n", 100);
+    let result = run_query(&locustdb, "SELECT id % 10, a / b, a % b FROM default;");
+    assert_eq!(result.rows, vec![
+        vec![Int(7), Int(3), Int(1)],
+        vec![Int(2), Int(-3), Int(-1)],
+        vec![Int(5), Null, Null],
+        vec![Int(-3), Int(-3), Int(1)],
+    ]);
+}
+
+#[test]
+fn test_constant_divided_by_column() {
+    use Value::*;
+    test_query_csv(
+        "id,x\n1,3\n2,0\n3,-4\n4,7\n",
+        "SELECT id, 10 / x, 10 % x FROM default ORDER BY id;",
+        &[vec![Int(1), Int(3), Int(1)],
+            vec![Int(2), Null, Null],
+            vec![Int(3), Int(-2), Int(2)],
+            vec![Int(4), Int(1), Int(3)]],
+    );
+}
+
+#[test]
+fn test_division_by_constant_expression() {
+    use Value::*;
+    test_query_csv(
+        "id,x\n1,3\n2,0\n3,-4\n4,7\n",
+        "SELECT id, x / (1 - 1), x % (2 * 0), x / (4 - 2) FROM default ORDER BY id;",
+        &[vec![Int(1), Null, Null, Int(1)],
+            vec![Int(2), Null, Null, Int(0)],
+            vec![Int(3), Null, Null, Int(-2)],
+            vec![Int(4), Null, Null, Int(3)]],
+    );
+}
+
+#[test]
+fn test_sum_exceeding_i64() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.gen_table(locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 4,
+        partition_size: 8,
+        columns: vec![
+            ("big".to_string(),
+             locustdb::colgen::int_weighted(vec![1 << 62], vec![1.0])),
+            ("large".to_string(),
+             locustdb::colgen::int_weighted(vec![(1 << 58) - 1], vec![1.0])),
+            ("small".to_string(),
+             locustdb::colgen::int_weighted(vec![3], vec![1.0])),
+        ],
+    }));
+    // 32 * 2^62 = 2^67
+    let result = block_on(locustdb.run_query("SELECT sum(big), sum(small) FROM test;", false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![vec![Value::BigInt(1 << 67), Int(96)]]);
+    assert_eq!(format!("{}", Value::BigInt(1 << 67)), "147573952589676412928");
+    // 32 * (2^58 - 1) = 2^63 - 32
+    let result = block_on(locustdb.run_query("SELECT sum(large), sum(small) FROM test;", false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![vec![Int(9_223_372_036_854_775_776), Int(96)]]);
+}
+
+#[test]
+fn test_ingest_errors() {
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::Write;
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("ingest_errors").unwrap();
+    let write_file = |name: &str, content: &[u8]| {
+        let path = tmp_dir.path().join(name);
+        File::create(&path).unwrap().write_all(content).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+    let load = |opts: LoadOptions| {
+        let locustdb = LocustDB::memory_only();
+        block_on(locustdb.load_csv(opts)).unwrap()
+    };
+
+    match load(LoadOptions::new("test_data/does_not_exist.csv", "default")) {
+        Err(IngestError::Io(_)) => {}
+        result => panic!("Expected IngestError::Io, got {:?}", result),
+    }
+
+    let ragged = write_file("ragged.csv", b"a,b\n1,2\n3\n");
+    match load(LoadOptions::new(&ragged, "default")) {
+        Err(IngestError::Parse { row: 3, col: 1 }) => {}
+        result => panic!("Expected IngestError::Parse, got {:?}", result),
+    }
+
+    let invalid_utf8 = write_file("invalid_utf8.csv", b"a,b\n1,\xff\n");
+    match load(LoadOptions::new(&invalid_utf8, "default")) {
+        Err(IngestError::Csv(_)) => {}
+        result => panic!("Expected IngestError::Csv, got {:?}", result),
+    }
+
+    let valid = write_file("valid.csv", b"a,b\n1,2\n");
+    match load(LoadOptions::new(&valid, "default").with_column_names(vec!["a".to_string()])) {
+        Err(IngestError::Schema(_)) => {}
+        result => panic!("Expected IngestError::Schema, got {:?}", result),
+    }
+    let mut float_type = HashMap::new();
+    float_type.insert("a".to_string(), BasicType::Float);
+    match load(LoadOptions::new(&valid, "default").with_column_types(float_type)) {
+        Err(IngestError::Unsupported(_)) => {}
+        result => panic!("Expected IngestError::Unsupported, got {:?}", result),
+    }
+    assert!(load(LoadOptions::new(&valid, "default")).is_ok());
+}
+
+#[test]
+fn test_unknown_column() {
+    let locustdb = load_edge_cases();
+    let result = block_on(locustdb.run_query("SELECT enum, nonexistent FROM default;", false, vec![])).unwrap();
+    match result.0 {
+        Err(QueryError::UnknownColumn(ref col)) => assert_eq!(col, "nonexistent"),
+        Err(other) => panic!("Expected unknown column error, got {:?}", other),
+        Ok(_) => panic!("Expected unknown column error"),
+    }
+    let result = block_on(locustdb.run_query("SELECT enum FROM default WHERE nonexistent = 1;", false, vec![])).unwrap();
+    assert_eq!(result.0.err().unwrap().to_string(), "Unknown column: nonexistent");
+    let result = block_on(locustdb.run_query("SELECT * FROM default ORDER BY enum, nonexistent;", false, vec![])).unwrap();
+    assert_eq!(result.0.err().unwrap().to_string(), "Unknown column: nonexistent");
+}
+
+#[test]
+fn test_sum_over_string_column() {
+    let locustdb = load_edge_cases();
+    let result = block_on(locustdb.run_query("SELECT sum(enum) FROM default;", false, vec![])).unwrap();
+    match result.0 {
+        Err(QueryError::TypeMismatch(ref msg)) => assert!(msg.contains("Sum"), "{}", msg),
+        Err(other) => panic!("Expected type mismatch error, got {:?}", other),
+        Ok(_) => panic!("Expected type mismatch error"),
+    }
+}
+
+#[test]
+fn test_type_check() {
+    let locustdb = load_edge_cases();
+    for query in &[
+        "SELECT enum FROM default WHERE enum < 3;",
+        "SELECT enum FROM default WHERE negative = 'abc';",
+        "SELECT enum FROM default WHERE enum = 'aa' AND negative;",
+        "SELECT enum + 1 FROM default;",
+        "SELECT to_year(string_packed) FROM default;",
+        "SELECT enum, max(string_packed) FROM default;",
+        "SELECT enum FROM default WHERE negative;",
+    ] {
+        let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+        match result.0 {
+            Err(QueryError::TypeMismatch(_)) => {}
+            Err(other) => panic!("Expected type mismatch error for {}, got {:?}", query, other),
+            Ok(_) => panic!("Expected type mismatch error for {}", query),
+        }
+    }
+    let result = block_on(locustdb.run_query("SELECT enum FROM default WHERE enum = 'aa' AND negative < 0;", false, vec![])).unwrap();
+    assert!(result.0.is_ok());
+}
+
+#[test]
+fn test_boolean_column() {
+    let csv = "id,flag\n1,true\n2,false\n3,TRUE\n4,false\n5,true\n";
+    test_query_csv(csv, "SELECT id FROM default WHERE flag ORDER BY id;", &[vec![Int(1)], vec![Int(3)], vec![Int(5)]]);
+    test_query_csv(csv, "SELECT count(0) FROM default WHERE flag AND id < 5;", &[vec![Int(2)]]);
+}
+
+#[test]
+fn test_high_cardinality_grouping() {
+    let mut csv = "key,value\n".to_string();
+    for round in 0..3 {
+        for i in 0..5000 {
+            csv.push_str(&format!("{},{}\n", i * 100_003, i + round));
+        }
+    }
+    let locustdb = load_csv_str(&csv, 1000);
+    let result = run_query(&locustdb, "SELECT key, count(0), sum(value) FROM default LIMIT 10000;");
+    assert_eq!(result.rows.len(), 5000);
+    for row in &result.rows {
+        match row[..] {
+            [Int(key), Int(count), Int(sum)] => {
+                assert_eq!(count, 3);
+                assert_eq!(sum, 3 * (key / 100_003) + 3);
+            }
+            _ => panic!("Unexpected row {:?}", row),
+        }
+    }
+}
+
+#[test]
+fn test_grouped_count_from_hashmap_grouping() {
+    let mut csv = "key,value\n".to_string();
+    for i in 0..2000 {
+        for j in 0..(i % 4 + 1) {
+            csv.push_str(&format!("{},{}\n", i * 100_003, j));
+        }
+    }
+    let locustdb = load_csv_str(&csv, 1000);
+    let result = block_on(locustdb.run_query("SELECT key, count(0) FROM default WHERE value > 0 LIMIT 10000;", true, vec![]))
+        .unwrap().0.unwrap();
+    assert_eq!(result.rows.len(), 1500);
+    for row in &result.rows {
+        match row[..] {
+            [Int(key), Int(count)] => assert_eq!(count, (key / 100_003) % 4),
+            _ => panic!("Unexpected row {:?}", row),
+        }
+    }
+    // Counts are tallied by the grouping operator instead of a separate count operator
+    for plan in result.query_plans.keys() {
+        assert!(plan.contains("hashmap_grouping"), "{}", plan);
+        assert!(!plan.contains("+= 1"), "{}", plan);
+    }
+}
+
+#[test]
+fn test_selective_filter_on_wide_table() {
+    let mut csv = (0..10).map(|c| format!("c{}", c)).collect::<Vec<_>>().join(",");
+    csv.push('\n');
+    for row in 0..5000 {
+        csv.push_str(&(0..10).map(|c| (row * 10 + c).to_string()).collect::<Vec<_>>().join(","));
+        csv.push('\n');
+    }
+    let locustdb = load_csv_str(&csv, 1000);
+    let query = "SELECT c0, c3, c9 FROM default WHERE c5 = 12345 OR c1 = 40001 ORDER BY c0;";
+    let result = block_on(locustdb.run_query(query, true, vec![])).unwrap().0.unwrap();
+    assert_eq!(result.rows, vec![
+        vec![Int(12340), Int(12343), Int(12349)],
+        vec![Int(40000), Int(40003), Int(40009)],
+    ]);
+    // Columns are gathered at the selected positions rather than filtering all rows of each column
+    for plan in result.query_plans.keys() {
+        assert!(plan.contains("filter_indices"), "{}", plan);
+        assert!(!plan.contains("filtered"), "{}", plan);
+    }
+}
+
+#[test]
+fn test_save_and_load() {
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("save_and_load").unwrap();
+    let path = tmp_dir.path().join("default.locustdb");
+    let path = path.to_str().unwrap();
+    let queries = [
+        "select passenger_count, to_year(pickup_datetime), trip_distance / 1000, count(0) from default;",
+        "select pickup_ntaname, sum(total_amount) from default where passenger_count < 3 limit 100;",
+        "select vendor_id, cab_type, count(0) from default;",
+    ];
+    let original = LocustDB::memory_only();
+    block_on(original.load_csv(
+        nyc_taxi_data::ingest_reduced_file("test_data/nyc-taxi.csv.gz", "default")
+            .with_partition_size(999))).unwrap().unwrap();
+    original.save(path).unwrap();
+
+    let loaded = LocustDB::memory_only();
+    loaded.load(path).unwrap();
+    for query in &queries {
+        let expected = run_query(&original, query).rows;
+        let actual = run_query(&loaded, query).rows;
+        assert_eq!(actual, expected, "{}", query);
+    }
+}
+
+#[test]
+fn test_wal_recovery() {
+    use std::fs;
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("wal_recovery").unwrap();
+    let mut opts = Options::default();
+    opts.wal_path = Some(tmp_dir.path().join("wal").to_str().unwrap().to_string());
+    let checkpoint = tmp_dir.path().join("checkpoint");
+    let checkpoint = checkpoint.to_str().unwrap();
+    let query = "select passenger_count, count(0), sum(total_amount) from default;";
+    let expected = {
+        let locustdb = LocustDB::with_wal(&opts).unwrap();
+        block_on(locustdb.load_csv(
+            nyc_taxi_data::ingest_reduced_file("test_data/nyc-taxi.csv.gz", "default")
+                .with_partition_size(999))).unwrap().unwrap();
+        run_query(&locustdb, query).rows
+        // Dropped without saving
+    };
+
+    let locustdb = LocustDB::with_wal(&opts).unwrap();
+    let recovered = run_query(&locustdb, query).rows;
+    assert_eq!(recovered, expected);
+
+    // Saving a checkpoint truncates the log
+    locustdb.save(checkpoint).unwrap();
+    assert_eq!(fs::metadata(opts.wal_path.as_ref().unwrap()).unwrap().len(), 0);
+    assert!(fs::metadata(format!("{}.tmp", checkpoint)).is_err());
+    let restarted = LocustDB::with_wal(&opts).unwrap();
+    restarted.load(checkpoint).unwrap();
+    let reloaded = run_query(&restarted, query).rows;
+    assert_eq!(reloaded, expected);
+}
+
+#[test]
+fn test_wal_truncated_record() {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use tempdir::TempDir;
+    let tmp_dir = TempDir::new("wal_truncated_record").unwrap();
+    let mut opts = Options::default();
+    opts.wal_path = Some(tmp_dir.path().join("wal").to_str().unwrap().to_string());
+    let query = "SELECT count(0), sum(n) FROM default;";
+    let options = || LoadOptions::new("stdin", "default").with_partition_size(2);
+    {
+        let locustdb = LocustDB::with_wal(&opts).unwrap();
+        locustdb.load_csv_stream("n\n1\n2\n3\n".as_bytes(), options()).unwrap();
+    }
+    // Length prefix of a record that was only partially written
+    let mut wal = OpenOptions::new().append(true).open(opts.wal_path.as_ref().unwrap()).unwrap();
+    wal.write_all(&[0, 1, 0, 0, 0, 0, 0, 0, 7, 7]).unwrap();
+    drop(wal);
+
+    {
+        let locustdb = LocustDB::with_wal(&opts).unwrap();
+        assert_eq!(run_query(&locustdb, query).rows, vec![vec![Int(3), Int(6)]]);
+        locustdb.load_csv_stream("n\n4\n".as_bytes(), options()).unwrap();
+    }
+
+    // Partitions appended after the truncated record are restored as well
+    let locustdb = LocustDB::with_wal(&opts).unwrap();
+    assert_eq!(run_query(&locustdb, query).rows, vec![vec![Int(4), Int(10)]]);
+}
+
+#[test]
+fn test_wal_open_error() {
+    use tempdir::TempDir;
+    let tmp_dir = TempDir::new("wal_open_error").unwrap();
+    let mut opts = Options::default();
+    opts.wal_path = Some(tmp_dir.path().join("missing_dir").join("wal").to_str().unwrap().to_string());
+    assert!(LocustDB::with_wal(&opts).is_err());
+}
+
+#[test]
+fn test_time_range_skips_partitions() {
+    let mut csv = "ts,value\n".to_string();
+    for ts in 0..1000 {
+        csv.push_str(&format!("{},{}\n", 1_500_000_000 + ts, ts % 7));
+    }
+    let locustdb = load_csv_str(&csv, 100);
+    let query = "SELECT count(0) FROM default WHERE (ts > 1500000249) AND (ts < 1500000450);";
+    let result = block_on(locustdb.run_query_in_time_range(query, false, vec![], "ts", 1_500_000_250, 1_500_000_449))
+        .unwrap().0.unwrap();
+    assert_eq!(result.rows, vec![vec![Int(200)]]);
+    // Only the three partitions overlapping the window are scanned
+    assert_eq!(result.stats.rows_scanned, 300);
+
+    let result = run_query(&locustdb, query);
+    assert_eq!(result.rows, vec![vec![Int(200)]]);
+    assert_eq!(result.stats.rows_scanned, 1000);
+}
+
+#[test]
+fn test_limit_and_offset() {
+    use Value::*;
+    let mut csv = "id\n".to_string();
+    for id in 0..10 {
+        csv.push_str(&format!("{}\n", id));
+    }
+    let locustdb = load_csv_str(&csv, 4);
+    let query = |q: &str| run_query(&locustdb, q).rows;
+    assert_eq!(query("SELECT id FROM default ORDER BY id OFFSET 7;"), vec![vec![Int(7)], vec![Int(8)], vec![Int(9)]]);
+    assert_eq!(query("SELECT id FROM default ORDER BY id LIMIT 2 OFFSET 3;"), vec![vec![Int(3)], vec![Int(4)]]);
+    assert_eq!(query("SELECT id FROM default OFFSET 4;").len(), 6);
+    assert!(query("SELECT id FROM default LIMIT 0;").is_empty());
+    assert!(query("SELECT id FROM default ORDER BY id LIMIT 0;").is_empty());
+    assert!(query("SELECT id, count(0) FROM default LIMIT 0;").is_empty());
+}
+
+#[test]
+fn test_offset_past_end() {
+    let locustdb = load_edge_cases();
+    for query in &[
+        "SELECT negative FROM default OFFSET 18446744073709551615;",
+        "SELECT negative FROM default LIMIT 18446744073709551615 OFFSET 18446744073709551615;",
+        "SELECT negative FROM default ORDER BY negative LIMIT 5 OFFSET 18446744073709551615;",
+        "SELECT enum, count(0) FROM default LIMIT 10 OFFSET 18446744073709551615;",
+        "SELECT negative FROM default OFFSET 1000;",
+        "SELECT negative FROM default ORDER BY negative LIMIT 3 OFFSET 1000;",
+        "SELECT enum, count(0) FROM default OFFSET 1000;",
+    ] {
+        let result = run_query(&locustdb, query);
+        assert!(result.rows.is_empty(), "{} returned {:?}", query, result.rows);
+    }
+}
+
+#[test]
+fn test_column_missing_from_older_partitions() {
+    use Value::*;
+    let locustdb = LocustDB::memory_only();
+    locustdb.load_csv_stream("id\n1\n2\n".as_bytes(), LoadOptions::new("stdin", "default")).unwrap();
+    locustdb.load_csv_stream("id,extra\n3,x\n4,y\n".as_bytes(), LoadOptions::new("stdin", "default")).unwrap();
+    let result = block_on(locustdb.run_query("SELECT id, extra FROM default ORDER BY id;", false, vec![]))
+        .unwrap().0.unwrap();
+    assert_eq!(result.rows, vec![
+        vec![Int(1), Null],
+        vec![Int(2), Null],
+        vec![Int(3), Str("x".to_string())],
+        vec![Int(4), Str("y".to_string())],
+    ]);
+    let result = block_on(locustdb.run_query("SELECT extra FROM default;", false, vec![]))
+        .unwrap().0.unwrap();
+    assert_eq!(result.rows.len(), 4);
+}
+
+#[test]
+fn test_select_distinct() {
+    use Value::*;
+    let csv = "city,country,population\nParis,FR,1\nBerlin,DE,2\nParis,FR,3\nLyon,FR,4\nBerlin,DE,5\nParis,FR,6\n";
+    let locustdb = load_csv_str(csv, 4);
+    let query = |q: &str| {
+        let mut rows = run_query(&locustdb, q).rows;
+        rows.sort();
+        rows
+    };
+    assert_eq!(query("SELECT DISTINCT city, country FROM default;"), vec![
+        vec![Str("Berlin".to_string()), Str("DE".to_string())],
+        vec![Str("Lyon".to_string()), Str("FR".to_string())],
+        vec![Str("Paris".to_string()), Str("FR".to_string())],
+    ]);
+    assert_eq!(query("SELECT DISTINCT country FROM default WHERE population > 1;"), vec![
+        vec![Str("DE".to_string())],
+        vec![Str("FR".to_string())],
+    ]);
+    // Duplicates are removed before the limit is applied
+    assert_eq!(query("SELECT DISTINCT country FROM default LIMIT 2;").len(), 2);
+    assert_eq!(query("SELECT country FROM default LIMIT 2;").len(), 2);
+}
+
+#[test]
+fn test_group_by_expression() {
+    use Value::*;
+    let mut csv = "id,value\n".to_string();
+    for id in 0..1000 {
+        csv.push_str(&format!("{},{}\n", id - 300, id % 7));
+    }
+    let locustdb = load_csv_str(&csv, 256);
+    let query = |q: &str| {
+        let mut rows = run_query(&locustdb, q).rows;
+        rows.sort();
+        rows
+    };
+
+    let expected = (0..10).map(|bucket| vec![Int(bucket), Int(70)]).collect::<Vec<_>>();
+    assert_eq!(query("SELECT id % 10, count(0) FROM default WHERE NOT (id < 0) GROUP BY id % 10;"), expected);
+    // Negative ids have negative remainders
+    let expected = (-9..10)
+        .map(|bucket| vec![Int(bucket), Int(if bucket < 0 { 30 } else if bucket == 0 { 100 } else { 70 })])
+        .collect::<Vec<_>>();
+    assert_eq!(query("SELECT id % 10, count(0) FROM default;"), expected);
+    let rows = query("SELECT id / 100, value, count(0) FROM default GROUP BY id / 100, value;");
+    assert_eq!(rows.iter().map(|row| match row[2] { Int(count) => count, _ => 0 }).sum::<i64>(), 1000);
+    // -300 is the only id in bucket -3, all other buckets contain every value
+    assert_eq!(rows.len(), 1 + 9 * 7);
+}
+
+#[test]
+fn test_typed_row_accessors() {
+    let locustdb = load_csv_str("name,age\nalice,31\nbob,\n", 2);
+    let output = run_query(&locustdb, "SELECT name, age FROM default ORDER BY name;");
+    assert_eq!(output.column_index("age"), Some(1));
+    assert_eq!(output.column_index("height"), None);
+    assert_eq!(output.get_str(0, "name"), Some("alice"));
+    assert_eq!(output.get_i64(0, "age"), Some(31));
+    // Null
+    assert_eq!(output.get_i64(1, "age"), None);
+    // Type mismatch
+    assert_eq!(output.get_i64(0, "name"), None);
+    assert_eq!(output.get_str(0, "age"), None);
+    // Out of bounds
+    assert_eq!(output.get_str(2, "name"), None);
+    assert_eq!(output.get_str(0, "height"), None);
+}
+
+#[test]
+fn test_global_aggregate_single_row() {
+    use Value::*;
+    let mut csv = "x\n".to_string();
+    for x in 1..101 {
+        csv.push_str(&format!("{}\n", x));
+    }
+    let locustdb = load_csv_str(&csv, 16);
+    let query = |q: &str| run_query(&locustdb, q).rows;
+    assert_eq!(query("SELECT sum(x) FROM default;"), vec![vec![Int(5050)]]);
+    assert_eq!(query("SELECT count(0) FROM default;"), vec![vec![Int(100)]]);
+    assert_eq!(query("SELECT count(0), sum(x), min(x), max(x) FROM default WHERE x < 11;"),
+               vec![vec![Int(10), Int(55), Int(1), Int(10)]]);
+    // No rows pass the filter
+    assert_eq!(query("SELECT count(0), sum(x), max(x) FROM default WHERE x > 1000;"),
+               vec![vec![Int(0), Null, Null]]);
+    assert!(query("SELECT count(0) FROM default WHERE x > 1000 LIMIT 0;").is_empty());
+}
+
+#[test]
+fn test_empty_table() {
+    use Value::*;
+    let locustdb = LocustDB::memory_only();
+    locustdb.load_csv_stream("id,name\n".as_bytes(), LoadOptions::new("stdin", "empty")).unwrap();
+    let query = |q: &str| run_query(&locustdb, q);
+
+    let output = query("SELECT id, name FROM empty;");
+    assert_eq!(output.colnames, vec!["id".to_string(), "name".to_string()]);
+    assert!(output.rows.is_empty());
+    let output = query("SELECT name, count(0) FROM empty GROUP BY name;");
+    assert_eq!(output.colnames, vec!["name".to_string(), "count_0".to_string()]);
+    assert!(output.rows.is_empty());
+    assert_eq!(query("SELECT count(0), sum(id) FROM empty;").rows, vec![vec![Int(0), Null]]);
+}
+
+#[test]
+fn test_zero_match_filter() {
+    use Value::*;
+    let locustdb = load_edge_cases();
+    let query = |q: &str| run_query(&locustdb, q);
+
+    let output = query("SELECT enum, negative FROM default WHERE negative > 100000;");
+    assert_eq!(output.colnames, vec!["enum".to_string(), "negative".to_string()]);
+    assert!(output.rows.is_empty());
+    let output = query("SELECT enum, negative FROM default WHERE negative > 100000 ORDER BY negative;");
+    assert!(output.rows.is_empty());
+    let output = query("SELECT enum, count(0) FROM default WHERE negative > 100000;");
+    assert_eq!(output.colnames, vec!["enum".to_string(), "count_0".to_string()]);
+    assert!(output.rows.is_empty());
+    assert_eq!(query("SELECT count(0), sum(negative) FROM default WHERE negative > 100000;").rows,
+               vec![vec![Int(0), Null]]);
+}
+
+#[test]
+fn test_tablesample() {
+    use Value::*;
+    let csv = (0..10_000).map(|i| format!("{}\n", i % 10)).collect::<String>();
+    let locustdb = load_csv_str(&format!("x\n{}", csv), 10);
+    let query = |q: &str| run_query(&locustdb, q);
+
+    assert_eq!(query("SELECT count(0), sum(x) FROM default;").rows, vec![vec![Int(10_000), Int(45_000)]]);
+    let sampled = query("SELECT count(0), sum(x) FROM default TABLESAMPLE 20 PERCENT REPEATABLE (42);");
+    assert!(sampled.stats.rows_scanned < 10_000);
+    let count = sampled.get_i64(0, "count_0").unwrap();
+    assert!(count > 7_000 && count < 13_000, "count {} not within tolerance", count);
+    let sum = sampled.get_i64(0, "sum_1").unwrap();
+    assert!(sum > 31_500 && sum < 58_500, "sum {} not within tolerance", sum);
+    for _ in 0..3 {
+        let repeated = query("SELECT count(0), sum(x) FROM default TABLESAMPLE 20 PERCENT REPEATABLE (42);");
+        assert_eq!(repeated.rows, sampled.rows);
+    }
+}
+
+#[test]
+fn test_like() {
+    test_query_ec(
+        "SELECT enum, string_packed FROM default WHERE string_packed LIKE 'ax_';",
+        &[vec![Str("aa"), Str("axz")],
+          vec![Str("bb"), Str("axy")]],
+    );
+    test_query_ec(
+        "SELECT count(0) FROM default WHERE string_packed LIKE '%s%';",
+        &[vec![Int(2)]],
+    );
+    test_query_ec(
+        "SELECT enum, count(0) FROM default WHERE enum LIKE 'b%';",
+        &[vec![Str("bb"), Int(3)]],
+    );
+}
+
+#[test]
+fn test_like_prefix_matches_naive() {
+    let words = ["ab", "abc", "abd", "b", "ba", "c"];
+    let csv = (0..5000).map(|i| format!("{}{}\n", words[i % words.len()], i % 7)).collect::<String>();
+    let locustdb = load_csv_str(&format!("word\n{}", csv), 1000);
+    for prefix in &["", "a", "ab", "abc", "abc3", "b", "ba", "c6", "d", "zz"] {
+        // `LIKE 'x%'` is answered from the dictionary, `LIKE 'x%%'` matches every string
+        let optimized = format!("SELECT word, count(0) FROM default WHERE word LIKE '{}%';", prefix);
+        let naive = format!("SELECT word, count(0) FROM default WHERE word LIKE '{}%%';", prefix);
+        let optimized = block_on(locustdb.run_query(&optimized, true, vec![])).unwrap().0.unwrap();
+        let naive = block_on(locustdb.run_query(&naive, true, vec![])).unwrap().0.unwrap();
+        assert_eq!(optimized.rows, naive.rows);
+        assert!(optimized.query_plans.keys().all(|plan| plan.contains("dict_prefix_match")));
+        assert!(naive.query_plans.keys().all(|plan| !plan.contains("dict_prefix_match")));
+    }
+}
+
+#[test]
+fn test_glob() {
+    let locustdb = load_csv_str("name\nabc\nabd\nax\na%c\nbcd\nb_\nc\n", 3);
+    let names = |condition: &str| {
+        let query = format!("SELECT name FROM default WHERE {} ORDER BY name;", condition);
+        run_query(&locustdb, &query).rows
+            .into_iter().map(|row| format!("{:?}", row[0])).collect::<Vec<_>>().join(",")
+    };
+    assert_eq!(names("glob(name, 'a*')"), names("name LIKE 'a%'"));
+    assert_eq!(names("glob(name, 'ab*')"), "Str(\"abc\"),Str(\"abd\")");
+    assert_eq!(names("glob(name, '?c?')"), "Str(\"bcd\")");
+    assert_eq!(names("glob(name, '?')"), "Str(\"c\")");
+    assert_eq!(names("glob(name, '[a-c]')"), "Str(\"c\")");
+    assert_eq!(names("glob(name, 'ab[a-c]')"), "Str(\"abc\")");
+    assert_eq!(names("glob(name, 'ab[!a-c]')"), "Str(\"abd\")");
+    // `%` and `_` are literals in glob patterns
+    assert_eq!(names("glob(name, 'a%c')"), "Str(\"a%c\")");
+    assert_eq!(names("glob(name, 'b_')"), "Str(\"b_\")");
+    assert_eq!(names("name LIKE 'b_'"), "Str(\"b_\")");
+    assert_eq!(names("name LIKE 'a_c'"), "Str(\"a%c\"),Str(\"abc\")");
+}
+
+#[test]
+fn test_limit_per_group() {
+    let csv = "city,name,score\n\
+               Berlin,a,5\nParis,b,9\nBerlin,c,7\nRome,d,1\nParis,e,3\nBerlin,f,8\n\
+               Paris,g,6\nBerlin,h,2\nParis,i,4\nRome,j,2\n";
+    test_query_csv(csv, "SELECT city, name, score FROM default ORDER BY score DESC LIMIT 2 PER city;", &[
+        vec![Str("Paris"), Str("b"), Int(9)],
+        vec![Str("Berlin"), Str("f"), Int(8)],
+        vec![Str("Berlin"), Str("c"), Int(7)],
+        vec![Str("Paris"), Str("g"), Int(6)],
+        vec![Str("Rome"), Str("j"), Int(2)],
+        vec![Str("Rome"), Str("d"), Int(1)],
+    ]);
+    test_query_csv(csv, "SELECT city, name, score FROM default ORDER BY score DESC LIMIT 1 PER city OFFSET 1;", &[
+        vec![Str("Berlin"), Str("f"), Int(8)],
+        vec![Str("Rome"), Str("j"), Int(2)],
+    ]);
+}
+
+#[test]
+fn test_top_k() {
+    let key = |i: usize| match i % 10 {
+        0 | 1 | 2 => "a".to_string(),
+        3 | 4 => "b".to_string(),
+        5 => "c".to_string(),
+        _ => format!("rare{}", i % 3001),
+    };
+    let csv = (0..20_000).map(|i| format!("{},{}\n", key(i), i % 7)).collect::<String>();
+    let locustdb = load_csv_str(&format!("key,n\n{}", csv), 1000);
+
+    let result = run_query(&locustdb, "SELECT top_k(key, 3) FROM default;");
+    assert_eq!(result.colnames, vec!["key".to_string(), "approx_count".to_string()]);
+    let keys = result.rows.iter().map(|row| row[0].clone()).collect::<Vec<_>>();
+    assert_eq!(keys, vec![Str("a"), Str("b"), Str("c")]);
+    // Estimates never undercount
+    let counts = result.rows.iter().map(|row| match row[1] { Int(count) => count, _ => panic!("{:?}", row) }).collect::<Vec<_>>();
+    assert!(counts[0] >= 6000 && counts[1] >= 4000 && counts[2] >= 2000, "{:?}", counts);
+
+    let result = run_query(&locustdb, "SELECT top_k(key, 1) FROM default WHERE n < 1;");
+    assert_eq!(result.rows.len(), 1);
+    assert_eq!(result.rows[0][0], Str("a"));
+}
+
+#[test]
+fn test_column_comparison() {
+    // Columns have different ranges and therefore different encodings
+    let csv = "start,stop\n1,5\n10,3\n7,7\n200000,100\n-5,70000\n";
+    test_query_csv(csv, "SELECT start, stop FROM default WHERE start < stop ORDER BY start;",
+                   &[vec![Int(-5), Int(70000)], vec![Int(1), Int(5)]]);
+    test_query_csv(csv, "SELECT start FROM default WHERE stop > start ORDER BY start;",
+                   &[vec![Int(-5)], vec![Int(1)]]);
+    test_query_csv(csv, "SELECT start FROM default WHERE start = stop;", &[vec![Int(7)]]);
+    test_query_csv(csv, "SELECT start FROM default WHERE start <> stop ORDER BY start;",
+                   &[vec![Int(-5)], vec![Int(1)], vec![Int(10)], vec![Int(200000)]]);
+}
+
+#[test]
+fn test_cast() {
+    let locustdb = load_csv_str("id,code\n1,007\n25,12.9\n-3,abc\n40,\n", 4);
+    let rows = |query: &str| run_query(&locustdb, query).rows;
+    // Values that can not be converted are null, like constants that can not be converted
+    assert_eq!(rows("SELECT id, cast(id AS string), cast(code AS int) FROM default ORDER BY id;"), vec![
+        vec![Int(-3), Str("-3"), Null],
+        vec![Int(1), Str("1"), Int(7)],
+        vec![Int(25), Str("25"), Int(12)],
+        vec![Int(40), Str("40"), Null],
+    ]);
+    assert_eq!(rows("SELECT id FROM default WHERE cast(code as int) IS NULL ORDER BY id;"), vec![vec![Int(-3)], vec![Int(40)]]);
+    assert_eq!(rows("SELECT id, cast(id AS float), cast(code AS float) FROM default ORDER BY id;"), vec![
+        vec![Int(-3), Float(-3.0), Null],
+        vec![Int(1), Float(1.0), Float(7.0)],
+        vec![Int(25), Float(25.0), Float(12.9)],
+        vec![Int(40), Float(40.0), Null],
+    ]);
+    assert_eq!(rows("SELECT cast(cast(code AS float) AS int), cast(cast(id AS float) AS string) FROM default ORDER BY id;"), vec![
+        vec![Null, Str("-3")],
+        vec![Int(7), Str("1")],
+        vec![Int(12), Str("25")],
+        vec![Null, Str("40")],
+    ]);
+    assert_eq!(rows("SELECT id FROM default WHERE cast(code as int) > 5 ORDER BY id;"), vec![vec![Int(1)], vec![Int(25)]]);
+    // Floats are truncated
+    assert_eq!(rows("SELECT id FROM default WHERE id < cast(25.9 as int) ORDER BY id;"), vec![vec![Int(-3)], vec![Int(1)]]);
+    assert_eq!(rows("SELECT id FROM default WHERE cast(id as string) = '25';"), vec![vec![Int(25)]]);
+}
+
+#[test]
+fn test_collation() {
+    let locustdb = load_csv_str("name\némile\nZoe\nana\nÁngel\nBob\nzoë\nEve\n", 2);
+    let names = |q: &str| {
+        run_query(&locustdb, q).rows.iter().map(|row| match row[0] {
+            Value::Str(ref s) => s.to_string(),
+            ref other => panic!("Unexpected value {:?}", other),
+        }).collect::<Vec<_>>()
+    };
+
+    // Binary collation orders by code point, so all uppercase ASCII letters come first
+    assert_eq!(names("SELECT name FROM default ORDER BY name;"),
+               vec!["Bob", "Eve", "Zoe", "ana", "zoë", "Ángel", "émile"]);
+    assert_eq!(names("SELECT name FROM default ORDER BY name COLLATE BINARY;"),
+               vec!["Bob", "Eve", "Zoe", "ana", "zoë", "Ángel", "émile"]);
+    assert_eq!(names("SELECT name FROM default ORDER BY name COLLATE NOCASE;"),
+               vec!["ana", "Bob", "Eve", "Zoe", "zoë", "Ángel", "émile"]);
+    assert_eq!(names("SELECT name FROM default ORDER BY name COLLATE NOCASE DESC;"),
+               vec!["émile", "Ángel", "zoë", "Zoe", "Eve", "Bob", "ana"]);
+    assert_eq!(names("SELECT name FROM default ORDER BY name COLLATE NOCASE LIMIT 3;"),
+               vec!["ana", "Bob", "Eve"]);
+
+    assert!(names("SELECT name FROM default WHERE name = 'ÉMILE';").is_empty());
+    assert!(names("SELECT name FROM default WHERE name = 'ÉMILE' ORDER BY name COLLATE NOCASE;").is_empty());
+    assert_eq!(names("SELECT name FROM default WHERE name = 'ÉMILE' COLLATE NOCASE;"), vec!["émile"]);
+    assert_eq!(names("SELECT name FROM default WHERE name <> 'zoë' COLLATE NOCASE ORDER BY name COLLATE NOCASE;"),
+               vec!["ana", "Bob", "Eve", "Zoe", "Ángel", "émile"]);
+}
+
+#[test]
+fn test_group_by_bitpacked_u8_columns() {
+    use std::collections::BTreeMap;
+    let mut csv = "a,b\n".to_string();
+    let mut expected = BTreeMap::new();
+    for i in 0..5000i64 {
+        let (a, b) = ((i * 7) % 256, (i * 13) % 200);
+        csv.push_str(&format!("{},{}\n", a, b));
+        *expected.entry((a, b)).or_insert(0) += 1;
+    }
+    let locustdb = load_csv_str(&csv, 1000);
+
+    let query = "SELECT a, b, count(0) FROM default LIMIT 100000;";
+    let output = block_on(locustdb.run_query(query, true, vec![])).unwrap().0.unwrap();
+    // Both columns are packed into a single integer grouping key
+    assert!(output.query_plans.keys().all(|plan| plan.contains("<<")));
+    let expected = expected.into_iter()
+        .map(|((a, b), count)| vec![Int(a), Int(b), Int(count)])
+        .collect::<Vec<_>>();
+    assert_eq!(output.rows, expected);
+}
+
+#[test]
+fn test_order_by_aggregate() {
+    test_query_ec(
+        "SELECT enum, count(0) FROM default ORDER BY count(0) LIMIT 2;",
+        &[vec![Str("cc"), Int(2)],
+            vec![Str("bb"), Int(3)]],
+    );
+    test_query_ec(
+        "SELECT enum, sum(negative) FROM default ORDER BY sum(negative) DESC;",
+        &[vec![Str("bb"), Int(4025)],
+            vec![Str("cc"), Int(3880)],
+            vec![Str("aa"), Int(-348)]],
+    );
+    test_query_ec(
+        "SELECT enum, sum(negative), count(0) FROM default ORDER BY count_1 DESC LIMIT 1 OFFSET 1;",
+        &[vec![Str("bb"), Int(4025), Int(3)]],
+    );
+}
+
+#[test]
+fn test_order_by_aggregate_ties() {
+    let csv = "city,country,x\nRome,IT,3\nOslo,NO,1\nBern,CH,2\nLima,PE,2\nOslo,NO,1\nBern,CH,1\nKiev,UA,3\nLima,PE,1\nAden,YE,5\n";
+    // Rows with the same aggregate value are ordered by the group by columns in ascending order
+    test_query_csv(csv, "SELECT city, country, sum(x) FROM default ORDER BY sum(x) DESC LIMIT 5;", &[
+        vec![Str("Aden"), Str("YE"), Int(5)],
+        vec![Str("Bern"), Str("CH"), Int(3)],
+        vec![Str("Kiev"), Str("UA"), Int(3)],
+        vec![Str("Lima"), Str("PE"), Int(3)],
+        vec![Str("Rome"), Str("IT"), Int(3)],
+    ]);
+    test_query_csv(csv, "SELECT city, country, sum(x) FROM default ORDER BY sum(x) LIMIT 2;", &[
+        vec![Str("Oslo"), Str("NO"), Int(2)],
+        vec![Str("Bern"), Str("CH"), Int(3)],
+    ]);
+}
+
+#[test]
+fn test_renamed_column() {
+    let locustdb = LocustDB::memory_only();
+    let csv = "Total $ Amount,city\n10,Berlin\n32,Paris\n5,Berlin\n";
+    let opts = LoadOptions::new("stdin", "default").with_renamed_cols(&[("Total $ Amount", "total")]);
+    locustdb.load_csv_stream(csv.as_bytes(), opts).unwrap();
+    let output = run_query(&locustdb, "SELECT city, sum(total) FROM default;");
+    assert_eq!(output.rows, vec![vec![Str("Berlin"), Int(15)], vec![Str("Paris"), Int(32)]]);
+}
+
+#[test]
+fn test_quoted_column_name() {
+    let locustdb = load_csv_str("Total Amount,2020\n10,1\n32,2\n5,3\n", 2);
+    let output = run_query(&locustdb, "SELECT `2020`, `Total Amount` FROM default WHERE `Total Amount` > 7 ORDER BY `2020` DESC;");
+    assert_eq!(output.colnames, vec!["2020".to_string(), "Total Amount".to_string()]);
+    assert_eq!(output.rows, vec![vec![Int(2), Int(32)], vec![Int(1), Int(10)]]);
+}
+
+#[test]
+fn test_partition_memory_budget() {
+    let budget = 1 << 16;
+    let rows = 50_000;
+    let load = |columns: usize, opts: LoadOptions| {
+        let mut csv = (0..columns).map(|c| format!("c{}", c)).collect::<Vec<_>>().join(",");
+        csv.push('\n');
+        for i in 0..rows {
+            let row = (0..columns).map(|c| ((i * 7919 + c * 104_729) % 1_000_003).to_string()).collect::<Vec<_>>();
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        let locustdb = LocustDB::memory_only();
+        locustdb.load_csv_stream(csv.as_bytes(), opts).unwrap();
+        let stats = block_on(locustdb.table_stats()).unwrap();
+        let table = stats.into_iter().find(|t| t.name == "default").unwrap();
+        assert_eq!(table.rows, rows);
+        table
+    };
+    let auto = || LoadOptions::new("stdin", "default").with_partition_memory_budget(budget);
+
+    let narrow = load(1, auto());
+    let wide = load(8, auto());
+    for table in &[&narrow, &wide] {
+        // The first partition is a small sample used to estimate the size per row
+        let average_bytes = table.batches_bytes / table.batches;
+        assert!(average_bytes <= budget + budget / 2, "{} bytes per partition", average_bytes);
+    }
+    assert!(wide.batches > 4 * narrow.batches, "wide: {}, narrow: {}", wide.batches, narrow.batches);
+
+    let explicit = load(8, auto().with_partition_size(1000));
+    assert_eq!(explicit.batches, rows / 1000);
+}
+
+#[test]
+fn test_infer_schema() {
+    let dir = tempdir::TempDir::new("infer_schema").unwrap();
+    let path = dir.path().join("mixed.csv");
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "int,float,string,boolean,nulls,sparse\n\
+                          1,1.5,a,true,,\n\
+                          -20,3,b,false,,7\n\
+                          300,0.25,c1,true,,\n\
+                          4,-1e3,d,false,,\n").unwrap();
+
+    let inferred = infer_schema(&LoadOptions::new(path, "default"), 100).unwrap();
+    let summary = inferred.iter()
+        .map(|column| (column.name.as_str(), column.basic_type, column.null_fraction))
+        .collect::<Vec<_>>();
+    assert_eq!(summary, vec![
+        ("int", BasicType::Integer, 0.0),
+        ("float", BasicType::Integer, 0.0),
+        ("string", BasicType::String, 0.0),
+        ("boolean", BasicType::Boolean, 0.0),
+        ("nulls", BasicType::Null, 1.0),
+        ("sparse", BasicType::Integer, 0.75),
+    ]);
+
+    let locustdb = LocustDB::memory_only();
+    block_on(locustdb.load_csv(LoadOptions::new(path, "default"))).unwrap().unwrap();
+    let mut schema = block_on(locustdb.schema("default")).unwrap().unwrap();
+    let mut expected = inferred.into_iter().map(|column| (column.name, column.basic_type)).collect::<Vec<_>>();
+    schema.sort_by(|a, b| a.0.cmp(&b.0));
+    expected.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(schema, expected);
+}
+
+#[test]
+fn test_duplicate_headers() {
+    let csv = "id,name,id,id_1,id\n1,a,2,3,4\n5,b,6,7,8\n";
+    let locustdb = load_csv_str(csv, 2);
+    let result = run_query(&locustdb, "SELECT id, id_1, id_2, name FROM default ORDER BY id;");
+    assert_eq!(result.rows, vec![
+        vec![Int(1), Int(3), Int(2), Str("a")],
+        vec![Int(5), Int(7), Int(6), Str("b")],
+    ]);
+    let mut schema = block_on(locustdb.schema("default")).unwrap().unwrap();
+    schema.sort_by(|a, b| a.0.cmp(&b.0));
+    let colnames = schema.into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+    assert_eq!(colnames, vec!["id", "id_1", "id_2", "id_3", "name"]);
+
+    let opts = LoadOptions::new("stdin", "default").with_duplicate_cols(DuplicateColumns::Error);
+    match LocustDB::memory_only().load_csv_stream(csv.as_bytes(), opts) {
+        Err(IngestError::Schema(ref msg)) if msg.contains("`id`") => {}
+        other => panic!("Expected schema error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_schema_file() {
+    let dir = tempdir::TempDir::new("schema_file").unwrap();
+    let data = dir.path().join("data.csv");
+    let data = data.to_str().unwrap();
+    let schema = dir.path().join("data.schema");
+    let schema = schema.to_str().unwrap();
+    std::fs::write(data, "01234,Berlin,1\n98765,Paris,0\n00501,Rome,1\n").unwrap();
+    std::fs::write(schema, "# zip codes\nzip: string\ncity\n\nflag:boolean\n").unwrap();
+
+    let locustdb = LocustDB::memory_only();
+    let opts = LoadOptions::new(data, "default")
+        .with_column_names(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+        .with_schema_file(schema).unwrap();
+    block_on(locustdb.load_csv(opts)).unwrap().unwrap();
+    let result = block_on(locustdb.run_query("SELECT zip, city FROM default WHERE flag ORDER BY zip;", false, vec![]))
+        .unwrap().0.unwrap();
+    assert_eq!(result.rows, vec![vec![Str("00501"), Str("Rome")], vec![Str("01234"), Str("Berlin")]]);
+    let mut schema_types = block_on(locustdb.schema("default")).unwrap().unwrap();
+    schema_types.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(schema_types, vec![
+        ("city".to_string(), BasicType::String),
+        ("flag".to_string(), BasicType::Boolean),
+        ("zip".to_string(), BasicType::String),
+    ]);
+
+    std::fs::write(schema, "zip:date\n").unwrap();
+    match LoadOptions::new(data, "default").with_schema_file(schema) {
+        Err(IngestError::Schema(_)) => {}
+        Err(err) => panic!("Expected schema error, got {:?}", err),
+        Ok(_) => panic!("Expected schema error"),
+    }
+}
+
+#[test]
+fn test_resume_ingest() {
+    use std::collections::HashMap;
+    let valid = (1..11).map(|i| format!("{}\n", i)).collect::<String>();
+    // The connection drops in the middle of the fourth partition and returns garbage
+    let corrupted = format!("{}x\n", (1..8).map(|i| format!("{}\n", i)).collect::<String>());
+    let options = || {
+        let mut column_types = HashMap::new();
+        column_types.insert("n".to_string(), BasicType::Integer);
+        LoadOptions::new("numbers.csv", "default")
+            .with_partition_size(2)
+            .with_column_types(column_types)
+    };
+
+    let locustdb = LocustDB::memory_only();
+    match locustdb.load_csv_stream(format!("n\n{}", corrupted).as_bytes(), options()) {
+        Err(IngestError::Parse { .. }) => {}
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+    assert_eq!(locustdb.ingest_checkpoint("default", "numbers.csv"), IngestCheckpoint { rows: 6, batches: 3 });
+
+    locustdb.load_csv_stream(format!("n\n{}", valid).as_bytes(), options().with_resume(true)).unwrap();
+    assert_eq!(locustdb.ingest_checkpoint("default", "numbers.csv"), IngestCheckpoint { rows: 10, batches: 5 });
+    let query = "SELECT n, count(0) FROM default ORDER BY n LIMIT 100;";
+    let result = run_query(&locustdb, query);
+    assert_eq!(result.rows, (1..11).map(|i| vec![Int(i), Int(1)]).collect::<Vec<_>>());
+
+    // Resuming a completed ingestion does not add any rows, ingesting without resuming appends all rows again
+    locustdb.load_csv_stream(format!("n\n{}", valid).as_bytes(), options().with_resume(true)).unwrap();
+    let result = block_on(locustdb.run_query("SELECT count(0) FROM default;", false, vec![])).unwrap().0.unwrap();
+    assert_eq!(result.rows, vec![vec![Int(10)]]);
+    locustdb.load_csv_stream(format!("n\n{}", valid).as_bytes(), options()).unwrap();
+    let result = block_on(locustdb.run_query("SELECT count(0) FROM default;", false, vec![])).unwrap().0.unwrap();
+    assert_eq!(result.rows, vec![vec![Int(20)]]);
+}
+
+#[test]
+fn test_deduplicate_batches() {
+    let count = |locustdb: &LocustDB| {
+        let result = block_on(locustdb.run_query("SELECT count(0), sum(n) FROM default;", false, vec![])).unwrap().0.unwrap();
+        result.rows
+    };
+    let options = || LoadOptions::new("stdin", "default").with_partition_size(3).with_deduplicate_batches(true);
+    let locustdb = LocustDB::memory_only();
+    locustdb.load_csv_stream("n\n1\n2\n3\n4\n5\n".as_bytes(), options()).unwrap();
+    assert_eq!(count(&locustdb), vec![vec![Int(5), Int(15)]]);
+
+    // Redelivered data is skipped, batches with new rows are stored
+    locustdb.load_csv_stream("n\n1\n2\n3\n4\n5\n".as_bytes(), options()).unwrap();
+    assert_eq!(count(&locustdb), vec![vec![Int(5), Int(15)]]);
+    locustdb.load_csv_stream("n\n1\n2\n3\n4\n6\n".as_bytes(), options()).unwrap();
+    assert_eq!(count(&locustdb), vec![vec![Int(7), Int(25)]]);
+
+    // Without deduplication all rows are stored again
+    locustdb.load_csv_stream("n\n1\n2\n3\n4\n5\n".as_bytes(), options().with_deduplicate_batches(false)).unwrap();
+    assert_eq!(count(&locustdb), vec![vec![Int(12), Int(40)]]);
+
+    // Batches are identified by the stored column names, ignored columns are not part of the batch
+    locustdb.load_csv_stream("n,x\n1,a\n2,b\n3,c\n".as_bytes(), options().with_ignore_cols(&["x".to_string()])).unwrap();
+    assert_eq!(count(&locustdb), vec![vec![Int(12), Int(40)]]);
+    locustdb.load_csv_stream("n\n1\n2\n3\n".as_bytes(), options().with_renamed_cols(&[("n", "m")])).unwrap();
+    assert_eq!(count(&locustdb), vec![vec![Int(15), Int(40)]]);
+
+    // Identical batches within a single load are all stored, only batches loaded before are skipped
+    let locustdb = LocustDB::memory_only();
+    locustdb.load_csv_stream("n\n1\n1\n1\n1\n1\n1\n".as_bytes(), options()).unwrap();
+    assert_eq!(count(&locustdb), vec![vec![Int(6), Int(6)]]);
+    locustdb.load_csv_stream("n\n1\n1\n1\n1\n1\n1\n1\n1\n1\n".as_bytes(), options()).unwrap();
+    assert_eq!(count(&locustdb), vec![vec![Int(9), Int(9)]]);
+}
+
+#[test]
+fn test_deduplicate_batches_persisted() {
+    use tempdir::TempDir;
+    let tmp_dir = TempDir::new("deduplicate_batches_persisted").unwrap();
+    let mut opts = Options::default();
+    opts.wal_path = Some(tmp_dir.path().join("wal").to_str().unwrap().to_string());
+    let checkpoint = tmp_dir.path().join("checkpoint");
+    let checkpoint = checkpoint.to_str().unwrap();
+    let query = "SELECT count(0), sum(n) FROM default;";
+    let options = || LoadOptions::new("stdin", "default").with_partition_size(3).with_deduplicate_batches(true);
+    let csv = "n\n1\n2\n3\n4\n5\n";
+    {
+        let locustdb = LocustDB::with_wal(&opts).unwrap();
+        locustdb.load_csv_stream(csv.as_bytes(), options()).unwrap();
+    }
+
+    // Batch hashes are restored from the write-ahead log
+    let locustdb = LocustDB::with_wal(&opts).unwrap();
+    locustdb.load_csv_stream(csv.as_bytes(), options()).unwrap();
+    assert_eq!(run_query(&locustdb, query).rows, vec![vec![Int(5), Int(15)]]);
+
+    // and saved with the tables
+    locustdb.save(checkpoint).unwrap();
+    let restarted = LocustDB::with_wal(&opts).unwrap();
+    restarted.load(checkpoint).unwrap();
+    restarted.load_csv_stream(csv.as_bytes(), options()).unwrap();
+    assert_eq!(run_query(&restarted, query).rows, vec![vec![Int(5), Int(15)]]);
+}
+
+#[test]
+fn test_ingest_stream_incremental() {
+    use std::io::{self, Read};
+
+    // Supplies one line per read and records how many rows are queryable at that point
+    struct LineReader<'a> {
+        locustdb: &'a LocustDB,
+        lines: Vec<String>,
+        queryable_rows: Vec<i64>,
+    }
+
+    impl<'a> Read for LineReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let query = block_on(self.locustdb.run_query("SELECT count(0) FROM default;", false, vec![])).unwrap().0;
+            self.queryable_rows.push(match query.map(|output| output.rows) {
+                Ok(ref rows) if !rows.is_empty() => match rows[0][0] { Int(count) => count, _ => 0 },
+                _ => 0,
+            });
+            if self.lines.is_empty() { return Ok(0); }
+            let line = self.lines.remove(0);
+            buf[..line.len()].copy_from_slice(line.as_bytes());
+            Ok(line.len())
+        }
+    }
+
+    let locustdb = LocustDB::memory_only();
+    let mut reader = LineReader {
+        locustdb: &locustdb,
+        lines: "n\n1\n2\n3\n4\n5\n6\n7\n".lines().map(|line| format!("{}\n", line)).collect(),
+        queryable_rows: Vec::new(),
+    };
+    locustdb.load_csv_stream(&mut reader, LoadOptions::new("stdin", "default").with_partition_size(2)).unwrap();
+    let mut queryable_rows = reader.queryable_rows.clone();
+    queryable_rows.dedup();
+    assert_eq!(queryable_rows, vec![0, 2, 4, 6]);
+}
+
+#[test]
+fn test_column_types() {
+    let locustdb = load_csv_str("id,name,empty,mixed\n1,a,,1\n2,b,,x\n", 1);
+    let output = |query: &str| run_query(&locustdb, query);
+
+    // Types are reported even if there are no rows
+    let result = output("SELECT id, name, empty, mixed FROM default WHERE id > 5;");
+    assert!(result.rows.is_empty());
+    assert_eq!(result.column_type("id"), Some(BasicType::Integer));
+    assert_eq!(result.column_type("name"), Some(BasicType::String));
+    assert_eq!(result.column_type("empty"), Some(BasicType::Null));
+    assert_eq!(result.column_type("mixed"), Some(BasicType::Val));
+    assert_eq!(result.column_type("missing"), None);
+
+    let result = output("SELECT name, count(0), max(id), id + 1 FROM default;");
+    assert_eq!(result.coltypes, vec![BasicType::String, BasicType::Integer, BasicType::Integer, BasicType::Integer]);
+
+    // Division by a column or by zero can produce nulls
+    let result = output("SELECT id / 2, id % id, id / 0 FROM default;");
+    assert_eq!(result.coltypes, vec![BasicType::Integer, BasicType::Val, BasicType::Val]);
+}
+
+#[test]
+fn test_sum_offset_encoded() {
+    let mut csv = "g,x\n".to_string();
+    for i in 0..300 {
+        // Unsorted values in a range of a few thousand are stored as u16 with an offset
+        csv.push_str(&format!("{},{}\n", i % 3, 100_000 + (i * 37 % 300) * 7));
+    }
+    let locustdb = load_csv_str(&csv, 128);
+    let run = |query: &str| block_on(locustdb.run_query(query, true, vec![])).unwrap().0.unwrap();
+    let expected = (0..3)
+        .map(|g| vec![Int(g), Int((0..300).filter(|i| i % 3 == g).map(|i| 100_000 + (i * 37 % 300) * 7).sum())])
+        .collect::<Vec<_>>();
+
+    let encoded = run("SELECT g, sum(x) FROM default;");
+    assert_eq!(encoded.rows, expected);
+    // Offset encoded values are summed without decoding them first
+    for plan in encoded.query_plans.keys() {
+        assert!(plan.contains("(offset"), "{}", plan);
+        assert!(!plan.contains("AdditionVS"), "{}", plan);
+    }
+    let decoded = run("SELECT g, sum(x + 0) FROM default;");
+    assert_eq!(decoded.rows, expected);
+    for plan in decoded.query_plans.keys() {
+        assert!(plan.contains("AdditionVS"), "{}", plan);
+    }
+}
+
+#[test]
+fn test_load_fixed_width() {
+    use Value::*;
+    let _ = env_logger::try_init();
+    let input = "\
+001Alice     42 NYC
+002Bob        7 SF
+003Carol     -5 LA
+";
+    let fields = vec![
+        ("id".to_string(), 0, 3),
+        ("name".to_string(), 3, 8),
+        ("num".to_string(), 11, 4),
+        ("city".to_string(), 16, 4),
+    ];
+    let locustdb = LocustDB::memory_only();
+    locustdb.load_fixed_width_stream(input.as_bytes(), &fields, LoadOptions::new("stdin", "default")).unwrap();
+    let result = block_on(locustdb.run_query(
+        "SELECT id, name, num, city FROM default ORDER BY id;", false, vec![])).unwrap().0.unwrap();
+    assert_eq!(result.rows, vec![
+        vec![Int(1), Str("Alice".to_string()), Int(42), Str("NYC".to_string())],
+        vec![Int(2), Str("Bob".to_string()), Int(7), Str("SF".to_string())],
+        vec![Int(3), Str("Carol".to_string()), Int(-5), Str("LA".to_string())],
+    ]);
+}
+
+#[test]
+fn test_ingestion_status() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    assert_eq!(locustdb.ingestion_status("default"), IngestionStatus::default());
+    let load = locustdb.load_csv(LoadOptions::new("test_data/tiny.csv", "default").with_partition_size(40));
+    let status = locustdb.ingestion_status("default");
+    assert_eq!(status.pending_tasks + status.completed_tasks, 1);
+    block_on(load).unwrap().unwrap();
+    assert_eq!(locustdb.ingestion_status("default"), IngestionStatus {
+        rows: 100,
+        pending_tasks: 0,
+        completed_tasks: 1,
+        failed_tasks: 0,
+    });
+    let missing = block_on(locustdb.load_csv(LoadOptions::new("test_data/does_not_exist.csv", "default"))).unwrap();
+    assert!(missing.is_err());
+    let status = locustdb.ingestion_status("default");
+    assert_eq!((status.pending_tasks, status.completed_tasks, status.failed_tasks), (0, 1, 1));
+}
+
+#[test]
+fn test_column_dictionaries() {
+    let locustdb = load_csv_str("name,num\nbob,1\nalice,2\ncarol,3\nalice,4\nbob,5\n", 100);
+    let dictionaries = locustdb.column_dictionaries("default", "name").unwrap();
+    assert_eq!(dictionaries, vec![vec![
+        (0, "alice".to_string()),
+        (1, "bob".to_string()),
+        (2, "carol".to_string()),
+    ]]);
+    assert_eq!(locustdb.column_dictionaries("default", "num"), Some(vec![]));
+    assert_eq!(locustdb.column_dictionaries("missing", "name"), None);
+}
+
+#[test]
+fn test_float_precision() {
+    let locustdb = load_csv_str("g,x\na,1\na,2\na,4\n", 2);
+    let output = run_query(&locustdb, "SELECT var(x) FROM default;");
+    assert_eq!(output.to_csv_with_precision(Some(2)).lines().nth(1), Some("2.33"));
+    assert_eq!(output.to_csv_with_precision(Some(0)).lines().nth(1), Some("2"));
+    assert!(output.to_json_with_precision(Some(3)).contains("\"rows\":[[2.333]]"), "{}", output.to_json_with_precision(Some(3)));
+    // JSON preserves full precision by default
+    assert!(output.to_json().contains("\"rows\":[[2.33333333333333"), "{}", output.to_json());
+    assert_eq!(format!("{:.1}", output.rows[0][0]), "2.3");
+}
+
+#[test]
+fn test_order_by_multiple_columns() {
+    use Value::*;
+    let csv = "city,score,name\nb,1,u\na,2,v\nb,3,w\na,2,x\nc,5,y\na,9,z\nb,3,q\n";
+    let row = |city: &str, score: i64, name: &str| vec![Str(city.to_string()), Int(score), Str(name.to_string())];
+    test_query_csv(csv, "SELECT city, score, name FROM default ORDER BY city ASC, score DESC, name ASC;", &[
+        row("a", 9, "z"),
+        row("a", 2, "v"),
+        row("a", 2, "x"),
+        row("b", 3, "q"),
+        row("b", 3, "w"),
+        row("b", 1, "u"),
+        row("c", 5, "y"),
+    ]);
+    test_query_csv(csv, "SELECT city, score, name FROM default ORDER BY city DESC, score, name DESC LIMIT 3 OFFSET 1;", &[
+        row("b", 1, "u"),
+        row("b", 3, "w"),
+        row("b", 3, "q"),
+    ]);
+    // Partitions are sorted and merged by all ORDER BY columns, so ties on the first column survive the limit
+    test_query_csv(csv, "SELECT city, score, name FROM default ORDER BY city, score, name DESC LIMIT 4;", &[
+        row("a", 2, "x"),
+        row("a", 2, "v"),
+        row("a", 9, "z"),
+        row("b", 1, "u"),
+    ]);
+    test_query_csv(csv, "SELECT city, score, name FROM default ORDER BY score DESC, city, name LIMIT 4;", &[
+        row("a", 9, "z"),
+        row("c", 5, "y"),
+        row("b", 3, "q"),
+        row("b", 3, "w"),
+    ]);
+    test_query_csv(csv, "SELECT city, count(0) FROM default ORDER BY count(0) DESC, city DESC;", &[
+        vec![Str("b".to_string()), Int(3)],
+        vec![Str("a".to_string()), Int(3)],
+        vec![Str("c".to_string()), Int(1)],
+    ]);
+}
+
+#[test]
+fn test_having_on_computed_column() {
+    use Value::*;
+    let csv = "a,b\n1,2\n5,7\n3,4\n10,1\n";
+    let locustdb = load_csv_str(csv, 2);
+    let result = run_query(&locustdb, "SELECT a + b AS s, a FROM default WHERE a > 1 HAVING s > 10 ORDER BY a;");
+    assert_eq!(result.colnames, vec!["s".to_string(), "a".to_string()]);
+    assert_eq!(result.rows, vec![vec![Int(12), Int(5)], vec![Int(11), Int(10)]]);
+    test_query_csv(csv, "SELECT a * 2, b FROM default HAVING col_0 > 10;", &[vec![Int(20), Int(1)]]);
+}
+
+#[test]
+fn test_load_arrow_file() {
+    use Value::*;
+    let _ = env_logger::try_init();
+    // Two record batches with the columns
+    // id: Int64, num: Int32 (nullable), price: Float64, name: Utf8 (nullable), flag: Boolean, missing: Int64 (all null)
+    let locustdb = LocustDB::memory_only();
+    locustdb.load_arrow_file("test_data/mixed_types.arrow", "default").unwrap();
+    let schema = block_on(locustdb.schema("default")).unwrap().unwrap();
+    assert!(schema.contains(&("id".to_string(), BasicType::Integer)), "{:?}", schema);
+    assert!(schema.contains(&("name".to_string(), BasicType::String)), "{:?}", schema);
+    assert!(schema.contains(&("flag".to_string(), BasicType::Boolean)), "{:?}", schema);
+    let result = run_query(&locustdb, "SELECT id, num, price, name, missing FROM default ORDER BY id;");
+    assert_eq!(result.rows, vec![
+        vec![Int(1), Int(-5), Int(1), Str("a".to_string()), Null],
+        vec![Int(2), Null, Int(20), Str("bb".to_string()), Null],
+        vec![Int(3), Int(7), Int(3), Null, Null],
+        vec![Int(4), Null, Int(4), Null, Null],
+        vec![Int(5), Int(12), Int(-2), Str("e".to_string()), Null],
+    ]);
+    let result = run_query(&locustdb, "SELECT count(0), count(num), sum(num) FROM default WHERE flag;");
+    assert_eq!(result.rows, vec![vec![Int(3), Int(3), Int(14)]]);
+
+    match LocustDB::memory_only().load_arrow_file("test_data/tiny.csv", "default") {
+        Err(IngestError::Arrow(_)) => {}
+        _ => panic!("Expected invalid Arrow file error"),
+    }
+}
+
+#[test]
+fn test_query_cache() {
+    use Value::*;
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::new(&locustdb::Options { query_cache_size: 10, ..locustdb::Options::default() });
+    let load = |csv: &str| locustdb.load_csv_stream(csv.as_bytes(), LoadOptions::new("stdin", "default")).unwrap();
+    let run = |query: &str| run_query(&locustdb, query).rows;
+    load("a\n1\n2\n");
+    let query = "SELECT count(0), sum(a) FROM default;";
+    assert_eq!(run(query), vec![vec![Int(2), Int(3)]]);
+    assert_eq!(locustdb.query_cache_hits(), 0);
+    assert_eq!(run(query), vec![vec![Int(2), Int(3)]]);
+    assert_eq!(locustdb.query_cache_hits(), 1);
+    // Explained queries bypass the cache
+    block_on(locustdb.run_query(query, true, vec![])).unwrap().0.unwrap();
+    assert_eq!(locustdb.query_cache_hits(), 1);
+    // Ingestion invalidates cached results of the table
+    load("a\n10\n");
+    assert_eq!(run(query), vec![vec![Int(3), Int(13)]]);
+    assert_eq!(locustdb.query_cache_hits(), 1);
+    assert_eq!(run(query), vec![vec![Int(3), Int(13)]]);
+    assert_eq!(locustdb.query_cache_hits(), 2);
+}
+
+#[test]
+fn test_case_insensitive_columns() {
+    use Value::*;
+    let _ = env_logger::try_init();
+    let csv = "UserID,Name\n1,a\n2,b\n2,c\n";
+    let case_sensitive = LocustDB::memory_only();
+    case_sensitive.load_csv_stream(csv.as_bytes(), LoadOptions::new("stdin", "default")).unwrap();
+    match block_on(case_sensitive.run_query("SELECT userid FROM default;", false, vec![])).unwrap().0 {
+        Err(QueryError::UnknownColumn(ref col)) => assert_eq!(col, "userid"),
+        Err(other) => panic!("Expected unknown column error, got {:?}", other),
+        Ok(_) => panic!("Expected unknown column error"),
+    }
+
+    let locustdb = LocustDB::new(&locustdb::Options { case_insensitive_columns: true, ..locustdb::Options::default() });
+    locustdb.load_csv_stream(csv.as_bytes(), LoadOptions::new("stdin", "default")).unwrap();
+    let run = |query: &str| run_query(&locustdb, query);
+    let result = run("SELECT userid, count(0) FROM default WHERE NAME <> 'a' ORDER BY userid;");
+    assert_eq!(result.colnames, vec!["UserID".to_string(), "count_0".to_string()]);
+    assert_eq!(result.rows, vec![vec![Int(2), Int(2)]]);
+    assert_eq!(run("SELECT name AS userid FROM default ORDER BY userid DESC;").rows,
+               vec![vec![Str("c".to_string())], vec![Str("b".to_string())], vec![Str("a".to_string())]]);
+}
+
+#[test]
+fn test_union_all() {
+    use Value::*;
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let load = |csv: &str, table: &str| locustdb.load_csv_stream(csv.as_bytes(), LoadOptions::new("stdin", table)).unwrap();
+    load("ts,name\n1,a\n2,b\n3,c\n4,d\n", "default");
+    load("ts,label\n9,x\n", "other");
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0;
+
+    let output = run("SELECT ts, name FROM default WHERE ts < 2 UNION ALL SELECT ts, name FROM default WHERE ts >= 3 ORDER BY ts;").unwrap();
+    assert_eq!(output.colnames, vec!["ts".to_string(), "name".to_string()]);
+    assert_eq!(output.rows, vec![
+        vec![Int(1), Str("a".to_string())],
+        vec![Int(3), Str("c".to_string())],
+        vec![Int(4), Str("d".to_string())],
+    ]);
+    // ORDER BY, LIMIT and OFFSET after the last query apply to the combined rows
+    let output = run("SELECT ts, name FROM default WHERE ts < 3 UNION ALL SELECT ts, label FROM other ORDER BY ts DESC LIMIT 2;").unwrap();
+    assert_eq!(output.rows, vec![
+        vec![Int(9), Str("x".to_string())],
+        vec![Int(2), Str("b".to_string())],
+    ]);
+    let output = run("SELECT name FROM default UNION ALL SELECT label FROM other ORDER BY name LIMIT 2 OFFSET 3;").unwrap();
+    assert_eq!(output.rows, vec![vec![Str("d".to_string())], vec![Str("x".to_string())]]);
+    match run("SELECT ts FROM default ORDER BY ts UNION ALL SELECT ts FROM other;") {
+        Err(QueryError::ParseError(_)) => {}
+        other => panic!("Expected parse error, got {:?}", other.map(|output| output.rows)),
+    }
+    // Duplicate rows are kept
+    let output = run("SELECT count(0) FROM default union all SELECT count(0) FROM default union all SELECT count(0) FROM other;").unwrap();
+    assert_eq!(output.rows, vec![vec![Int(4)], vec![Int(4)], vec![Int(1)]]);
+    assert_eq!(output.stats.rows_scanned, 9);
+
+    match run("SELECT ts FROM default UNION ALL SELECT ts, label FROM other;") {
+        Err(QueryError::TypeMismatch(_)) => {}
+        other => panic!("Expected type mismatch, got {:?}", other.map(|output| output.rows)),
+    }
+    match run("SELECT name FROM default UNION ALL SELECT ts FROM other;") {
+        Err(QueryError::TypeMismatch(_)) => {}
+        other => panic!("Expected type mismatch, got {:?}", other.map(|output| output.rows)),
+    }
+    match run("SELECT name FROM default UNION SELECT label FROM other;") {
+        Err(QueryError::NotImplemented(_)) => {}
+        other => panic!("Expected unsupported UNION, got {:?}", other.map(|output| output.rows)),
+    }
+}
+
+#[test]
+fn test_encoding_hints() {
+    use std::collections::HashMap;
+    let mut csv = "forced,automatic,wide,name\n".to_string();
+    for i in 0..100 {
+        csv.push_str(&format!("{},{},{},n{}\n", i % 10, i % 10, i * 100, i));
+    }
+    let load = |encodings: &[(&str, EncodingType)]| {
+        let locustdb = LocustDB::memory_only();
+        let encodings = encodings.iter().map(|&(col, t)| (col.to_string(), t)).collect::<HashMap<_, _>>();
+        let options = LoadOptions::new("stdin", "default").with_encodings(encodings);
+        locustdb.load_csv_stream(csv.as_bytes(), options).map(|_| locustdb)
+    };
+
+    let locustdb = load(&[("forced", EncodingType::I64), ("wide", EncodingType::U32)]).unwrap();
+    let stats = block_on(locustdb.table_stats()).unwrap();
+    let table = stats.iter().find(|t| t.name == "default").unwrap();
+    let encodings = |col: &str| table.columns.iter().find(|c| c.name == col).unwrap().encodings.clone();
+    assert_eq!(encodings("forced"), vec![EncodingType::I64]);
+    assert_eq!(encodings("automatic"), vec![EncodingType::U8]);
+    assert_eq!(encodings("wide"), vec![EncodingType::U32]);
+    let query = "select sum(forced), sum(automatic), max(wide) from default;";
+    let result = run_query(&locustdb, query);
+    assert_eq!(result.rows, vec![vec![Int(450), Int(450), Int(9900)]]);
+
+    for encoding in &[("wide", EncodingType::U8), ("name", EncodingType::I64), ("forced", EncodingType::Str)] {
+        match load(&[*encoding]) {
+            Err(IngestError::Encoding(_)) => {}
+            Err(err) => panic!("Expected IngestError::Encoding for {:?}, got {:?}", encoding, err),
+            Ok(_) => panic!("Expected IngestError::Encoding for {:?}", encoding),
+        }
+    }
+}
+
+#[test]
+fn test_empty_strings_and_null_tokens() {
+    use Value::*;
+    let csv = "id,note\n1,\n2,\n3,NULL\n4,NULL\n5,x\n6,NULL\n";
+    let query = |options: LoadOptions| {
+        let locustdb = LocustDB::memory_only();
+        locustdb.load_csv_stream(csv.as_bytes(), options).unwrap();
+        let result = block_on(locustdb.run_query("SELECT id, note FROM default ORDER BY id;", false, vec![])).unwrap().0.unwrap();
+        result.rows.into_iter().map(|row| row[1].clone()).collect::<Vec<_>>()
+    };
+    let empty = || Str(String::new());
+
+    for &partition_size in &[1, 2, 3, 1 << 16] {
+        let distinguished = query(LoadOptions::new("stdin", "default")
+            .with_partition_size(partition_size)
+            .with_null_tokens(&["NULL"])
+            .with_empty_strings(&["note"]));
+        assert_eq!(distinguished, vec![empty(), empty(), Null, Null, Str("x".to_string()), Null]);
+
+        let default = query(LoadOptions::new("stdin", "default").with_partition_size(partition_size));
+        assert_eq!(default, vec![Null, Null, Str("NULL".to_string()), Str("NULL".to_string()), Str("x".to_string()), Str("NULL".to_string())]);
+    }
+}
+
+#[test]
+fn test_group_by_rollup() {
+    use Value::*;
+    let csv = "region,product,amount\neast,a,10\neast,b,20\neast,a,5\nwest,a,7\nwest,c,3\n";
+    let s = |s: &str| Str(s.to_string());
+
+    let query = "SELECT region, product, sum(amount), count(1) FROM default GROUP BY region, product WITH ROLLUP;";
+    test_query_csv(csv, query, &[
+        vec![s("east"), s("a"), Int(15), Int(2)],
+        vec![s("east"), s("b"), Int(20), Int(1)],
+        vec![s("east"), Null, Int(35), Int(3)],
+        vec![s("west"), s("a"), Int(7), Int(1)],
+        vec![s("west"), s("c"), Int(3), Int(1)],
+        vec![s("west"), Null, Int(10), Int(2)],
+        vec![Null, Null, Int(45), Int(5)],
+    ]);
+
+    let query = "SELECT region, median(amount) FROM default GROUP BY region WITH ROLLUP;";
+    test_query_csv(csv, query, &[
+        vec![s("east"), Int(10)],
+        vec![s("west"), Int(3)],
+        vec![Null, Int(7)],
+    ]);
+}
+
+#[test]
+fn test_combined_aggregation() {
+    let mut csv = "g,a,b\n".to_string();
+    for i in 0..400 {
+        csv.push_str(&format!("{},{},{}\n", i % 3, 1000 + i, i * i));
+    }
+    let combined = load_csv_str(&csv, 100);
+    let per_aggregate = load_csv_str_with_options(&csv, 100, &Options { combine_aggregates: false, ..Options::default() });
+    for query in &["SELECT g, sum(a), sum(b), count(1), min(b), max(a) FROM default;",
+                   // Grouping by `b` uses a hashmap, which already counts the rows of each group
+                   "SELECT b, sum(a), max(a), count(1) FROM default ORDER BY b LIMIT 10;"] {
+        assert_eq!(run_query(&combined, query).rows, run_query(&per_aggregate, query).rows, "{}", query);
+    }
+    let query = "SELECT g, sum(a), sum(b), count(1) FROM default;";
+    let result = run_query(&combined, query);
+    assert_eq!(result.rows[0], vec![Int(0), Int((0..400).filter(|i| i % 3 == 0).map(|i| 1000 + i).sum()),
+                                    Int((0..400).filter(|i| i % 3 == 0).map(|i| i * i).sum()), Int(134)]);
+
+    // The per-aggregate path runs one pass over the grouping key for each of the two sums and the count,
+    // which are folded into a single operator in each of the 4 partitions
+    let partitions = 4;
+    let per_aggregate_ops = run_query(&per_aggregate, query).stats.vector_ops;
+    assert!(result.stats.vector_ops + 2 * partitions <= per_aggregate_ops,
+            "{} operators, {} without combining aggregates", result.stats.vector_ops, per_aggregate_ops);
+}
+
+#[test]
+fn test_column_type_override() {
+    use std::collections::HashMap;
+    let csv = "zip,city,flag\n01234,Berlin,1\n98765,Paris,0\n00501,Rome,1\n";
+    let zips = |column_types: HashMap<String, BasicType>, query: &str| {
+        let locustdb = LocustDB::memory_only();
+        let opts = LoadOptions::new("stdin", "default").with_column_types(column_types);
+        locustdb.load_csv_stream(csv.as_bytes(), opts).unwrap();
+        run_query(&locustdb, query).rows
+    };
+    assert_eq!(zips(HashMap::new(), "SELECT zip FROM default ORDER BY zip;"),
+               vec![vec![Int(501)], vec![Int(1234)], vec![Int(98765)]]);
+    let mut column_types = HashMap::new();
+    column_types.insert("zip".to_string(), BasicType::String);
+    column_types.insert("flag".to_string(), BasicType::Boolean);
+    assert_eq!(zips(column_types, "SELECT zip FROM default WHERE flag ORDER BY zip;"),
+               vec![vec![Str("00501")], vec![Str("01234")]]);
+
+    let mut column_types = HashMap::new();
+    column_types.insert("city".to_string(), BasicType::Integer);
+    let opts = LoadOptions::new("stdin", "default").with_column_types(column_types);
+    match LocustDB::memory_only().load_csv_stream(csv.as_bytes(), opts) {
+        Err(IngestError::Parse { row: 2, col: 1 }) => {}
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_count_skips_nulls() {
+    // Depending on the partition size, partitions of `x` contain only values, only nulls, or both
+    let csv = "g,x,s\na,1,u\na,2,\na,,v\nb,,\nb,3,\nb,4,w\nb,,x\nb,,\n";
+    test_query_csv(csv, "SELECT count(*), count(x), count(s) FROM default;", &[vec![Int(8), Int(4), Int(4)]]);
+    test_query_csv(csv, "SELECT g, count(*), count(x) FROM default ORDER BY g;",
+                   &[vec![Str("a"), Int(3), Int(2)], vec![Str("b"), Int(5), Int(2)]]);
+    test_query_csv(csv, "SELECT g, count(x) FROM default ORDER BY g;",
+                   &[vec![Str("a"), Int(2)], vec![Str("b"), Int(2)]]);
+}
+
+#[test]
+fn test_derived_column() {
+    let locustdb = LocustDB::memory_only();
+    let csv = "first,last,age\nAda,Lovelace,36\nAlan,Turing,41\nGrace,Hopper,85\n";
+    let opts = LoadOptions::new("stdin", "default")
+        .with_derived_col("full_name", &["first", "last"], |fields| format!("{} {}", fields[0], fields[1]))
+        .with_derived_col("age_in_months", &["age"], |fields| (fields[0].parse::<i64>().unwrap() * 12).to_string());
+    locustdb.load_csv_stream(csv.as_bytes(), opts).unwrap();
+    let output = run_query(&locustdb, "SELECT full_name, age_in_months FROM default WHERE age < 50 ORDER BY full_name;");
+    assert_eq!(output.rows, vec![vec![Str("Ada Lovelace"), Int(432)], vec![Str("Alan Turing"), Int(492)]]);
+
+    let opts = LoadOptions::new("stdin", "default").with_derived_col("x", &["middle"], |fields| fields[0].to_string());
+    assert!(LocustDB::memory_only().load_csv_stream(csv.as_bytes(), opts).is_err());
+}
+
+#[test]
+fn test_query_builder() {
+    let locustdb = load_edge_cases();
+    let query = QueryBuilder::select(vec![Expr::col("u8_offset_encoded")])
+        .aggregate(Aggregator::Count, Expr::int(1))
+        .aggregate(Aggregator::Sum, Expr::col("u8_offset_encoded"))
+        .from("default")
+        .filter(Expr::and(Expr::gt(Expr::col("u8_offset_encoded"), Expr::int(300)),
+                          Expr::not(Expr::eq(Expr::col("enum"), Expr::string("aa")))))
+        .group_by(vec![Expr::col("u8_offset_encoded")])
+        .limit(5)
+        .build()
+        .unwrap();
+    let built = block_on(locustdb.run_query_ast(query, false, vec![])).unwrap().0.unwrap();
+    let parsed = run_query(&locustdb, "SELECT u8_offset_encoded, count(1), sum(u8_offset_encoded) FROM default \
+                                       WHERE u8_offset_encoded > 300 AND NOT (enum = 'aa') LIMIT 5;");
+    assert!(!parsed.rows.is_empty());
+    assert_eq!(built.rows, parsed.rows);
+
+    assert!(QueryBuilder::select(vec![Expr::col("u8_offset_encoded")]).build().is_err());
+    assert!(QueryBuilder::select(vec![Expr::col("u8_offset_encoded")])
+        .from("default")
+        .group_by(vec![Expr::col("enum")])
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_run_on_batch() {
+    let batch = || {
+        let mut batch = Batch::default();
+        for &(name, age) in &[("Ada", 36), ("Alan", 41), ("Grace", 85), ("Edsger", 72)] {
+            batch.push_row(vec![("name".to_string(), Str(name)), ("age".to_string(), Int(age))]);
+        }
+        batch
+    };
+    let query = QueryBuilder::select(vec![Expr::col("name"), Expr::col("age")])
+        .from("batch")
+        .filter(Expr::gt(Expr::col("age"), Expr::int(40)))
+        .order_by("age", false)
+        .build()
+        .unwrap();
+    let output = query.run_on_batch(batch()).unwrap();
+    assert_eq!(output.colnames, vec!["name".to_string(), "age".to_string()]);
+    assert_eq!(output.rows, vec![
+        vec![Str("Alan"), Int(41)],
+        vec![Str("Edsger"), Int(72)],
+        vec![Str("Grace"), Int(85)],
+    ]);
+
+    let aggregate = |min_age: i64| QueryBuilder::select(vec![])
+        .aggregate(Aggregator::Count, Expr::int(1))
+        .aggregate(Aggregator::Sum, Expr::col("age"))
+        .from("batch")
+        .filter(Expr::gt(Expr::col("age"), Expr::int(min_age)))
+        .build()
+        .unwrap();
+    assert_eq!(aggregate(40).run_on_batch(batch()).unwrap().rows, vec![vec![Int(3), Int(198)]]);
+    assert_eq!(aggregate(100).run_on_batch(batch()).unwrap().rows, vec![vec![Int(0), Null]]);
+
+    let query = QueryBuilder::select(vec![Expr::col("missing")]).from("batch").build().unwrap();
+    assert!(query.run_on_batch(Batch::default()).unwrap().rows.is_empty());
+}
+
+#[test]
+fn test_null_safe_equals() {
+    // Depending on the partition size, partitions of `x` and `y` contain only values, only nulls, or both
+    let csv = "g,x,y\na,,\na,,\nb,1,\nb,2,\nc,,3\nc,0,0\n";
+    test_query_csv(csv, "SELECT count(0) FROM default WHERE x <=> NULL;", &[vec![Int(3)]]);
+    test_query_csv(csv, "SELECT count(0) FROM default WHERE NULL <=> y;", &[vec![Int(4)]]);
+    test_query_csv(csv, "SELECT g, count(0) FROM default WHERE x <=> 1 ORDER BY g;", &[vec![Str("b"), Int(1)]]);
+    test_query_csv(csv, "SELECT g, count(0) FROM default WHERE x <=> 0 ORDER BY g;", &[vec![Str("c"), Int(1)]]);
+    test_query_csv(csv, "SELECT g, count(0) FROM default WHERE x <=> y ORDER BY g;",
+                   &[vec![Str("a"), Int(2)], vec![Str("c"), Int(1)]]);
+}
+
+#[test]
+fn test_pagination_cursor() {
+    let locustdb = load_csv_str("id,name\n7,g\n2,b\n9,i\n0,z\n4,d\n8,h\n1,a\n5,e\n3,c\n6,f\n", 3);
+    for &(order, expected) in &[("ASC", [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]), ("DESC", [9, 8, 7, 6, 5, 4, 3, 2, 1, 0])] {
+        let query = format!("SELECT id FROM default WHERE id < 100 ORDER BY id {} LIMIT 4;", order);
+        let mut output = run_query(&locustdb, &query);
+        let mut ids = Vec::new();
+        let mut pages = 1;
+        loop {
+            ids.extend(output.rows.iter().map(|row| row[0].clone()));
+            let cursor = match output.next_cursor() {
+                Some(cursor) => cursor.to_string(),
+                None => break,
+            };
+            output = block_on(locustdb.run_query_after(&query, &cursor, false, vec![])).unwrap().0.unwrap();
+            pages += 1;
+        }
+        assert_eq!(pages, 3);
+        assert_eq!(ids, expected.iter().map(|&id| Int(id)).collect::<Vec<_>>());
+    }
+    let query = "SELECT id FROM default ORDER BY id LIMIT 4;";
+    let output = run_query(&locustdb, query);
+    let cursor = output.next_cursor().unwrap();
+    assert!(block_on(locustdb.run_query_after("SELECT id FROM default ORDER BY name LIMIT 4;", cursor, false, vec![])).unwrap().0.is_err());
+    assert!(block_on(locustdb.run_query_after(query, "not a cursor", false, vec![])).unwrap().0.is_err());
+
+    // Rows that tie on the ORDER BY column across a page boundary can't be resumed without skipping rows
+    let locustdb = load_csv_str("id\n1\n2\n3\n3\n3\n4\n", 6);
+    let query = "SELECT id FROM default ORDER BY id LIMIT 2;";
+    let output = run_query(&locustdb, query);
+    let output = block_on(locustdb.run_query_after(query, output.next_cursor().unwrap(), false, vec![])).unwrap().0.unwrap();
+    assert_eq!(output.rows, vec![vec![Int(3)], vec![Int(3)]]);
+    match block_on(locustdb.run_query_after(query, output.next_cursor().unwrap(), false, vec![])).unwrap().0 {
+        Err(QueryError::NotImplemented(_)) => {}
+        other => panic!("Expected error for non-unique column, got {:?}", other.map(|output| output.rows)),
+    }
+    // No cursor is returned once all rows have been returned
+    assert!(run_query(&locustdb, "SELECT id FROM default ORDER BY id LIMIT 6;").next_cursor().is_none());
+}
+
+#[test]
+fn test_column_compression_ratio() {
+    let mut csv = "small_range,name\n".to_string();
+    for i in 0..1000 {
+        csv.push_str(&format!("{},{}\n", 1000 + i % 200, if i % 2 == 0 { "ab" } else { "xyz" }));
+    }
+    let locustdb = load_csv_str(&csv, 1000);
+    let stats = block_on(locustdb.table_stats()).unwrap();
+    let table = stats.iter().find(|t| t.name == "default").unwrap();
+    let colnames = table.columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(colnames, vec!["name", "small_range"]);
+
+    let small_range = &table.columns[1];
+    assert_eq!(small_range.raw_bytes, 8000);
+    assert!(small_range.compression_ratio() < 0.25, "{:?}", small_range);
+
+    let name = &table.columns[0];
+    assert_eq!(name.raw_bytes, 500 * 2 + 500 * 3);
+
+    // Unique strings are not dictionary encoded
+    let mut csv = "id\n".to_string();
+    for i in 0..2000 {
+        csv.push_str(&format!("id{:04}\n", i));
+    }
+    locustdb.load_csv_stream(csv.as_bytes(), LoadOptions::new("stdin", "unique").with_partition_size(2000)).unwrap();
+    let stats = block_on(locustdb.table_stats()).unwrap();
+    let table = stats.iter().find(|t| t.name == "unique").unwrap();
+    assert_eq!(table.columns[0].raw_bytes, 2000 * 6);
+}
+
+#[test]
+fn test_bit_packing() {
+    let mut csv = "small,large\n".to_string();
+    for i in 0..2000 {
+        csv.push_str(&format!("{},{}\n", 100 + i % 5, i % 200));
+    }
+    let query = "SELECT small, large, count(0) FROM default ORDER BY large LIMIT 1000;";
+    let mut results = Vec::new();
+    let mut sizes = Vec::new();
+    for &bit_packing in &[false, true] {
+        let locustdb = LocustDB::memory_only();
+        let opts = LoadOptions::new("stdin", "default")
+            .with_partition_size(1000)
+            .with_bit_packing(bit_packing);
+        locustdb.load_csv_stream(csv.as_bytes(), opts).unwrap();
+        results.push(run_query(&locustdb, query).rows);
+        let stats = block_on(locustdb.table_stats()).unwrap();
+        let table = stats.iter().find(|t| t.name == "default").unwrap();
+        sizes.push(table.columns.iter().map(|c| (c.name.clone(), c.encoded_bytes)).collect::<Vec<_>>());
+    }
+    assert_eq!(results[0], results[1]);
+    assert_eq!(results[0].len(), 200);
+    // Only the column with a range of 3 bits is packed
+    assert_eq!(sizes[0][0], sizes[1][0]);
+    assert!(sizes[1][1].1 * 2 < sizes[0][1].1, "{:?}", sizes);
+}
+
+#[test]
+fn test_worker_thread_limit() {
+    for &threads in &[1, 2] {
+        let locustdb = LocustDB::new(&locustdb::Options { threads, ..locustdb::Options::default() });
+        block_on(locustdb.load_csv(
+            LoadOptions::new("test_data/tiny.csv", "default")
+                .with_partition_size(5))).unwrap().unwrap();
+        let queries = (0..8)
+            .map(|_| locustdb.run_query("SELECT tld, count(0), sum(num) FROM default;", false, vec![]))
+            .collect::<Vec<_>>();
+        for query in queries {
+            block_on(query).unwrap().0.unwrap();
+        }
+        let max_busy_workers = locustdb.max_busy_workers();
+        assert!(max_busy_workers >= 1 && max_busy_workers <= threads, "{} > {}", max_busy_workers, threads);
+    }
+}
+
+#[test]
+fn test_parallel_matches_serial() {
+    let _ = env_logger::try_init();
+    let gen_table = || locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 64,
+        partition_size: 1 << 12,
+        columns: vec![
+            ("ints".to_string(),
+             locustdb::colgen::int_uniform(-10, 256)),
+            ("scrambled".to_string(),
+             locustdb::colgen::random_string(1, 2)),
+        ],
+    };
+    let queries = [
+        "SELECT ints, count(1) FROM test;",
+        "SELECT scrambled, sum(ints) FROM test;",
+        "SELECT scrambled, ints, count(1) FROM test WHERE ints < 0;",
+    ];
+
+    let mut opts = Options::default();
+    opts.threads = 1;
+    let serial = LocustDB::new(&opts);
+    let _ = block_on(serial.gen_table(gen_table()));
+    opts.threads = 8;
+    let parallel = LocustDB::new(&opts);
+    let _ = block_on(parallel.gen_table(gen_table()));
+
+    for query in &queries {
+        let expected = run_query(&serial, query);
+        let actual = run_query(&parallel, query);
+        assert_eq!(actual.rows, expected.rows, "{}", query);
+        assert_eq!(actual.stats.rows_scanned, expected.stats.rows_scanned, "{}", query);
+    }
+}
+
+#[test]
+fn test_parallel_merge_high_cardinality() {
+    let _ = env_logger::try_init();
+    let gen_table = || locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 32,
+        partition_size: 1 << 13,
+        columns: vec![
+            ("key".to_string(), locustdb::colgen::int_uniform(0, 100_000)),
+            ("value".to_string(), locustdb::colgen::int_uniform(-1000, 1000)),
+        ],
+    };
+    let query = "SELECT key, count(0), sum(value), min(value), max(value), approx_count_distinct(value) FROM test LIMIT 200000;";
+
+    let mut opts = Options::default();
+    opts.detailed_stats = true;
+    opts.threads = 1;
+    let serial = LocustDB::new(&opts);
+    let _ = block_on(serial.gen_table(gen_table()));
+    opts.threads = 8;
+    let parallel = LocustDB::new(&opts);
+    let _ = block_on(parallel.gen_table(gen_table()));
+
+    let expected = run_query(&serial, query);
+    let actual = run_query(&parallel, query);
+    assert!(expected.rows.len() > 50_000);
+    assert_eq!(actual.rows, expected.rows);
+    // A single thread merges all partitions itself, with 8 threads the partial results of the threads are merged
+    // by the worker threads that produced them instead of by additional threads
+    assert!(!expected.stats.breakdown.contains_key("final_merge"));
+    assert!(actual.stats.breakdown["final_merge"] > 0);
+    assert!(parallel.max_busy_workers() <= 8);
+}
+
+#[test]
+fn test_detailed_stats() {
+    let _ = env_logger::try_init();
+    let query = "select first_name, count(1) from default;";
+    for &detailed_stats in &[false, true] {
+        let mut opts = Options::default();
+        opts.detailed_stats = detailed_stats;
+        let locustdb = LocustDB::new(&opts);
+        let _ = block_on(locustdb.load_csv(
+            LoadOptions::new("test_data/tiny.csv", "default")
+                .with_partition_size(40)));
+        let result = run_query(&locustdb, query);
+        let breakdown = result.stats.breakdown;
+        if detailed_stats {
+            for phase in &["load_columns", "run_partition", "plan_partition", "execute_partition", "merge", "convert_output"] {
+                assert!(breakdown.contains_key(*phase), "{} missing from {:?}", phase, breakdown);
+            }
+        } else {
+            assert!(breakdown.is_empty());
+        }
+    }
+}
+
+#[test]
+fn test_detailed_stats_phases() {
+    let mut opts = Options::default();
+    opts.detailed_stats = true;
+    let locustdb = LocustDB::new(&opts);
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    let stats = block_on(locustdb.run_query("select first_name from default where num < 10;", false, vec![]))
+        .unwrap().0.unwrap().stats;
+    assert!(stats.phase_ns("execute_partition") > 0);
+    // Planning and execution happen within the time recorded for each partition
+    assert!(stats.phase_ns("plan_partition") + stats.phase_ns("execute_partition") <= stats.phase_ns("run_partition"));
+    assert_eq!(stats.phase_ns("no_such_phase"), 0);
+}
+
+#[test]
+fn test_rows_returned() {
+    let mut csv = "id,value\n".to_string();
+    for id in 0..1000 {
+        csv.push_str(&format!("{},{}\n", id, id % 10));
+    }
+    let locustdb = load_csv_str(&csv, 128);
+    let stats = |query: &str| run_query(&locustdb, query).stats;
+
+    // One in ten rows passes the filter, independent of how many rows are returned after the limit
+    let select = stats("SELECT id FROM default WHERE value = 3 LIMIT 10000;");
+    assert_eq!((select.rows_scanned, select.rows_returned), (1000, 100));
+    let aggregate = stats("SELECT value, count(0) FROM default WHERE value < 3;");
+    assert_eq!((aggregate.rows_scanned, aggregate.rows_returned), (1000, 300));
+    let unfiltered = stats("SELECT value, count(0) FROM default;");
+    assert_eq!((unfiltered.rows_scanned, unfiltered.rows_returned), (1000, 1000));
+}
+
+#[test]
+fn test_parallel_speedup() {
+    use std::time::{Duration, Instant};
+    let _ = env_logger::try_init();
+    let threads = min(num_cpus::get(), 4);
+    if threads < 2 {
+        return;
+    }
+    let gen_table = || locustdb::colgen::GenTable {
+        name: "test".to_string(),
+        partitions: 256,
+        partition_size: 1 << 16,
+        columns: vec![
+            ("ints".to_string(),
+             locustdb::colgen::int_uniform(-10, 256)),
+        ],
+    };
+    let query = "SELECT ints, count(1) FROM test;";
+    let best_runtime = |threads: usize| {
+        let locustdb = LocustDB::new(&Options { threads, ..Options::default() });
+        let _ = block_on(locustdb.gen_table(gen_table()));
+        (0..3).map(|_| {
+            let start = Instant::now();
+            run_query(&locustdb, query);
+            start.elapsed()
+        }).min().unwrap()
+    };
+
+    let serial = best_runtime(1);
+    let parallel = best_runtime(threads);
+    // Allow for scheduling overhead, a fully serial execution would take at least as long as `serial`
+    assert!(parallel * 4 < serial * 3 + Duration::from_millis(10),
+            "{} threads took {:?}, 1 thread took {:?}", threads, parallel, serial);
+}
+
+#[test]
+fn test_query_timeout() {
+    use std::time::Duration;
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.gen_table(
+        locustdb::colgen::GenTable {
+            name: "test".to_string(),
+            partitions: 1000,
+            partition_size: 1024,
+            columns: vec![
+                ("ints".to_string(),
+                 locustdb::colgen::int_uniform(-10, 256))
+            ],
+        }
+    ));
+
+    let query = "SELECT ints, count(1) FROM test;";
+    let result = block_on(locustdb.run_query_with_timeout(query, false, vec![], Some(Duration::from_nanos(1)))).unwrap().0;
+    match result {
+        Err(QueryError::Timeout(_)) => {}
+        Err(err) => panic!("Expected timeout, got {:?}", err),
+        Ok(_) => panic!("Expected timeout, query succeeded"),
+    }
+
+    let result = block_on(locustdb.run_query_with_timeout(query, false, vec![], Some(Duration::from_secs(60)))).unwrap().0;
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_query_cancel() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Instant;
+    let _ = env_logger::try_init();
+    // A single worker processes partitions one at a time, so the query is still running when it is cancelled
+    let locustdb = LocustDB::new(&locustdb::Options { threads: 1, ..locustdb::Options::default() });
+    let _ = block_on(locustdb.gen_table(
+        locustdb::colgen::GenTable {
+            name: "test".to_string(),
+            partitions: 400,
+            partition_size: 1 << 16,
+            columns: vec![
+                ("ints".to_string(),
+                 locustdb::colgen::int_uniform(-10, 256))
+            ],
+        }
+    ));
+
+    let query = "SELECT ints, count(1) FROM test;";
+    let cancel = Arc::new(AtomicBool::new(false));
+    let start = Instant::now();
+    let result = block_on(locustdb.run_query_cancellable(query, false, vec![], None, Some(cancel.clone()))).unwrap().0;
+    let full_runtime = start.elapsed();
+    let full_ops = result.unwrap().stats.vector_ops;
+
+    let canceller = {
+        let cancel = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(full_runtime / 10);
+            cancel.store(true, Ordering::SeqCst);
+        })
+    };
+    let result = block_on(locustdb.run_query_cancellable(query, false, vec![], None, Some(cancel))).unwrap().0;
+    canceller.join().unwrap();
+    match result {
+        // Partitions that were not started when the flag was set are skipped
+        Err(QueryError::Cancelled(ops)) => assert!(ops < full_ops / 2, "executed {} of {} operators", ops, full_ops),
+        Err(err) => panic!("Expected cancellation, got {:?}", err),
+        Ok(_) => panic!("Expected cancellation, query succeeded"),
+    }
+}
+
 #[cfg(feature = "enable_rocksdb")]
 #[test]
 fn test_restore_from_disk() {